@@ -0,0 +1,19 @@
+CREATE TYPE savepoint_state as ENUM ('requested', 'inprogress', 'ready', 'failed');
+
+CREATE TABLE savepoints (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    job_id VARCHAR REFERENCES job_configs(id) ON DELETE CASCADE NOT NULL,
+    url TEXT NOT NULL,
+    state savepoint_state NOT NULL DEFAULT 'requested',
+    epoch INT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finish_time TIMESTAMPTZ,
+    failure_message TEXT
+);
+
+CREATE INDEX savepoints_job_id_idx ON savepoints (job_id);
+
+ALTER TABLE job_configs
+ADD COLUMN restore_from TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE checkpoints
+ADD COLUMN failure_reason TEXT;
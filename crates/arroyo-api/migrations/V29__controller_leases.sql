@@ -0,0 +1,6 @@
+CREATE TABLE controller_leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    term BIGINT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE job_configs
+ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
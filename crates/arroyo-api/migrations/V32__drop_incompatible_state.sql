@@ -0,0 +1,2 @@
+ALTER TABLE job_configs
+ADD COLUMN drop_incompatible_state BOOLEAN NOT NULL DEFAULT FALSE;
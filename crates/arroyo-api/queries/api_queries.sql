@@ -165,7 +165,7 @@ WHERE pub_id = :pub_id AND organization_id = :organization_id;
 
 ----------- jobs -----------------------
 
---! update_job(checkpoint_interval_micros?, stop?, parallelism_overrides?)
+--! update_job(checkpoint_interval_micros?, stop?, parallelism_overrides?, restore_from?, state_mapping?, drop_incompatible_state?, restart_strategy?, node_selector?, priority?)
 UPDATE job_configs
 SET
    updated_at = :updated_at,
@@ -173,22 +173,29 @@ SET
 
    stop = COALESCE(:stop, stop),
    checkpoint_interval_micros = COALESCE(:checkpoint_interval_micros, checkpoint_interval_micros),
-   parallelism_overrides = COALESCE(:parallelism_overrides, parallelism_overrides)
+   parallelism_overrides = COALESCE(:parallelism_overrides, parallelism_overrides),
+   restore_from = COALESCE(:restore_from, restore_from),
+   state_mapping = COALESCE(:state_mapping, state_mapping),
+   drop_incompatible_state = COALESCE(:drop_incompatible_state, drop_incompatible_state),
+   restart_strategy = COALESCE(:restart_strategy, restart_strategy),
+   node_selector = COALESCE(:node_selector, node_selector),
+   priority = COALESCE(:priority, priority)
 WHERE id = :job_id AND organization_id = :organization_id;
 
---! restart_job(mode)
+--! restart_job(mode, restore_epoch?)
 UPDATE job_configs
 SET
    updated_at = :updated_at,
    updated_by = :updated_by,
    restart_nonce = restart_nonce + 1,
-   restart_mode = :mode
+   restart_mode = :mode,
+   restore_epoch = :restore_epoch
 WHERE id = :job_id AND organization_id = :organization_id;
 
---! create_job(ttl_micros?)
+--! create_job(ttl_micros?, restore_from?, state_mapping?, restart_strategy?, node_selector?)
 INSERT INTO job_configs
-(id, organization_id, pipeline_name, created_by, pipeline_id, checkpoint_interval_micros, ttl_micros)
-VALUES (:id, :organization_id, :pipeline_name, :created_by, :pipeline_id, :checkpoint_interval_micros, :ttl_micros);
+(id, organization_id, pipeline_name, created_by, pipeline_id, checkpoint_interval_micros, ttl_micros, restore_from, state_mapping, drop_incompatible_state, restart_strategy, node_selector, priority)
+VALUES (:id, :organization_id, :pipeline_name, :created_by, :pipeline_id, :checkpoint_interval_micros, :ttl_micros, :restore_from, :state_mapping, :drop_incompatible_state, :restart_strategy, :node_selector, :priority);
 
 --! create_job_status
 INSERT INTO job_statuses (pub_id, id, organization_id) VALUES (:pub_id, :id, :organization_id);
@@ -201,24 +208,24 @@ FROM job_configs
 WHERE job_configs.organization_id = :organization_id AND ttl_micros IS NULL
 ORDER BY COALESCE(job_configs.updated_at, job_configs.created_at) DESC;
 
---! get_pipeline_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_pipeline_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?, restart_delay_micros?)
+SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at, restart_delay_micros
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
 WHERE job_configs.organization_id = :organization_id AND pipelines.pub_id = :pub_id
 ORDER BY job_configs.created_at DESC;
 
---! get_all_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_all_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?, restart_delay_micros?)
+SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at, restart_delay_micros
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
 WHERE job_configs.organization_id = :organization_id AND ttl_micros IS NULL
 ORDER BY job_configs.created_at DESC;
 
---! get_pipeline_job : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_pipeline_job : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?, restart_delay_micros?)
+SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at, restart_delay_micros
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
@@ -233,24 +240,24 @@ FROM job_configs
          INNER JOIN pipelines ON pipeline_id = pipelines.id
 WHERE job_configs.organization_id = :organization_id AND job_configs.id = :job_id;
 
---: DbCheckpoint (finish_time?, operators?)
+--: DbCheckpoint (finish_time?, operators?, failure_reason?)
 
 --! get_job_checkpoints: DbCheckpoint
-SELECT epoch, state_backend, start_time, finish_time, operators FROM checkpoints
+SELECT epoch, state_backend, start_time, finish_time, operators, failure_reason FROM checkpoints
 JOIN job_configs ON checkpoints.job_id = job_configs.id
 WHERE job_configs.id = :job_id
     AND checkpoints.organization_id = :organization_id
     AND state != 'compacted'
-    AND state != 'failed'
+    AND (state != 'failed' OR failure_reason IS NOT NULL)
 ORDER BY epoch;
 
 --! get_job_checkpoint: DbCheckpoint
-SELECT epoch, state_backend, start_time, finish_time, operators FROM checkpoints
+SELECT epoch, state_backend, start_time, finish_time, operators, failure_reason FROM checkpoints
 JOIN job_configs ON checkpoints.job_id = job_configs.id
 WHERE job_configs.id = :job_id
     AND checkpoints.organization_id = :organization_id
     AND state != 'compacted'
-    AND state != 'failed'
+    AND (state != 'failed' OR failure_reason IS NOT NULL)
     AND checkpoints.pub_id = :checkpoint_pub_id;
 
 --! get_checkpoint_details: (finish_time?, operators?)
@@ -260,6 +267,13 @@ WHERE job_id = :job_id
     AND epoch = :epoch
     AND state != 'failed';
 
+--! get_restorable_checkpoint_epoch
+SELECT epoch FROM checkpoints
+WHERE job_id = :job_id
+    AND organization_id = :organization_id
+    AND epoch = :epoch
+    AND (state = 'ready' OR state = 'committing');
+
 --! delete_pipeline_for_job
 DELETE FROM pipelines WHERE pipelines.id = (
     SELECT pipeline_id
@@ -281,6 +295,25 @@ WHERE job_configs.organization_id = :organization_id AND job_configs.id = :job_i
 ORDER BY jlm.created_at DESC
 LIMIT cast(:limit as integer);
 
+----------- savepoints -------------------
+
+--: DbSavepoint (epoch?, finish_time?, failure_message?)
+
+--! create_savepoint
+INSERT INTO savepoints (pub_id, organization_id, job_id, url)
+VALUES (:pub_id, :organization_id, :job_id, :url);
+
+--! get_savepoints: DbSavepoint
+SELECT pub_id, url, state, epoch, created_at, finish_time, failure_message
+FROM savepoints
+WHERE job_id = :job_id AND organization_id = :organization_id
+ORDER BY created_at DESC;
+
+--! get_savepoint: DbSavepoint
+SELECT pub_id, url, state, epoch, created_at, finish_time, failure_message
+FROM savepoints
+WHERE pub_id = :pub_id AND organization_id = :organization_id;
+
 ----------- udfs -----------------------
 
 --: DbUdf (description?)
@@ -0,0 +1,17 @@
+CREATE TABLE savepoints (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pub_id TEXT NOT NULL UNIQUE,
+    organization_id TEXT NOT NULL,
+    job_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    state TEXT DEFAULT 'requested' NOT NULL,
+    epoch INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    finish_time TIMESTAMP,
+    failure_message TEXT,
+    FOREIGN KEY (job_id) references job_configs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX savepoints_job_id_idx ON savepoints (job_id);
+
+ALTER TABLE job_configs ADD COLUMN restore_from TEXT;
@@ -0,0 +1 @@
+ALTER TABLE checkpoints ADD COLUMN failure_reason TEXT;
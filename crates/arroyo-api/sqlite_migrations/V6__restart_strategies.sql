@@ -0,0 +1,2 @@
+ALTER TABLE job_configs ADD COLUMN restart_strategy TEXT;
+ALTER TABLE job_statuses ADD COLUMN restart_delay_micros INTEGER;
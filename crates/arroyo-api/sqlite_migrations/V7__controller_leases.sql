@@ -0,0 +1,6 @@
+CREATE TABLE controller_leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    term INTEGER NOT NULL,
+    expires_at TIMESTAMP NOT NULL
+);
@@ -1,4 +1,5 @@
 use crate::{rest_utils::ErrorResp, AuthData, OrgMetadata};
+use arroyo_rpc::config::config;
 use axum::headers::authorization::{Authorization, Bearer};
 use axum::TypedHeader;
 use cornucopia_async::Database;
@@ -7,17 +8,20 @@ pub(crate) async fn authenticate(
     _client: &Database<'_>,
     _bearer_auth: Option<TypedHeader<Authorization<Bearer>>>,
 ) -> Result<AuthData, ErrorResp> {
+    let organization_id = "org".to_string();
+    let quota = config().api.quotas.for_organization(&organization_id);
+
     Ok(AuthData {
         user_id: "user".to_string(),
-        organization_id: "org".to_string(),
+        organization_id,
         role: "admin".to_string(),
         org_metadata: OrgMetadata {
             can_create_programs: true,
             max_nexmark_qps: f64::MAX,
             max_impulse_qps: f64::MAX,
-            max_parallelism: u32::MAX,
-            max_operators: u32::MAX,
-            max_running_jobs: u32::MAX,
+            max_parallelism: quota.max_parallelism.unwrap_or(u32::MAX),
+            max_operators: quota.max_operators.unwrap_or(u32::MAX),
+            max_running_jobs: quota.max_running_jobs.unwrap_or(u32::MAX),
             kafka_qps: u32::MAX,
         },
     })
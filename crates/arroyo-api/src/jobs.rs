@@ -37,11 +37,49 @@ use crate::types::public::LogLevel;
 use crate::{queries::api_queries, to_micros, types::public, AuthData};
 use cornucopia_async::DatabaseSource;
 
+/// Checks that the organization can run another job without exceeding its quota, not counting
+/// `job_id` (which is about to be started)
+pub(crate) async fn check_running_jobs(
+    job_id: Option<&str>,
+    auth: &AuthData,
+    db: &DatabaseSource,
+) -> Result<(), ErrorResp> {
+    let running_jobs = api_queries::fetch_get_jobs(&db.client().await?, &auth.organization_id)
+        .await?
+        .iter()
+        .filter(|j| {
+            Some(j.id.as_str()) != job_id
+                && j.stop == public::StopMode::none
+                && !j
+                    .state
+                    .as_ref()
+                    .map(|s| s == "Failed" || s == "Finished")
+                    .unwrap_or(false)
+        })
+        .count();
+
+    if running_jobs >= auth.org_metadata.max_running_jobs as usize {
+        let message = format!("You have exceeded the maximum number
+            of running jobs in your plan ({}). Stop an existing job or contact support@arroyo.systems for
+            an increase", auth.org_metadata.max_running_jobs);
+
+        return Err(bad_request(message));
+    }
+
+    Ok(())
+}
+
 pub(crate) async fn create_job<'a>(
     pipeline_name: &str,
     pipeline_id: i64,
     checkpoint_interval: Duration,
     preview: bool,
+    restore_from: Option<String>,
+    state_mapping: Option<serde_json::Value>,
+    drop_incompatible_state: bool,
+    restart_strategy: Option<serde_json::Value>,
+    node_selector: Option<serde_json::Value>,
+    priority: i32,
     auth: &AuthData,
     db: &DatabaseSource,
 ) -> Result<String, ErrorResp> {
@@ -59,25 +97,9 @@ pub(crate) async fn create_job<'a>(
         ));
     }
 
-    let running_jobs = api_queries::fetch_get_jobs(&db.client().await?, &auth.organization_id)
-        .await?
-        .iter()
-        .filter(|j| {
-            j.stop == public::StopMode::none
-                && !j
-                    .state
-                    .as_ref()
-                    .map(|s| s == "Failed" || s == "Finished")
-                    .unwrap_or(false)
-        })
-        .count();
-
-    if running_jobs > auth.org_metadata.max_running_jobs as usize {
-        let message = format!("You have exceeded the maximum number
-            of running jobs in your plan ({}). Stop an existing job or contact support@arroyo.systems for
-            an increase", auth.org_metadata.max_running_jobs);
-
-        return Err(bad_request(message));
+    // previews don't count towards the organization's running jobs
+    if !preview {
+        check_running_jobs(None, auth, db).await?;
     }
 
     let job_id = generate_id(IdTypes::JobConfig);
@@ -96,6 +118,12 @@ pub(crate) async fn create_job<'a>(
         } else {
             None
         }),
+        &restore_from,
+        &state_mapping,
+        &drop_incompatible_state,
+        &restart_strategy,
+        &node_selector,
+        &priority,
     )
     .await?;
 
@@ -126,12 +154,18 @@ pub(crate) fn get_action(state: &str, running_desired: &bool) -> (String, Option
         ("Compiling", true) => ("Stop", Some(Checkpoint), InProgress),
         ("Compiling", false) => ("Stopping", Option::None, InProgress),
 
+        ("Queued", true) => ("Stop", Some(Checkpoint), InProgress),
+        ("Queued", false) => ("Stopping", Option::None, InProgress),
+
         ("Scheduling", true) => ("Stop", Some(Checkpoint), InProgress),
         ("Scheduling", false) => ("Stopping", Option::None, InProgress),
 
         ("Running", true) => ("Stop", Some(Checkpoint), Stable),
         ("Running", false) => ("Stopping", Option::None, InProgress),
 
+        ("Adopting", true) => ("Stop", Some(Checkpoint), InProgress),
+        ("Adopting", false) => ("Stopping", Option::None, InProgress),
+
         ("Rescaling", true) => ("Stop", Some(Checkpoint), InProgress),
         ("Rescaling", false) => ("Stopping", Option::None, InProgress),
 
@@ -141,6 +175,9 @@ pub(crate) fn get_action(state: &str, running_desired: &bool) -> (String, Option
         ("Recovering", true) => ("Stop", Some(Checkpoint), InProgress),
         ("Recovering", false) => ("Stopping", Option::None, InProgress),
 
+        ("RecoveringRegion", true) => ("Stop", Some(Checkpoint), InProgress),
+        ("RecoveringRegion", false) => ("Stopping", Option::None, InProgress),
+
         ("Restarting", true) => ("Stop", Some(Checkpoint), InProgress),
         ("Restarting", false) => ("Stopping", Option::None, InProgress),
 
@@ -529,11 +566,27 @@ pub async fn get_jobs(
 
 impl From<DbCheckpoint> for Checkpoint {
     fn from(val: DbCheckpoint) -> Self {
+        let bytes = val
+            .operators
+            .and_then(|o| {
+                serde_json::from_value::<HashMap<String, OperatorCheckpointDetail>>(o).ok()
+            })
+            .map(|operators| {
+                operators
+                    .values()
+                    .flat_map(|operator| operator.tasks.values())
+                    .map(|task| task.bytes.unwrap_or(0))
+                    .sum()
+            })
+            .unwrap_or(0);
+
         Checkpoint {
             epoch: val.epoch as u32,
             backend: val.state_backend,
             start_time: to_micros(val.start_time),
             finish_time: val.finish_time.map(to_micros),
+            bytes,
+            failure_reason: val.failure_reason,
         }
     }
 }
@@ -34,8 +34,12 @@ use crate::pipelines::{
 };
 use crate::rest::__path_ping;
 use crate::rest_utils::{service_unavailable, ErrorResp};
+use crate::savepoints::{__path_create_savepoint, __path_get_savepoints};
+use crate::state::__path_query_operator_state;
 use crate::udfs::{__path_create_udf, __path_delete_udf, __path_get_udfs, __path_validate_udf};
-use arroyo_rpc::api_types::{checkpoints::*, connections::*, metrics::*, pipelines::*, udfs::*, *};
+use arroyo_rpc::api_types::{
+    checkpoints::*, connections::*, metrics::*, pipelines::*, state::*, udfs::*, *,
+};
 use arroyo_rpc::config::config;
 use arroyo_rpc::formats::*;
 use arroyo_rpc::grpc::rpc::compiler_grpc_client::CompilerGrpcClient;
@@ -51,7 +55,9 @@ mod metrics;
 mod pipelines;
 pub mod rest;
 mod rest_utils;
+mod savepoints;
 pub mod sql;
+mod state;
 mod udfs;
 
 include!(concat!(env!("OUT_DIR"), "/api-sql.rs"));
@@ -220,6 +226,8 @@ impl IntoResponse for HttpError {
         create_preview_pipeline,
         patch_pipeline,
         restart_pipeline,
+        create_savepoint,
+        get_savepoints,
         get_pipeline,
         delete_pipeline,
         get_pipelines,
@@ -229,6 +237,7 @@ impl IntoResponse for HttpError {
         get_job_checkpoints,
         get_job_output,
         get_operator_metric_groups,
+        query_operator_state,
         get_connectors,
         get_connection_profiles,
         test_connection_profile,
@@ -251,6 +260,7 @@ impl IntoResponse for HttpError {
         PreviewPost,
         PipelinePatch,
         PipelineRestart,
+        RestartStrategy,
         Pipeline,
         PipelineGraph,
         PipelineNode,
@@ -264,12 +274,17 @@ impl IntoResponse for HttpError {
         JobLogLevel,
         Checkpoint,
         CheckpointCollection,
+        Savepoint,
+        SavepointPost,
+        SavepointState,
+        SavepointCollection,
         OutputData,
         MetricName,
         Metric,
         SubtaskMetrics,
         MetricGroup,
         OperatorMetricGroup,
+        StateQueryResult,
         ConnectorCollection,
         Connector,
         ConnectionProfile,
@@ -16,7 +16,7 @@ use crate::{compiler_service, connection_profiles, jobs, types};
 use arroyo_datastream::default_sink;
 use arroyo_rpc::api_types::pipelines::{
     Job, Pipeline, PipelinePatch, PipelinePost, PipelineRestart, PreviewPost,
-    QueryValidationResult, StopType, ValidateQueryPost,
+    QueryValidationResult, RestartStrategy, StopType, ValidateQueryPost,
 };
 use arroyo_rpc::api_types::udfs::{GlobalUdf, Udf};
 use arroyo_rpc::api_types::{JobCollection, PaginationQueryParams, PipelineCollection};
@@ -46,6 +46,7 @@ use crate::rest_utils::{
     authenticate, bad_request, log_and_map, not_found, paginate_results, required_field,
     validate_pagination_params, ApiError, BearerAuth, ErrorResp,
 };
+use crate::savepoints::{drop_incompatible_state_value, resolve_restore_from, state_mapping_value};
 use crate::types::public::{PipelineType, RestartMode, StopMode};
 use crate::udfs::build_udf;
 use crate::AuthData;
@@ -167,6 +168,112 @@ fn set_parallelism(program: &mut LogicalProgram, parallelism: usize) {
     }
 }
 
+/// Applies per-operator parallelism overrides from the API to the program, returning the
+/// overrides expanded to cover all of the operators whose parallelism was changed
+fn apply_parallelism_overrides(
+    program: &mut LogicalProgram,
+    overrides: &HashMap<String, u64>,
+) -> Result<HashMap<String, usize>, ErrorResp> {
+    let overrides = overrides
+        .iter()
+        .map(|(k, v)| (k.clone(), *v as usize))
+        .collect();
+
+    let expanded = program
+        .expand_parallelism_overrides(&overrides)
+        .map_err(|e| bad_request(format!("Invalid parallelism overrides: {}", e)))?;
+    program.update_parallelism(&expanded);
+
+    Ok(expanded)
+}
+
+/// Validates a restart strategy from the API, returning it in the form it's stored in the database
+fn restart_strategy_value(
+    strategy: Option<RestartStrategy>,
+) -> Result<Option<serde_json::Value>, ErrorResp> {
+    let Some(strategy) = strategy else {
+        return Ok(None);
+    };
+
+    let error = match &strategy {
+        RestartStrategy::FixedDelay { .. } => None,
+        RestartStrategy::ExponentialBackoff {
+            initial_delay_micros,
+            max_delay_micros,
+            multiplier,
+        } => {
+            if initial_delay_micros > max_delay_micros {
+                Some("initialDelayMicros must not be greater than maxDelayMicros")
+            } else if !(*multiplier >= 1.0 && multiplier.is_finite()) {
+                Some("multiplier must be at least 1")
+            } else {
+                None
+            }
+        }
+        RestartStrategy::FailureRate {
+            max_failures,
+            interval_micros,
+            ..
+        } => {
+            if *max_failures == 0 {
+                Some("maxFailures must be at least 1")
+            } else if *interval_micros == 0 {
+                Some("intervalMicros must be greater than 0")
+            } else {
+                None
+            }
+        }
+    };
+
+    if let Some(error) = error {
+        return Err(bad_request(format!("Invalid restart strategy: {}", error)));
+    }
+
+    Ok(Some(serde_json::to_value(strategy).map_err(log_and_map)?))
+}
+
+/// Validates a node selector from the API, returning it in the form it's stored in the database
+fn node_selector_value(
+    node_selector: Option<HashMap<String, String>>,
+) -> Result<Option<serde_json::Value>, ErrorResp> {
+    let Some(node_selector) = node_selector else {
+        return Ok(None);
+    };
+
+    if node_selector.keys().any(|k| k.trim().is_empty()) {
+        return Err(bad_request(
+            "Invalid node selector: labels must not be empty",
+        ));
+    }
+
+    Ok(Some(
+        serde_json::to_value(node_selector).map_err(log_and_map)?,
+    ))
+}
+
+fn check_parallelism(program: &LogicalProgram, auth: &AuthData) -> Result<(), ErrorResp> {
+    program
+        .validate_parallelism()
+        .map_err(|e| bad_request(format!("Invalid parallelism: {}", e)))?;
+
+    let max = program
+        .graph
+        .node_weights()
+        .map(|n| n.parallelism)
+        .max()
+        .unwrap_or(0);
+
+    if max > auth.org_metadata.max_parallelism as usize {
+        return Err(bad_request(format!(
+            "Your plan allows you to run pipelines up to parallelism {};
+            contact support@arroyo.systems for an increase",
+            auth.org_metadata.max_parallelism
+        )));
+    }
+
+    Ok(())
+}
+
 #[allow(unused)]
 async fn try_register_confluent_schema(
     sink: &mut ConnectorOp,
@@ -269,19 +376,30 @@ pub(crate) async fn create_pipeline_int<'a>(
     query: String,
     udfs: Vec<Udf>,
     parallelism: u64,
+    parallelism_overrides: Option<HashMap<String, u64>>,
     checkpoint_interval: Duration,
     is_preview: bool,
     enable_sinks: bool,
+    restore_from: Option<String>,
+    state_mapping: Option<HashMap<String, String>>,
+    drop_incompatible_state: Option<bool>,
+    restart_strategy: Option<RestartStrategy>,
+    node_selector: Option<HashMap<String, String>>,
+    priority: Option<i32>,
     auth: AuthData,
     db: &DatabaseSource,
 ) -> Result<String, ErrorResp> {
-    if parallelism > auth.org_metadata.max_parallelism as u64 {
-        return Err(bad_request(format!(
-            "Your plan allows you to run pipelines up to parallelism {};
-            contact support@arroyo.systems for an increase",
-            auth.org_metadata.max_parallelism
-        )));
-    }
+    let restart_strategy = restart_strategy_value(restart_strategy)?;
+    let node_selector = node_selector_value(node_selector)?;
+    let restore_from = match restore_from {
+        Some(restore_from) => {
+            Some(resolve_restore_from(&restore_from, &db.client().await?, &auth).await?)
+        }
+        None => None,
+    };
+    let state_mapping = state_mapping_value(&restore_from, state_mapping)?;
+    let drop_incompatible_state =
+        drop_incompatible_state_value(&restore_from, drop_incompatible_state)?;
 
     let pub_id = generate_id(IdTypes::Pipeline);
 
@@ -294,7 +412,14 @@ pub(crate) async fn create_pipeline_int<'a>(
                 contact support@arroyo.systems for an increase", auth.org_metadata.max_operators)));
     }
 
-    set_parallelism(&mut compiled.program, parallelism as usize);
+    if is_preview {
+        // previews ignore any parallelism hints in the query
+        set_parallelism(&mut compiled.program, 1);
+    } else if let Some(overrides) = &parallelism_overrides {
+        apply_parallelism_overrides(&mut compiled.program, overrides)?;
+    }
+
+    check_parallelism(&compiled.program, &auth)?;
 
     if is_preview {
         // in Preview, we either replace sinks with a preview sink, or add a preview sink
@@ -388,6 +513,12 @@ pub(crate) async fn create_pipeline_int<'a>(
         pipeline_id,
         checkpoint_interval,
         is_preview,
+        restore_from,
+        state_mapping,
+        drop_incompatible_state.unwrap_or_default(),
+        restart_strategy,
+        node_selector,
+        priority.unwrap_or_default(),
         &auth,
         &db,
     )
@@ -468,6 +599,7 @@ impl From<DbPipelineJob> for Job {
             finish_time: val.finish_time.map(to_micros),
             tasks: val.tasks.map(|t| t as u64),
             failure_message: val.failure_message,
+            restart_delay_micros: val.restart_delay_micros.map(|d| d as u64),
             created_at: to_micros(val.created_at),
         }
     }
@@ -546,9 +678,16 @@ pub async fn create_pipeline(
         pipeline_post.query,
         pipeline_post.udfs.unwrap_or_default(),
         pipeline_post.parallelism,
+        pipeline_post.parallelism_overrides,
         checkpoint_interval,
         false,
         true,
+        pipeline_post.restore_from,
+        pipeline_post.state_mapping,
+        pipeline_post.drop_incompatible_state,
+        pipeline_post.restart_strategy,
+        pipeline_post.node_selector,
+        pipeline_post.priority,
         auth_data.clone(),
         &state.database,
     )
@@ -585,9 +724,16 @@ pub async fn create_preview_pipeline(
         req.query,
         req.udfs.unwrap_or_default(),
         1,
+        None,
         Duration::MAX,
         true,
         req.enable_sinks,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
         auth_data.clone(),
         &state.database,
     )
@@ -650,25 +796,61 @@ pub async fn patch_pipeline(
         }
     }
 
-    let parallelism_overrides = if let Some(parallelism) = pipeline_patch.parallelism {
-        let res = api_queries::fetch_get_job_details(&db, &auth_data.organization_id, &job_id)
-            .await?
-            .into_iter()
-            .next()
-            .ok_or_else(|| not_found("Job"))?;
+    let parallelism_overrides =
+        if pipeline_patch.parallelism.is_some() || pipeline_patch.parallelism_overrides.is_some() {
+            let res = api_queries::fetch_get_job_details(&db, &auth_data.organization_id, &job_id)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| not_found("Job"))?;
 
-        let program = ArrowProgram::decode(&res.program[..]).map_err(log_and_map)?;
-        let map: HashMap<String, u32> = program
-            .nodes
-            .into_iter()
-            .map(|node| (node.node_id, parallelism as u32))
-            .collect();
+            let mut program: LogicalProgram = ArrowProgram::decode(&res.program[..])
+                .map_err(log_and_map)?
+                .try_into()
+                .map_err(log_and_map)?;
 
-        Some(serde_json::to_value(map).map_err(log_and_map)?)
-    } else {
-        None
+            // start from the existing overrides, so that patching some operators leaves the
+            // parallelism of the others unchanged
+            let mut overrides: HashMap<String, usize> = res
+                .parallelism_overrides
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter_map(|(k, v)| Some((k.clone(), v.as_u64()? as usize)))
+                .collect();
+            program.update_parallelism(&overrides);
+
+            if let Some(parallelism) = pipeline_patch.parallelism {
+                set_parallelism(&mut program, parallelism as usize);
+                overrides = program.tasks_per_operator();
+            }
+
+            if let Some(patch) = &pipeline_patch.parallelism_overrides {
+                overrides.extend(apply_parallelism_overrides(&mut program, patch)?);
+            }
+
+            check_parallelism(&program, &auth_data)?;
+
+            Some(serde_json::to_value(overrides).map_err(log_and_map)?)
+        } else {
+            None
+        };
+
+    let restore_from = match &pipeline_patch.restore_from {
+        Some(restore_from) => Some(resolve_restore_from(restore_from, &db, &auth_data).await?),
+        None => None,
     };
 
+    let state_mapping = state_mapping_value(&restore_from, pipeline_patch.state_mapping)?;
+    let drop_incompatible_state =
+        drop_incompatible_state_value(&restore_from, pipeline_patch.drop_incompatible_state)?;
+    let restart_strategy = restart_strategy_value(pipeline_patch.restart_strategy)?;
+    let node_selector = node_selector_value(pipeline_patch.node_selector)?;
+
+    if matches!(pipeline_patch.stop, Some(StopType::None)) {
+        jobs::check_running_jobs(Some(&job_id), &auth_data, &state.database).await?;
+    }
+
     let res = api_queries::execute_update_job(
         &db,
         &OffsetDateTime::now_utc(),
@@ -676,6 +858,12 @@ pub async fn patch_pipeline(
         stop,
         &interval.map(|i| i.as_micros() as i64),
         &parallelism_overrides,
+        &restore_from,
+        &state_mapping,
+        &drop_incompatible_state,
+        &restart_strategy,
+        &node_selector,
+        &pipeline_patch.priority,
         &job_id,
         &auth_data.organization_id,
     )
@@ -723,11 +911,31 @@ pub async fn restart_pipeline(
         RestartMode::safe
     };
 
+    jobs::check_running_jobs(Some(&job_id), &auth_data, &state.database).await?;
+
+    if let Some(epoch) = req.checkpoint_epoch {
+        let found = api_queries::fetch_get_restorable_checkpoint_epoch(
+            &db,
+            &job_id,
+            &auth_data.organization_id,
+            &(epoch as i32),
+        )
+        .await?;
+
+        if found.is_empty() {
+            return Err(bad_request(format!(
+                "Checkpoint {} is not available to restore from; it may have failed or been cleaned up",
+                epoch
+            )));
+        }
+    }
+
     let res = api_queries::execute_restart_job(
         &db,
         &OffsetDateTime::now_utc(),
         &auth_data.user_id,
         &mode,
+        &req.checkpoint_epoch.map(|e| e as i32),
         &job_id,
         &auth_data.organization_id,
     )
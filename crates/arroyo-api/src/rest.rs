@@ -29,6 +29,8 @@ use crate::pipelines::{
     get_pipelines, patch_pipeline, restart_pipeline, validate_query,
 };
 use crate::rest_utils::not_found;
+use crate::savepoints::{create_savepoint, get_savepoints};
+use crate::state::query_operator_state;
 use crate::udfs::{create_udf, delete_udf, get_udfs, validate_udf};
 use crate::ApiDoc;
 use arroyo_rpc::config::config;
@@ -136,6 +138,10 @@ pub fn create_rest_app(database: DatabaseSource, controller_addr: &str) -> Route
         .route(
             "/:job_id/operator_metric_groups",
             get(get_operator_metric_groups),
+        )
+        .route(
+            "/:job_id/operators/:operator_id/state/:table",
+            get(query_operator_state),
         );
 
     let api_routes = Router::new()
@@ -169,6 +175,8 @@ pub fn create_rest_app(database: DatabaseSource, controller_addr: &str) -> Route
         .route("/pipelines/:id", patch(patch_pipeline))
         .route("/pipelines/:id", get(get_pipeline))
         .route("/pipelines/:id/restart", post(restart_pipeline))
+        .route("/pipelines/:id/savepoints", post(create_savepoint))
+        .route("/pipelines/:id/savepoints", get(get_savepoints))
         .route("/pipelines/:id", delete(delete_pipeline))
         .nest("/pipelines/:id/jobs", jobs_routes)
         .fallback(api_fallback);
@@ -0,0 +1,214 @@
+use crate::queries::api_queries;
+use crate::queries::api_queries::DbSavepoint;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, internal_server_error, log_and_map, map_insert_err, not_found,
+    ApiError, BearerAuth, ErrorResp,
+};
+use crate::types::public::SavepointState as DbSavepointState;
+use crate::types::public::StopMode;
+use crate::{to_micros, AuthData};
+use arroyo_rpc::api_types::checkpoints::{Savepoint, SavepointPost, SavepointState};
+use arroyo_rpc::api_types::SavepointCollection;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_storage::BackendConfig;
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use cornucopia_async::Database;
+use std::collections::HashMap;
+
+impl From<DbSavepoint> for Savepoint {
+    fn from(val: DbSavepoint) -> Self {
+        Savepoint {
+            id: val.pub_id,
+            url: val.url,
+            state: match val.state {
+                DbSavepointState::requested => SavepointState::Requested,
+                DbSavepointState::inprogress => SavepointState::InProgress,
+                DbSavepointState::ready => SavepointState::Ready,
+                DbSavepointState::failed => SavepointState::Failed,
+            },
+            epoch: val.epoch.map(|e| e as u32),
+            created_at: to_micros(val.created_at),
+            finish_time: val.finish_time.map(to_micros),
+            failure_message: val.failure_message,
+        }
+    }
+}
+
+fn validate_url(url: &str) -> Result<(), ErrorResp> {
+    BackendConfig::parse_url(url, false)
+        .map(|_| ())
+        .map_err(|e| bad_request(format!("Invalid savepoint URL '{}': {}", url, e)))
+}
+
+/// Resolves the `restore_from` field of a pipeline request, which may either be the id of a
+/// savepoint or the URL that a savepoint was written to, into a savepoint URL
+pub(crate) async fn resolve_restore_from<'a>(
+    restore_from: &str,
+    db: &Database<'a>,
+    auth_data: &AuthData,
+) -> Result<String, ErrorResp> {
+    if !restore_from.starts_with("sp_") {
+        validate_url(restore_from)?;
+        return Ok(restore_from.to_string());
+    }
+
+    let savepoint = api_queries::fetch_get_savepoint(db, &restore_from, &auth_data.organization_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found("Savepoint"))?;
+
+    if savepoint.state != DbSavepointState::ready {
+        return Err(bad_request(format!(
+            "Savepoint {} is not ready to be restored from",
+            restore_from
+        )));
+    }
+
+    Ok(savepoint.url)
+}
+
+/// Validates the `state_mapping` field of a pipeline request, which maps operator ids in the new
+/// pipeline to the operators in the savepoint whose state they should be restored from
+pub(crate) fn state_mapping_value(
+    restore_from: &Option<String>,
+    state_mapping: Option<HashMap<String, String>>,
+) -> Result<Option<serde_json::Value>, ErrorResp> {
+    let Some(state_mapping) = state_mapping else {
+        return Ok(None);
+    };
+
+    if restore_from.is_none() {
+        return Err(bad_request(
+            "stateMapping can only be provided along with restoreFrom",
+        ));
+    }
+
+    Ok(Some(
+        serde_json::to_value(state_mapping).map_err(log_and_map)?,
+    ))
+}
+
+/// Validates the `drop_incompatible_state` field of a pipeline request, which only applies to
+/// restores
+pub(crate) fn drop_incompatible_state_value(
+    restore_from: &Option<String>,
+    drop_incompatible_state: Option<bool>,
+) -> Result<Option<bool>, ErrorResp> {
+    if drop_incompatible_state.is_some() && restore_from.is_none() {
+        return Err(bad_request(
+            "dropIncompatibleState can only be provided along with restoreFrom",
+        ));
+    }
+
+    Ok(drop_incompatible_state)
+}
+
+async fn job_for_pipeline<'a>(
+    pipeline_pub_id: &str,
+    db: &Database<'a>,
+    auth_data: &AuthData,
+) -> Result<String, ErrorResp> {
+    // this assumes there is just one job for the pipeline
+    Ok(
+        api_queries::fetch_get_pipeline_jobs(db, &auth_data.organization_id, &pipeline_pub_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| bad_request("There are no jobs for the pipeline"))?
+            .id,
+    )
+}
+
+/// Trigger a savepoint for a running pipeline
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{id}/savepoints",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    request_body = SavepointPost,
+    responses(
+        (status = 200, description = "Requested savepoint", body = Savepoint),
+        (status = 400, description = "Bad request", body = ErrorResp),
+    ),
+)]
+pub async fn create_savepoint(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+    WithRejection(Json(req), _): WithRejection<Json<SavepointPost>, ApiError>,
+) -> Result<Json<Savepoint>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let db = state.database.client().await?;
+
+    validate_url(&req.url)?;
+
+    let job_id = job_for_pipeline(&pipeline_pub_id, &db, &auth_data).await?;
+
+    let job = api_queries::fetch_get_job_details(&db, &auth_data.organization_id, &job_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found("Job"))?;
+
+    if job.stop != StopMode::none || job.state.as_deref() != Some("Running") {
+        return Err(bad_request(
+            "Savepoints can only be taken of running pipelines",
+        ));
+    }
+
+    let pub_id = generate_id(IdTypes::Savepoint);
+    api_queries::execute_create_savepoint(
+        &db,
+        &pub_id,
+        &auth_data.organization_id,
+        &job_id,
+        &req.url,
+    )
+    .await
+    .map_err(|e| map_insert_err("savepoint", e))?;
+
+    let savepoint = api_queries::fetch_get_savepoint(&db, &pub_id, &auth_data.organization_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| internal_server_error("Failed to fetch created savepoint"))?
+        .into();
+
+    Ok(Json(savepoint))
+}
+
+/// List a pipeline's savepoints
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{id}/savepoints",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    responses(
+        (status = 200, description = "Got savepoints", body = SavepointCollection),
+    ),
+)]
+pub async fn get_savepoints(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+) -> Result<Json<SavepointCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let db = state.database.client().await?;
+
+    let job_id = job_for_pipeline(&pipeline_pub_id, &db, &auth_data).await?;
+
+    let savepoints =
+        api_queries::fetch_get_savepoints(&db, &job_id, &auth_data.organization_id).await?;
+
+    Ok(Json(SavepointCollection {
+        data: savepoints.into_iter().map(|s| s.into()).collect(),
+    }))
+}
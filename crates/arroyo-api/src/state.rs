@@ -0,0 +1,101 @@
+use axum::extract::{Path, Query, State};
+use axum::Json;
+
+use crate::pipelines::query_job_by_pub_id;
+use crate::rest::AppState;
+use crate::rest_utils::{authenticate, bad_request, log_and_map, BearerAuth, ErrorResp};
+use arroyo_rpc::api_types::state::{StateQueryParams, StateQueryResult};
+use arroyo_rpc::grpc::rpc::controller_grpc_client::ControllerGrpcClient;
+use arroyo_rpc::grpc::rpc::QueryStateReq;
+use tonic::codec::CompressionEncoding;
+use tonic::transport::Channel;
+use tonic::Code;
+
+const DEFAULT_STATE_QUERY_LIMIT: u32 = 100;
+const MAX_STATE_QUERY_LIMIT: u32 = 1000;
+
+/// Query the current contents of one of an operator's state tables in a running job
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/operators/{operator_id}/state/{table}",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+        ("operator_id" = String, Path, description = "Operator id"),
+        ("table" = String, Path, description = "State table name"),
+        StateQueryParams,
+    ),
+    responses(
+        (status = 200, description = "Got state", body = StateQueryResult),
+        (status = 400, description = "Bad request", body = ErrorResp),
+    ),
+)]
+pub async fn query_operator_state(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id, operator_id, table)): Path<(String, String, String, String)>,
+    Query(params): Query<StateQueryParams>,
+) -> Result<Json<StateQueryResult>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let job = query_job_by_pub_id(
+        &pipeline_pub_id,
+        &job_pub_id,
+        &state.database.client().await?,
+        &auth_data,
+    )
+    .await?;
+
+    if job.state != "Running" {
+        return Err(bad_request("State can only be queried for running jobs"));
+    }
+
+    if let Some(key) = &params.key {
+        if !matches!(
+            serde_json::from_str::<serde_json::Value>(key),
+            Ok(serde_json::Value::Object(_))
+        ) {
+            return Err(bad_request("key must be a JSON object"));
+        }
+    }
+
+    let limit = params.limit.unwrap_or(DEFAULT_STATE_QUERY_LIMIT);
+    if limit == 0 || limit > MAX_STATE_QUERY_LIMIT {
+        return Err(bad_request(format!(
+            "limit must be between 1 and {}",
+            MAX_STATE_QUERY_LIMIT
+        )));
+    }
+
+    let channel = Channel::builder(state.controller_addr.parse().unwrap())
+        .connect()
+        .await
+        .map_err(log_and_map)?;
+
+    let mut controller = ControllerGrpcClient::new(channel)
+        .accept_compressed(CompressionEncoding::Zstd)
+        .send_compressed(CompressionEncoding::Zstd);
+
+    let resp = controller
+        .query_state(QueryStateReq {
+            job_id: job.id,
+            operator_id: operator_id.clone(),
+            table: table.clone(),
+            key: params.key,
+            limit,
+        })
+        .await
+        .map_err(|e| match e.code() {
+            Code::InvalidArgument | Code::FailedPrecondition => bad_request(e.message()),
+            _ => log_and_map(e),
+        })?;
+
+    let rows = serde_json::from_str(&resp.into_inner().rows).map_err(log_and_map)?;
+
+    Ok(Json(StateQueryResult {
+        operator_id,
+        table,
+        rows,
+    }))
+}
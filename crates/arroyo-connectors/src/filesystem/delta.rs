@@ -9,21 +9,22 @@ use arroyo_rpc::api_types::connections::{
 use arroyo_rpc::OperatorConfig;
 
 use crate::filesystem::{
-    file_system_sink_from_options, CommitStyle, FileSystemTable, FormatSettings, TableType,
+    apply_profile_storage_options, file_system_sink_from_options, profile_from_options,
+    CommitStyle, FileSystemConfig, FileSystemTable, FormatSettings, TableType,
 };
-use crate::EmptyConfig;
 
 use arroyo_operator::connector::Connector;
 use arroyo_operator::operator::OperatorNode;
 
 use super::sink::{LocalParquetFileSystemSink, ParquetFileSystemSink};
 
+const CONFIG_SCHEMA: &str = include_str!("./profile.json");
 const TABLE_SCHEMA: &str = include_str!("./table.json");
 
 pub struct DeltaLakeConnector {}
 
 impl Connector for DeltaLakeConnector {
-    type ProfileT = EmptyConfig;
+    type ProfileT = FileSystemConfig;
 
     type TableT = FileSystemTable;
 
@@ -43,7 +44,7 @@ impl Connector for DeltaLakeConnector {
             testing: false,
             hidden: true,
             custom_schemas: true,
-            connection_config: None,
+            connection_config: Some(CONFIG_SCHEMA.to_owned()),
             table_config: TABLE_SCHEMA.to_owned(),
         }
     }
@@ -75,9 +76,11 @@ impl Connector for DeltaLakeConnector {
         id: Option<i64>,
         name: &str,
         config: Self::ProfileT,
-        table: Self::TableT,
+        mut table: Self::TableT,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<arroyo_operator::connector::Connection> {
+        apply_profile_storage_options(&mut table, &config);
+
         let TableType::Sink {
             write_path,
             file_settings,
@@ -141,11 +144,12 @@ impl Connector for DeltaLakeConnector {
         name: &str,
         options: &mut HashMap<String, String>,
         schema: Option<&ConnectionSchema>,
-        _profile: Option<&ConnectionProfile>,
+        profile: Option<&ConnectionProfile>,
     ) -> anyhow::Result<Connection> {
+        let config = profile_from_options(profile)?;
         let table = file_system_sink_from_options(options, schema, CommitStyle::DeltaLake)?;
 
-        self.from_config(None, name, EmptyConfig {}, table, schema)
+        self.from_config(None, name, config, table, schema)
     }
 
     fn make_operator(
@@ -16,7 +16,7 @@ use arroyo_rpc::formats::Format;
 use arroyo_rpc::OperatorConfig;
 use serde::{Deserialize, Serialize};
 
-use crate::{pull_opt, pull_option_to_i64, EmptyConfig};
+use crate::{pull_opt, pull_option_to_i64};
 
 use crate::filesystem::source::FileSystemSourceFunc;
 use arroyo_operator::connector::Connector;
@@ -26,15 +26,17 @@ use self::sink::{
     JsonFileSystemSink, LocalJsonFileSystemSink, LocalParquetFileSystemSink, ParquetFileSystemSink,
 };
 
+const CONFIG_SCHEMA: &str = include_str!("./profile.json");
 const TABLE_SCHEMA: &str = include_str!("./table.json");
 const ICON: &str = include_str!("./filesystem.svg");
 
+import_types!(schema = "src/filesystem/profile.json");
 import_types!(schema = "src/filesystem/table.json");
 
 pub struct FileSystemConnector {}
 
 impl Connector for FileSystemConnector {
-    type ProfileT = EmptyConfig;
+    type ProfileT = FileSystemConfig;
 
     type TableT = FileSystemTable;
 
@@ -54,7 +56,7 @@ impl Connector for FileSystemConnector {
             testing: false,
             hidden: false,
             custom_schemas: true,
-            connection_config: None,
+            connection_config: Some(CONFIG_SCHEMA.to_owned()),
             table_config: TABLE_SCHEMA.to_owned(),
         }
     }
@@ -89,9 +91,11 @@ impl Connector for FileSystemConnector {
         id: Option<i64>,
         name: &str,
         config: Self::ProfileT,
-        table: Self::TableT,
+        mut table: Self::TableT,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<Connection> {
+        apply_profile_storage_options(&mut table, &config);
+
         let (description, connection_type) = match table.table_type {
             TableType::Source { .. } => ("FileSystem".to_string(), ConnectionType::Source),
             TableType::Sink {
@@ -163,8 +167,9 @@ impl Connector for FileSystemConnector {
         name: &str,
         options: &mut HashMap<String, String>,
         schema: Option<&ConnectionSchema>,
-        _profile: Option<&ConnectionProfile>,
+        profile: Option<&ConnectionProfile>,
     ) -> anyhow::Result<Connection> {
+        let config = profile_from_options(profile)?;
         match options.remove("type") {
             Some(t) if t == "source" => {
                 let (storage_url, storage_options) = get_storage_url_and_options(options)?;
@@ -177,7 +182,7 @@ impl Connector for FileSystemConnector {
                 self.from_config(
                     None,
                     name,
-                    EmptyConfig {},
+                    config,
                     FileSystemTable {
                         table_type: TableType::Source {
                             path: storage_url,
@@ -192,7 +197,7 @@ impl Connector for FileSystemConnector {
             Some(t) if t == "sink" => {
                 let table = file_system_sink_from_options(options, schema, CommitStyle::Direct)?;
 
-                self.from_config(None, name, EmptyConfig {}, table, schema)
+                self.from_config(None, name, config, table, schema)
             }
             Some(t) => bail!("unknown type: {}", t),
             None => bail!("must have type set"),
@@ -250,6 +255,40 @@ impl Connector for FileSystemConnector {
     }
 }
 
+pub(crate) fn profile_from_options(
+    profile: Option<&ConnectionProfile>,
+) -> Result<FileSystemConfig> {
+    Ok(profile
+        .map(|p| {
+            serde_json::from_value(p.config.clone())
+                .map_err(|e| anyhow!("invalid config for profile '{}' in database: {}", p.id, e))
+        })
+        .transpose()?
+        .unwrap_or_else(|| FileSystemConfig {
+            storage_options: HashMap::new(),
+        }))
+}
+
+/// Adds the storage options from the connection profile (e.g., credentials for a particular
+/// bucket) to the table, where they aren't already set on the table itself
+pub(crate) fn apply_profile_storage_options(
+    table: &mut FileSystemTable,
+    profile: &FileSystemConfig,
+) {
+    let (TableType::Source {
+        storage_options, ..
+    }
+    | TableType::Sink {
+        storage_options, ..
+    }) = &mut table.table_type;
+
+    for (k, v) in &profile.storage_options {
+        storage_options
+            .entry(k.clone())
+            .or_insert_with(|| v.clone());
+    }
+}
+
 fn get_storage_url_and_options(
     opts: &mut HashMap<String, String>,
 ) -> Result<(String, HashMap<String, String>)> {
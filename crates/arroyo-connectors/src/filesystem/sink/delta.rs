@@ -1,7 +1,7 @@
 use super::FinishedFile;
 use anyhow::{Context, Result};
 use arrow::datatypes::{Schema, SchemaRef};
-use arroyo_storage::{get_current_credentials, StorageProvider};
+use arroyo_storage::StorageProvider;
 use arroyo_types::to_millis;
 use deltalake::{
     aws::storage::s3_constants::AWS_S3_ALLOW_UNSAFE_RENAME,
@@ -22,6 +22,7 @@ use tracing::info;
 
 static INIT: Lazy<()> = Lazy::new(|| {
     deltalake::aws::register_handlers(None);
+    deltalake::azure::register_handlers(None);
 });
 
 pub(crate) async fn commit_files_to_delta(
@@ -97,14 +98,20 @@ async fn configure_storage_options(
 ) -> Result<HashMap<String, String>> {
     let mut options = storage_provider.storage_options().clone();
     if table_path.starts_with("s3://") {
-        update_s3_credentials(&mut options).await?;
+        update_s3_credentials(&mut options, &storage_provider).await?;
     }
     Ok(options)
 }
 
-async fn update_s3_credentials(options: &mut HashMap<String, String>) -> Result<()> {
+async fn update_s3_credentials(
+    options: &mut HashMap<String, String>,
+    storage_provider: &StorageProvider,
+) -> Result<()> {
     if !options.contains_key(AmazonS3ConfigKey::SecretAccessKey.as_ref()) {
-        let tmp_credentials = get_current_credentials().await?;
+        let tmp_credentials = storage_provider
+            .aws_credentials()
+            .await?
+            .context("no AWS credentials available for S3 table")?;
         options.insert(
             AmazonS3ConfigKey::AccessKeyId.as_ref().to_string(),
             tmp_credentials.key_id.clone(),
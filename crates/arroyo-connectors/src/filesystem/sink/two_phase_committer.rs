@@ -145,6 +145,7 @@ impl<TPC: TwoPhaseCommitter> ArrowOperator for TwoPhaseCommitterOperator<TPC> {
                     table_name: "p".into(),
                     description: "pre-commit data".into(),
                     uses_two_phase_commit: true,
+                    ttl_micros: 0,
                 }
                 .encode_to_vec(),
             },
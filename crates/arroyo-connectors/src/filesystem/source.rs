@@ -424,6 +424,10 @@ impl FileSystemSourceFunc {
             ControlMessage::Commit { .. } => {
                 unreachable!("sources shouldn't receive commit messages");
             }
+            ControlMessage::QueryState(query) => {
+                ctx.query_state(query).await;
+                None
+            }
             _ => None,
         }
     }
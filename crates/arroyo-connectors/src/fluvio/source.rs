@@ -224,6 +224,9 @@ impl FluvioSourceFunc {
                         Some(ControlMessage::LoadCompacted {compacted}) => {
                             ctx.load_compacted(compacted).await;
                         }
+                        Some(ControlMessage::QueryState(query)) => {
+                            ctx.query_state(query).await;
+                        }
                         Some(ControlMessage::NoOp ) => {}
                         None => {
 
@@ -205,6 +205,9 @@ impl ImpulseSourceFunc {
                 Ok(ControlMessage::LoadCompacted { compacted }) => {
                     ctx.load_compacted(compacted).await;
                 }
+                Ok(ControlMessage::QueryState(query)) => {
+                    ctx.query_state(query).await;
+                }
                 Ok(ControlMessage::NoOp) => {}
                 Err(_) => {
                     // no messages
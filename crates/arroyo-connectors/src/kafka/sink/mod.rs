@@ -166,6 +166,7 @@ impl ArrowOperator for KafkaSinkFunc {
                         table_name: "i".to_string(),
                         description: "index for transactional ids".to_string(),
                         uses_two_phase_commit: true,
+                        ttl_micros: 0,
                     }
                     .encode_to_vec(),
                 },
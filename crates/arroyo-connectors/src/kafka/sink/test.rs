@@ -88,6 +88,7 @@ impl KafkaTopicTester {
         let mut ctx = ArrowContext::new(
             task_info,
             None,
+            None,
             control_rx,
             command_tx,
             1,
@@ -160,6 +161,7 @@ async fn test_kafka_checkpoint_flushes() {
         min_epoch: 0,
         timestamp: SystemTime::now(),
         then_stop: false,
+        unaligned: false,
     };
     sink_with_writes
         .sink
@@ -3,6 +3,7 @@ use arroyo_rpc::grpc::rpc::TableConfig;
 use arroyo_rpc::schema_resolver::SchemaResolver;
 use arroyo_rpc::{grpc::rpc::StopMode, ControlMessage, ControlResp};
 
+use arroyo_metrics::source_lag_gauge;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
 use arroyo_operator::SourceFinishType;
@@ -10,8 +11,9 @@ use arroyo_types::*;
 use async_trait::async_trait;
 use bincode::{Decode, Encode};
 use governor::{Quota, RateLimiter as GovernorRateLimiter};
-use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
-use rdkafka::{ClientConfig, Message as KMessage, Offset, TopicPartitionList};
+use rdkafka::consumer::{CommitMode, Consumer, ConsumerContext, StreamConsumer};
+use rdkafka::statistics::Statistics;
+use rdkafka::{ClientConfig, ClientContext, Message as KMessage, Offset, TopicPartitionList};
 use std::collections::HashMap;
 use std::num::NonZeroU32;
 use std::sync::Arc;
@@ -43,8 +45,35 @@ pub struct KafkaState {
     offset: i64,
 }
 
+/// Reports the consumer's lag, from the statistics that librdkafka periodically emits
+pub struct SourceLagContext {
+    topic: String,
+    task_info: TaskInfoRef,
+}
+
+impl ClientContext for SourceLagContext {
+    fn stats(&self, statistics: Statistics) {
+        let Some(topic) = statistics.topics.get(&self.topic) else {
+            return;
+        };
+
+        // the lag is -1 for partitions that aren't assigned to us, or that we haven't read yet
+        let lag = topic
+            .partitions
+            .values()
+            .map(|p| p.consumer_lag.max(0))
+            .sum();
+        source_lag_gauge(&self.task_info).set(lag);
+    }
+}
+
+impl ConsumerContext for SourceLagContext {}
+
 impl KafkaSourceFunc {
-    async fn get_consumer(&mut self, ctx: &mut ArrowContext) -> anyhow::Result<StreamConsumer> {
+    async fn get_consumer(
+        &mut self,
+        ctx: &mut ArrowContext,
+    ) -> anyhow::Result<StreamConsumer<SourceLagContext>> {
         info!("Creating kafka consumer for {}", self.bootstrap_servers);
         let mut client_config = ClientConfig::new();
 
@@ -69,15 +98,20 @@ impl KafkaSourceFunc {
             }
         };
 
+        // statistics are needed to report the consumer's lag
+        client_config.set("statistics.interval.ms", "1000");
         for (key, value) in &self.client_configs {
             client_config.set(key, value);
         }
-        let consumer: StreamConsumer = client_config
+        let consumer: StreamConsumer<SourceLagContext> = client_config
             .set("bootstrap.servers", &self.bootstrap_servers)
             .set("enable.partition.eof", "false")
             .set("enable.auto.commit", "false")
             .set("group.id", group_id)
-            .create()?;
+            .create_with_context(SourceLagContext {
+                topic: self.topic.clone(),
+                task_info: ctx.task_info.clone(),
+            })?;
 
         let state: Vec<_> = ctx
             .table_manager
@@ -233,6 +267,9 @@ impl KafkaSourceFunc {
                         Some(ControlMessage::LoadCompacted {compacted}) => {
                             ctx.load_compacted(compacted).await;
                         }
+                        Some(ControlMessage::QueryState(query)) => {
+                            ctx.query_state(query).await;
+                        }
                         Some(ControlMessage::NoOp) => {}
                         None => {
 
@@ -101,11 +101,13 @@ impl KafkaTopicTester {
             start_time: to_micros(SystemTime::now()),
             finish_time: to_micros(SystemTime::now()),
             operator_ids: vec![task_info.operator_id.clone()],
+            bytes: 0,
         });
 
         let mut ctx = ArrowContext::new(
             task_info,
             checkpoint_metadata,
+            None,
             control_rx,
             command_tx,
             1,
@@ -266,6 +268,7 @@ async fn test_kafka() {
         min_epoch: 0,
         timestamp: (SystemTime::now()),
         then_stop: false,
+        unaligned: false,
     });
     reader.to_control_tx.send(barrier).await.unwrap();
     let checkpoint_completed = reader.assert_control_checkpoint(1).await;
@@ -296,6 +299,8 @@ async fn test_kafka() {
             max_watermark: Some(0),
             parallelism: 1,
         }),
+        file_refs: HashMap::new(),
+        in_flight: HashMap::new(),
     })
     .await
     .unwrap();
@@ -307,6 +312,7 @@ async fn test_kafka() {
         start_time: 0,
         finish_time: 0,
         operator_ids: vec![task_info.operator_id.clone()],
+        bytes: 0,
     })
     .await
     .unwrap();
@@ -183,6 +183,7 @@ impl Connector for KinesisConnector {
                     aws_region: table.aws_region,
                     offset,
                     shards: HashMap::new(),
+                    shard_lag: HashMap::new(),
                     format: config
                         .format
                         .ok_or_else(|| anyhow!("format required for kinesis source"))?,
@@ -7,6 +7,7 @@ use std::{
 };
 
 use anyhow::{anyhow, bail, Context as AnyhowContext, Result};
+use arroyo_metrics::source_lag_gauge;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
 use arroyo_operator::SourceFinishType;
@@ -52,6 +53,8 @@ pub struct KinesisSourceFunc {
     pub aws_region: Option<String>,
     pub shards: HashMap<String, ShardState>,
     pub offset: SourceOffset,
+    // how far behind the tip of each open shard we are, in milliseconds
+    pub shard_lag: HashMap<String, i64>,
 }
 
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
@@ -316,6 +319,11 @@ impl KinesisSourceFunc {
                 .map(|record| record.sequence_number().unwrap().to_owned())
         });
 
+        if let Some(lag) = get_records.millis_behind_latest() {
+            self.shard_lag.insert(shard_id.clone(), lag);
+            self.report_lag(ctx);
+        }
+
         let next_shard_iterator = self.process_records(get_records, ctx).await?;
         let shard_state = self.shards.get_mut(&shard_id).unwrap();
 
@@ -327,10 +335,17 @@ impl KinesisSourceFunc {
             Some(shard_iterator_id) => Ok(Some(self.next_read_future(shard_id, shard_iterator_id))),
             None => {
                 shard_state.closed = true;
+                self.shard_lag.remove(&shard_id);
+                self.report_lag(ctx);
                 Ok(None)
             }
         }
     }
+
+    fn report_lag(&self, ctx: &ArrowContext) {
+        source_lag_gauge(&ctx.task_info).set(self.shard_lag.values().copied().max().unwrap_or(0));
+    }
+
     async fn handle_need_new_iterator(
         &mut self,
         shard_id: String,
@@ -421,6 +436,9 @@ impl KinesisSourceFunc {
                         Some(ControlMessage::LoadCompacted { compacted }) => {
                             ctx.load_compacted(compacted).await;
                         },
+                        Some(ControlMessage::QueryState(query)) => {
+                            ctx.query_state(query).await;
+                        }
                         Some(ControlMessage::NoOp ) => {}
                         None => {
                         }
@@ -200,6 +200,9 @@ impl MqttSourceFunc {
                         Some(ControlMessage::LoadCompacted {compacted}) => {
                             ctx.load_compacted(compacted).await;
                         }
+                        Some(ControlMessage::QueryState(query)) => {
+                            ctx.query_state(query).await;
+                        }
                         Some(ControlMessage::NoOp) => {}
                         None => {
 
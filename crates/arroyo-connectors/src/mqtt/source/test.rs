@@ -134,6 +134,7 @@ impl MqttTopicTester {
         let mut ctx = ArrowContext::new(
             task_info,
             None,
+            None,
             control_rx,
             command_tx,
             1,
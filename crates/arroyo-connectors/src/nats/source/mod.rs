@@ -473,6 +473,9 @@ impl NatsSourceFunc {
                                 Some(ControlMessage::LoadCompacted {compacted}) => {
                                     ctx.load_compacted(compacted).await;
                                 }
+                                Some(ControlMessage::QueryState(query)) => {
+                                    ctx.query_state(query).await;
+                                }
                                 Some(ControlMessage::NoOp) => {}
                                 None => {}
                             }
@@ -530,6 +533,9 @@ impl NatsSourceFunc {
                                 Some(ControlMessage::LoadCompacted {compacted}) => {
                                     ctx.load_compacted(compacted).await;
                                 }
+                                Some(ControlMessage::QueryState(query)) => {
+                                    ctx.query_state(query).await;
+                                }
                                 Some(ControlMessage::NoOp) => {}
                                 None => {}
                             }
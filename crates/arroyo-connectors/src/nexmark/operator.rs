@@ -321,6 +321,9 @@ impl SourceOperator for NexmarkSourceFunc {
                             }
                         }
                     }
+                    Ok(ControlMessage::QueryState(query)) => {
+                        ctx.query_state(query).await;
+                    }
                     Err(TryRecvError::Empty) => {}
                     x => {
                         warn!("{:?}", x);
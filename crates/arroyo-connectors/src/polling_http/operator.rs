@@ -115,6 +115,9 @@ impl PollingHttpSourceFunc {
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
+            ControlMessage::QueryState(query) => {
+                ctx.query_state(query).await;
+            }
             ControlMessage::NoOp => {}
         }
         None
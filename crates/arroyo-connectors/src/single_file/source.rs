@@ -109,6 +109,9 @@ impl SingleFileSourceFunc {
                     }
                 }
             }
+            Some(ControlMessage::QueryState(query)) => {
+                ctx.query_state(query).await;
+            }
             Some(ControlMessage::NoOp) => {
                 // No-op messages allow the source to advance and process a record
             }
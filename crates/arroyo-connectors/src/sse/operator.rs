@@ -127,6 +127,9 @@ impl SSESourceFunc {
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
+            ControlMessage::QueryState(query) => {
+                ctx.query_state(query).await;
+            }
             ControlMessage::NoOp => {}
         }
         None
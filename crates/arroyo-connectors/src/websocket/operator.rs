@@ -112,6 +112,9 @@ impl WebsocketSourceFunc {
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
+            ControlMessage::QueryState(query) => {
+                ctx.query_state(query).await;
+            }
             ControlMessage::NoOp => {}
         }
         None
@@ -1,4 +1,4 @@
---! all_jobs : Job(ttl_micros?, state?, start_time?, finish_time?, tasks?, failure_message?, run_id?, pipeline_path?, wasm_path?)
+--! all_jobs : Job(ttl_micros?, state?, start_time?, finish_time?, tasks?, failure_message?, run_id?, pipeline_path?, wasm_path?, restore_from?, state_mapping?, restore_epoch?, restart_strategy?, restart_delay_micros?, node_selector?, savepoint_id?, savepoint_url?)
 SELECT
     c.id as id,
     c.organization_id as org_id,
@@ -19,11 +19,38 @@ SELECT
     wasm_path,
     c.restart_nonce as config_restart_nonce,
     s.restart_nonce as status_restart_nonce,
-    restart_mode
+    restart_mode,
+    restore_from,
+    state_mapping,
+    drop_incompatible_state,
+    restore_epoch,
+    restart_strategy,
+    restart_delay_micros,
+    node_selector,
+    priority,
+    sp.pub_id as savepoint_id,
+    sp.url as savepoint_url
 FROM job_configs c
-LEFT JOIN job_statuses s ON c.id = s.id;
+LEFT JOIN job_statuses s ON c.id = s.id
+LEFT JOIN savepoints sp ON sp.id = (
+    SELECT id FROM savepoints
+    WHERE job_id = c.id AND (state = 'requested' OR state = 'inprogress')
+    ORDER BY created_at
+    LIMIT 1
+);
 
---! update_job_status (start_time?, finish_time?, tasks?, failure_message?, pipeline_path?, wasm_path?)
+--! clear_restore_epoch (lease_holder?, lease_term?)
+UPDATE job_configs
+SET restore_epoch = NULL
+WHERE id = :job_id
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
+
+-- with leader election, this and the other writes made while running jobs are fenced by the
+-- lease, so that a controller that has lost leadership can't overwrite the new leader's changes
+--! update_job_status (start_time?, finish_time?, tasks?, failure_message?, pipeline_path?, wasm_path?, restart_delay_micros?, lease_holder?, lease_term?)
 UPDATE job_statuses
 SET state = :state,
     start_time = :start_time,
@@ -34,64 +61,151 @@ SET state = :state,
     pipeline_path = :pipeline_path,
     wasm_path = :wasm_path,
     run_id = :run_id,
-    restart_nonce = :restart_nonce
-WHERE id = :job_id;
+    restart_nonce = :restart_nonce,
+    restart_delay_micros = :restart_delay_micros
+WHERE id = :job_id
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
 
 --! get_program
 SELECT program, proto_version FROM pipelines WHERE id = :id;
 
---! mark_checkpoints_compacted
+--! mark_checkpoints_compacted (lease_holder?, lease_term?)
 UPDATE checkpoints
     set state = 'compacted'
-WHERE job_id = :job_id AND epoch < :epoch;
+WHERE job_id = :job_id AND epoch < :epoch AND state != 'failed'
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
 
---! drop_old_checkpoint_rows
+--! drop_old_checkpoint_rows (lease_holder?, lease_term?)
 DELETE FROM checkpoints
-WHERE job_id = :job_id AND epoch < :epoch;
+WHERE job_id = :job_id AND epoch < :epoch
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
 
---! create_checkpoint
+--! create_checkpoint (lease_holder?, lease_term?)
 INSERT INTO checkpoints
 (pub_id, organization_id, job_id, state_backend, epoch, min_epoch, start_time)
-VALUES (:pub_id, :organization_id, :job_id, :state_backend, :epoch, :min_epoch, :start_time);
+SELECT :pub_id, :organization_id, :job_id, :state_backend, :epoch, :min_epoch, :start_time
+WHERE EXISTS (
+    SELECT 1 FROM controller_leases
+    WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+) OR :lease_term IS NULL;
 
---! update_checkpoint (finish_time?)
+--! update_checkpoint (finish_time?, lease_holder?, lease_term?)
 UPDATE checkpoints
 SET
     operators = :operators,
     finish_time = :finish_time,
     state = :state
-WHERE pub_id = :pub_id;
+WHERE pub_id = :pub_id
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
+
+--! fail_checkpoint (lease_holder?, lease_term?)
+UPDATE checkpoints
+SET
+    finish_time = :finish_time,
+    state = 'failed',
+    failure_reason = :failure_reason
+WHERE pub_id = :pub_id
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
 
---! commit_checkpoint
+--! commit_checkpoint (lease_holder?, lease_term?)
 UPDATE checkpoints
 SET
     finish_time = :finish_time,
     state = 'ready'
-WHERE pub_id = :pub_id;
+WHERE pub_id = :pub_id
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
 
---! mark_compacting
+--! mark_compacting (lease_holder?, lease_term?)
 UPDATE checkpoints
 SET
     state = 'compacting'
-WHERE job_id = :job_id AND epoch >= :min_epoch AND epoch < :epoch;
+WHERE job_id = :job_id AND epoch >= :min_epoch AND epoch < :epoch AND state != 'failed'
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
 
---! mark_failed
+--! mark_failed (lease_holder?, lease_term?)
 UPDATE checkpoints
 SET
     state = 'failed'
-WHERE job_id = :job_id AND epoch >= :epoch;
+WHERE job_id = :job_id AND epoch >= :epoch
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
+
+--! last_checkpoint_epoch
+SELECT COALESCE(MAX(epoch), 0) as epoch
+FROM checkpoints
+WHERE job_id = :job_id;
 
---! last_successful_checkpoint
+--: RestorableCheckpoint
+
+--! last_successful_checkpoint : RestorableCheckpoint
 SELECT pub_id, epoch, min_epoch, state = 'committing' as needs_commits
 FROM checkpoints
 WHERE job_id = :job_id AND (state = 'ready' or state = 'committing')
 ORDER BY epoch DESC
 LIMIT 1;
 
+--! successful_checkpoint_at_epoch : RestorableCheckpoint
+SELECT pub_id, epoch, min_epoch, state = 'committing' as needs_commits
+FROM checkpoints
+WHERE job_id = :job_id AND epoch = :epoch AND (state = 'ready' or state = 'committing');
+
+--! fail_pending_savepoints
+UPDATE savepoints
+SET
+    state = 'failed',
+    finish_time = :finish_time,
+    failure_message = :failure_message
+WHERE job_id = :job_id AND (state = 'requested' OR state = 'inprogress');
+
+--! update_savepoint (epoch?, finish_time?, failure_message?)
+UPDATE savepoints
+SET
+    state = :state,
+    epoch = :epoch,
+    finish_time = :finish_time,
+    failure_message = :failure_message
+WHERE pub_id = :pub_id;
+
 --! create_job_log_message
 INSERT INTO job_log_messages (pub_id, job_id, operator_id, task_index, log_level, message, details)
 VALUES (:pub_id, :job_id, :operator_id, :task_index, :log_level, :message, :details);
 
+--! create_job_event
+INSERT INTO job_log_messages (pub_id, job_id, log_level, message, details)
+VALUES (:pub_id, :job_id, :log_level, :message, :details);
+
+--! update_parallelism_overrides (lease_holder?, lease_term?)
+UPDATE job_configs
+SET parallelism_overrides = :parallelism_overrides, updated_at = :updated_at
+WHERE id = :job_id
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = :lease_holder AND term = :lease_term
+    ) OR :lease_term IS NULL);
+
 --! clean_preview_pipelines
 DELETE FROM pipelines WHERE id in (
   SELECT jc.pipeline_id
@@ -99,4 +213,21 @@ DELETE FROM pipelines WHERE id in (
   INNER JOIN job_statuses js ON jc.id = js.id
   WHERE (js.state = 'Finished' OR js.state = 'Stopped' OR js.state = 'Failed')
     AND jc.ttl_micros > 0
-    AND jc.created_at < :created_at);
\ No newline at end of file
+    AND jc.created_at < :created_at);
+
+-- takes (or extends) the lease if we already hold it or the current holder's has expired; the
+-- term is incremented each time the lease changes hands
+--! acquire_controller_lease
+INSERT INTO controller_leases (name, holder, term, expires_at)
+VALUES (:name, :holder, 1, :expires_at)
+ON CONFLICT (name) DO UPDATE SET
+    holder = excluded.holder,
+    term = CASE WHEN controller_leases.holder = excluded.holder
+        THEN controller_leases.term
+        ELSE controller_leases.term + 1 END,
+    expires_at = excluded.expires_at
+WHERE controller_leases.holder = excluded.holder OR controller_leases.expires_at < :now
+RETURNING term;
+
+--! get_controller_lease
+SELECT holder, term, expires_at FROM controller_leases WHERE name = :name;
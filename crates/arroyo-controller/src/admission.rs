@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Entries that haven't been checked on for this long belong to jobs that are no longer waiting
+/// (for example, because their state machine was shut down), and are dropped
+const STALE_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct QueuedJob {
+    priority: i32,
+    /// Orders jobs with the same priority by when they were queued
+    seq: u64,
+    last_seen: Instant,
+}
+
+/// Orders the jobs that are waiting for the cluster to have enough free slots to schedule them.
+/// Jobs with higher priorities are admitted first, and jobs with the same priority in the order
+/// they were queued. A job is only admitted once every job ahead of it has been, so a large job
+/// isn't starved by smaller ones behind it.
+///
+/// An admitted job stays in the queue until its workers have started, so that the jobs behind it
+/// can't take the slots it was admitted for, and so that it keeps its place if they were taken
+/// by something else in the meantime.
+#[derive(Default)]
+pub struct AdmissionQueue {
+    jobs: Mutex<HashMap<Arc<String>, QueuedJob>>,
+}
+
+impl AdmissionQueue {
+    /// Returns whether the job can be scheduled now, given the slots it needs and the free slots
+    /// in the cluster (None if the scheduler doesn't track them). The job is added to the queue if
+    /// it isn't already there, and stays there either way until it's removed.
+    pub fn can_admit(
+        &self,
+        job_id: &Arc<String>,
+        priority: i32,
+        slots: usize,
+        free_slots: Option<usize>,
+    ) -> bool {
+        let mut jobs = self.jobs.lock().unwrap();
+        Self::admissible(&mut jobs, job_id, priority, slots, free_slots)
+    }
+
+    /// Returns the number of jobs ahead of this one in the queue
+    pub fn position(&self, job_id: &Arc<String>) -> Option<usize> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(job_id)?;
+        Some(jobs.values().filter(|j| Self::ahead(j, job)).count())
+    }
+
+    /// Removes the job from the queue, once its workers have started or it's no longer waiting
+    pub fn remove(&self, job_id: &Arc<String>) {
+        self.jobs.lock().unwrap().remove(job_id);
+    }
+
+    fn ahead(a: &QueuedJob, b: &QueuedJob) -> bool {
+        (a.priority, b.seq) > (b.priority, a.seq)
+    }
+
+    fn admissible(
+        jobs: &mut HashMap<Arc<String>, QueuedJob>,
+        job_id: &Arc<String>,
+        priority: i32,
+        slots: usize,
+        free_slots: Option<usize>,
+    ) -> bool {
+        let now = Instant::now();
+        jobs.retain(|_, j| now.duration_since(j.last_seen) < STALE_AFTER);
+
+        let seq = jobs.values().map(|j| j.seq + 1).max().unwrap_or(0);
+        let job = jobs.entry(job_id.clone()).or_insert(QueuedJob {
+            priority,
+            seq,
+            last_seen: now,
+        });
+        job.priority = priority;
+        job.last_seen = now;
+
+        let job = &jobs[job_id];
+        free_slots.unwrap_or(usize::MAX) >= slots
+            && !jobs
+                .iter()
+                .any(|(id, other)| id != job_id && Self::ahead(other, job))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AdmissionQueue;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_admission_order() {
+        let queue = AdmissionQueue::default();
+        let low = Arc::new("low".to_string());
+        let first = Arc::new("first".to_string());
+        let second = Arc::new("second".to_string());
+
+        assert!(!queue.can_admit(&low, 0, 4, Some(2)));
+        assert!(!queue.can_admit(&first, 1, 4, Some(2)));
+        assert!(!queue.can_admit(&second, 1, 1, Some(2)));
+
+        assert_eq!(queue.position(&first), Some(0));
+        assert_eq!(queue.position(&second), Some(1));
+        assert_eq!(queue.position(&low), Some(2));
+
+        // the smaller job fits, but has to wait for the one ahead of it
+        assert!(!queue.can_admit(&second, 1, 1, Some(2)));
+
+        // an admitted job holds its place until its workers have started
+        assert!(queue.can_admit(&first, 1, 4, Some(4)));
+        assert!(!queue.can_admit(&second, 1, 1, Some(4)));
+        assert_eq!(queue.position(&first), Some(0));
+
+        // and keeps it if they couldn't be, even if a job with the same priority gets there first
+        let third = Arc::new("third".to_string());
+        assert!(!queue.can_admit(&third, 1, 1, Some(4)));
+        assert!(queue.can_admit(&first, 1, 4, Some(4)));
+
+        queue.remove(&first);
+        assert!(queue.can_admit(&second, 1, 1, Some(1)));
+        queue.remove(&second);
+        assert!(!queue.can_admit(&low, 0, 4, None));
+        queue.remove(&third);
+        assert!(queue.can_admit(&low, 0, 4, None));
+        queue.remove(&low);
+        assert_eq!(queue.position(&low), None);
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use arroyo_rpc::config::config;
+
+/// A change in parallelism chosen by the autoscaler, along with the reason for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalingDecision {
+    pub current: usize,
+    pub target: usize,
+    pub reason: String,
+}
+
+impl ScalingDecision {
+    /// Scales the parallelism of each operator by the ratio of the target to the current
+    /// parallelism, so that operators that were given a different parallelism keep their
+    /// relative sizes (and operators that must have equal parallelism still do)
+    pub fn apply(&self, parallelism: &HashMap<String, usize>) -> HashMap<String, usize> {
+        let ratio = self.target as f64 / self.current as f64;
+        parallelism
+            .iter()
+            .map(|(op, p)| {
+                let scaled = ((*p as f64 * ratio).round() as usize).clamp(1, self.target);
+                (op.clone(), scaled)
+            })
+            .collect()
+    }
+}
+
+/// The metrics of each operator that the autoscaler makes its decisions from, over its window
+#[derive(Debug, Clone, Default)]
+pub struct ScalingInputs {
+    /// Average backpressure, between 0 and 1
+    pub backpressure: HashMap<String, f64>,
+    /// Average fraction of time spent processing data, between 0 and 1
+    pub busy: HashMap<String, f64>,
+    /// How much the lag of each source grew over the window, in the source's own units
+    pub lag_growth: HashMap<String, f64>,
+}
+
+fn max_entry(values: &HashMap<String, f64>) -> Option<(&String, f64)> {
+    values
+        .iter()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(k, v)| (k, *v))
+}
+
+/// Chooses a new parallelism for a running pipeline from the backpressure, busy time and
+/// source lag of its operators.
+///
+/// Backpressure on an operator means that its downstream can't keep up, an operator that's
+/// busy nearly all the time can't take on more data, and a source whose lag is growing is
+/// falling further behind its input. The pipeline is scaled up when any of these exceeds its
+/// threshold, and scaled down when both backpressure and busy time are below their scale-down
+/// thresholds for every operator. Decisions are made for the largest operator parallelism, and
+/// applied proportionally to the others. A new autoscaler is created each time the pipeline is
+/// scheduled, so the cooldown applies from the last time the pipeline was (re)started.
+pub struct Autoscaler {
+    min_parallelism: usize,
+    max_parallelism: usize,
+    scale_up_threshold: f64,
+    scale_down_threshold: f64,
+    scale_up_busy_threshold: f64,
+    scale_down_busy_threshold: f64,
+    scale_up_factor: f64,
+    scale_down_factor: f64,
+    window: Duration,
+    cooldown: Duration,
+    started: Instant,
+    last_decision: Option<Instant>,
+}
+
+impl Autoscaler {
+    /// Returns an autoscaler if it's enabled in the config
+    pub fn from_config() -> Option<Self> {
+        let config = &config().pipeline.autoscaler;
+        if !config.enabled {
+            return None;
+        }
+
+        let min_parallelism = config.min_parallelism.max(1);
+        Some(Self {
+            min_parallelism,
+            max_parallelism: config.max_parallelism.max(min_parallelism),
+            scale_up_threshold: config.scale_up_threshold,
+            scale_down_threshold: config.scale_down_threshold,
+            scale_up_busy_threshold: config.scale_up_busy_threshold,
+            scale_down_busy_threshold: config.scale_down_busy_threshold,
+            scale_up_factor: config.scale_up_factor,
+            scale_down_factor: config.scale_down_factor,
+            window: *config.window,
+            cooldown: *config.cooldown,
+            started: Instant::now(),
+            last_decision: None,
+        })
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Whether enough time has passed since the pipeline started, or since the last decision,
+    /// to make a new decision
+    pub fn ready(&self) -> bool {
+        let since = self.last_decision.unwrap_or(self.started);
+        since.elapsed() >= self.cooldown
+    }
+
+    pub fn record_decision(&mut self) {
+        self.last_decision = Some(Instant::now());
+    }
+
+    /// Decides whether the pipeline, currently running at parallelism `current`, should be
+    /// rescaled given the metrics of its operators
+    pub fn decide(&self, current: usize, inputs: &ScalingInputs) -> Option<ScalingDecision> {
+        let (min, max) = (self.min_parallelism, self.max_parallelism);
+
+        if current < min || current > max {
+            return Some(ScalingDecision {
+                current,
+                target: current.clamp(min, max),
+                reason: format!(
+                    "parallelism {} is outside of the autoscaler bounds [{}, {}]",
+                    current, min, max
+                ),
+            });
+        }
+
+        let backpressure = max_entry(&inputs.backpressure);
+        let busy = max_entry(&inputs.busy);
+        if backpressure.is_none() && busy.is_none() {
+            // no metrics yet
+            return None;
+        }
+
+        let scale_up_reason = if let Some((operator, b)) =
+            backpressure.filter(|(_, b)| *b >= self.scale_up_threshold)
+        {
+            Some(format!(
+                "operator {} had an average backpressure of {:.2} over {:?}, above the \
+                 scale-up threshold of {:.2}",
+                operator, b, self.window, self.scale_up_threshold
+            ))
+        } else if let Some((operator, b)) = busy.filter(|(_, b)| *b >= self.scale_up_busy_threshold)
+        {
+            Some(format!(
+                "operator {} was busy for {:.2} of the time over {:?}, above the scale-up \
+                 threshold of {:.2}",
+                operator, b, self.window, self.scale_up_busy_threshold
+            ))
+        } else {
+            max_entry(&inputs.lag_growth)
+                .filter(|(_, growth)| *growth > 0.0)
+                .map(|(operator, growth)| {
+                    format!(
+                        "the lag of source {} grew by {} over {:?}",
+                        operator, growth, self.window
+                    )
+                })
+        };
+
+        if let Some(reason) = scale_up_reason {
+            if current >= max {
+                return None;
+            }
+            let target = ((current as f64 * self.scale_up_factor).ceil() as usize)
+                .max(current + 1)
+                .min(max);
+            return Some(ScalingDecision {
+                current,
+                target,
+                reason,
+            });
+        }
+
+        let describe = |value: Option<(&String, f64)>| match value {
+            Some((operator, v)) => format!("{:.2} (operator {})", v, operator),
+            None => "unknown".to_string(),
+        };
+
+        if backpressure.map_or(true, |(_, b)| b <= self.scale_down_threshold)
+            && busy.map_or(true, |(_, b)| b <= self.scale_down_busy_threshold)
+            && current > min
+        {
+            let target = ((current as f64 * self.scale_down_factor).floor() as usize)
+                .min(current - 1)
+                .max(min);
+            Some(ScalingDecision {
+                current,
+                target,
+                reason: format!(
+                    "over {:?}, the maximum average backpressure was {} and the maximum busy \
+                     time was {}, below the scale-down thresholds of {:.2} and {:.2}",
+                    self.window,
+                    describe(backpressure),
+                    describe(busy),
+                    self.scale_down_threshold,
+                    self.scale_down_busy_threshold
+                ),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn autoscaler() -> Autoscaler {
+        Autoscaler {
+            min_parallelism: 1,
+            max_parallelism: 8,
+            scale_up_threshold: 0.5,
+            scale_down_threshold: 0.1,
+            scale_up_busy_threshold: 0.8,
+            scale_down_busy_threshold: 0.2,
+            scale_up_factor: 2.0,
+            scale_down_factor: 0.5,
+            window: Duration::from_secs(120),
+            cooldown: Duration::from_secs(300),
+            started: Instant::now(),
+            last_decision: None,
+        }
+    }
+
+    fn values(values: &[(&str, f64)]) -> HashMap<String, f64> {
+        values.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn test_decide() {
+        let autoscaler = autoscaler();
+        let target = |current, backpressure: &[(&str, f64)]| {
+            let inputs = ScalingInputs {
+                backpressure: values(backpressure),
+                ..Default::default()
+            };
+            autoscaler.decide(current, &inputs).map(|d| d.target)
+        };
+
+        // no metrics yet
+        assert_eq!(target(2, &[]), None);
+
+        // scale up, capped at the max
+        assert_eq!(target(2, &[("a", 0.2), ("b", 0.7)]), Some(4));
+        assert_eq!(target(6, &[("a", 0.9)]), Some(8));
+        assert_eq!(target(8, &[("a", 0.9)]), None);
+
+        // within the thresholds
+        assert_eq!(target(4, &[("a", 0.3), ("b", 0.05)]), None);
+
+        // scale down, bounded by the min
+        assert_eq!(target(4, &[("a", 0.05), ("b", 0.0)]), Some(2));
+        assert_eq!(target(1, &[("a", 0.0)]), None);
+
+        // out of bounds
+        assert_eq!(target(16, &[("a", 0.3)]), Some(8));
+    }
+
+    #[test]
+    fn test_decide_busy_and_lag() {
+        let autoscaler = autoscaler();
+        let target = |busy: &[(&str, f64)], lag_growth: &[(&str, f64)]| {
+            let inputs = ScalingInputs {
+                backpressure: values(&[("a", 0.05), ("b", 0.0)]),
+                busy: values(busy),
+                lag_growth: values(lag_growth),
+            };
+            autoscaler.decide(4, &inputs).map(|d| d.target)
+        };
+
+        // an operator that's nearly always busy scales up even without backpressure
+        assert_eq!(target(&[("a", 0.9)], &[]), Some(8));
+
+        // as does a source that's falling further behind
+        assert_eq!(target(&[("a", 0.5)], &[("source", 1000.0)]), Some(8));
+
+        // busy operators keep the pipeline from scaling down
+        assert_eq!(target(&[("a", 0.5)], &[("source", -1000.0)]), None);
+
+        // and idle ones let it
+        assert_eq!(target(&[("a", 0.1)], &[("source", 0.0)]), Some(2));
+    }
+
+    #[test]
+    fn test_apply() {
+        let decision = ScalingDecision {
+            current: 8,
+            target: 4,
+            reason: String::new(),
+        };
+
+        let parallelism: HashMap<_, _> = [("source", 3), ("agg", 8), ("sink", 1)]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect();
+
+        let scaled = decision.apply(&parallelism);
+        assert_eq!(scaled["source"], 2);
+        assert_eq!(scaled["agg"], 4);
+        assert_eq!(scaled["sink"], 1);
+    }
+}
@@ -99,6 +99,84 @@ impl JobMetrics {
         // never reads any data
         let backpressure = 1.0 - (queue_remaining + 1.0) / (queue_size + 1.0);
         task.update_backpressure(now, backpressure);
+
+        if let Some(busy_time) = values.get(&MetricName::BusyTime) {
+            task.busy.add(now, *busy_time);
+        }
+
+        if let Some(lag) = values.get(&MetricName::SourceLag) {
+            task.source_lag.push((now, *lag as f64));
+        }
+    }
+
+    /// Returns the backpressure of each operator, averaged over the given window; for operators
+    /// with multiple subtasks this is the backpressure of the most backpressured subtask.
+    /// Operators for which no metrics have been collected in the window are omitted.
+    pub async fn operator_backpressure(&self, window: Duration) -> HashMap<String, f64> {
+        let since = SystemTime::now() - window;
+        self.per_operator(
+            |task| average(task.backpressure.iter().filter(|(t, _)| *t >= since)),
+            f64::max,
+        )
+        .await
+    }
+
+    /// Returns the fraction of time (between 0 and 1) each operator spent processing data,
+    /// averaged over the given window; as for backpressure, this is the busiest subtask's
+    pub async fn operator_busy(&self, window: Duration) -> HashMap<String, f64> {
+        let since = SystemTime::now() - window;
+        self.per_operator(
+            // busy time is reported in microseconds, so its rate is in microseconds per second
+            |task| average(task.busy.iter().filter(|(t, _)| *t >= since)).map(|r| r / 1e6),
+            f64::max,
+        )
+        .await
+    }
+
+    /// Returns how much the lag of each source grew (or, if negative, shrank) over the given
+    /// window, summed over its subtasks, in the source's own units. Operators that don't
+    /// report lag are omitted.
+    pub async fn source_lag_growth(&self, window: Duration) -> HashMap<String, f64> {
+        let since = SystemTime::now() - window;
+        self.per_operator(
+            |task| {
+                let mut lags = task.source_lag.iter().filter(|(t, _)| *t >= since);
+                let (_, first) = lags.next()?;
+                let (_, last) = lags.last()?;
+                Some(last - first)
+            },
+            |a, b| a + b,
+        )
+        .await
+    }
+
+    async fn per_operator(
+        &self,
+        value: impl Fn(&TaskMetrics) -> Option<f64>,
+        combine: impl Fn(f64, f64) -> f64,
+    ) -> HashMap<String, f64> {
+        let mut result: HashMap<String, f64> = HashMap::new();
+
+        for (k, v) in self.tasks.read().await.iter() {
+            let Some(value) = value(v) else {
+                continue;
+            };
+
+            let Some(node) = self
+                .program
+                .graph
+                .node_weight(NodeIndex::new(k.operator_id as usize))
+            else {
+                continue;
+            };
+
+            result
+                .entry(node.operator_id.clone())
+                .and_modify(|e| *e = combine(*e, value))
+                .or_insert(value);
+        }
+
+        result
     }
 
     pub async fn get_groups(&self) -> Vec<OperatorMetricGroup> {
@@ -167,6 +245,8 @@ impl JobMetrics {
 pub struct TaskMetrics {
     rates: HashMap<MetricName, RateMetric>,
     backpressure: CircularBuffer<(SystemTime, f64), NUM_BUCKETS>,
+    busy: RateMetric,
+    source_lag: CircularBuffer<(SystemTime, f64), NUM_BUCKETS>,
 }
 
 impl TaskMetrics {
@@ -177,6 +257,8 @@ impl TaskMetrics {
                 .map(|&m| (m, RateMetric::new()))
                 .collect(),
             backpressure: CircularBuffer::new((UNIX_EPOCH, 0.0)),
+            busy: RateMetric::new(),
+            source_lag: CircularBuffer::new((UNIX_EPOCH, 0.0)),
         }
     }
 
@@ -185,6 +267,11 @@ impl TaskMetrics {
     }
 }
 
+fn average(values: impl Iterator<Item = (SystemTime, f64)>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), (_, v)| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
 /// Calculates an exponentially-weighted moving average over metrics collected from the job
 pub struct RateMetric {
     values: CircularBuffer<(SystemTime, f64), NUM_BUCKETS>,
@@ -1,7 +1,7 @@
 use std::str::FromStr;
 use std::sync::Arc;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     time::{Duration, Instant, SystemTime},
 };
 
@@ -9,10 +9,12 @@ use crate::types::public::StopMode as SqlStopMode;
 use anyhow::bail;
 use arroyo_rpc::grpc::rpc::{
     worker_grpc_client::WorkerGrpcClient, CheckpointReq, CommitReq, JobFinishedReq, LabelPair,
-    LoadCompactedDataReq, MetricsReq, StopExecutionReq, StopMode, TaskCheckpointEventType,
+    LoadCompactedDataReq, MetricsReq, QueryStateReq, QueryStateResp, StopExecutionReq, StopMode,
+    TaskAssignment, TaskCheckpointEventType,
 };
+use arroyo_state::tables::{decode_key, key_hash};
 use arroyo_state::{BackingStore, StateBackend};
-use arroyo_types::{to_micros, WorkerId};
+use arroyo_types::{server_for_hash, to_micros, WorkerId};
 use cornucopia_async::DatabaseSource;
 use rand::{thread_rng, Rng};
 
@@ -20,8 +22,10 @@ use time::OffsetDateTime;
 
 use crate::job_controller::job_metrics::{get_metric_name, JobMetrics};
 use crate::types::public::CheckpointState as DbCheckpointState;
-use crate::{queries::controller_queries, JobConfig, JobMessage, RunningMessage};
-use arroyo_datastream::logical::LogicalProgram;
+use crate::types::public::LogLevel;
+use crate::types::public::SavepointState as DbSavepointState;
+use crate::{leader, queries::controller_queries, JobConfig, JobMessage, RunningMessage};
+use arroyo_datastream::logical::{LogicalEdgeType, LogicalProgram};
 use arroyo_rpc::api_types::metrics::MetricName;
 use arroyo_rpc::config::config;
 use arroyo_rpc::notify_db;
@@ -29,23 +33,29 @@ use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_state::checkpoint_state::CheckpointState;
 use arroyo_state::committing_state::CommittingState;
 use arroyo_state::parquet::ParquetBackend;
+use futures::future::join_all;
+use petgraph::graph::NodeIndex;
+use petgraph::Direction;
 use tokio::{sync::mpsc::Receiver, task::JoinHandle};
-use tonic::{transport::Channel, Request};
+use tonic::{transport::Channel, Code, Request, Status};
 use tracing::{debug, error, info, warn};
 
+use self::autoscaler::{Autoscaler, ScalingInputs};
 use self::checkpointer::CheckpointingOrCommittingState;
 
+mod autoscaler;
 mod checkpointer;
 pub mod job_metrics;
 
-const CHECKPOINTS_TO_KEEP: u32 = 4;
 const CHECKPOINT_ROWS_TO_KEEP: u32 = 100;
 const COMPACT_EVERY: u32 = 2;
+const STATE_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum WorkerState {
     Running,
     Stopped,
+    Failed(String),
 }
 
 #[allow(unused)]
@@ -60,6 +70,14 @@ impl WorkerStatus {
     fn heartbeat_timeout(&self) -> bool {
         self.last_heartbeat.elapsed() > *config().pipeline.worker_heartbeat_timeout
     }
+
+    fn failure(&self) -> Option<&str> {
+        match &self.state {
+            WorkerState::Failed(reason) => Some(reason),
+            _ if self.heartbeat_timeout() => Some("worker failed to heartbeat"),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -72,6 +90,18 @@ pub enum TaskState {
 #[derive(Debug)]
 pub struct TaskStatus {
     state: TaskState,
+    // the worker running the task, once assignments have been made
+    worker_id: Option<WorkerId>,
+}
+
+/// A set of operators that only exchange data with each other, which is run on its own workers so
+/// that it can be restarted without affecting the rest of the job
+#[derive(Debug)]
+pub struct FailoverRegion {
+    pub operators: HashSet<String>,
+    pub workers: HashSet<WorkerId>,
+    /// Number of times the region has been restarted on its own
+    pub restarts: u32,
 }
 
 // Stores a model of the current state of a running job to use in the state machine
@@ -85,25 +115,108 @@ pub struct RunningJobModel {
     job_id: Arc<String>,
     state: JobState,
     program: Arc<LogicalProgram>,
-    checkpoint_state: Option<CheckpointingOrCommittingState>,
+    // in-progress checkpoints, by epoch; they finish in epoch order
+    checkpoints: BTreeMap<u32, CheckpointingOrCommittingState>,
     epoch: u32,
+    // the last epoch whose checkpoint completed successfully
+    completed_epoch: u32,
     min_epoch: u32,
     last_checkpoint: Instant,
+    last_checkpoint_started: Instant,
+    consecutive_checkpoint_failures: u32,
+    // epochs whose checkpoints failed, and whose data hasn't been cleaned up yet
+    failed_epochs: Vec<u32>,
     workers: HashMap<WorkerId, WorkerStatus>,
     tasks: HashMap<(String, u32), TaskStatus>,
     operator_parallelism: HashMap<String, usize>,
+    // empty unless the job's failover regions were scheduled onto separate workers
+    failover_regions: Vec<FailoverRegion>,
     metrics: JobMetrics,
     metric_update_task: Option<JoinHandle<()>>,
     last_updated_metrics: Instant,
 }
 
+/// Returns the subtask of the queried operator that owns the key in a state query. Keys are
+/// hashed with the key columns of the operator's shuffled input, in the same way that rows are
+/// routed to its subtasks; if that isn't possible (for example because no key was given, or the
+/// operator isn't keyed), returns None and every subtask needs to be queried.
+fn state_query_subtask(program: &LogicalProgram, req: &QueryStateReq) -> Option<u32> {
+    let key: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(req.key.as_ref()?).ok()?;
+    let idx = NodeIndex::new(program.operator_index(&req.operator_id)? as usize);
+    let parallelism = program.graph.node_weight(idx)?.parallelism;
+
+    let edge = program
+        .graph
+        .edges_directed(idx, Direction::Incoming)
+        .find(|edge| edge.weight().edge_type == LogicalEdgeType::Shuffle)?;
+    let schema = &edge.weight().schema;
+    let key_indices = schema.key_indices.as_ref()?;
+    if key.len() != key_indices.len() {
+        return None;
+    }
+
+    let columns = decode_key(&schema.schema, key_indices, &key).ok()?;
+    let hash = key_hash(&columns).ok()?;
+    Some(server_for_hash(hash, parallelism) as u32)
+}
+
+/// Sends a state query to each of the workers, whose subtasks respond with the entries for keys
+/// that fall into their key range
+async fn query_workers(
+    workers: Vec<(WorkerId, WorkerGrpcClient<Channel>)>,
+    req: QueryStateReq,
+) -> Result<QueryStateResp, Status> {
+    let limit = req.limit as usize;
+
+    let responses = join_all(workers.into_iter().map(|(id, mut connect)| {
+        let req = req.clone();
+        async move {
+            let resp = tokio::time::timeout(STATE_QUERY_TIMEOUT, connect.query_state(req))
+                .await
+                .map_err(|_| {
+                    Status::deadline_exceeded(format!(
+                        "Timed out querying state on worker {}",
+                        id.0
+                    ))
+                })?
+                .map_err(|e| match e.code() {
+                    Code::InvalidArgument => e,
+                    _ => Status::unavailable(format!(
+                        "Failed to query state on worker {}: {}",
+                        id.0,
+                        e.message()
+                    )),
+                })?;
+
+            serde_json::from_str::<Vec<serde_json::Value>>(&resp.into_inner().rows)
+                .map_err(|e| Status::internal(format!("Invalid state query response: {}", e)))
+        }
+    }))
+    .await;
+
+    let mut rows: Vec<serde_json::Value> = vec![];
+    for worker_rows in responses {
+        rows.extend(worker_rows?);
+    }
+    rows.truncate(limit);
+
+    Ok(QueryStateResp {
+        rows: serde_json::to_string(&rows).unwrap(),
+    })
+}
+
 impl std::fmt::Debug for RunningJobModel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RunningJobModel")
             .field("job_id", &self.job_id)
             .field("state", &self.state)
-            .field("checkpointing", &self.checkpoint_state.is_some())
+            .field(
+                "checkpointing",
+                &self.checkpoints.keys().collect::<Vec<_>>(),
+            )
             .field("epoch", &self.epoch)
+            .field("completed_epoch", &self.completed_epoch)
             .field("min_epoch", &self.min_epoch)
             .field("last_checkpoint", &self.last_checkpoint)
             .finish()
@@ -117,16 +230,19 @@ impl RunningJobModel {
     ) -> anyhow::Result<()> {
         let c = db.client().await?;
 
-        controller_queries::execute_update_checkpoint(
+        let (lease_holder, lease_term) = leader::lease_fence();
+        let updated = controller_queries::execute_update_checkpoint(
             &c,
             &serde_json::to_value(&checkpoint_state.operator_details).unwrap(),
             &None,
             &DbCheckpointState::inprogress,
             &checkpoint_state.checkpoint_id(),
+            &lease_holder,
+            &lease_term,
         )
         .await?;
 
-        Ok(())
+        leader::check_fenced(updated, "update checkpoint")
     }
 
     pub async fn update_checkpoint_in_db(
@@ -141,16 +257,19 @@ impl RunningJobModel {
             None
         };
         let operator_state = serde_json::to_value(&checkpoint_state.operator_details).unwrap();
-        controller_queries::execute_update_checkpoint(
+        let (lease_holder, lease_term) = leader::lease_fence();
+        let updated = controller_queries::execute_update_checkpoint(
             &c,
             &operator_state,
             &finish_time,
             &db_checkpoint_state,
             &checkpoint_state.checkpoint_id(),
+            &lease_holder,
+            &lease_term,
         )
         .await?;
 
-        Ok(())
+        leader::check_fenced(updated, "update checkpoint")
     }
 
     pub async fn finish_committing(checkpoint_id: &str, db: &DatabaseSource) -> anyhow::Result<()> {
@@ -158,10 +277,17 @@ impl RunningJobModel {
         let finish_time = SystemTime::now();
 
         let c = db.client().await?;
-        controller_queries::execute_commit_checkpoint(&c, &finish_time.into(), &checkpoint_id)
-            .await?;
+        let (lease_holder, lease_term) = leader::lease_fence();
+        let updated = controller_queries::execute_commit_checkpoint(
+            &c,
+            &finish_time.into(),
+            &checkpoint_id,
+            &lease_holder,
+            &lease_term,
+        )
+        .await?;
 
-        Ok(())
+        leader::check_fenced(updated, "commit checkpoint")
     }
 
     pub async fn handle_message(
@@ -171,73 +297,64 @@ impl RunningJobModel {
     ) -> anyhow::Result<()> {
         match msg {
             RunningMessage::TaskCheckpointEvent(c) => {
-                if let Some(checkpoint_state) = &mut self.checkpoint_state {
-                    if c.epoch != self.epoch {
-                        warn!(
-                            message = "Received checkpoint event for wrong epoch",
-                            epoch = c.epoch,
-                            expected = self.epoch,
-                            job_id = *self.job_id,
-                        );
-                    } else {
-                        match checkpoint_state {
-                            CheckpointingOrCommittingState::Checkpointing(checkpoint_state) => {
-                                checkpoint_state.checkpoint_event(c)?;
-                                Self::update_db(checkpoint_state, db).await?
-                            }
-                            CheckpointingOrCommittingState::Committing(committing_state) => {
-                                if matches!(c.event_type(), TaskCheckpointEventType::FinishedCommit)
-                                {
-                                    committing_state
-                                        .subtask_committed(c.operator_id.clone(), c.subtask_index);
-                                    self.compact_state().await?;
-                                } else {
-                                    warn!("unexpected checkpoint event type {:?}", c.event_type())
-                                }
+                // events for checkpoints that have failed may still arrive from the workers
+                if let Some(checkpoint_state) = self.checkpoints.get_mut(&c.epoch) {
+                    match checkpoint_state {
+                        CheckpointingOrCommittingState::Checkpointing(checkpoint_state) => {
+                            checkpoint_state.checkpoint_event(c)?;
+                            Self::update_db(checkpoint_state, db).await?
+                        }
+                        CheckpointingOrCommittingState::Committing(committing_state) => {
+                            if matches!(c.event_type(), TaskCheckpointEventType::FinishedCommit) {
+                                committing_state
+                                    .subtask_committed(c.operator_id.clone(), c.subtask_index);
+                                self.compact_state().await?;
+                            } else {
+                                warn!("unexpected checkpoint event type {:?}", c.event_type())
                             }
-                        };
-                    }
+                        }
+                    };
                 } else {
                     debug!(
-                        message = "Received checkpoint event but not checkpointing",
+                        message = "Received checkpoint event for epoch that is not checkpointing",
                         job_id = *self.job_id,
                         event = format!("{:?}", c)
                     )
                 }
             }
             RunningMessage::TaskCheckpointFinished(c) => {
-                if let Some(checkpoint_state) = &mut self.checkpoint_state {
-                    if c.epoch != self.epoch {
-                        warn!(
-                            message = "Received checkpoint finished for wrong epoch",
-                            epoch = c.epoch,
-                            expected = self.epoch,
-                            job_id = *self.job_id,
-                        );
-                    } else {
-                        let CheckpointingOrCommittingState::Checkpointing(checkpoint_state) =
-                            checkpoint_state
-                        else {
-                            bail!("Received checkpoint finished but not checkpointing");
-                        };
-                        checkpoint_state.checkpoint_finished(c).await?;
-                        Self::update_db(checkpoint_state, db).await?;
-                    }
+                if let Some(checkpoint_state) = self.checkpoints.get_mut(&c.epoch) {
+                    let CheckpointingOrCommittingState::Checkpointing(checkpoint_state) =
+                        checkpoint_state
+                    else {
+                        bail!("Received checkpoint finished but not checkpointing");
+                    };
+                    checkpoint_state.checkpoint_finished(c).await?;
+                    Self::update_db(checkpoint_state, db).await?;
                 } else {
                     warn!(
-                        message = "Received checkpoint finished but not checkpointing",
+                        message =
+                            "Received checkpoint finished for epoch that is not checkpointing",
+                        epoch = c.epoch,
                         job_id = *self.job_id
                     )
                 }
             }
             RunningMessage::TaskFinished {
-                worker_id: _,
+                worker_id,
                 time: _,
                 operator_id,
                 subtask_index,
             } => {
                 let key = (operator_id, subtask_index);
-                if let Some(status) = self.tasks.get_mut(&key) {
+                if !self.workers.contains_key(&worker_id) {
+                    // the worker was replaced when its failover region was restarted
+                    debug!(
+                        message = "Ignoring task finished from replaced worker",
+                        job_id = *self.job_id,
+                        worker_id = worker_id.0
+                    );
+                } else if let Some(status) = self.tasks.get_mut(&key) {
                     status.state = TaskState::Finished;
                 } else {
                     warn!(
@@ -249,13 +366,20 @@ impl RunningJobModel {
                 }
             }
             RunningMessage::TaskFailed {
+                worker_id,
                 operator_id,
                 subtask_index,
                 reason,
-                ..
             } => {
                 let key = (operator_id, subtask_index);
-                if let Some(status) = self.tasks.get_mut(&key) {
+                if !self.workers.contains_key(&worker_id) {
+                    debug!(
+                        message = "Ignoring task failure from replaced worker",
+                        job_id = *self.job_id,
+                        worker_id = worker_id.0,
+                        reason,
+                    );
+                } else if let Some(status) = self.tasks.get_mut(&key) {
                     status.state = TaskState::Failed(reason);
                 } else {
                     warn!(
@@ -289,12 +413,52 @@ impl RunningJobModel {
                     );
                 }
             }
+            RunningMessage::WorkerFailed { worker_id, reason } => {
+                let Some(worker) = self.workers.get_mut(&worker_id) else {
+                    warn!(
+                        message = "Received failure for unknown worker",
+                        job_id = *self.job_id,
+                        worker_id = worker_id.0,
+                        reason
+                    );
+                    return Ok(());
+                };
+
+                worker.state = WorkerState::Failed(reason.clone());
+
+                let c = db.client().await?;
+                controller_queries::execute_create_job_event(
+                    &c,
+                    &generate_id(IdTypes::JobLogMessage),
+                    &*self.job_id,
+                    &LogLevel::error,
+                    &format!("Worker {} failed", worker_id.0),
+                    &reason,
+                )
+                .await?;
+            }
+            RunningMessage::QueryState { req, tx } => {
+                // if we know which worker owns the key, only that worker needs to be asked
+                let owner = state_query_subtask(&self.program, &req)
+                    .and_then(|subtask| self.tasks.get(&(req.operator_id.clone(), subtask)))
+                    .and_then(|task| task.worker_id);
+
+                let workers: Vec<_> = self
+                    .workers
+                    .values()
+                    .filter(|w| w.state == WorkerState::Running)
+                    .filter(|w| owner.map(|owner| owner == w.id).unwrap_or(true))
+                    .map(|w| (w.id, w.connect.clone()))
+                    .collect();
+
+                // don't block the job's message loop while the workers respond
+                tokio::spawn(async move {
+                    let _ = tx.send(query_workers(workers, req).await);
+                });
+            }
         }
 
-        if self.state == JobState::Running
-            && self.all_tasks_finished()
-            && self.checkpoint_state.is_none()
-        {
+        if self.state == JobState::Running && self.all_tasks_finished() && !self.checkpointing() {
             for w in &mut self.workers.values_mut() {
                 if let Err(e) = w.connect.job_finished(JobFinishedReq {}).await {
                     warn!(
@@ -318,6 +482,7 @@ impl RunningJobModel {
         then_stop: bool,
     ) -> anyhow::Result<()> {
         self.epoch += 1;
+        self.last_checkpoint_started = Instant::now();
 
         info!(
             message = "Starting checkpointing",
@@ -336,6 +501,9 @@ impl RunningJobModel {
                     min_epoch: self.min_epoch,
                     then_stop,
                     is_commit: false,
+                    // stopping checkpoints are always aligned, so that there's no in-flight
+                    // data left to replay when the pipeline is restarted
+                    unaligned: config().pipeline.unaligned_checkpoints && !then_stop,
                 }))
                 .await?;
         }
@@ -343,7 +511,8 @@ impl RunningJobModel {
         let checkpoint_id = generate_id(IdTypes::Checkpoint);
 
         let c = db.client().await?;
-        controller_queries::execute_create_checkpoint(
+        let (lease_holder, lease_term) = leader::lease_fence();
+        let created = controller_queries::execute_create_checkpoint(
             &c,
             &checkpoint_id,
             &organization_id,
@@ -352,8 +521,11 @@ impl RunningJobModel {
             &(self.epoch as i32),
             &(self.min_epoch as i32),
             &OffsetDateTime::now_utc(),
+            &lease_holder,
+            &lease_term,
         )
         .await?;
+        leader::check_fenced(created, "create checkpoint")?;
 
         let state = CheckpointState::new(
             self.job_id.clone(),
@@ -363,7 +535,129 @@ impl RunningJobModel {
             self.program.tasks_per_operator(),
         );
 
-        self.checkpoint_state = Some(CheckpointingOrCommittingState::Checkpointing(state));
+        self.checkpoints.insert(
+            self.epoch,
+            CheckpointingOrCommittingState::Checkpointing(state),
+        );
+
+        Ok(())
+    }
+
+    pub fn checkpointing(&self) -> bool {
+        !self.checkpoints.is_empty()
+    }
+
+    /// Whether a new checkpoint should be started, given the checkpoint interval and how many
+    /// checkpoints may be in progress at once
+    fn should_start_checkpoint(&self, interval: Duration, max_concurrent: usize) -> bool {
+        self.checkpoints.len() < max_concurrent
+            && self.last_checkpoint.elapsed() > interval
+            && self.last_checkpoint_started.elapsed() > interval
+    }
+
+    /// Records that the checkpoint for `epoch` has failed (the caller has already removed it),
+    /// returning whether there have now been more consecutive failures than are tolerable
+    fn record_checkpoint_failure(&mut self, epoch: u32, tolerable: u32) -> bool {
+        self.consecutive_checkpoint_failures += 1;
+        // don't immediately retry
+        self.last_checkpoint = Instant::now();
+        self.failed_epochs.push(epoch);
+        self.consecutive_checkpoint_failures > tolerable
+    }
+
+    fn record_checkpoint_success(&mut self, epoch: u32) {
+        self.completed_epoch = epoch;
+        self.consecutive_checkpoint_failures = 0;
+    }
+
+    /// Takes the failed epochs whose data can be cleaned up, which are those before the latest
+    /// completed checkpoint. Subtasks may carry files from a failed epoch over into later
+    /// ones, so that's only safe once a later checkpoint has recorded what it references.
+    fn take_cleanable_failed_epochs(&mut self) -> Vec<u32> {
+        let completed_epoch = self.completed_epoch;
+        let (cleanable, remaining) = std::mem::take(&mut self.failed_epochs)
+            .into_iter()
+            .partition(|epoch| *epoch < completed_epoch);
+        self.failed_epochs = remaining;
+        cleanable
+    }
+
+    /// Records that the checkpoint has failed, and fails the job if there have been more
+    /// consecutive failures than we tolerate. The data it wrote is cleaned up once a later
+    /// checkpoint completes.
+    async fn fail_checkpoint(
+        &mut self,
+        checkpoint: &CheckpointState,
+        reason: String,
+        db: &DatabaseSource,
+    ) -> anyhow::Result<()> {
+        let exceeded = self.record_checkpoint_failure(
+            checkpoint.epoch(),
+            config().pipeline.tolerable_checkpoint_failures,
+        );
+
+        warn!(
+            message = "Checkpoint failed",
+            job_id = *self.job_id,
+            epoch = checkpoint.epoch(),
+            reason,
+            consecutive_failures = self.consecutive_checkpoint_failures
+        );
+
+        let (lease_holder, lease_term) = leader::lease_fence();
+        let updated = controller_queries::execute_fail_checkpoint(
+            &db.client().await?,
+            &OffsetDateTime::now_utc(),
+            &reason,
+            &checkpoint.checkpoint_id(),
+            &lease_holder,
+            &lease_term,
+        )
+        .await?;
+        leader::check_fenced(updated, "fail checkpoint")?;
+
+        if exceeded {
+            bail!(
+                "checkpoint {} failed ({}), and {} consecutive checkpoints have failed",
+                checkpoint.epoch(),
+                reason,
+                self.consecutive_checkpoint_failures
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the epochs of the checkpoints that have been running for longer than `timeout`
+    fn timed_out_checkpoints(&self, now: SystemTime, timeout: Duration) -> Vec<u32> {
+        self.checkpoints
+            .iter()
+            .filter_map(|(epoch, state)| match state {
+                // committing can't be abandoned, as sinks may have already committed
+                CheckpointingOrCommittingState::Checkpointing(checkpointing)
+                    if now
+                        .duration_since(checkpointing.start_time())
+                        .is_ok_and(|elapsed| elapsed > timeout) =>
+                {
+                    Some(*epoch)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Fails any checkpoints that have been running for longer than the checkpoint timeout
+    pub async fn check_checkpoint_timeouts(&mut self, db: &DatabaseSource) -> anyhow::Result<()> {
+        let timeout = *config().pipeline.checkpoint_timeout;
+
+        for epoch in self.timed_out_checkpoints(SystemTime::now(), timeout) {
+            if let Some(CheckpointingOrCommittingState::Checkpointing(checkpointing)) =
+                self.checkpoints.remove(&epoch)
+            {
+                self.fail_checkpoint(&checkpointing, format!("timed out after {:?}", timeout), db)
+                    .await?;
+            }
+        }
 
         Ok(())
     }
@@ -377,7 +671,7 @@ impl RunningJobModel {
         info!(
             message = "Compacting state",
             job_id = *self.job_id,
-            epoch = self.epoch,
+            epoch = self.completed_epoch,
         );
 
         let mut worker_clients: Vec<WorkerGrpcClient<Channel>> =
@@ -387,7 +681,7 @@ impl RunningJobModel {
                 // compact the operator's state and notify the workers to load the new files
                 self.job_id.clone(),
                 operator_id.clone(),
-                self.epoch,
+                self.completed_epoch,
             )
             .await?;
 
@@ -409,17 +703,28 @@ impl RunningJobModel {
         info!(
             message = "Finished compaction",
             job_id = *self.job_id,
-            epoch = self.epoch,
+            epoch = self.completed_epoch,
         );
         Ok(())
     }
 
     pub async fn finish_checkpoint_if_done(&mut self, db: &DatabaseSource) -> anyhow::Result<()> {
-        if self.checkpoint_state.as_ref().unwrap().done() {
-            let state = self.checkpoint_state.take().unwrap();
+        // checkpoints build on each other, so they're finished in order
+        while let Some(entry) = self.checkpoints.first_entry() {
+            if !entry.get().done() {
+                break;
+            }
+            let (epoch, state) = entry.remove_entry();
+
             match state {
                 CheckpointingOrCommittingState::Checkpointing(checkpointing) => {
-                    checkpointing.save_state().await?;
+                    if let Err(e) = checkpointing.save_state().await {
+                        self.fail_checkpoint(&checkpointing, format!("{:?}", e), db)
+                            .await?;
+                        continue;
+                    }
+
+                    self.record_checkpoint_success(epoch);
 
                     let committing_state = checkpointing.committing_state();
                     let duration = checkpointing
@@ -432,14 +737,14 @@ impl RunningJobModel {
                         Self::update_checkpoint_in_db(&checkpointing, db, DbCheckpointState::ready)
                             .await?;
                         self.last_checkpoint = Instant::now();
-                        self.checkpoint_state = None;
                         self.compact_state().await?;
 
                         info!(
                             message = "Finished checkpointing",
                             job_id = *self.job_id,
-                            epoch = self.epoch,
-                            duration
+                            epoch,
+                            duration,
+                            bytes = checkpointing.bytes()
                         );
                         // trigger a DB backup now that we're done checkpointing
                         notify_db();
@@ -451,18 +756,22 @@ impl RunningJobModel {
                         )
                         .await?;
                         let committing_data = committing_state.committing_data();
-                        self.checkpoint_state =
-                            Some(CheckpointingOrCommittingState::Committing(committing_state));
+                        self.checkpoints.insert(
+                            epoch,
+                            CheckpointingOrCommittingState::Committing(committing_state),
+                        );
                         info!(
                             message = "Committing checkpoint",
                             job_id = *self.job_id,
-                            epoch = self.epoch,
+                            epoch,
+                            duration,
+                            bytes = checkpointing.bytes()
                         );
                         for worker in self.workers.values_mut() {
                             worker
                                 .connect
                                 .commit(Request::new(CommitReq {
-                                    epoch: self.epoch,
+                                    epoch,
                                     committing_data: committing_data.clone(),
                                 }))
                                 .await?;
@@ -472,11 +781,10 @@ impl RunningJobModel {
                 CheckpointingOrCommittingState::Committing(committing) => {
                     Self::finish_committing(committing.checkpoint_id(), db).await?;
                     self.last_checkpoint = Instant::now();
-                    self.checkpoint_state = None;
                     info!(
                         message = "Finished committing checkpointing",
                         job_id = *self.job_id,
-                        epoch = self.epoch,
+                        epoch,
                     );
                     // trigger a DB backup now that we're done checkpointing
                     notify_db();
@@ -487,8 +795,11 @@ impl RunningJobModel {
     }
 
     pub fn cleanup_needed(&self) -> Option<u32> {
-        if self.epoch - self.min_epoch > CHECKPOINTS_TO_KEEP && self.epoch % COMPACT_EVERY == 0 {
-            Some(self.epoch - CHECKPOINTS_TO_KEEP)
+        let checkpoints_to_keep = config().pipeline.checkpoints_to_keep.max(1);
+        if self.completed_epoch.saturating_sub(self.min_epoch) > checkpoints_to_keep
+            && self.completed_epoch % COMPACT_EVERY == 0
+        {
+            Some(self.completed_epoch - checkpoints_to_keep)
         } else {
             None
         }
@@ -496,11 +807,12 @@ impl RunningJobModel {
 
     pub fn failed(&self) -> bool {
         for (worker, status) in &self.workers {
-            if status.heartbeat_timeout() {
+            if let Some(reason) = status.failure() {
                 error!(
-                    message = "worker failed to heartbeat",
+                    message = "worker failed",
                     job_id = *self.job_id,
-                    worker_id = worker.0
+                    worker_id = worker.0,
+                    reason,
                 );
                 return true;
             }
@@ -522,6 +834,55 @@ impl RunningJobModel {
         false
     }
 
+    fn restore_epoch(&self) -> Option<u32> {
+        (self.completed_epoch > 0).then_some(self.completed_epoch)
+    }
+
+    fn next_epoch(&self) -> u32 {
+        self.epoch + 1
+    }
+
+    /// If all of the job's failures are confined to a single failover region, and that region
+    /// can be restarted on its own, returns the index of the region
+    pub fn failed_region(&self) -> Option<usize> {
+        // a checkpoint that is committing can't be abandoned, so the whole job has to be restored
+        if self.failover_regions.len() < 2
+            || self
+                .checkpoints
+                .values()
+                .any(|c| matches!(c, CheckpointingOrCommittingState::Committing(_)))
+        {
+            return None;
+        }
+
+        let mut failed = HashSet::new();
+        for (worker, status) in &self.workers {
+            if status.failure().is_some() {
+                failed.insert(
+                    self.failover_regions
+                        .iter()
+                        .position(|r| r.workers.contains(worker))?,
+                );
+            }
+        }
+
+        for ((operator_id, _), status) in &self.tasks {
+            if matches!(status.state, TaskState::Failed(_)) {
+                failed.insert(
+                    self.failover_regions
+                        .iter()
+                        .position(|r| r.operators.contains(operator_id))?,
+                );
+            }
+        }
+
+        if failed.len() == 1 {
+            failed.into_iter().next()
+        } else {
+            None
+        }
+    }
+
     pub fn any_finished_sources(&self) -> bool {
         let source_tasks = self.program.sources();
 
@@ -537,11 +898,23 @@ impl RunningJobModel {
     }
 }
 
+// A savepoint that is being taken by this controller; first we wait for the checkpoint
+// at `epoch` to complete, then we copy it to the savepoint's URL
+struct SavepointProgress {
+    id: String,
+    url: String,
+    epoch: u32,
+    write_task: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
 pub struct JobController {
     db: DatabaseSource,
     config: JobConfig,
     model: RunningJobModel,
     cleanup_task: Option<JoinHandle<anyhow::Result<u32>>>,
+    savepoint: Option<SavepointProgress>,
+    last_savepoint: Option<String>,
+    autoscaler: Option<Autoscaler>,
 }
 
 impl std::fmt::Debug for JobController {
@@ -550,6 +923,7 @@ impl std::fmt::Debug for JobController {
             .field("config", &self.config)
             .field("model", &self.model)
             .field("cleaning", &self.cleanup_task.is_some())
+            .field("savepoint", &self.savepoint.as_ref().map(|s| &s.id))
             .finish()
     }
 }
@@ -557,6 +931,7 @@ impl std::fmt::Debug for JobController {
 pub enum ControllerProgress {
     Continue,
     Finishing,
+    RegionFailed(usize),
 }
 
 impl JobController {
@@ -575,8 +950,12 @@ impl JobController {
             model: RunningJobModel {
                 job_id: config.id.clone(),
                 state: JobState::Running,
-                checkpoint_state: commit_state.map(CheckpointingOrCommittingState::Committing),
+                checkpoints: commit_state
+                    .map(|c| (epoch, CheckpointingOrCommittingState::Committing(c)))
+                    .into_iter()
+                    .collect(),
                 epoch,
+                completed_epoch: epoch,
                 min_epoch,
                 // delay the initial checkpoint by a random amount so that on controller restart,
                 // checkpoint times are staggered across jobs
@@ -584,6 +963,9 @@ impl JobController {
                     + Duration::from_millis(
                         thread_rng().gen_range(0..config.checkpoint_interval.as_millis() as u64),
                     ),
+                last_checkpoint_started: Instant::now(),
+                consecutive_checkpoint_failures: 0,
+                failed_epochs: vec![],
                 workers: worker_connects
                     .into_iter()
                     .map(|(id, connect)| {
@@ -607,12 +989,14 @@ impl JobController {
                                 (node.operator_id.clone(), idx as u32),
                                 TaskStatus {
                                     state: TaskState::Running,
+                                    worker_id: None,
                                 },
                             )
                         })
                     })
                     .collect(),
                 operator_parallelism: program.tasks_per_operator(),
+                failover_regions: vec![],
                 metrics,
                 metric_update_task: None,
                 last_updated_metrics: Instant::now(),
@@ -620,6 +1004,9 @@ impl JobController {
             },
             config,
             cleanup_task: None,
+            savepoint: None,
+            last_savepoint: None,
+            autoscaler: Autoscaler::from_config(),
         }
     }
 
@@ -709,7 +1096,13 @@ impl JobController {
     pub async fn progress(&mut self) -> anyhow::Result<ControllerProgress> {
         // have any of our workers failed?
         if self.model.failed() {
-            bail!("worker failed");
+            if let Some(region) = self.model.failed_region() {
+                return Ok(ControllerProgress::RegionFailed(region));
+            }
+            match self.model.workers.values().find_map(|w| w.failure()) {
+                Some(reason) => bail!("worker failed: {}", reason),
+                None => bail!("task failed"),
+            }
         }
 
         // have any of our tasks finished?
@@ -753,31 +1146,137 @@ impl JobController {
             }
         }
 
-        if let Some(new_epoch) = self.model.cleanup_needed() {
-            if self.cleanup_task.is_none() && self.model.checkpoint_state.is_none() {
+        self.progress_savepoint().await?;
+
+        if let Some(mut new_epoch) = self.model.cleanup_needed() {
+            // the checkpoint for an in-progress savepoint must not be cleaned up until it
+            // has been copied
+            if let Some(savepoint) = &self.savepoint {
+                new_epoch = new_epoch.min(savepoint.epoch);
+            }
+
+            if self.cleanup_task.is_none() && !self.model.checkpointing() {
                 self.cleanup_task = Some(self.start_cleanup(new_epoch));
             }
         }
 
         // check on checkpointing
-        if self.model.checkpoint_state.is_some() {
+        if self.model.checkpointing() {
+            self.model.check_checkpoint_timeouts(&self.db).await?;
             self.model.finish_checkpoint_if_done(&self.db).await?;
-        } else if self.model.last_checkpoint.elapsed() > self.config.checkpoint_interval
+        }
+
+        // remove the data written by failed checkpoints, rather than waiting for it to fall
+        // out of the retained epochs
+        if self.cleanup_task.is_none() {
+            let epochs = self.model.take_cleanable_failed_epochs();
+            if !epochs.is_empty() {
+                self.cleanup_task = Some(self.start_failed_cleanup(epochs));
+            }
+        }
+
+        // or do we need to start checkpointing?
+        let max_concurrent = config().pipeline.max_concurrent_checkpoints.max(1) as usize;
+        if self
+            .model
+            .should_start_checkpoint(self.config.checkpoint_interval, max_concurrent)
             && self.cleanup_task.is_none()
         {
-            // or do we need to start checkpointing?
-            self.checkpoint(false).await?;
+            self.model
+                .start_checkpoint(&self.config.organization_id, &self.db, false)
+                .await?;
         }
 
         // update metrics
         if self.model.last_updated_metrics.elapsed() > job_metrics::COLLECTION_RATE {
             self.update_metrics().await;
             self.model.last_updated_metrics = Instant::now();
+            self.autoscale().await;
         }
 
         Ok(ControllerProgress::Continue)
     }
 
+    /// Runs the autoscaler, if enabled; a rescale is triggered by writing new parallelism
+    /// overrides for the job, which are picked up by the state machine like any other config
+    /// update
+    async fn autoscale(&mut self) {
+        let Some(autoscaler) = &mut self.autoscaler else {
+            return;
+        };
+
+        if !autoscaler.ready() {
+            return;
+        }
+
+        let Some(current) = self.model.operator_parallelism.values().max().copied() else {
+            return;
+        };
+
+        let metrics = &self.model.metrics;
+        let window = autoscaler.window();
+        let inputs = ScalingInputs {
+            backpressure: metrics.operator_backpressure(window).await,
+            busy: metrics.operator_busy(window).await,
+            lag_growth: metrics.source_lag_growth(window).await,
+        };
+
+        let Some(decision) = autoscaler.decide(current, &inputs) else {
+            return;
+        };
+
+        // don't try again until the cooldown has passed, even if we fail to apply the decision
+        autoscaler.record_decision();
+
+        let message = format!(
+            "Autoscaler rescaling pipeline from parallelism {} to {}",
+            decision.current, decision.target
+        );
+        info!(
+            message = message.as_str(),
+            job_id = *self.config.id,
+            reason = decision.reason.as_str()
+        );
+
+        let overrides = decision.apply(&self.model.operator_parallelism);
+
+        let result: anyhow::Result<()> = async {
+            let c = self.db.client().await?;
+            let (lease_holder, lease_term) = leader::lease_fence();
+            let updated = controller_queries::execute_update_parallelism_overrides(
+                &c,
+                &serde_json::to_value(&overrides)?,
+                &OffsetDateTime::now_utc(),
+                &*self.config.id,
+                &lease_holder,
+                &lease_term,
+            )
+            .await?;
+            leader::check_fenced(updated, "update parallelism overrides")?;
+
+            controller_queries::execute_create_job_event(
+                &c,
+                &generate_id(IdTypes::JobLogMessage),
+                &*self.config.id,
+                &LogLevel::info,
+                &message,
+                &decision.reason,
+            )
+            .await?;
+
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            error!(
+                message = "failed to apply autoscaler decision",
+                job_id = *self.config.id,
+                error = format!("{:?}", e)
+            );
+        }
+    }
+
     pub async fn stop_job(&mut self, stop_mode: StopMode) -> anyhow::Result<()> {
         for c in self.model.workers.values_mut() {
             c.connect
@@ -791,7 +1290,7 @@ impl JobController {
     }
 
     pub async fn checkpoint(&mut self, then_stop: bool) -> anyhow::Result<bool> {
-        if self.model.checkpoint_state.is_none() {
+        if !self.model.checkpointing() {
             self.model
                 .start_checkpoint(&self.config.organization_id, &self.db, then_stop)
                 .await?;
@@ -805,16 +1304,156 @@ impl JobController {
         self.model.all_tasks_finished()
     }
 
+    async fn progress_savepoint(&mut self) -> anyhow::Result<()> {
+        let Some(mut savepoint) = self.savepoint.take() else {
+            let Some(pending) = self.config.pending_savepoint.clone() else {
+                return Ok(());
+            };
+
+            // the config may not yet reflect that we've finished the last savepoint
+            if self.last_savepoint.as_ref() == Some(&pending.id)
+                || self.model.checkpointing()
+                || self.cleanup_task.is_some()
+            {
+                return Ok(());
+            }
+
+            self.checkpoint(false).await?;
+            let epoch = self.model.epoch;
+
+            info!(
+                message = "Starting savepoint",
+                job_id = *self.config.id,
+                savepoint_id = pending.id,
+                epoch
+            );
+
+            controller_queries::execute_update_savepoint(
+                &self.db.client().await?,
+                &DbSavepointState::inprogress,
+                &Some(epoch as i32),
+                &None,
+                &None,
+                &pending.id,
+            )
+            .await?;
+
+            self.savepoint = Some(SavepointProgress {
+                id: pending.id,
+                url: pending.url,
+                epoch,
+                write_task: None,
+            });
+            return Ok(());
+        };
+
+        match savepoint.write_task.take() {
+            Some(task) if task.is_finished() => {
+                match task.await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        error!(
+                            message = "failed to record savepoint result",
+                            job_id = *self.config.id,
+                            savepoint_id = savepoint.id,
+                            error = format!("{:?}", e)
+                        );
+                    }
+                    Err(e) => {
+                        error!(
+                            message = "savepoint panicked",
+                            job_id = *self.config.id,
+                            savepoint_id = savepoint.id,
+                            error = format!("{:?}", e)
+                        );
+                    }
+                }
+                self.last_savepoint = Some(savepoint.id);
+                return Ok(());
+            }
+            Some(task) => {
+                savepoint.write_task = Some(task);
+            }
+            None => {
+                // if the checkpoint failed, writing the savepoint will fail as well
+                if !self.model.checkpoints.contains_key(&savepoint.epoch) {
+                    savepoint.write_task = Some(self.start_savepoint_write(&savepoint));
+                }
+            }
+        }
+
+        self.savepoint = Some(savepoint);
+        Ok(())
+    }
+
+    fn start_savepoint_write(
+        &self,
+        savepoint: &SavepointProgress,
+    ) -> JoinHandle<anyhow::Result<()>> {
+        let job_id = self.config.id.clone();
+        let db = self.db.clone();
+        let id = savepoint.id.clone();
+        let url = savepoint.url.clone();
+        let epoch = savepoint.epoch;
+
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let (state, failure_message) =
+                match StateBackend::write_savepoint(&job_id, epoch, &url).await {
+                    Ok(()) => {
+                        info!(
+                            message = "Finished savepoint",
+                            job_id = *job_id,
+                            savepoint_id = id,
+                            epoch,
+                            duration = start.elapsed().as_secs_f32()
+                        );
+                        (DbSavepointState::ready, None)
+                    }
+                    Err(e) => {
+                        error!(
+                            message = "Failed to write savepoint",
+                            job_id = *job_id,
+                            savepoint_id = id,
+                            epoch,
+                            error = format!("{:?}", e)
+                        );
+                        (DbSavepointState::failed, Some(format!("{:?}", e)))
+                    }
+                };
+
+            controller_queries::execute_update_savepoint(
+                &db.client().await?,
+                &state,
+                &Some(epoch as i32),
+                &Some(OffsetDateTime::now_utc()),
+                &failure_message,
+                &id,
+            )
+            .await?;
+
+            Ok(())
+        })
+    }
+
     pub async fn checkpoint_finished(&mut self) -> anyhow::Result<bool> {
-        if self.model.checkpoint_state.is_some() {
+        if self.model.checkpointing() {
             self.model.finish_checkpoint_if_done(&self.db).await?;
         }
-        Ok(self.model.checkpoint_state.is_none())
+        Ok(!self.model.checkpointing())
     }
 
     pub async fn send_commit_messages(&mut self) -> anyhow::Result<()> {
-        let Some(CheckpointingOrCommittingState::Committing(committing)) =
-            &self.model.checkpoint_state
+        let Some((epoch, committing)) =
+            self.model
+                .checkpoints
+                .iter()
+                .find_map(|(epoch, state)| match state {
+                    CheckpointingOrCommittingState::Committing(committing) => {
+                        Some((*epoch, committing))
+                    }
+                    _ => None,
+                })
         else {
             bail!("should be committing")
         };
@@ -822,7 +1461,7 @@ impl JobController {
             worker
                 .connect
                 .commit(CommitReq {
-                    epoch: self.model.epoch,
+                    epoch,
                     committing_data: committing.committing_data(),
                 })
                 .await?;
@@ -864,6 +1503,150 @@ impl JobController {
         self.model.operator_parallelism.get(op).cloned()
     }
 
+    pub fn set_failover_regions(&mut self, regions: Vec<FailoverRegion>) {
+        self.model.failover_regions = regions;
+    }
+
+    /// Starts the next checkpoint after `epoch`, which may be later than the last completed one
+    /// when an adopted job's previous leader had started checkpoints that never completed
+    pub fn set_last_started_epoch(&mut self, epoch: u32) {
+        self.model.epoch = self.model.epoch.max(epoch);
+    }
+
+    /// Records which worker each task was assigned to
+    pub fn set_task_workers(&mut self, assignments: &[TaskAssignment]) {
+        for assignment in assignments {
+            if let Some(task) = self.model.tasks.get_mut(&(
+                assignment.operator_id.clone(),
+                assignment.operator_subtask as u32,
+            )) {
+                task.worker_id = Some(WorkerId(assignment.worker_id));
+            }
+        }
+    }
+
+    pub fn failover_region(&self, region: usize) -> &FailoverRegion {
+        &self.model.failover_regions[region]
+    }
+
+    /// The epoch that a restarted failover region should restore its state from
+    pub fn restore_epoch(&self) -> Option<u32> {
+        self.model.restore_epoch()
+    }
+
+    /// The epoch of the next checkpoint barrier, which may be several epochs past the one being
+    /// restored if checkpoints were started (and failed) since it completed
+    pub fn next_epoch(&self) -> u32 {
+        self.model.next_epoch()
+    }
+
+    /// Removes a failover region's workers from the job and tells them to shut down. In-progress
+    /// checkpoints can't complete without the region's tasks, so they are failed.
+    pub async fn stop_failover_region(&mut self, region: usize) -> anyhow::Result<()> {
+        let workers = std::mem::take(&mut self.model.failover_regions[region].workers);
+        self.model.failover_regions[region].restarts += 1;
+
+        for worker_id in workers {
+            let Some(mut worker) = self.model.workers.remove(&worker_id) else {
+                continue;
+            };
+
+            // the worker may already be gone; any that aren't are cleaned up with the job
+            let result = tokio::time::timeout(
+                Duration::from_secs(5),
+                worker.connect.job_finished(JobFinishedReq {}),
+            )
+            .await;
+            if !matches!(result, Ok(Ok(_))) {
+                warn!(
+                    message = "Failed to shut down worker in failed region",
+                    job_id = *self.config.id,
+                    worker_id = worker_id.0,
+                    region
+                );
+            }
+        }
+
+        let epochs: Vec<u32> = self.model.checkpoints.keys().copied().collect();
+        for epoch in epochs {
+            if let Some(CheckpointingOrCommittingState::Checkpointing(checkpoint)) =
+                self.model.checkpoints.remove(&epoch)
+            {
+                let (lease_holder, lease_term) = leader::lease_fence();
+                let updated = controller_queries::execute_fail_checkpoint(
+                    &self.db.client().await?,
+                    &OffsetDateTime::now_utc(),
+                    &"failover region restarted",
+                    &checkpoint.checkpoint_id(),
+                    &lease_holder,
+                    &lease_term,
+                )
+                .await?;
+                leader::check_fenced(updated, "fail checkpoint")?;
+                self.model.failed_epochs.push(epoch);
+            }
+        }
+        self.model.last_checkpoint = Instant::now();
+
+        Ok(())
+    }
+
+    /// Adds the new workers for a restarted failover region to the job, once all of the region's
+    /// tasks are running on them
+    pub fn restore_failover_region(
+        &mut self,
+        region: usize,
+        worker_connects: HashMap<WorkerId, WorkerGrpcClient<Channel>>,
+        assignments: &[TaskAssignment],
+    ) {
+        self.set_task_workers(assignments);
+
+        let failover_region = &mut self.model.failover_regions[region];
+
+        for ((operator_id, _), status) in self.model.tasks.iter_mut() {
+            if failover_region.operators.contains(operator_id) {
+                status.state = TaskState::Running;
+            }
+        }
+
+        for (id, connect) in worker_connects {
+            failover_region.workers.insert(id);
+            self.model.workers.insert(
+                id,
+                WorkerStatus {
+                    id,
+                    connect,
+                    last_heartbeat: Instant::now(),
+                    state: WorkerState::Running,
+                },
+            );
+        }
+    }
+
+    /// Removes the data written by the checkpoints for `epochs`, which have failed, other than
+    /// files that the latest completed checkpoint references. The min epoch is unchanged.
+    fn start_failed_cleanup(&mut self, epochs: Vec<u32>) -> JoinHandle<anyhow::Result<u32>> {
+        let min_epoch = self.model.min_epoch;
+        let completed_epoch = self.model.completed_epoch;
+        let job_id = self.config.id.clone();
+        let db = self.db.clone();
+
+        info!(
+            message = "Cleaning failed checkpoints",
+            job_id = *job_id,
+            epochs = format!("{:?}", epochs),
+            completed_epoch
+        );
+
+        tokio::spawn(async move {
+            let checkpoint =
+                StateBackend::load_checkpoint_metadata(&job_id, completed_epoch).await?;
+            leader::ensure_leader(&db).await?;
+            StateBackend::cleanup_failed_checkpoints(checkpoint, &epochs).await?;
+            Ok(min_epoch)
+        })
+    }
+
     fn start_cleanup(&mut self, new_min: u32) -> JoinHandle<anyhow::Result<u32>> {
         let min_epoch = self.model.min_epoch.max(1);
         let job_id = self.config.id.clone();
@@ -876,25 +1659,31 @@ impl JobController {
             new_min
         );
         let start = Instant::now();
-        let cur_epoch = self.model.epoch;
+        let cur_epoch = self.model.completed_epoch;
 
         tokio::spawn(async move {
             let checkpoint = StateBackend::load_checkpoint_metadata(&job_id, cur_epoch).await?;
 
+            let (lease_holder, lease_term) = leader::lease_fence();
             controller_queries::execute_mark_compacting(
                 &db.client().await?,
                 &*job_id,
                 &(min_epoch as i32),
                 &(new_min as i32),
+                &lease_holder,
+                &lease_term,
             )
             .await?;
 
+            leader::ensure_leader(&db).await?;
             StateBackend::cleanup_checkpoint(checkpoint, min_epoch, new_min).await?;
 
             controller_queries::execute_mark_checkpoints_compacted(
                 &db.client().await?,
                 &*job_id,
                 &(new_min as i32),
+                &lease_holder,
+                &lease_term,
             )
             .await?;
 
@@ -903,6 +1692,8 @@ impl JobController {
                     &db.client().await?,
                     &*job_id,
                     &(epoch_to_filter_before as i32),
+                    &lease_holder,
+                    &lease_term,
                 )
                 .await?;
             }
@@ -919,3 +1710,366 @@ impl JobController {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use arroyo_datastream::logical::{
+        LogicalEdge, LogicalGraph, LogicalNode, OperatorName, ProgramConfig,
+    };
+    use arroyo_rpc::df::ArroyoSchema;
+
+    fn node(operator_id: &str, parallelism: usize) -> LogicalNode {
+        LogicalNode {
+            operator_id: operator_id.to_string(),
+            description: operator_id.to_string(),
+            operator_name: OperatorName::ArrowValue,
+            operator_config: vec![],
+            parallelism,
+        }
+    }
+
+    fn program_with_input(edge_type: LogicalEdgeType) -> LogicalProgram {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("count", DataType::Int64, false),
+            Field::new("user_id", DataType::Utf8, false),
+            Field::new(
+                "_timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let mut graph = LogicalGraph::new();
+        let source = graph.add_node(node("source", 1));
+        let aggregate = graph.add_node(node("aggregate", 4));
+        graph.add_edge(
+            source,
+            aggregate,
+            LogicalEdge::new(edge_type, ArroyoSchema::new_keyed(schema, 2, vec![1]), None),
+        );
+        LogicalProgram::new(graph, ProgramConfig::default())
+    }
+
+    fn model() -> RunningJobModel {
+        model_for(program_with_input(LogicalEdgeType::Shuffle))
+    }
+
+    fn model_for(program: LogicalProgram) -> RunningJobModel {
+        let program = Arc::new(program);
+        RunningJobModel {
+            job_id: Arc::new("job".to_string()),
+            state: JobState::Running,
+            program: program.clone(),
+            checkpoints: BTreeMap::new(),
+            epoch: 0,
+            completed_epoch: 0,
+            min_epoch: 1,
+            last_checkpoint: Instant::now() - Duration::from_secs(60),
+            last_checkpoint_started: Instant::now() - Duration::from_secs(60),
+            consecutive_checkpoint_failures: 0,
+            failed_epochs: vec![],
+            workers: HashMap::new(),
+            tasks: HashMap::new(),
+            operator_parallelism: program.tasks_per_operator(),
+            failover_regions: vec![],
+            metrics: JobMetrics::new(program),
+            metric_update_task: None,
+            last_updated_metrics: Instant::now(),
+        }
+    }
+
+    fn start_checkpoint(model: &mut RunningJobModel) -> u32 {
+        model.epoch += 1;
+        let epoch = model.epoch;
+        model.checkpoints.insert(
+            epoch,
+            CheckpointingOrCommittingState::Checkpointing(CheckpointState::new(
+                model.job_id.clone(),
+                format!("checkpoint-{}", epoch),
+                epoch,
+                model.min_epoch,
+                model.operator_parallelism.clone(),
+            )),
+        );
+        model.last_checkpoint_started = Instant::now();
+        epoch
+    }
+
+    #[test]
+    fn test_checkpoint_concurrency_limit() {
+        let mut model = model();
+        let interval = Duration::from_secs(10);
+        assert!(model.should_start_checkpoint(interval, 2));
+
+        // the next checkpoint waits for the interval to pass since the last one started
+        start_checkpoint(&mut model);
+        assert!(!model.should_start_checkpoint(interval, 2));
+        assert!(model.should_start_checkpoint(Duration::ZERO, 2));
+
+        // and no more than the limit are in progress at once
+        start_checkpoint(&mut model);
+        assert!(!model.should_start_checkpoint(Duration::ZERO, 2));
+        assert!(model.should_start_checkpoint(Duration::ZERO, 3));
+
+        model.checkpoints.pop_first();
+        assert!(model.should_start_checkpoint(Duration::ZERO, 2));
+    }
+
+    #[test]
+    fn test_checkpoint_timeouts() {
+        let mut model = model();
+        let timeout = Duration::from_secs(60);
+        let first = start_checkpoint(&mut model);
+        let second = start_checkpoint(&mut model);
+
+        assert!(model
+            .timed_out_checkpoints(SystemTime::now(), timeout)
+            .is_empty());
+        assert_eq!(
+            model.timed_out_checkpoints(SystemTime::now() + timeout * 2, timeout),
+            vec![first, second]
+        );
+
+        // committing checkpoints can't be timed out
+        let Some(CheckpointingOrCommittingState::Checkpointing(checkpointing)) =
+            model.checkpoints.remove(&first)
+        else {
+            panic!("checkpoint should be in progress");
+        };
+        model.checkpoints.insert(
+            first,
+            CheckpointingOrCommittingState::Committing(checkpointing.committing_state()),
+        );
+        assert_eq!(
+            model.timed_out_checkpoints(SystemTime::now() + timeout * 2, timeout),
+            vec![second]
+        );
+    }
+
+    #[test]
+    fn test_tolerable_checkpoint_failures() {
+        let mut model = model();
+
+        assert!(!model.record_checkpoint_failure(1, 1));
+        // a failure delays the next checkpoint
+        assert!(!model.should_start_checkpoint(Duration::from_secs(10), 1));
+        assert!(model.record_checkpoint_failure(2, 1));
+
+        // a successful checkpoint resets the count
+        model.record_checkpoint_success(3);
+        assert!(!model.record_checkpoint_failure(4, 1));
+        assert!(!model.record_checkpoint_failure(5, 2));
+        assert!(model.record_checkpoint_failure(6, 2));
+
+        // with none tolerated, the first failure fails the job
+        model.record_checkpoint_success(7);
+        assert!(model.record_checkpoint_failure(8, 0));
+    }
+
+    #[test]
+    fn test_failed_epochs_cleaned_after_later_checkpoint() {
+        let mut model = model();
+        model.record_checkpoint_success(1);
+        model.record_checkpoint_failure(2, 10);
+        model.record_checkpoint_failure(3, 10);
+
+        // nothing can be cleaned up until a later checkpoint records which files it carried
+        // over from the failed epochs
+        assert!(model.take_cleanable_failed_epochs().is_empty());
+
+        model.record_checkpoint_success(4);
+        model.record_checkpoint_failure(5, 10);
+        assert_eq!(model.take_cleanable_failed_epochs(), vec![2, 3]);
+        assert!(model.take_cleanable_failed_epochs().is_empty());
+
+        model.record_checkpoint_success(6);
+        assert_eq!(model.take_cleanable_failed_epochs(), vec![5]);
+    }
+
+    /// A job with two independent pipelines, each running as its own failover region on its own
+    /// worker
+    fn two_region_model() -> RunningJobModel {
+        let schema = ArroyoSchema::from_fields(vec![]);
+        let mut graph = LogicalGraph::new();
+        for region in ["a", "b"] {
+            let source = graph.add_node(node(&format!("{region}_source"), 1));
+            let sink = graph.add_node(node(&format!("{region}_sink"), 2));
+            graph.add_edge(
+                source,
+                sink,
+                LogicalEdge::project_all(LogicalEdgeType::Shuffle, schema.clone()),
+            );
+        }
+        let mut model = model_for(LogicalProgram::new(graph, ProgramConfig::default()));
+
+        for (i, operators) in model.program.failover_regions().into_iter().enumerate() {
+            let worker_id = WorkerId(i as u64);
+            model.workers.insert(
+                worker_id,
+                WorkerStatus {
+                    id: worker_id,
+                    connect: WorkerGrpcClient::new(
+                        Channel::from_static("http://localhost:1").connect_lazy(),
+                    ),
+                    last_heartbeat: Instant::now(),
+                    state: WorkerState::Running,
+                },
+            );
+            for operator_id in &operators {
+                for subtask in 0..model.operator_parallelism[operator_id] {
+                    model.tasks.insert(
+                        (operator_id.clone(), subtask as u32),
+                        TaskStatus {
+                            state: TaskState::Running,
+                            worker_id: Some(worker_id),
+                        },
+                    );
+                }
+            }
+            model.failover_regions.push(FailoverRegion {
+                operators,
+                workers: HashSet::from([worker_id]),
+                restarts: 0,
+            });
+        }
+        model
+    }
+
+    fn fail_task(model: &mut RunningJobModel, operator_id: &str, subtask: u32) {
+        model
+            .tasks
+            .get_mut(&(operator_id.to_string(), subtask))
+            .unwrap()
+            .state = TaskState::Failed("failed".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_failed_region() {
+        let mut model = two_region_model();
+        assert!(!model.failed());
+        assert_eq!(model.failed_region(), None);
+
+        // any number of failures within one region only restart that region
+        fail_task(&mut model, "b_sink", 0);
+        fail_task(&mut model, "b_sink", 1);
+        assert!(model.failed());
+        assert_eq!(model.failed_region(), Some(1));
+
+        model.workers.get_mut(&WorkerId(1)).unwrap().last_heartbeat =
+            Instant::now() - *config().pipeline.worker_heartbeat_timeout * 2;
+        assert_eq!(model.failed_region(), Some(1));
+
+        // but not while a checkpoint is committing, as that can't be abandoned
+        let epoch = start_checkpoint(&mut model);
+        let Some(CheckpointingOrCommittingState::Checkpointing(checkpointing)) =
+            model.checkpoints.remove(&epoch)
+        else {
+            panic!("checkpoint should be in progress");
+        };
+        model.checkpoints.insert(
+            epoch,
+            CheckpointingOrCommittingState::Committing(checkpointing.committing_state()),
+        );
+        assert_eq!(model.failed_region(), None);
+    }
+
+    #[tokio::test]
+    async fn test_failures_in_several_regions() {
+        let mut model = two_region_model();
+        fail_task(&mut model, "a_source", 0);
+        fail_task(&mut model, "b_sink", 1);
+        assert!(model.failed());
+        assert_eq!(model.failed_region(), None);
+
+        // a failed worker counts against its region like a failed task
+        let mut model = two_region_model();
+        fail_task(&mut model, "a_sink", 0);
+        model.workers.get_mut(&WorkerId(1)).unwrap().state =
+            WorkerState::Failed("killed".to_string());
+        assert_eq!(model.failed_region(), None);
+
+        // failures in a job that isn't split into regions always restart the whole job
+        let mut model = two_region_model();
+        model.failover_regions.clear();
+        fail_task(&mut model, "a_sink", 0);
+        assert!(model.failed());
+        assert_eq!(model.failed_region(), None);
+    }
+
+    #[test]
+    fn test_restarted_region_epochs() {
+        let mut model = model();
+        assert_eq!(model.restore_epoch(), None);
+        assert_eq!(model.next_epoch(), 1);
+
+        // checkpoint 1 completes, 2 fails and 3 is in progress when the region fails; it's
+        // restored from 1, but the rest of the job has already seen the barriers for 2 and 3
+        let first = start_checkpoint(&mut model);
+        model.record_checkpoint_success(first);
+        let second = start_checkpoint(&mut model);
+        model.record_checkpoint_failure(second, 10);
+        start_checkpoint(&mut model);
+
+        assert_eq!(model.restore_epoch(), Some(1));
+        assert_eq!(model.next_epoch(), 4);
+    }
+
+    fn query(key: Option<&str>) -> QueryStateReq {
+        QueryStateReq {
+            job_id: "job".to_string(),
+            operator_id: "aggregate".to_string(),
+            table: "f".to_string(),
+            key: key.map(|key| key.to_string()),
+            limit: 10,
+        }
+    }
+
+    #[test]
+    fn test_state_query_subtask() {
+        let program = program_with_input(LogicalEdgeType::Shuffle);
+
+        // the operator's state tables are keyed by the same column, which is checked against the
+        // subtask's key range when the query is handled
+        let table_schema = Schema::new(vec![
+            Field::new("user_id", DataType::Utf8, false),
+            Field::new("count", DataType::Int64, false),
+        ]);
+
+        let mut subtasks = HashSet::new();
+        for i in 0..50 {
+            let key = format!("{{\"user_id\": \"user-{}\"}}", i);
+            let subtask = state_query_subtask(&program, &query(Some(&key))).unwrap();
+
+            let columns =
+                decode_key(&table_schema, &[0], &serde_json::from_str(&key).unwrap()).unwrap();
+            let hash = key_hash(&columns).unwrap();
+            assert_eq!(subtask as usize, server_for_hash(hash, 4));
+            subtasks.insert(subtask);
+        }
+        assert_eq!(subtasks.len(), 4);
+
+        // without a key, or with one that doesn't match the key columns, every subtask is queried
+        assert_eq!(state_query_subtask(&program, &query(None)), None);
+        assert_eq!(
+            state_query_subtask(&program, &query(Some("{\"count\": 5}"))),
+            None
+        );
+        assert_eq!(
+            state_query_subtask(&program, &query(Some("{\"user_id\": \"a\", \"count\": 5}"))),
+            None
+        );
+        assert_eq!(
+            state_query_subtask(&program, &query(Some("not json"))),
+            None
+        );
+
+        // and the same for operators whose input isn't keyed
+        let program = program_with_input(LogicalEdgeType::Forward);
+        assert_eq!(
+            state_query_subtask(&program, &query(Some("{\"user_id\": \"a\"}"))),
+            None
+        );
+    }
+}
@@ -0,0 +1,441 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use arroyo_rpc::config::{config, DatabaseType};
+use cornucopia_async::DatabaseSource;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::queries::controller_queries;
+
+const LEASE_NAME: &str = "controller";
+
+/// The lease held by this controller, once it has become the leader
+pub struct HeldLease {
+    pub holder: String,
+    pub term: i64,
+}
+
+// a controller only ever holds a single term, as it shuts down when it loses leadership
+static HELD_LEASE: OnceLock<HeldLease> = OnceLock::new();
+
+/// Returns the lease that this controller holds as the leader, which fences its writes to the
+/// database; without leader election there is none, and writes aren't fenced
+pub fn held_lease() -> Option<&'static HeldLease> {
+    HELD_LEASE.get()
+}
+
+/// The lease holder and term to pass to a fenced query
+pub fn lease_fence() -> (Option<&'static str>, Option<i64>) {
+    let lease = held_lease();
+    (
+        lease.map(|lease| lease.holder.as_str()),
+        lease.map(|lease| lease.term),
+    )
+}
+
+/// Fails if a fenced write to a row that should exist didn't apply, which means that we've lost
+/// leadership
+pub fn check_fenced(updated: u64, what: &str) -> anyhow::Result<()> {
+    if updated == 0 && held_lease().is_some() {
+        bail!(
+            "failed to {}; this controller is no longer the leader",
+            what
+        );
+    }
+    Ok(())
+}
+
+/// Checks that we still hold the lease, before changes outside of the database (like deleting
+/// checkpoint files) that can't be fenced by it. The lease could still be lost right after the
+/// check, but as the leader steps down before its lease expires (see [LeaderElection::maintain]),
+/// a new leader can't have taken over by then.
+pub async fn ensure_leader(db: &DatabaseSource) -> anyhow::Result<()> {
+    let Some(lease) = held_lease() else {
+        return Ok(());
+    };
+
+    let current = controller_queries::fetch_get_controller_lease(&db.client().await?, &LEASE_NAME)
+        .await?
+        .into_iter()
+        .next();
+
+    match current {
+        Some(current)
+            if current.holder == lease.holder
+                && current.term == lease.term
+                && current.expires_at > OffsetDateTime::now_utc() =>
+        {
+            Ok(())
+        }
+        _ => bail!(
+            "controller {} no longer holds its lease (term {})",
+            lease.holder,
+            lease.term
+        ),
+    }
+}
+
+/// Elects a single leader among the controllers that share a database, using a lease stored in
+/// the database. The leader renews its lease periodically; if it fails to do so before the lease
+/// expires, a standby acquires it and takes over.
+///
+/// Lease expiration is compared against the controllers' clocks, so the lease duration should be
+/// much longer than any expected clock skew between them.
+///
+/// Because a leader may not notice that it has lost its lease until after a standby has taken
+/// over, its writes to job statuses, checkpoints and job configs are fenced by the lease term
+/// (see [held_lease]): once the term has moved on, the old leader's writes no longer match any
+/// row. Checkpoint files are only deleted after checking that the lease is still held (see
+/// [ensure_leader]).
+///
+/// The new leader adopts pipelines that were running under the old one: their workers
+/// re-register once they see that the leader term has changed, and the job picks up from them
+/// rather than restarting (see `states::adopting`).
+///
+/// Leader election needs a Postgres database; with SQLite, which can only be used by a single
+/// controller, it is disabled and the controller always acts as the leader.
+#[derive(Clone)]
+pub struct LeaderElection {
+    db: DatabaseSource,
+    holder: String,
+    lease_duration: Duration,
+    renew_interval: Duration,
+    is_leader: Arc<AtomicBool>,
+}
+
+impl LeaderElection {
+    /// Returns an election if it's enabled in the config; otherwise, this controller is the only
+    /// one and always acts as the leader
+    pub fn from_config(db: DatabaseSource) -> Option<Self> {
+        let config = config();
+        let election = &config.controller.leader_election;
+        if !election.enabled {
+            return None;
+        }
+
+        if config.database.r#type == DatabaseType::Sqlite {
+            warn!("leader election is not supported with a SQLite database; running as the only controller");
+            return None;
+        }
+
+        let holder = format!(
+            "{}-{:08x}",
+            std::env::var("HOSTNAME").unwrap_or_else(|_| "controller".to_string()),
+            rand::random::<u32>()
+        );
+
+        Some(Self {
+            db,
+            holder,
+            lease_duration: *election.lease_duration,
+            renew_interval: (*election.renew_interval).min(*election.lease_duration / 2),
+            is_leader: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    /// A flag that's set while this controller holds the lease
+    pub fn is_leader(&self) -> Arc<AtomicBool> {
+        self.is_leader.clone()
+    }
+
+    /// Tries to acquire or renew the lease, returning the leader term if we hold it
+    async fn try_acquire(&self) -> anyhow::Result<Option<i64>> {
+        let now = OffsetDateTime::now_utc();
+        let c = self.db.client().await?;
+        Ok(controller_queries::fetch_acquire_controller_lease(
+            &c,
+            &LEASE_NAME,
+            &self.holder,
+            &(now + self.lease_duration),
+            &now,
+        )
+        .await?
+        .into_iter()
+        .next())
+    }
+
+    /// Waits until this controller becomes the leader, returning its term
+    pub async fn acquire(&self) -> anyhow::Result<i64> {
+        let mut current_leader = None;
+        loop {
+            match self.try_acquire().await {
+                Ok(Some(term)) => {
+                    info!(
+                        message = "acquired controller leadership",
+                        holder = self.holder,
+                        term
+                    );
+                    let _ = HELD_LEASE.set(HeldLease {
+                        holder: self.holder.clone(),
+                        term,
+                    });
+                    self.is_leader.store(true, Ordering::SeqCst);
+                    return Ok(term);
+                }
+                Ok(None) => {
+                    let leader = controller_queries::fetch_get_controller_lease(
+                        &self.db.client().await?,
+                        &LEASE_NAME,
+                    )
+                    .await?
+                    .into_iter()
+                    .next()
+                    .map(|l| l.holder);
+
+                    if leader != current_leader {
+                        info!(
+                            message = "waiting to acquire controller leadership",
+                            holder = self.holder,
+                            leader = leader.as_deref().unwrap_or("none")
+                        );
+                        current_leader = leader;
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        message = "failed to acquire controller lease",
+                        error = format!("{:?}", e)
+                    );
+                }
+            }
+
+            tokio::time::sleep(self.renew_interval).await;
+        }
+    }
+
+    /// Renews the lease while we hold it; returns an error once leadership has been lost, after
+    /// which this controller must stop managing pipelines
+    pub async fn maintain(&self, term: i64) -> anyhow::Result<()> {
+        let mut renewed_at = Instant::now();
+        loop {
+            tokio::time::sleep(self.renew_interval).await;
+
+            match self.try_acquire().await {
+                Ok(Some(t)) if t == term => {
+                    renewed_at = Instant::now();
+                }
+                Ok(_) => {
+                    self.is_leader.store(false, Ordering::SeqCst);
+                    bail!(
+                        "controller {} lost leadership (term {}) to another controller",
+                        self.holder,
+                        term
+                    );
+                }
+                Err(e) => {
+                    // we can't tell whether we still hold the lease; step down before it could
+                    // have expired, so that we never overlap with a new leader
+                    if renewed_at.elapsed() + self.renew_interval >= self.lease_duration {
+                        self.is_leader.store(false, Ordering::SeqCst);
+                        bail!(
+                            "controller {} failed to renew its lease (term {}): {:?}",
+                            self.holder,
+                            term,
+                            e
+                        );
+                    }
+
+                    warn!(
+                        message = "failed to renew controller lease",
+                        error = format!("{:?}", e),
+                        term
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn db() -> DatabaseSource {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        let migrations = refinery::load_sql_migrations("../arroyo-api/sqlite_migrations").unwrap();
+        refinery::Runner::new(&migrations).run(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO job_statuses (id, pub_id) VALUES ('job', 'job')",
+            [],
+        )
+        .unwrap();
+        DatabaseSource::Sqlite(Arc::new(Mutex::new(conn)))
+    }
+
+    fn election(db: &DatabaseSource, holder: &str, lease_duration: Duration) -> LeaderElection {
+        LeaderElection {
+            db: db.clone(),
+            holder: holder.to_string(),
+            lease_duration,
+            renew_interval: lease_duration / 4,
+            is_leader: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    async fn lease_holder(db: &DatabaseSource) -> Option<(String, i64)> {
+        controller_queries::fetch_get_controller_lease(&db.client().await.unwrap(), &LEASE_NAME)
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .map(|lease| (lease.holder, lease.term))
+    }
+
+    #[tokio::test]
+    async fn test_lease_acquire_and_renew() {
+        let db = db();
+        let a = election(&db, "a", Duration::from_secs(60));
+        let b = election(&db, "b", Duration::from_secs(60));
+
+        assert_eq!(a.try_acquire().await.unwrap(), Some(1));
+        assert_eq!(lease_holder(&db).await, Some(("a".to_string(), 1)));
+
+        // renewing the lease keeps the term, while no one else can take it until it expires
+        assert_eq!(a.try_acquire().await.unwrap(), Some(1));
+        assert_eq!(b.try_acquire().await.unwrap(), None);
+        assert_eq!(a.try_acquire().await.unwrap(), Some(1));
+        assert_eq!(lease_holder(&db).await, Some(("a".to_string(), 1)));
+    }
+
+    #[tokio::test]
+    async fn test_lease_takeover_after_expiry() {
+        let db = db();
+        let a = election(&db, "a", Duration::from_millis(200));
+        let b = election(&db, "b", Duration::from_secs(60));
+
+        let term = a.acquire().await.unwrap();
+        assert_eq!(term, 1);
+        assert!(a.is_leader().load(Ordering::SeqCst));
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        // the lease changing hands increments the term
+        assert_eq!(b.try_acquire().await.unwrap(), Some(2));
+        assert_eq!(lease_holder(&db).await, Some(("b".to_string(), 2)));
+
+        // and the old leader finds out that it has lost it the next time it renews
+        assert_eq!(a.try_acquire().await.unwrap(), None);
+        let result = tokio::time::timeout(Duration::from_secs(5), a.maintain(term))
+            .await
+            .unwrap();
+        assert!(result.is_err());
+        assert!(!a.is_leader().load(Ordering::SeqCst));
+
+        // a lease that comes back to a previous holder still gets a new term
+        let c = election(&db, "c", Duration::from_millis(200));
+        let b = election(&db, "b", Duration::from_millis(200));
+        assert_eq!(b.try_acquire().await.unwrap(), Some(2));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(c.try_acquire().await.unwrap(), Some(3));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(b.try_acquire().await.unwrap(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_status_updates_fenced_by_term() {
+        let db = db();
+        let a = election(&db, "a", Duration::from_millis(200));
+        let b = election(&db, "b", Duration::from_secs(60));
+        assert_eq!(a.try_acquire().await.unwrap(), Some(1));
+
+        let update = |state: &'static str, lease: Option<(&'static str, i64)>| {
+            let db = db.clone();
+            async move {
+                controller_queries::execute_update_job_status(
+                    &db.client().await.unwrap(),
+                    &state,
+                    &None,
+                    &None,
+                    &None,
+                    &None::<String>,
+                    &0,
+                    &None::<String>,
+                    &None::<String>,
+                    &1,
+                    &0,
+                    &None,
+                    &"job",
+                    &lease.map(|(holder, _)| holder),
+                    &lease.map(|(_, term)| term),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        assert_eq!(update("Running", Some(("a", 1))).await, 1);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(b.try_acquire().await.unwrap(), Some(2));
+
+        // the previous leader's writes no longer apply, while the new leader's do
+        assert_eq!(update("Failed", Some(("a", 1))).await, 0);
+        assert_eq!(update("Running", Some(("b", 2))).await, 1);
+
+        // without leader election, writes aren't fenced
+        assert_eq!(update("Stopping", None).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_writes_fenced_by_term() {
+        let db = db();
+        let a = election(&db, "a", Duration::from_millis(200));
+        let b = election(&db, "b", Duration::from_secs(60));
+        assert_eq!(a.try_acquire().await.unwrap(), Some(1));
+
+        let create = |id: &'static str, lease: (&'static str, i64)| {
+            let db = db.clone();
+            async move {
+                controller_queries::execute_create_checkpoint(
+                    &db.client().await.unwrap(),
+                    &id,
+                    &"org",
+                    &"job",
+                    &"parquet",
+                    &1,
+                    &1,
+                    &OffsetDateTime::now_utc(),
+                    &Some(lease.0),
+                    &Some(lease.1),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let commit = |id: &'static str, lease: (&'static str, i64)| {
+            let db = db.clone();
+            async move {
+                controller_queries::execute_commit_checkpoint(
+                    &db.client().await.unwrap(),
+                    &OffsetDateTime::now_utc(),
+                    &id,
+                    &Some(lease.0),
+                    &Some(lease.1),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        assert_eq!(create("c1", ("a", 1)).await, 1);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(b.try_acquire().await.unwrap(), Some(2));
+
+        // the previous leader can neither start new checkpoints nor complete its old ones
+        assert_eq!(create("c2", ("a", 1)).await, 0);
+        assert_eq!(commit("c1", ("a", 1)).await, 0);
+
+        assert_eq!(create("c2", ("b", 2)).await, 1);
+        assert_eq!(commit("c1", ("b", 2)).await, 1);
+    }
+}
@@ -3,15 +3,17 @@
 #![allow(clippy::type_complexity)]
 
 use anyhow::Result;
+use arroyo_rpc::api_types::pipelines::RestartStrategy;
 use arroyo_rpc::config;
 use arroyo_rpc::config::config;
 use arroyo_rpc::grpc::rpc::controller_grpc_server::{ControllerGrpc, ControllerGrpcServer};
 use arroyo_rpc::grpc::rpc::{
     GrpcOutputSubscription, HeartbeatNodeReq, HeartbeatNodeResp, HeartbeatReq, HeartbeatResp,
-    JobMetricsReq, JobMetricsResp, OutputData, RegisterNodeReq, RegisterNodeResp,
-    RegisterWorkerReq, RegisterWorkerResp, TaskCheckpointCompletedReq, TaskCheckpointCompletedResp,
-    TaskFailedReq, TaskFailedResp, TaskFinishedReq, TaskFinishedResp, TaskStartedReq,
-    TaskStartedResp, WorkerFinishedReq, WorkerFinishedResp,
+    JobMetricsReq, JobMetricsResp, OutputData, QueryStateReq, QueryStateResp, RegisterNodeReq,
+    RegisterNodeResp, RegisterWorkerReq, RegisterWorkerResp, TaskAssignment,
+    TaskCheckpointCompletedReq, TaskCheckpointCompletedResp, TaskFailedReq, TaskFailedResp,
+    TaskFinishedReq, TaskFinishedResp, TaskStartedReq, TaskStartedResp, WorkerFinishedReq,
+    WorkerFinishedResp,
 };
 use arroyo_rpc::grpc::rpc::{
     SinkDataReq, SinkDataResp, TaskCheckpointEventReq, TaskCheckpointEventResp, WorkerErrorReq,
@@ -21,27 +23,32 @@ use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_server_common::shutdown::ShutdownGuard;
 use arroyo_server_common::wrap_start;
 use arroyo_types::{from_micros, NodeId, WorkerId};
-use cornucopia_async::DatabaseSource;
+use cornucopia_async::{Database, DatabaseSource};
 use lazy_static::lazy_static;
 use prometheus::{register_gauge, Gauge};
 use states::{Created, State, StateMachine};
 use std::collections::{HashMap, HashSet};
 use std::env;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
 use time::OffsetDateTime;
 use tokio::net::TcpListener;
 use tokio::sync::mpsc::error::TrySendError;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{unbounded_channel, Sender, UnboundedReceiver};
+use tokio::sync::oneshot;
 use tokio::sync::RwLock;
 use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
 use tonic::codec::CompressionEncoding;
+use tonic::service::interceptor::InterceptedService;
 use tonic::{Request, Response, Status};
 use tracing::{debug, info, warn};
 
 //pub mod compiler;
+mod admission;
 pub mod job_controller;
+mod leader;
 pub mod schedulers;
 mod states;
 
@@ -49,13 +56,13 @@ const TTL_PIPELINE_CLEANUP_TIME: Duration = Duration::from_secs(60 * 60);
 
 include!(concat!(env!("OUT_DIR"), "/controller-sql.rs"));
 
+use crate::admission::AdmissionQueue;
 use crate::job_controller::job_metrics::JobMetrics;
-use crate::schedulers::{NodeScheduler, ProcessScheduler, Scheduler};
+use crate::leader::LeaderElection;
+use crate::schedulers::{NodeScheduler, ProcessScheduler, Scheduler, WorkerFailure};
 use types::public::LogLevel;
 use types::public::{RestartMode, StopMode};
 
-pub const CHECKPOINTS_TO_KEEP: u32 = 5;
-
 lazy_static! {
     static ref ACTIVE_PIPELINES: Gauge = register_gauge!(
         "arroyo_controller_active_pipelines",
@@ -74,7 +81,7 @@ lazy_static! {
     .unwrap();
 }
 
-#[derive(Eq, PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub struct JobConfig {
     id: Arc<String>,
     organization_id: String,
@@ -86,6 +93,20 @@ pub struct JobConfig {
     parallelism_overrides: HashMap<String, usize>,
     restart_nonce: i32,
     restart_mode: RestartMode,
+    restore_from: Option<String>,
+    state_mapping: HashMap<String, String>,
+    drop_incompatible_state: bool,
+    restore_epoch: Option<u32>,
+    restart_strategy: Option<RestartStrategy>,
+    node_selector: HashMap<String, String>,
+    priority: i32,
+    pending_savepoint: Option<PendingSavepoint>,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct PendingSavepoint {
+    id: String,
+    url: String,
 }
 
 #[derive(Clone, Debug)]
@@ -101,13 +122,138 @@ pub struct JobStatus {
     pipeline_path: Option<String>,
     wasm_path: Option<String>,
     restart_nonce: i32,
+    /// The delay (in micros) before the job is restarted after a failure, while it's recovering
+    restart_delay: Option<i64>,
 }
 
+// The generated queries each run on their own pooled connection, so the statements that need to
+// be applied together are run directly in a transaction; they're the update_job_status and
+// clear_restore_from queries, with positional parameters that both postgres and sqlite accept
+const UPDATE_STATUS_CLEARING_RESTORE: &str = "UPDATE job_statuses
+SET state = $1,
+    start_time = $2,
+    finish_time = $3,
+    tasks = $4,
+    failure_message = $5,
+    restarts = $6,
+    pipeline_path = $7,
+    wasm_path = $8,
+    run_id = $9,
+    restart_nonce = $10,
+    restart_delay_micros = $11
+WHERE id = $12
+    AND (EXISTS (
+        SELECT 1 FROM controller_leases
+        WHERE name = 'controller' AND holder = $13 AND term = $14
+    ) OR $14 IS NULL)";
+
+const CLEAR_RESTORE_FROM: &str = "UPDATE job_configs
+SET restore_from = NULL, state_mapping = NULL, drop_incompatible_state = FALSE
+WHERE id = $1";
+
 impl JobStatus {
     pub async fn update_db(&self, database: &DatabaseSource) -> Result<(), String> {
         let c = database.client().await.map_err(|e| format!("{:?}", e))?;
-        let res = queries::controller_queries::execute_update_job_status(
-            &c,
+        let res = self.write_status(&c).await?;
+
+        if res == 0 {
+            Err("Job status does not exist, or this controller is no longer the leader".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Updates the status and, in the same transaction, clears the savepoint that the job was
+    /// restored from, so that it will not be restored again once the job is running
+    pub async fn update_db_clearing_restore(
+        &self,
+        database: &DatabaseSource,
+    ) -> Result<(), String> {
+        let lease = leader::held_lease();
+        let lease_holder = lease.map(|lease| lease.holder.as_str());
+        let lease_term = lease.map(|lease| lease.term);
+
+        let updated = match database {
+            DatabaseSource::Postgres(pool) => {
+                let mut client = pool.get().await.map_err(|e| format!("{:?}", e))?;
+                let transaction = client.transaction().await.map_err(|e| format!("{:?}", e))?;
+                let updated = transaction
+                    .execute(
+                        UPDATE_STATUS_CLEARING_RESTORE,
+                        &[
+                            &self.state,
+                            &self.start_time,
+                            &self.finish_time,
+                            &self.tasks,
+                            &self.failure_message,
+                            &self.restarts,
+                            &self.pipeline_path,
+                            &self.wasm_path,
+                            &self.run_id,
+                            &self.restart_nonce,
+                            &self.restart_delay,
+                            &*self.id,
+                            &lease_holder,
+                            &lease_term,
+                        ],
+                    )
+                    .await
+                    .map_err(|e| format!("{:?}", e))?;
+                if updated > 0 {
+                    transaction
+                        .execute(CLEAR_RESTORE_FROM, &[&*self.id])
+                        .await
+                        .map_err(|e| format!("{:?}", e))?;
+                    transaction.commit().await.map_err(|e| format!("{:?}", e))?;
+                }
+                // otherwise the transaction is rolled back when it's dropped
+                updated
+            }
+            DatabaseSource::Sqlite(connection) => {
+                let mut connection = connection.lock().unwrap();
+                let transaction = connection.transaction().map_err(|e| format!("{:?}", e))?;
+                let updated = transaction
+                    .execute(
+                        UPDATE_STATUS_CLEARING_RESTORE,
+                        rusqlite::params![
+                            self.state,
+                            self.start_time,
+                            self.finish_time,
+                            self.tasks,
+                            self.failure_message,
+                            self.restarts,
+                            self.pipeline_path,
+                            self.wasm_path,
+                            self.run_id,
+                            self.restart_nonce,
+                            self.restart_delay,
+                            *self.id,
+                            lease_holder,
+                            lease_term,
+                        ],
+                    )
+                    .map_err(|e| format!("{:?}", e))?;
+                if updated > 0 {
+                    transaction
+                        .execute(CLEAR_RESTORE_FROM, [&*self.id])
+                        .map_err(|e| format!("{:?}", e))?;
+                    transaction.commit().map_err(|e| format!("{:?}", e))?;
+                }
+                updated as u64
+            }
+        };
+
+        if updated == 0 {
+            Err("Job status does not exist, or this controller is no longer the leader".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn write_status(&self, c: &Database<'_>) -> Result<u64, String> {
+        let lease = leader::held_lease();
+        queries::controller_queries::execute_update_job_status(
+            c,
             &self.state,
             &self.start_time,
             &self.finish_time,
@@ -118,16 +264,13 @@ impl JobStatus {
             &self.wasm_path,
             &self.run_id,
             &self.restart_nonce,
+            &self.restart_delay,
             &*self.id,
+            &lease.map(|lease| lease.holder.as_str()),
+            &lease.map(|lease| lease.term),
         )
         .await
-        .map_err(|e| format!("{:?}", e))?;
-
-        if res == 0 {
-            Err("Job status does not exist".to_string())
-        } else {
-            Ok(())
-        }
+        .map_err(|e| format!("{:?}", e))
     }
 }
 
@@ -162,6 +305,14 @@ pub enum RunningMessage {
     WorkerFinished {
         worker_id: WorkerId,
     },
+    WorkerFailed {
+        worker_id: WorkerId,
+        reason: String,
+    },
+    QueryState {
+        req: QueryStateReq,
+        tx: oneshot::Sender<Result<QueryStateResp, Status>>,
+    },
 }
 
 #[derive(Debug)]
@@ -173,6 +324,9 @@ pub enum JobMessage {
         rpc_address: String,
         data_address: String,
         slots: usize,
+        run_id: i64,
+        /// The tasks that the worker is already running, if it was started by a previous leader
+        tasks: Vec<TaskAssignment>,
     },
     TaskStarted {
         worker_id: WorkerId,
@@ -187,6 +341,7 @@ pub struct ControllerServer {
     job_state: Arc<tokio::sync::Mutex<HashMap<String, StateMachine>>>,
     data_txs: Arc<tokio::sync::Mutex<HashMap<String, Vec<Sender<Result<OutputData, Status>>>>>>,
     scheduler: Arc<dyn Scheduler>,
+    admission: Arc<AdmissionQueue>,
     metrics: Arc<RwLock<HashMap<Arc<String>, JobMetrics>>>,
     db: DatabaseSource,
 }
@@ -213,6 +368,8 @@ impl ControllerGrpc for ControllerServer {
                 rpc_address: req.rpc_address,
                 data_address: req.data_address,
                 slots: req.slots as usize,
+                run_id: req.run_id as i64,
+                tasks: req.tasks,
             },
         )
         .await?;
@@ -235,7 +392,10 @@ impl ControllerGrpc for ControllerServer {
         )
         .await?;
 
-        return Ok(Response::new(HeartbeatResp {}));
+        // workers re-register when they see a new term, so that the new leader can adopt them
+        return Ok(Response::new(HeartbeatResp {
+            leader_term: leader::held_lease().map(|lease| lease.term).unwrap_or(0),
+        }));
     }
 
     async fn task_started(
@@ -461,10 +621,30 @@ impl ControllerGrpc for ControllerServer {
             metrics: serde_json::to_string(&metrics.get_groups().await).unwrap(),
         }))
     }
+
+    async fn query_state(
+        &self,
+        request: Request<QueryStateReq>,
+    ) -> Result<Response<QueryStateResp>, Status> {
+        let req = request.into_inner();
+        let job_id = req.job_id.clone();
+
+        let (tx, rx) = oneshot::channel();
+        self.send_to_job_queue(
+            &job_id,
+            JobMessage::RunningMessage(RunningMessage::QueryState { req, tx }),
+        )
+        .await?;
+
+        rx.await
+            .map_err(|_| Status::failed_precondition("Job is not running"))?
+            .map(Response::new)
+    }
 }
 
 impl ControllerServer {
     pub async fn new(database: DatabaseSource) -> Self {
+        let (failures_tx, failures_rx) = unbounded_channel();
         let scheduler: Arc<dyn Scheduler> = match &config().controller.scheduler {
             config::Scheduler::Node => {
                 info!("Using node scheduler");
@@ -480,17 +660,39 @@ impl ControllerServer {
             }
             config::Scheduler::Process => {
                 info!("Using process scheduler");
-                Arc::new(ProcessScheduler::new())
+                Arc::new(ProcessScheduler::new(failures_tx))
             }
         };
 
-        Self {
+        let server = Self {
             scheduler,
+            admission: Default::default(),
             data_txs: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
             job_state: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
             db: database,
             metrics: Default::default(),
-        }
+        };
+
+        server.forward_worker_failures(failures_rx);
+        server
+    }
+
+    /// Passes failures detected by the scheduler on to the failed workers' jobs
+    fn forward_worker_failures(&self, mut rx: UnboundedReceiver<WorkerFailure>) {
+        let server = self.clone();
+        tokio::spawn(async move {
+            while let Some(failure) = rx.recv().await {
+                let _ = server
+                    .send_to_job_queue(
+                        &failure.job_id,
+                        JobMessage::RunningMessage(RunningMessage::WorkerFailed {
+                            worker_id: failure.worker_id,
+                            reason: failure.reason,
+                        }),
+                    )
+                    .await;
+            }
+        });
     }
 
     async fn send_to_job_queue(&self, job_id: &str, msg: JobMessage) -> Result<(), Status> {
@@ -518,6 +720,7 @@ impl ControllerServer {
         let db = self.db.clone();
         let jobs = Arc::clone(&self.job_state);
         let scheduler = Arc::clone(&self.scheduler);
+        let admission = Arc::clone(&self.admission);
         let metrics = Arc::clone(&self.metrics);
 
         let token = guard.token();
@@ -550,6 +753,25 @@ impl ControllerServer {
                             .collect(),
                         restart_nonce: p.config_restart_nonce,
                         restart_mode: p.restart_mode,
+                        restore_from: p.restore_from,
+                        state_mapping: p
+                            .state_mapping
+                            .and_then(|m| serde_json::from_value(m).ok())
+                            .unwrap_or_default(),
+                        drop_incompatible_state: p.drop_incompatible_state,
+                        restore_epoch: p.restore_epoch.map(|e| e as u32),
+                        restart_strategy: p
+                            .restart_strategy
+                            .and_then(|s| serde_json::from_value(s).ok()),
+                        node_selector: p
+                            .node_selector
+                            .and_then(|s| serde_json::from_value(s).ok())
+                            .unwrap_or_default(),
+                        priority: p.priority,
+                        pending_savepoint: p
+                            .savepoint_id
+                            .zip(p.savepoint_url)
+                            .map(|(id, url)| PendingSavepoint { id, url }),
                     };
 
                     let mut jobs = jobs.lock().await;
@@ -566,6 +788,7 @@ impl ControllerServer {
                         pipeline_path: p.pipeline_path,
                         wasm_path: p.wasm_path,
                         restart_nonce: p.status_restart_nonce,
+                        restart_delay: p.restart_delay_micros,
                     };
 
                     if let Some(sm) = jobs.get_mut(&*id) {
@@ -578,6 +801,7 @@ impl ControllerServer {
                                 status,
                                 db.clone(),
                                 scheduler.clone(),
+                                admission.clone(),
                                 guard.clone_temporary(),
                                 metrics.clone(),
                             )
@@ -621,17 +845,48 @@ impl ControllerServer {
 
         info!("Starting arroyo-controller on {}", local_addr);
 
-        self.start_updater(guard.child("updater"));
+        // with leader election, pipelines are only run once we become the leader; if we later
+        // lose leadership the election task fails, which shuts down the controller
+        let election = LeaderElection::from_config(self.db.clone());
+        let is_leader = match election {
+            Some(election) => {
+                let is_leader = election.is_leader();
+                let updater_guard = guard.child("updater");
+                let this = self.clone();
+                guard.child("leader-election").into_spawn_task(async move {
+                    let term = election.acquire().await?;
+                    this.start_updater(updater_guard);
+                    election.maintain(term).await
+                });
+                is_leader
+            }
+            None => {
+                self.start_updater(guard.child("updater"));
+                Arc::new(AtomicBool::new(true))
+            }
+        };
+
+        // standby controllers reject requests, so that nodes and workers retry until they reach
+        // the leader
+        let leader_check = move |req: Request<()>| {
+            if is_leader.load(Ordering::SeqCst) {
+                Ok(req)
+            } else {
+                Err(Status::unavailable("this controller is not the leader"))
+            }
+        };
+
         guard.into_spawn_task(wrap_start(
             "controller",
             local_addr,
             arroyo_server_common::grpc_server()
                 .accept_http1(true)
-                .add_service(
+                .add_service(InterceptedService::new(
                     ControllerGrpcServer::new(self.clone())
                         .send_compressed(CompressionEncoding::Zstd)
                         .accept_compressed(CompressionEncoding::Zstd),
-                )
+                    leader_check,
+                ))
                 .add_service(reflection)
                 .serve_with_incoming(TcpListenerStream::new(listener)),
         ));
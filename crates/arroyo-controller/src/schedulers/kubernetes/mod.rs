@@ -1,17 +1,19 @@
-mod quantities;
+pub(crate) mod quantities;
 
-use crate::schedulers::kubernetes::quantities::QuantityParser;
+use crate::schedulers::kubernetes::quantities::{ParsedQuantity, QuantityParser};
 use crate::schedulers::{Scheduler, SchedulerError, StartPipelineReq};
 use anyhow::bail;
 use arroyo_rpc::config::{config, KubernetesSchedulerConfig, ResourceMode};
 use arroyo_rpc::grpc::rpc::{HeartbeatNodeReq, RegisterNodeReq, WorkerFinishedReq};
 use arroyo_types::{WorkerId, JOB_ID_ENV, RUN_ID_ENV};
 use async_trait::async_trait;
-use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::api::core::v1::{Node, Pod, ResourceRequirements};
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use kube::api::{DeleteParams, ListParams};
 use kube::{Api, Client};
 use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Once;
 use std::time::Duration;
 use tonic::Status;
 use tracing::{info, warn};
@@ -21,6 +23,41 @@ const JOB_ID_LABEL: &str = "job_id";
 const RUN_ID_LABEL: &str = "run_id";
 const JOB_NAME_LABEL: &str = "job_name";
 
+static CAPACITY_WARNING: Once = Once::new();
+
+// The cpu (in millicores) and memory (in bytes) in a set of resources, each taken from the first
+// of `resources` that has it
+fn cpu_and_memory(resources: &[Option<&BTreeMap<String, Quantity>>]) -> (i128, i128) {
+    let get = |name: &str, parse: fn(&Quantity) -> anyhow::Result<ParsedQuantity>| {
+        resources
+            .iter()
+            .flatten()
+            .find_map(|r| r.get(name))
+            .and_then(|q| parse(q).ok())
+            .map(|q| q.value())
+            .unwrap_or(0)
+    };
+
+    (
+        get("cpu", Quantity::parse_cpu),
+        get("memory", Quantity::parse_memory),
+    )
+}
+
+// as in Kubernetes, a resource's limit is used as its request if it doesn't have one
+fn requested(resources: &ResourceRequirements) -> (i128, i128) {
+    cpu_and_memory(&[resources.requests.as_ref(), resources.limits.as_ref()])
+}
+
+fn pod_requested(pod: &Pod) -> (i128, i128) {
+    pod.spec
+        .iter()
+        .flat_map(|s| &s.containers)
+        .filter_map(|c| c.resources.as_ref())
+        .map(requested)
+        .fold((0, 0), |(cpu, mem), (c, m)| (cpu + c, mem + m))
+}
+
 pub struct KubernetesScheduler {
     client: Option<Client>,
     config: KubernetesSchedulerConfig,
@@ -38,6 +75,87 @@ impl KubernetesScheduler {
         Self { client, config }
     }
 
+    /// Estimates the number of slots that a job's workers could be given on the nodes matching its
+    /// node selector, from the nodes' allocatable resources less those requested by the pods that
+    /// are already running or waiting to be placed. Returns None if the workers don't request any
+    /// resources, in which case there's nothing to estimate from.
+    fn estimate_free_slots(
+        &self,
+        nodes: &[Node],
+        pods: &[Pod],
+        job_id: &str,
+        node_selector: &HashMap<String, String>,
+    ) -> Option<usize> {
+        // the resources of a unit of scheduling, and the number of slots it provides
+        let unit = requested(&self.config.worker.resources);
+        let unit_slots = match self.config.resource_mode {
+            ResourceMode::PerSlot => 1,
+            ResourceMode::PerPod => self.config.worker.task_slots as i128,
+        };
+        if unit == (0, 0) {
+            return None;
+        }
+
+        // the number of units that fit in the given resources, rounding down if `free` and up
+        // otherwise
+        let units = |(cpu, mem): (i128, i128), free: bool| {
+            [(cpu, unit.0), (mem, unit.1)]
+                .into_iter()
+                .filter(|(_, u)| *u > 0)
+                .map(|(v, u)| if free { v.max(0) / u } else { (v + u - 1) / u })
+                .reduce(|a, b| if free { a.min(b) } else { a.max(b) })
+                .unwrap()
+        };
+
+        let mut free: HashMap<&str, (i128, i128)> = nodes
+            .iter()
+            .filter(|n| {
+                !n.spec
+                    .as_ref()
+                    .and_then(|s| s.unschedulable)
+                    .unwrap_or(false)
+            })
+            .filter(|n| {
+                let labels = n.metadata.labels.as_ref();
+                node_selector
+                    .iter()
+                    .all(|(k, v)| labels.and_then(|l| l.get(k)) == Some(v))
+            })
+            .filter_map(|n| {
+                let allocatable = n.status.as_ref()?.allocatable.as_ref();
+                Some((n.metadata.name.as_deref()?, cpu_and_memory(&[allocatable])))
+            })
+            .collect();
+
+        let mut pending = 0;
+        for pod in pods {
+            let labels = pod.metadata.labels.as_ref();
+            let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref());
+            // the job's own workers are stopped before it's scheduled
+            if labels.and_then(|l| l.get(JOB_ID_LABEL)).map(String::as_str) == Some(job_id)
+                || matches!(phase, Some("Succeeded" | "Failed"))
+            {
+                continue;
+            }
+
+            let (cpu, mem) = pod_requested(pod);
+            match pod.spec.as_ref().and_then(|s| s.node_name.as_deref()) {
+                Some(node) => {
+                    if let Some(free) = free.get_mut(node) {
+                        free.0 -= cpu;
+                        free.1 -= mem;
+                    }
+                }
+                None => {
+                    pending += units((cpu, mem), false);
+                }
+            }
+        }
+
+        let total: i128 = free.into_values().map(|r| units(r, true)).sum();
+        Some(((total - pending).max(0) * unit_slots) as usize)
+    }
+
     fn make_pod(&self, req: &StartPipelineReq, number: usize, slots: usize) -> Pod {
         let c = &self.config;
 
@@ -129,11 +247,22 @@ impl KubernetesScheduler {
             .split(|w: char| w.is_whitespace())
             .collect();
 
+        let mut name = format!(
+            "{}-{}-{}",
+            c.worker.name(),
+            req.job_id.to_ascii_lowercase().replace('_', "-"),
+            req.run_id
+        );
+        if let Some(group) = &req.worker_group {
+            name.push_str(&format!("-{}", group));
+        }
+        name.push_str(&format!("-{}", number));
+
         serde_json::from_value(json!({
             "apiVersion": "v1",
             "kind": "Pod",
             "metadata": {
-                "name": format!("{}-{}-{}-{}", c.worker.name(), req.job_id.to_ascii_lowercase().replace('_', "-"), req.run_id, number),
+                "name": name,
                 "namespace": c.namespace,
                 "labels": labels,
                 "annotations": c.worker.annotations,
@@ -165,8 +294,10 @@ impl KubernetesScheduler {
                     }
                 ],
                 "serviceAccountName": c.worker.service_account_name,
+                "nodeSelector": req.node_selector,
             }
-        })).unwrap()
+        }))
+        .unwrap()
     }
 }
 
@@ -215,6 +346,40 @@ impl Scheduler for KubernetesScheduler {
         Ok(())
     }
 
+    async fn free_slots(
+        &self,
+        job_id: &str,
+        node_selector: &HashMap<String, String>,
+    ) -> Option<usize> {
+        let client = self.client.as_ref()?;
+
+        let result = async {
+            let nodes = Api::<Node>::all(client.clone())
+                .list(&ListParams::default())
+                .await?;
+            let pods = Api::<Pod>::all(client.clone())
+                .list(&ListParams::default().fields("status.phase!=Succeeded,status.phase!=Failed"))
+                .await?;
+            anyhow::Ok((nodes.items, pods.items))
+        }
+        .await;
+
+        match result {
+            Ok((nodes, pods)) => self.estimate_free_slots(&nodes, &pods, job_id, node_selector),
+            Err(e) => {
+                CAPACITY_WARNING.call_once(|| {
+                    warn!(
+                        message =
+                            "failed to list nodes and pods to estimate the cluster's capacity; \
+                            jobs will not wait for free slots",
+                        error = format!("{:?}", e)
+                    );
+                });
+                None
+            }
+        }
+    }
+
     async fn register_node(&self, _req: RegisterNodeReq) {
         // n/a
     }
@@ -306,6 +471,9 @@ mod test {
 
     use crate::schedulers::kubernetes::KubernetesScheduler;
     use crate::schedulers::StartPipelineReq;
+    use arroyo_rpc::config::ResourceMode;
+    use k8s_openapi::api::core::v1::{Node, Pod};
+    use std::collections::HashMap;
 
     #[test]
     fn test_resource_creation() {
@@ -318,6 +486,8 @@ mod test {
             run_id: 1,
             slots: 8,
             env_vars: Default::default(),
+            worker_group: None,
+            node_selector: Default::default(),
         };
 
         let mut config = config().kubernetes_scheduler.clone();
@@ -354,4 +524,79 @@ mod test {
             // test that we don't panic when creating the replicaset
             .make_pod(&req, 3, 4);
     }
+
+    fn node(name: &str, zone: &str, cpu: &str, memory: &str) -> Node {
+        serde_json::from_value(json!({
+            "metadata": { "name": name, "labels": { "zone": zone } },
+            "status": { "allocatable": { "cpu": cpu, "memory": memory } }
+        }))
+        .unwrap()
+    }
+
+    fn pod(job_id: &str, node: Option<&str>, cpu: &str, memory: &str) -> Pod {
+        serde_json::from_value(json!({
+            "metadata": { "labels": { "job_id": job_id } },
+            "spec": {
+                "nodeName": node,
+                "containers": [{
+                    "name": "worker",
+                    "resources": { "requests": { "cpu": cpu, "memory": memory } }
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_estimate_free_slots() {
+        let mut config = config().kubernetes_scheduler.clone();
+        config.resource_mode = ResourceMode::PerSlot;
+        config.worker.resources = serde_json::from_value(json!({
+            "requests": { "cpu": "1", "memory": "1Gi" }
+        }))
+        .unwrap();
+        let scheduler = KubernetesScheduler::with_config(None, config.clone());
+
+        let nodes = [
+            node("a", "us-east-1a", "4", "8Gi"),
+            node("b", "us-east-1b", "8", "2Gi"),
+        ];
+        let pods = [
+            pod("other", Some("a"), "1500m", "1Gi"),
+            // waiting to be placed
+            pod("other", None, "1", "1Gi"),
+            // the job's own workers will be stopped
+            pod("job", Some("b"), "8", "2Gi"),
+        ];
+
+        // 2 slots' worth of cpu are free on a, and 2 slots' worth of memory on b, less the
+        // pending pod
+        assert_eq!(
+            scheduler.estimate_free_slots(&nodes, &pods, "job", &HashMap::new()),
+            Some(3)
+        );
+
+        let selector = [("zone".to_string(), "us-east-1b".to_string())].into();
+        assert_eq!(
+            scheduler.estimate_free_slots(&nodes, &pods, "job", &selector),
+            Some(1)
+        );
+
+        // with per-pod resources, each pod provides all of its task slots
+        config.resource_mode = ResourceMode::PerPod;
+        config.worker.task_slots = 4;
+        let scheduler = KubernetesScheduler::with_config(None, config.clone());
+        assert_eq!(
+            scheduler.estimate_free_slots(&nodes, &pods, "job", &HashMap::new()),
+            Some(12)
+        );
+
+        // there's nothing to estimate from if the workers don't request any resources
+        config.worker.resources = Default::default();
+        let scheduler = KubernetesScheduler::with_config(None, config);
+        assert_eq!(
+            scheduler.estimate_free_slots(&nodes, &pods, "job", &HashMap::new()),
+            None
+        );
+    }
 }
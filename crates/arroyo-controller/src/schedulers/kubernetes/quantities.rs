@@ -94,7 +94,6 @@ pub enum ParsedQuantity {
 }
 
 impl ParsedQuantity {
-    #[allow(unused)]
     pub fn value(&self) -> i128 {
         match self {
             ParsedQuantity::Cpu(i) => *i as i128,
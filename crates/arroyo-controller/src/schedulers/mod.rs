@@ -1,6 +1,6 @@
 use anyhow::bail;
 use arroyo_datastream::logical::LogicalProgram;
-use arroyo_rpc::config::config;
+use arroyo_rpc::config::{config, NodePlacement};
 use arroyo_rpc::grpc::rpc::node_grpc_client::NodeGrpcClient;
 use arroyo_rpc::grpc::rpc::{
     HeartbeatNodeReq, RegisterNodeReq, StartWorkerReq, StopWorkerReq, StopWorkerStatus,
@@ -9,20 +9,25 @@ use arroyo_rpc::grpc::rpc::{
 use arroyo_types::{NodeId, WorkerId, JOB_ID_ENV, RUN_ID_ENV};
 use lazy_static::lazy_static;
 use prometheus::{register_gauge, Gauge};
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::env::current_exe;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Once};
 use std::time::{Duration, Instant};
 use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{oneshot, Mutex};
-use tonic::{Request, Status};
+use tonic::{Code, Request, Status};
 use tracing::{info, warn};
 
+use self::resources::{WorkerCgroup, WorkerLimits};
+
 pub mod embedded;
 pub mod kubernetes;
+mod resources;
 
 lazy_static! {
     static ref FREE_SLOTS: Gauge =
@@ -60,6 +65,28 @@ pub trait Scheduler: Send + Sync {
         job_id: &str,
         run_id: Option<i64>,
     ) -> anyhow::Result<Vec<WorkerId>>;
+
+    /// Records a worker that was started by a previous leader and has re-registered with this
+    /// one, so that it's accounted for and can be stopped like the workers we started ourselves
+    async fn adopt_worker(
+        &self,
+        _job_id: Arc<String>,
+        _run_id: i64,
+        _worker_id: WorkerId,
+        _node_id: NodeId,
+        _slots: usize,
+    ) {
+    }
+
+    /// Returns the number of slots that are free for a job's workers, or None if the scheduler
+    /// doesn't track its capacity, in which case jobs never wait for free slots
+    async fn free_slots(
+        &self,
+        _job_id: &str,
+        _node_selector: &HashMap<String, String>,
+    ) -> Option<usize> {
+        None
+    }
 }
 
 pub struct ProcessWorker {
@@ -68,21 +95,34 @@ pub struct ProcessWorker {
     shutdown_tx: oneshot::Sender<()>,
 }
 
+/// Reported by a scheduler when one of its workers exits unexpectedly, so that the job can be
+/// failed with the reason rather than waiting for the worker's heartbeats to time out
+#[derive(Debug)]
+pub struct WorkerFailure {
+    pub job_id: Arc<String>,
+    pub worker_id: WorkerId,
+    pub reason: String,
+}
+
 /// This Scheduler starts new processes to run the worker nodes
 pub struct ProcessScheduler {
     workers: Arc<Mutex<HashMap<WorkerId, ProcessWorker>>>,
     worker_counter: AtomicU64,
+    failures: UnboundedSender<WorkerFailure>,
 }
 
 impl ProcessScheduler {
-    pub fn new() -> Self {
+    pub fn new(failures: UnboundedSender<WorkerFailure>) -> Self {
         Self {
             workers: Arc::new(Mutex::new(HashMap::new())),
             worker_counter: AtomicU64::new(100),
+            failures,
         }
     }
 }
 
+static CGROUP_WARNING: Once = Once::new();
+
 pub struct StartPipelineReq {
     pub name: String,
     pub program: LogicalProgram,
@@ -92,6 +132,11 @@ pub struct StartPipelineReq {
     pub run_id: i64,
     pub slots: usize,
     pub env_vars: HashMap<String, String>,
+    /// Distinguishes sets of workers that are started separately for the same run, such as the
+    /// workers for each failover region of a job
+    pub worker_group: Option<String>,
+    /// Labels that a node must have for the job's workers to be placed on it
+    pub node_selector: HashMap<String, String>,
 }
 
 #[async_trait::async_trait]
@@ -138,6 +183,26 @@ impl Scheduler for ProcessScheduler {
             let job_id = start_pipeline_req.job_id.clone();
             let workers = self.workers.clone();
             let env_map = start_pipeline_req.env_vars.clone();
+            let failures = self.failures.clone();
+
+            let limits = WorkerLimits::for_slots(slots_here);
+            let cgroup = if limits.is_empty() {
+                None
+            } else {
+                match WorkerCgroup::create(&format!("arroyo-worker-{}", worker_id), &limits) {
+                    Ok(cgroup) => Some(cgroup),
+                    Err(e) => {
+                        CGROUP_WARNING.call_once(|| {
+                            warn!(
+                                message = "cgroups are not available to limit worker resources; \
+                                    falling back to rlimits, which can only limit memory",
+                                error = format!("{:?}", e)
+                            );
+                        });
+                        None
+                    }
+                }
+            };
 
             tokio::spawn(async move {
                 let mut command =
@@ -146,7 +211,13 @@ impl Scheduler for ProcessScheduler {
                 for (env, value) in env_map {
                     command.env(env, value);
                 }
-                let mut child = command
+
+                match &cgroup {
+                    Some(cgroup) => cgroup.join_on_spawn(&mut command),
+                    None => limits.apply_rlimits(&mut command),
+                }
+
+                let child = command
                     .arg("worker")
                     .env("ARROYO__ADMIN__HTTP_PORT", "0")
                     .env("ARROYO__WORKER__TASK_SLOTS", format!("{}", slots_here))
@@ -159,12 +230,43 @@ impl Scheduler for ProcessScheduler {
                     .env(JOB_ID_ENV, &*job_id)
                     .env(RUN_ID_ENV, format!("{}", start_pipeline_req.run_id))
                     .kill_on_drop(true)
-                    .spawn()
-                    .unwrap();
+                    .spawn();
+
+                // this fails if the worker can't be moved into its cgroup
+                let mut child = match child {
+                    Ok(child) => child,
+                    Err(e) => {
+                        let reason = format!("failed to start worker process: {}", e);
+                        warn!(
+                            message = "worker failed",
+                            worker_id,
+                            job_id = *job_id,
+                            reason
+                        );
+                        let _ = failures.send(WorkerFailure {
+                            job_id: job_id.clone(),
+                            worker_id: WorkerId(worker_id),
+                            reason,
+                        });
+                        if let Some(cgroup) = cgroup {
+                            cgroup.remove();
+                        }
+                        workers.lock().await.remove(&WorkerId(worker_id));
+                        return;
+                    }
+                };
 
                 tokio::select! {
                     status = child.wait() => {
                         info!("Child ({:?}) exited with status {:?}", path, status);
+                        if let Some(reason) = limits.failure_reason(&status, cgroup.as_ref()) {
+                            warn!(message = "worker failed", worker_id, job_id = *job_id, reason);
+                            let _ = failures.send(WorkerFailure {
+                                job_id: job_id.clone(),
+                                worker_id: WorkerId(worker_id),
+                                reason,
+                            });
+                        }
                     }
                     _ = rx => {
                         info!(message = "Killing child", worker_id = worker_id, job_id = *job_id);
@@ -172,6 +274,10 @@ impl Scheduler for ProcessScheduler {
                     }
                 }
 
+                if let Some(cgroup) = cgroup {
+                    cgroup.remove();
+                }
+
                 let mut state = workers.lock().await;
                 state.remove(&WorkerId(worker_id));
             });
@@ -231,11 +337,12 @@ struct NodeStatus {
     free_slots: usize,
     scheduled_slots: HashMap<WorkerId, usize>,
     addr: String,
+    labels: HashMap<String, String>,
     last_heartbeat: Instant,
 }
 
 impl NodeStatus {
-    fn new(id: NodeId, slots: usize, addr: String) -> NodeStatus {
+    fn new(id: NodeId, slots: usize, addr: String, labels: HashMap<String, String>) -> NodeStatus {
         FREE_SLOTS.add(slots as f64);
         REGISTERED_SLOTS.add(slots as f64);
 
@@ -244,10 +351,17 @@ impl NodeStatus {
             free_slots: slots,
             scheduled_slots: HashMap::new(),
             addr,
+            labels,
             last_heartbeat: Instant::now(),
         }
     }
 
+    fn matches(&self, node_selector: &HashMap<String, String>) -> bool {
+        node_selector
+            .iter()
+            .all(|(k, v)| self.labels.get(k) == Some(v))
+    }
+
     fn take_slots(&mut self, worker: WorkerId, slots: usize) {
         if let Some(v) = self.free_slots.checked_sub(slots) {
             FREE_SLOTS.sub(slots as f64);
@@ -311,6 +425,113 @@ impl NodeSchedulerState {
             self.nodes.remove(&node_id);
         }
     }
+
+    /// Decides how many slots of a job to schedule on each node that matches its node selector,
+    /// according to the configured placement; fails if that isn't possible without
+    /// overcommitting the nodes
+    fn place_workers(
+        &self,
+        job_id: &str,
+        node_selector: &HashMap<String, String>,
+        slots: usize,
+    ) -> Result<Vec<(NodeId, usize)>, SchedulerError> {
+        let candidates = self.candidate_nodes(job_id, node_selector);
+
+        plan_placement(candidates, slots, config().node_scheduler.placement).map_err(
+            |slots_needed| {
+                info!(
+                    message = "not enough capacity on matching nodes to schedule job",
+                    job_id,
+                    node_selector = format!("{:?}", node_selector),
+                    slots,
+                    slots_needed
+                );
+                SchedulerError::NotEnoughSlots { slots_needed }
+            },
+        )
+    }
+
+    /// Returns the nodes that the job's workers may be placed on, as (node, free slots, slots
+    /// already scheduled for the job)
+    fn candidate_nodes(
+        &self,
+        job_id: &str,
+        node_selector: &HashMap<String, String>,
+    ) -> Vec<(NodeId, usize, usize)> {
+        let anti_affinity = config().node_scheduler.anti_affinity;
+
+        self.nodes
+            .values()
+            .filter(|n| {
+                n.free_slots > 0
+                    && n.last_heartbeat.elapsed() < Duration::from_secs(30)
+                    && n.matches(node_selector)
+            })
+            .map(|n| {
+                let job_slots = n
+                    .scheduled_slots
+                    .iter()
+                    .filter(|(w, _)| {
+                        self.workers
+                            .get(*w)
+                            .is_some_and(|w| w.running && *w.job_id == job_id)
+                    })
+                    .map(|(_, slots)| *slots)
+                    .sum::<usize>();
+                (n.id, n.free_slots, job_slots)
+            })
+            .filter(|(_, _, job_slots)| !anti_affinity || *job_slots == 0)
+            .collect()
+    }
+}
+
+/// Assigns `slots` to the candidate nodes, given as (node, free slots, slots already scheduled
+/// for the job), returning the slots for each node that gets a worker. If the candidates don't
+/// have enough free slots, returns how many more are needed.
+fn plan_placement(
+    mut candidates: Vec<(NodeId, usize, usize)>,
+    slots: usize,
+    placement: NodePlacement,
+) -> Result<Vec<(NodeId, usize)>, usize> {
+    let capacity: usize = candidates.iter().map(|(_, free, _)| *free).sum();
+    if slots > capacity {
+        return Err(slots - capacity);
+    }
+
+    let mut assigned: Vec<usize> = vec![0; candidates.len()];
+    match placement {
+        NodePlacement::Pack => {
+            candidates.sort_by_key(|(id, free, _)| (Reverse(*free), id.0));
+            let mut to_schedule = slots;
+            for ((_, free, _), assigned) in candidates.iter().zip(assigned.iter_mut()) {
+                *assigned = (*free).min(to_schedule);
+                to_schedule -= *assigned;
+            }
+        }
+        NodePlacement::Spread => {
+            candidates.sort_by_key(|(id, _, _)| id.0);
+            for _ in 0..slots {
+                // give the next slot to the node with the fewest of the job's slots, breaking ties
+                // in favor of the node with the most free slots left
+                let (i, _) = candidates
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, (_, free, _))| assigned[*i] < *free)
+                    .min_by_key(|(i, (_, free, job_slots))| {
+                        (job_slots + assigned[*i], Reverse(free - assigned[*i]))
+                    })
+                    .expect("capacity was checked above");
+                assigned[i] += 1;
+            }
+        }
+    }
+
+    Ok(candidates
+        .into_iter()
+        .zip(assigned)
+        .filter(|(_, assigned)| *assigned > 0)
+        .map(|((id, _, _), assigned)| (id, assigned))
+        .collect())
 }
 
 pub struct NodeScheduler {
@@ -399,6 +620,7 @@ impl Scheduler for NodeScheduler {
                 NodeId(req.node_id),
                 req.task_slots as usize,
                 req.addr,
+                req.labels,
             ));
         }
     }
@@ -459,7 +681,55 @@ impl Scheduler for NodeScheduler {
             .collect())
     }
 
-    #[allow(unreachable_code, unused)]
+    async fn adopt_worker(
+        &self,
+        job_id: Arc<String>,
+        run_id: i64,
+        worker_id: WorkerId,
+        node_id: NodeId,
+        slots: usize,
+    ) {
+        let mut state = self.state.lock().await;
+        if state.workers.contains_key(&worker_id) {
+            return;
+        }
+
+        // the node may not have re-registered yet, in which case it will reject new workers
+        // that don't fit alongside this one
+        if let Some(node) = state.nodes.get_mut(&node_id) {
+            if node.free_slots >= slots {
+                node.take_slots(worker_id, slots);
+            }
+        }
+
+        state.workers.insert(
+            worker_id,
+            NodeWorker {
+                job_id,
+                node_id,
+                run_id,
+                running: true,
+            },
+        );
+    }
+
+    async fn free_slots(
+        &self,
+        job_id: &str,
+        node_selector: &HashMap<String, String>,
+    ) -> Option<usize> {
+        let mut state = self.state.lock().await;
+        state.expire_nodes(Instant::now() - Duration::from_secs(30));
+
+        Some(
+            state
+                .candidate_nodes(job_id, node_selector)
+                .iter()
+                .map(|(_, free, _)| *free)
+                .sum(),
+        )
+    }
+
     async fn start_workers(
         &self,
         start_pipeline_req: StartPipelineReq,
@@ -469,35 +739,16 @@ impl Scheduler for NodeScheduler {
 
         state.expire_nodes(Instant::now() - Duration::from_secs(30));
 
-        let free_slots = state.nodes.values().map(|n| n.free_slots).sum::<usize>();
-        let slots = start_pipeline_req.slots;
-        if slots > free_slots {
-            return Err(SchedulerError::NotEnoughSlots {
-                slots_needed: slots - free_slots,
-            });
-        }
+        let placements = state.place_workers(
+            &start_pipeline_req.job_id,
+            &start_pipeline_req.node_selector,
+            start_pipeline_req.slots,
+        )?;
 
-        let mut to_schedule = slots;
+        let mut to_schedule = start_pipeline_req.slots;
         let mut slots_assigned = vec![];
-        while to_schedule > 0 {
-            // find the node with the most free slots and fill it
-            let node = {
-                if let Some(status) = state
-                    .nodes
-                    .values()
-                    .filter(|n| {
-                        n.free_slots > 0 && n.last_heartbeat.elapsed() < Duration::from_secs(30)
-                    })
-                    .max_by_key(|n| n.free_slots)
-                    .cloned()
-                {
-                    status
-                } else {
-                    unreachable!();
-                }
-            };
-
-            let slots_for_this_one = node.free_slots.min(to_schedule);
+        for (node_id, slots_for_this_one) in placements {
+            let node = state.nodes.get(&node_id).unwrap().clone();
             info!(
                 "Scheduling {} slots on node {}",
                 slots_for_this_one, node.addr
@@ -546,10 +797,24 @@ impl Scheduler for NodeScheduler {
                                 .unwrap()
                                 .release_slots(*worker_id, *slots);
                         });
-                    SchedulerError::Other(format!(
-                        "Failed to start worker on node {}: {:?}",
-                        node.addr, e
-                    ))
+                    if e.code() == Code::ResourceExhausted {
+                        // the node has fewer free slots than we thought (for example, if it was
+                        // already running workers when we became leader); this is handled like
+                        // any other lack of capacity rather than overcommitting the node
+                        warn!(
+                            message = "node rejected worker for lack of slots",
+                            node_addr = node.addr,
+                            error = e.message()
+                        );
+                        SchedulerError::NotEnoughSlots {
+                            slots_needed: to_schedule,
+                        }
+                    } else {
+                        SchedulerError::Other(format!(
+                            "Failed to start worker on node {}: {:?}",
+                            node.addr, e
+                        ))
+                    }
                 })?
                 .into_inner();
 
@@ -606,3 +871,32 @@ impl Scheduler for NodeScheduler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::plan_placement;
+    use arroyo_rpc::config::NodePlacement;
+    use arroyo_types::NodeId;
+
+    #[test]
+    fn test_plan_placement() {
+        let nodes = vec![(NodeId(1), 4, 0), (NodeId(2), 8, 0), (NodeId(3), 2, 2)];
+
+        assert_eq!(
+            plan_placement(nodes.clone(), 10, NodePlacement::Pack),
+            Ok(vec![(NodeId(2), 8), (NodeId(1), 2)])
+        );
+
+        assert_eq!(
+            plan_placement(nodes.clone(), 6, NodePlacement::Spread),
+            Ok(vec![(NodeId(1), 3), (NodeId(2), 3)])
+        );
+
+        assert_eq!(
+            plan_placement(nodes.clone(), 12, NodePlacement::Spread),
+            Ok(vec![(NodeId(1), 4), (NodeId(2), 6), (NodeId(3), 2)])
+        );
+
+        assert_eq!(plan_placement(nodes, 16, NodePlacement::Pack), Err(2));
+    }
+}
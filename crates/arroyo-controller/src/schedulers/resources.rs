@@ -0,0 +1,489 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail, Context};
+use arroyo_rpc::config::{config, ResourceMode};
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::schedulers::kubernetes::quantities::{ParsedQuantity, QuantityParser};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CPU_PERIOD_MICROS: u64 = 100_000;
+
+/// Resource limits for a single worker process, derived from the process scheduler config
+#[derive(Debug, Clone, Default)]
+pub struct WorkerLimits {
+    pub memory_bytes: Option<u64>,
+    pub cpu_millis: Option<u64>,
+}
+
+impl WorkerLimits {
+    pub fn for_slots(slots: usize) -> Self {
+        let c = &config().process_scheduler;
+        let scale = match c.resource_mode {
+            ResourceMode::PerSlot => slots as i64,
+            ResourceMode::PerPod => 1,
+        };
+
+        let Some(limits) = &c.resources.limits else {
+            return Self::default();
+        };
+
+        let cpu_millis = limits.get("cpu").and_then(|cpu| match cpu.parse_cpu() {
+            Ok(v) => Some((v * scale).value() as u64),
+            Err(e) => {
+                warn!(
+                    "Invalid value '{}' for process-scheduler.resources.limits.cpu: {}; not limiting cpu",
+                    cpu.0, e
+                );
+                None
+            }
+        });
+
+        let memory_bytes = limits.get("memory").and_then(|mem| match mem.parse_memory() {
+            // memory quantities are parsed in thousandths of a byte
+            Ok(v) => Some(((v * scale).value() / 1000) as u64),
+            Err(e) => {
+                warn!(
+                    "Invalid value '{}' for process-scheduler.resources.limits.memory: {}; not limiting memory",
+                    mem.0, e
+                );
+                None
+            }
+        });
+
+        Self {
+            memory_bytes,
+            cpu_millis,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memory_bytes.is_none() && self.cpu_millis.is_none()
+    }
+
+    fn memory_description(&self) -> String {
+        self.memory_bytes
+            .map(|m| ParsedQuantity::Memory(m as i128 * 1000).to_canonical())
+            .unwrap_or_else(|| "none".to_string())
+    }
+
+    /// Limits the memory of the process started by `command` with rlimits, for when cgroups
+    /// aren't available; cpu can't be limited this way
+    pub fn apply_rlimits(&self, command: &mut Command) {
+        #[cfg(unix)]
+        if let Some(memory) = self.memory_bytes {
+            let limit = libc::rlimit {
+                rlim_cur: memory as libc::rlim_t,
+                rlim_max: memory as libc::rlim_t,
+            };
+
+            // safety: setrlimit is async-signal-safe, and nothing is allocated between fork
+            // and exec
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::setrlimit(libc::RLIMIT_DATA, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+    }
+
+    /// Describes why a worker process exited unexpectedly, or returns None if it exited cleanly
+    pub fn failure_reason(
+        &self,
+        status: &io::Result<ExitStatus>,
+        cgroup: Option<&WorkerCgroup>,
+    ) -> Option<String> {
+        let status = match status {
+            Ok(status) if status.success() => return None,
+            Ok(status) => status,
+            Err(e) => return Some(format!("failed to wait for worker process: {}", e)),
+        };
+
+        if cgroup.is_some_and(|c| c.oom_killed()) {
+            return Some(format!(
+                "worker was killed after exceeding its memory limit of {}",
+                self.memory_description()
+            ));
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            match status.signal() {
+                Some(libc::SIGKILL) => {
+                    return Some(
+                        "worker was killed with SIGKILL, possibly by the kernel's out-of-memory killer"
+                            .to_string(),
+                    );
+                }
+                Some(libc::SIGABRT) if self.memory_bytes.is_some() => {
+                    return Some(format!(
+                        "worker aborted, likely after failing to allocate memory within its limit of {}",
+                        self.memory_description()
+                    ));
+                }
+                Some(signal) => {
+                    return Some(format!("worker was killed by signal {}", signal));
+                }
+                None => {}
+            }
+        }
+
+        Some(format!("worker exited with {}", status))
+    }
+}
+
+/// A cgroup (v2) that limits the resources of a single worker process, created under the
+/// workers' parent cgroup (see [workers_parent])
+pub struct WorkerCgroup {
+    path: PathBuf,
+    // the path of the cgroup's `cgroup.procs`, prepared ahead of time as it's written between
+    // fork and exec
+    procs: CString,
+}
+
+impl WorkerCgroup {
+    pub fn create(name: &str, limits: &WorkerLimits) -> anyhow::Result<Self> {
+        if !cfg!(target_os = "linux") {
+            bail!("cgroups are only supported on Linux");
+        }
+
+        Self::create_in(&workers_parent()?, name, limits)
+    }
+
+    fn create_in(parent: &Path, name: &str, limits: &WorkerLimits) -> anyhow::Result<Self> {
+        let path = parent.join(name);
+        let procs = CString::new(path.join("cgroup.procs").to_string_lossy().into_owned())
+            .context("invalid cgroup path")?;
+        let cgroup = Self { path, procs };
+
+        fs::create_dir_all(&cgroup.path).with_context(|| {
+            format!("failed to create cgroup {}", cgroup.path.to_string_lossy())
+        })?;
+
+        if let Err(e) = cgroup.set_limits(limits) {
+            cgroup.remove();
+            return Err(e);
+        }
+
+        Ok(cgroup)
+    }
+
+    fn set_limits(&self, limits: &WorkerLimits) -> anyhow::Result<()> {
+        if let Some(memory) = limits.memory_bytes {
+            fs::write(self.path.join("memory.max"), memory.to_string())
+                .context("failed to set memory.max")?;
+            // the limit should apply to the worker's memory as a whole, not just what's in RAM;
+            // this file doesn't exist if swap accounting is disabled
+            let _ = fs::write(self.path.join("memory.swap.max"), "0");
+        }
+
+        if let Some(cpu) = limits.cpu_millis {
+            fs::write(
+                self.path.join("cpu.max"),
+                format!("{} {}", cpu * CPU_PERIOD_MICROS / 1000, CPU_PERIOD_MICROS),
+            )
+            .context("failed to set cpu.max")?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the process started by `command` into the cgroup before it execs, so that it's
+    /// limited from the start
+    pub fn join_on_spawn(&self, command: &mut Command) {
+        let procs = self.procs.clone();
+
+        // safety: open, write and close are async-signal-safe, and nothing is allocated between
+        // fork and exec
+        #[cfg(unix)]
+        unsafe {
+            command.pre_exec(move || {
+                let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // writing 0 moves the writing process, which is the child
+                let written = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+                let result = if written < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                };
+                libc::close(fd);
+                result
+            });
+        }
+    }
+
+    /// Whether any process in the cgroup was killed for exceeding the memory limit
+    pub fn oom_killed(&self) -> bool {
+        fs::read_to_string(self.path.join("memory.events"))
+            .ok()
+            .and_then(|events| {
+                events
+                    .lines()
+                    .find_map(|l| l.strip_prefix("oom_kill "))
+                    .and_then(|n| n.trim().parse::<u64>().ok())
+            })
+            .is_some_and(|n| n > 0)
+    }
+
+    /// Removes the cgroup, which must no longer contain any processes
+    pub fn remove(&self) {
+        if let Err(e) = fs::remove_dir(&self.path) {
+            warn!(
+                message = "failed to remove worker cgroup",
+                path = self.path.to_string_lossy().to_string(),
+                error = format!("{:?}", e)
+            );
+        }
+    }
+}
+
+/// Returns the cgroup under which worker cgroups are created, setting it up the first time it's
+/// needed.
+///
+/// cgroup v2 only lets controllers be enabled for a cgroup's children if it has no processes of
+/// its own (other than at the root), so the workers can't be placed under the controller's own
+/// cgroup. Instead, they go under `process-scheduler.cgroup-parent`, which must be delegated to
+/// the controller and dedicated to the workers (e.g., by systemd with `Delegate=yes`); if it
+/// isn't configured, cgroups aren't used.
+fn workers_parent() -> anyhow::Result<PathBuf> {
+    static PARENT: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+    PARENT
+        .get_or_init(|| {
+            let Some(parent) = config().process_scheduler.cgroup_parent.as_deref() else {
+                return Err("process-scheduler.cgroup-parent is not configured".to_string());
+            };
+            setup_workers_parent(Path::new(CGROUP_ROOT), parent).map_err(|e| format!("{:?}", e))
+        })
+        .clone()
+        .map_err(|e| anyhow!(e))
+}
+
+fn setup_workers_parent(root: &Path, configured: &Path) -> anyhow::Result<PathBuf> {
+    if !root.join("cgroup.controllers").exists() {
+        bail!("cgroup v2 is not mounted at {}", root.to_string_lossy());
+    }
+
+    let parent = configured.strip_prefix(root).unwrap_or(configured);
+    let parent = root.join(parent.to_string_lossy().trim_start_matches('/'));
+
+    // we don't create it, as it has to have been delegated to us
+    if !parent.is_dir() {
+        bail!(
+            "cgroup {} does not exist; it must be created and delegated to the controller",
+            parent.to_string_lossy()
+        );
+    }
+
+    enable_controllers(&parent)?;
+
+    Ok(parent)
+}
+
+/// Enables the memory and cpu controllers (those of them that are available) for the children
+/// of `cgroup`
+fn enable_controllers(cgroup: &Path) -> anyhow::Result<()> {
+    let available = fs::read_to_string(cgroup.join("cgroup.controllers"))
+        .with_context(|| format!("failed to read controllers of {}", cgroup.to_string_lossy()))?;
+
+    let controllers: Vec<_> = ["memory", "cpu"]
+        .into_iter()
+        .filter(|c| available.split_whitespace().any(|a| a == *c))
+        .map(|c| format!("+{}", c))
+        .collect();
+
+    if controllers.is_empty() {
+        bail!(
+            "neither the memory nor cpu controllers are available in {}",
+            cgroup.to_string_lossy()
+        );
+    }
+
+    fs::write(cgroup.join("cgroup.subtree_control"), controllers.join(" ")).with_context(|| {
+        format!(
+            "failed to enable cgroup controllers in {}",
+            cgroup.to_string_lossy()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> WorkerLimits {
+        WorkerLimits {
+            memory_bytes: Some(512 * 1024 * 1024),
+            cpu_millis: Some(1500),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failure_reason() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let limits = limits();
+        let reason = |raw: i32| limits.failure_reason(&Ok(ExitStatus::from_raw(raw)), None);
+
+        assert_eq!(reason(0), None);
+        assert_eq!(
+            reason(libc::SIGKILL).unwrap(),
+            "worker was killed with SIGKILL, possibly by the kernel's out-of-memory killer"
+        );
+        assert_eq!(
+            reason(libc::SIGABRT).unwrap(),
+            "worker aborted, likely after failing to allocate memory within its limit of 512Mi"
+        );
+        assert!(reason(1 << 8).unwrap().starts_with("worker exited with"));
+        assert!(WorkerLimits::default()
+            .failure_reason(&Ok(ExitStatus::from_raw(libc::SIGABRT)), None)
+            .unwrap()
+            .starts_with("worker was killed by signal"));
+    }
+
+    fn cgroup_root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("arroyo-cgroups-{}", rand::random::<u64>()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("cgroup.controllers"), "cpuset cpu io memory pids").unwrap();
+        root
+    }
+
+    fn read(path: PathBuf) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_configured_workers_parent() {
+        let root = cgroup_root();
+        let configured = root.join("arroyo.slice");
+        fs::create_dir_all(&configured).unwrap();
+        fs::write(configured.join("cgroup.controllers"), "memory pids").unwrap();
+
+        for path in ["/arroyo.slice", "arroyo.slice"] {
+            let parent = setup_workers_parent(&root, Path::new(path)).unwrap();
+            assert_eq!(parent, configured);
+        }
+        let parent = setup_workers_parent(&root, &configured).unwrap();
+        assert_eq!(parent, configured);
+
+        // only the available controllers are enabled
+        assert_eq!(read(configured.join("cgroup.subtree_control")), "+memory");
+
+        // a parent that hasn't been created for us isn't created either
+        assert!(setup_workers_parent(&root, Path::new("missing.slice")).is_err());
+        assert!(!root.join("missing.slice").exists());
+
+        // while a parent without either controller can't be used
+        fs::write(configured.join("cgroup.controllers"), "pids").unwrap();
+        assert!(setup_workers_parent(&root, &configured).is_err());
+
+        // nor can anything without cgroup v2
+        fs::remove_file(root.join("cgroup.controllers")).unwrap();
+        assert!(setup_workers_parent(&root, &configured).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_create_worker_cgroup() {
+        let root = cgroup_root();
+
+        let cgroup = WorkerCgroup::create_in(&root, "arroyo-worker-100", &limits()).unwrap();
+        assert_eq!(
+            read(root.join("arroyo-worker-100/memory.max")),
+            (512 * 1024 * 1024).to_string()
+        );
+        assert_eq!(
+            read(root.join("arroyo-worker-100/cpu.max")),
+            "150000 100000"
+        );
+        assert_eq!(
+            cgroup.procs.to_str().unwrap(),
+            root.join("arroyo-worker-100/cgroup.procs")
+                .to_str()
+                .unwrap()
+        );
+
+        let memory_only = WorkerLimits {
+            memory_bytes: Some(1024),
+            cpu_millis: None,
+        };
+        WorkerCgroup::create_in(&root, "arroyo-worker-101", &memory_only).unwrap();
+        assert!(!root.join("arroyo-worker-101/cpu.max").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_join_on_spawn() {
+        let root = cgroup_root();
+        let cgroup = WorkerCgroup::create_in(&root, "arroyo-worker-100", &limits()).unwrap();
+
+        // the child writes itself into the cgroup before it execs
+        fs::write(root.join("arroyo-worker-100/cgroup.procs"), "").unwrap();
+        let mut command = Command::new("true");
+        cgroup.join_on_spawn(&mut command);
+        assert!(command.status().await.unwrap().success());
+        assert_eq!(read(root.join("arroyo-worker-100/cgroup.procs")), "0");
+
+        // and fails to start if it can't
+        fs::remove_dir_all(root.join("arroyo-worker-100")).unwrap();
+        let mut command = Command::new("true");
+        cgroup.join_on_spawn(&mut command);
+        assert!(command.status().await.is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_oom_killed() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let path = std::env::temp_dir().join(format!("arroyo-cgroup-{}", rand::random::<u64>()));
+        fs::create_dir_all(&path).unwrap();
+        let cgroup = WorkerCgroup {
+            path: path.clone(),
+            procs: CString::new("cgroup.procs").unwrap(),
+        };
+
+        // no events file, as when the memory controller isn't enabled
+        assert!(!cgroup.oom_killed());
+
+        fs::write(
+            path.join("memory.events"),
+            "low 0\nhigh 0\nmax 3\noom 1\noom_kill 0\n",
+        )
+        .unwrap();
+        assert!(!cgroup.oom_killed());
+
+        fs::write(
+            path.join("memory.events"),
+            "low 0\nhigh 0\nmax 5\noom 1\noom_kill 1\n",
+        )
+        .unwrap();
+        assert!(cgroup.oom_killed());
+        assert_eq!(
+            limits()
+                .failure_reason(&Ok(ExitStatus::from_raw(libc::SIGKILL)), Some(&cgroup))
+                .unwrap(),
+            "worker was killed after exceeding its memory limit of 512Mi"
+        );
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use arroyo_datastream::logical::LogicalProgram;
+use arroyo_rpc::config::config;
+use arroyo_rpc::grpc::rpc::{worker_grpc_client::WorkerGrpcClient, TaskAssignment};
+use arroyo_types::WorkerId;
+use tonic::transport::Channel;
+use tracing::{info, warn};
+
+use crate::job_controller::job_metrics::JobMetrics;
+use crate::job_controller::JobController;
+use crate::leader;
+use crate::queries::controller_queries;
+use crate::states::{stop_if_desired_non_running, StateError};
+use crate::JobMessage;
+
+use super::compiling::Compiling;
+use super::running::Running;
+use super::{JobContext, State, Transition};
+
+/// Takes over a job that was running when a previous leader lost its lease. The job's workers
+/// keep running through the failover, and re-register with us once they see the new leader
+/// term; when all of the job's tasks are accounted for, we pick up from them as if we had
+/// scheduled them ourselves. If they don't all come back within the heartbeat timeout, or the
+/// job can't be picked up where it was, it's restarted from its last checkpoint instead.
+///
+/// An adopted job is restarted as a whole on failure, as we don't know how its workers were
+/// split between failover regions.
+#[derive(Debug)]
+pub struct Adopting {}
+
+struct AdoptedWorker {
+    rpc_address: String,
+    tasks: Vec<TaskAssignment>,
+}
+
+// Returns the task assignments if the re-registered workers are running every task of the
+// program, and nothing else; None if we're still waiting for some of them
+fn adopted_assignments(
+    program: &LogicalProgram,
+    workers: &HashMap<WorkerId, AdoptedWorker>,
+) -> anyhow::Result<Option<Vec<TaskAssignment>>> {
+    let expected: HashSet<_> = program
+        .graph
+        .node_weights()
+        .flat_map(|node| (0..node.parallelism).map(|i| (node.operator_id.clone(), i as u64)))
+        .collect();
+
+    let mut assignments = HashMap::new();
+    for assignment in workers.values().flat_map(|w| &w.tasks) {
+        let task = (assignment.operator_id.clone(), assignment.operator_subtask);
+        if !expected.contains(&task) {
+            bail!(
+                "worker {} is running task {}-{}, which isn't part of the pipeline",
+                assignment.worker_id,
+                task.0,
+                task.1
+            );
+        }
+        assignments.insert(task, assignment.clone());
+    }
+
+    let complete = assignments.len() == expected.len()
+        && assignments
+            .values()
+            .all(|a| workers.contains_key(&WorkerId(a.worker_id)));
+
+    Ok(complete.then(|| assignments.into_values().collect()))
+}
+
+impl Adopting {
+    fn restart(self: Box<Self>, ctx: &JobContext, reason: anyhow::Error) -> Transition {
+        warn!(
+            message = "could not adopt running job; restarting it from its last checkpoint",
+            job_id = *ctx.config.id,
+            reason = format!("{:?}", reason)
+        );
+        Transition::next(*self, Compiling {})
+    }
+}
+
+#[async_trait::async_trait]
+impl State for Adopting {
+    fn name(&self) -> &'static str {
+        "Adopting"
+    }
+
+    async fn next(self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
+        stop_if_desired_non_running!(self, &ctx.config);
+
+        ctx.program
+            .update_parallelism(&ctx.config.parallelism_overrides);
+
+        let timeout = *config().pipeline.worker_heartbeat_timeout;
+        let start = Instant::now();
+        let mut workers = HashMap::new();
+
+        let assignments = loop {
+            match adopted_assignments(ctx.program, &workers) {
+                Ok(Some(assignments)) => break assignments,
+                Ok(None) => {}
+                Err(e) => return Ok(self.restart(ctx, e)),
+            }
+
+            let remaining = timeout
+                .checked_sub(start.elapsed())
+                .unwrap_or(Duration::ZERO);
+
+            tokio::select! {
+                msg = ctx.rx.recv() => {
+                    match msg {
+                        Some(JobMessage::ConfigUpdate(c)) => {
+                            stop_if_desired_non_running!(self, &c);
+                        }
+                        Some(JobMessage::WorkerConnect {
+                            worker_id,
+                            node_id,
+                            rpc_address,
+                            slots,
+                            run_id,
+                            tasks,
+                            ..
+                        }) => {
+                            // track the worker even if we don't end up adopting it, so that it's
+                            // stopped along with the rest of the job
+                            ctx.scheduler
+                                .adopt_worker(ctx.config.id.clone(), run_id, worker_id, node_id, slots)
+                                .await;
+
+                            if run_id != ctx.status.run_id || tasks.is_empty() {
+                                warn!(
+                                    message = "worker is not part of the running job",
+                                    job_id = *ctx.config.id,
+                                    worker_id = worker_id.0,
+                                    run_id
+                                );
+                                continue;
+                            }
+
+                            info!(
+                                message = "running worker re-registered",
+                                job_id = *ctx.config.id,
+                                worker_id = worker_id.0
+                            );
+                            workers.insert(worker_id, AdoptedWorker { rpc_address, tasks });
+                        }
+                        Some(msg) => {
+                            ctx.handle(msg)?;
+                        }
+                        None => {
+                            panic!("Job message channel closed: {}", ctx.config.id);
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(remaining) => {
+                    return Ok(self.restart(ctx, anyhow!(
+                        "only {} workers re-registered within {:?}",
+                        workers.len(),
+                        timeout
+                    )));
+                }
+            }
+        };
+
+        let mut worker_connects: HashMap<WorkerId, WorkerGrpcClient<Channel>> = HashMap::new();
+        for (id, worker) in &workers {
+            match Channel::from_shared(worker.rpc_address.clone())
+                .unwrap()
+                .timeout(Duration::from_secs(90))
+                .connect()
+                .await
+            {
+                Ok(channel) => {
+                    worker_connects.insert(*id, WorkerGrpcClient::new(channel));
+                }
+                Err(e) => {
+                    return Ok(self.restart(
+                        ctx,
+                        anyhow!("failed to connect to worker {}: {:?}", id.0, e),
+                    ));
+                }
+            }
+        }
+
+        // checkpoints are picked up after the last one that the previous leader started, which
+        // the workers may still be in the middle of; the incomplete ones are marked as failed
+        let checkpoints = async {
+            let c = ctx.db.client().await?;
+            let last_successful =
+                controller_queries::fetch_last_successful_checkpoint(&c, &*ctx.config.id)
+                    .await?
+                    .into_iter()
+                    .next();
+            let last_started = controller_queries::fetch_last_checkpoint_epoch(&c, &*ctx.config.id)
+                .await?
+                .into_iter()
+                .next()
+                .unwrap_or(0);
+
+            let (lease_holder, lease_term) = leader::lease_fence();
+            controller_queries::execute_mark_failed(
+                &c,
+                &*ctx.config.id,
+                &(last_successful.as_ref().map(|c| c.epoch).unwrap_or(0) + 1),
+                &lease_holder,
+                &lease_term,
+            )
+            .await?;
+
+            anyhow::Ok((last_successful, last_started))
+        }
+        .await;
+
+        let (last_successful, last_started) = match checkpoints {
+            Ok(checkpoints) => checkpoints,
+            Err(e) => {
+                return Err(ctx.retryable(self, "failed to load checkpoints", e, 10));
+            }
+        };
+
+        if last_successful.as_ref().is_some_and(|c| c.needs_commits) {
+            // the previous leader may not have finished committing it, which is handled when
+            // the job is restored from the checkpoint
+            return Ok(self.restart(
+                ctx,
+                anyhow!("the last checkpoint has not finished committing"),
+            ));
+        }
+
+        let task_count = ctx.program.task_count();
+        ctx.status.tasks = Some(task_count as i32);
+
+        let program = Arc::new(ctx.program.clone());
+        let metrics = JobMetrics::new(program.clone());
+        ctx.metrics
+            .write()
+            .await
+            .insert(ctx.config.id.clone(), metrics.clone());
+
+        let mut controller = JobController::new(
+            ctx.db.clone(),
+            ctx.config.clone(),
+            program,
+            last_successful
+                .as_ref()
+                .map(|c| c.epoch as u32)
+                .unwrap_or(0),
+            last_successful
+                .as_ref()
+                .map(|c| c.min_epoch as u32)
+                .unwrap_or(0),
+            worker_connects,
+            None,
+            metrics,
+        );
+        controller.set_last_started_epoch(last_started as u32);
+        controller.set_task_workers(&assignments);
+
+        info!(
+            message = "adopted running job",
+            job_id = *ctx.config.id,
+            workers = workers.len(),
+            tasks = task_count
+        );
+
+        ctx.job_controller = Some(controller);
+        Ok(Transition::next(*self, Running {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_datastream::logical::{LogicalGraph, LogicalNode, OperatorName, ProgramConfig};
+
+    fn program() -> LogicalProgram {
+        let mut graph = LogicalGraph::new();
+        for (operator_id, parallelism) in [("source", 1), ("aggregate", 2)] {
+            graph.add_node(LogicalNode {
+                operator_id: operator_id.to_string(),
+                description: operator_id.to_string(),
+                operator_name: OperatorName::ArrowValue,
+                operator_config: vec![],
+                parallelism,
+            });
+        }
+        LogicalProgram::new(graph, ProgramConfig::default())
+    }
+
+    fn assignment(operator_id: &str, subtask: u64, worker_id: u64) -> TaskAssignment {
+        TaskAssignment {
+            operator_id: operator_id.to_string(),
+            operator_subtask: subtask,
+            worker_id,
+            worker_addr: format!("worker-{}:1234", worker_id),
+        }
+    }
+
+    fn worker(tasks: &[TaskAssignment]) -> AdoptedWorker {
+        AdoptedWorker {
+            rpc_address: "http://worker:1234".to_string(),
+            tasks: tasks.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_adopted_assignments() {
+        let program = program();
+        let assignments = [
+            assignment("source", 0, 1),
+            assignment("aggregate", 0, 1),
+            assignment("aggregate", 1, 2),
+        ];
+
+        // every worker knows the whole assignment, but we wait until all of them are back
+        let mut workers = HashMap::new();
+        workers.insert(WorkerId(1), worker(&assignments));
+        assert!(adopted_assignments(&program, &workers).unwrap().is_none());
+
+        workers.insert(WorkerId(2), worker(&assignments));
+        let mut adopted = adopted_assignments(&program, &workers).unwrap().unwrap();
+        adopted.sort_by_key(|a| (a.operator_id.clone(), a.operator_subtask));
+        assert_eq!(
+            adopted,
+            vec![
+                assignment("aggregate", 0, 1),
+                assignment("aggregate", 1, 2),
+                assignment("source", 0, 1),
+            ]
+        );
+
+        // workers running a different version of the pipeline can't be adopted
+        workers.insert(WorkerId(3), worker(&[assignment("aggregate", 2, 3)]));
+        assert!(adopted_assignments(&program, &workers).is_err());
+    }
+}
@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
 use std::{fmt::Debug, sync::Arc};
 
+use arroyo_rpc::api_types::pipelines::RestartStrategy;
 use arroyo_rpc::grpc::api::ArrowProgram;
 
 use arroyo_server_common::log_event;
@@ -16,7 +17,9 @@ use tracing::{error, info, warn};
 use anyhow::{anyhow, Result};
 use cornucopia_async::DatabaseSource;
 
+use crate::admission::AdmissionQueue;
 use crate::job_controller::JobController;
+use crate::leader;
 use crate::queries::controller_queries;
 use crate::types::public::StopMode;
 use crate::{schedulers::Scheduler, JobConfig, JobMessage, JobStatus, RunningMessage};
@@ -25,19 +28,25 @@ use arroyo_rpc::config::config;
 use arroyo_server_common::shutdown::ShutdownGuard;
 use prost::Message;
 
+use self::adopting::Adopting;
 use self::checkpoint_stopping::CheckpointStopping;
 use self::compiling::Compiling;
 use self::finishing::Finishing;
+use self::queued::Queued;
 use self::recovering::Recovering;
+use self::recovering_region::RecoveringRegion;
 use self::rescaling::Rescaling;
 use self::running::Running;
 use self::scheduling::Scheduling;
 use self::stopping::Stopping;
 
+mod adopting;
 mod checkpoint_stopping;
 mod compiling;
 mod finishing;
+mod queued;
 mod recovering;
+mod recovering_region;
 mod rescaling;
 mod restarting;
 mod running;
@@ -169,9 +178,18 @@ impl State for Stopped {
 // State transitions
 impl TransitionTo<Compiling> for Created {}
 
-impl TransitionTo<Compiling> for Stopped {}
+impl TransitionTo<Compiling> for Stopped {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(|ctx| {
+            // a restart requested while the job was stopped is satisfied by starting it again
+            ctx.status.restart_nonce = ctx.config.restart_nonce;
+        })
+    }
+}
 
 impl TransitionTo<Compiling> for Scheduling {}
+impl TransitionTo<Queued> for Scheduling {}
+impl TransitionTo<Scheduling> for Queued {}
 
 impl TransitionTo<Scheduling> for Compiling {
     fn update_status(&self) -> TransitionFn {
@@ -194,9 +212,14 @@ impl TransitionTo<Running> for Scheduling {
     }
 }
 
+impl TransitionTo<Running> for Adopting {}
+impl TransitionTo<Compiling> for Adopting {}
+impl TransitionTo<Stopping> for Adopting {}
+
 impl TransitionTo<CheckpointStopping> for Running {}
 impl TransitionTo<Stopping> for Running {}
 impl TransitionTo<Stopping> for Scheduling {}
+impl TransitionTo<Stopping> for Queued {}
 impl TransitionTo<Stopping> for Compiling {}
 impl TransitionTo<Stopping> for Rescaling {}
 impl TransitionTo<Finishing> for Running {}
@@ -207,6 +230,13 @@ impl TransitionTo<Recovering> for Running {
         })
     }
 }
+impl TransitionTo<RecoveringRegion> for Running {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(|ctx| {
+            ctx.status.restarts += 1;
+        })
+    }
+}
 impl TransitionTo<Rescaling> for Running {}
 
 impl TransitionTo<Scheduling> for Rescaling {
@@ -217,7 +247,35 @@ impl TransitionTo<Scheduling> for Rescaling {
     }
 }
 
-impl TransitionTo<Compiling> for Recovering {}
+impl TransitionTo<Compiling> for Recovering {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(|ctx| {
+            ctx.status.restart_delay = None;
+        })
+    }
+}
+impl TransitionTo<Stopping> for Recovering {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(|ctx| {
+            ctx.status.restart_delay = None;
+        })
+    }
+}
+impl TransitionTo<Running> for RecoveringRegion {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(|ctx| {
+            ctx.status.restart_delay = None;
+        })
+    }
+}
+impl TransitionTo<Recovering> for RecoveringRegion {}
+impl TransitionTo<Stopping> for RecoveringRegion {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(|ctx| {
+            ctx.status.restart_delay = None;
+        })
+    }
+}
 impl TransitionTo<Compiling> for Failed {
     fn update_status(&self) -> TransitionFn {
         Box::new(|ctx| {
@@ -358,14 +416,25 @@ pub struct JobContext<'a> {
     program: &'a mut LogicalProgram,
     db: DatabaseSource,
     scheduler: Arc<dyn Scheduler>,
+    admission: Arc<AdmissionQueue>,
     rx: &'a mut Receiver<JobMessage>,
     retries_attempted: usize,
     job_controller: Option<JobController>,
     last_transitioned_at: Instant,
     metrics: Arc<tokio::sync::RwLock<HashMap<Arc<String>, JobMetrics>>>,
+    /// Times of recent failures, used by the failure-rate restart strategy
+    failure_times: VecDeque<Instant>,
 }
 
 impl<'a> JobContext<'a> {
+    /// The restart strategy for the job, falling back to the one configured for the cluster
+    pub fn restart_strategy(&self) -> RestartStrategy {
+        self.config
+            .restart_strategy
+            .clone()
+            .unwrap_or_else(|| config().pipeline.restart_strategy.strategy())
+    }
+
     pub fn handle(&mut self, msg: JobMessage) -> Result<(), StateError> {
         if !matches!(
             msg,
@@ -536,15 +605,58 @@ async fn execute_state<'a>(
     if let Some(s) = &next {
         ctx.status.state = s.name().to_string();
 
-        ctx.status
-            .update_db(&ctx.db)
-            .await
-            .expect("Failed to update status");
+        if s.name() == "Running" && ctx.config.restore_from.is_some() {
+            // the savepoint has been restored into the job's checkpoints, so later restarts
+            // should resume from those instead
+            ctx.status
+                .update_db_clearing_restore(&ctx.db)
+                .await
+                .expect("Failed to update status");
+            ctx.config.restore_from = None;
+        } else {
+            ctx.status
+                .update_db(&ctx.db)
+                .await
+                .expect("Failed to update status");
+        }
+
+        // savepoints can only be taken while the job is running (region recovery keeps the job
+        // controller, and with it any savepoint in progress)
+        if s.name() != "Running" && s.name() != "RecoveringRegion" {
+            if let Err(e) = fail_pending_savepoints(&ctx.db, &ctx.config.id, s.name()).await {
+                warn!(
+                    message = "failed to fail pending savepoints",
+                    job_id = *ctx.config.id,
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+    } else if let Err(e) = fail_pending_savepoints(&ctx.db, &ctx.config.id, state_name).await {
+        warn!(
+            message = "failed to fail pending savepoints",
+            job_id = *ctx.config.id,
+            error = format!("{:?}", e)
+        );
     }
 
     (next, ctx)
 }
 
+async fn fail_pending_savepoints(
+    db: &DatabaseSource,
+    job_id: &Arc<String>,
+    state: &str,
+) -> Result<()> {
+    controller_queries::execute_fail_pending_savepoints(
+        &db.client().await?,
+        &OffsetDateTime::now_utc(),
+        &format!("job is no longer running (now {})", state),
+        &**job_id,
+    )
+    .await?;
+    Ok(())
+}
+
 pub async fn run_to_completion(
     config: Arc<RwLock<JobConfig>>,
     mut program: LogicalProgram,
@@ -553,6 +665,7 @@ pub async fn run_to_completion(
     db: DatabaseSource,
     mut rx: Receiver<JobMessage>,
     scheduler: Arc<dyn Scheduler>,
+    admission: Arc<AdmissionQueue>,
     metrics: Arc<tokio::sync::RwLock<HashMap<Arc<String>, JobMetrics>>>,
 ) {
     let mut ctx = JobContext {
@@ -561,14 +674,18 @@ pub async fn run_to_completion(
         program: &mut program,
         db: db.clone(),
         scheduler,
+        admission,
         rx: &mut rx,
         retries_attempted: 0,
         job_controller: None,
         last_transitioned_at: Instant::now(),
         metrics,
+        failure_times: VecDeque::new(),
     };
 
     loop {
+        let restore_from = ctx.config.restore_from.clone();
+        let restore_epoch = ctx.config.restore_epoch;
         match execute_state(state, ctx).await {
             (Some(new_state), new_ctx) => {
                 state = new_state;
@@ -577,7 +694,26 @@ pub async fn run_to_completion(
             (None, _) => break,
         }
 
-        ctx.config = config.read().unwrap().clone();
+        ctx.config = {
+            let mut shared = config.write().unwrap();
+            if restore_from.is_some()
+                && ctx.config.restore_from.is_none()
+                && shared.restore_from == restore_from
+            {
+                // the job is now running from the restored savepoint, so it shouldn't be restored
+                // again even if the updater hasn't yet seen that it was cleared in the database
+                shared.restore_from = None;
+            }
+            if restore_epoch.is_some()
+                && ctx.config.restore_epoch.is_none()
+                && shared.restore_epoch == restore_epoch
+            {
+                // likewise, the job has been rolled back to the checkpoint, and shouldn't be rolled
+                // back again on later restarts
+                shared.restore_epoch = None;
+            }
+            shared.clone()
+        };
     }
 }
 
@@ -587,6 +723,7 @@ pub struct StateMachine {
     metrics: Arc<tokio::sync::RwLock<HashMap<Arc<String>, JobMetrics>>>,
     db: DatabaseSource,
     scheduler: Arc<dyn Scheduler>,
+    admission: Arc<AdmissionQueue>,
 }
 
 impl StateMachine {
@@ -595,6 +732,7 @@ impl StateMachine {
         status: JobStatus,
         db: DatabaseSource,
         scheduler: Arc<dyn Scheduler>,
+        admission: Arc<AdmissionQueue>,
         shutdown_guard: ShutdownGuard,
         metrics: Arc<tokio::sync::RwLock<HashMap<Arc<String>, JobMetrics>>>,
     ) -> Self {
@@ -604,6 +742,7 @@ impl StateMachine {
             metrics,
             db,
             scheduler,
+            admission,
         };
 
         this.start(status, shutdown_guard).await;
@@ -655,9 +794,11 @@ impl StateMachine {
             "Stopped" => Some(Box::new(Stopped {})),
             "Finished" => Some(Box::new(Finished {})),
             "Failed" => Some(Box::new(Failed {})),
-            "Compiling" | "Scheduling" | "Running" | "Recovering" | "Rescaling" => {
-                Some(Box::new(Compiling {}))
-            }
+            // after a leader failover, the job's workers are still running, and can be adopted
+            // rather than restarting the job
+            "Running" | "Adopting" if leader::held_lease().is_some() => Some(Box::new(Adopting {})),
+            "Compiling" | "Queued" | "Scheduling" | "Running" | "Adopting" | "Recovering"
+            | "RecoveringRegion" | "Rescaling" => Some(Box::new(Compiling {})),
             "Stopping" | "CheckpointStopping" => {
                 // TODO: do we need to handle a failure in CheckpointStopping specially?
                 if status.finish_time.is_none() {
@@ -684,6 +825,7 @@ impl StateMachine {
                 let config = self.config.clone();
                 let db = self.db.clone();
                 let scheduler = self.scheduler.clone();
+                let admission = self.admission.clone();
                 let metrics = self.metrics.clone();
                 let pipeline_id = config.read().unwrap().pipeline_id;
                 match Self::get_program(&db, &status.id, pipeline_id).await {
@@ -699,6 +841,7 @@ impl StateMachine {
                                 db,
                                 rx,
                                 scheduler,
+                                admission,
                                 metrics,
                             )
                             .await;
@@ -759,7 +902,7 @@ impl StateMachine {
     // for states that should be running, check them and restart if needed
     async fn restart_if_needed(&mut self, status: JobStatus, shutdown_guard: &ShutdownGuard) {
         match status.state.as_str() {
-            "Running" | "Recovering" | "Rescaling" => {
+            "Running" | "Adopting" | "Recovering" | "Rescaling" => {
                 // done() means there isn't a task running, but these states
                 // need to be advanced.
                 if self.done() {
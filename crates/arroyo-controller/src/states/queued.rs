@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use cornucopia_async::DatabaseSource;
+use tracing::{info, warn};
+
+use crate::queries::controller_queries;
+use crate::states::stop_if_desired_non_running;
+use crate::types::public::{LogLevel, StopMode};
+use crate::JobMessage;
+
+use super::scheduling::Scheduling;
+use super::{JobContext, State, StateError, Transition};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Waits until the cluster has enough free slots for the job and every job ahead of it in the
+/// admission queue has been scheduled
+#[derive(Debug)]
+pub struct Queued {
+    /// The number of slots the job needs
+    pub slots: usize,
+}
+
+// Lets the user know why the job isn't starting
+async fn record_queued_event(db: &DatabaseSource, job_id: &str, message: String) {
+    let result: anyhow::Result<()> = async {
+        let c = db.client().await?;
+        controller_queries::execute_create_job_event(
+            &c,
+            &generate_id(IdTypes::JobLogMessage),
+            &job_id,
+            &LogLevel::info,
+            &message,
+            &"",
+        )
+        .await?;
+
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        warn!(
+            message = "failed to record job event",
+            job_id,
+            error = format!("{:?}", e)
+        );
+    }
+}
+
+#[async_trait::async_trait]
+impl State for Queued {
+    fn name(&self) -> &'static str {
+        "Queued"
+    }
+
+    async fn next(self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
+        if ctx.config.stop_mode != StopMode::none {
+            ctx.admission.remove(&ctx.config.id);
+            stop_if_desired_non_running!(self, &ctx.config);
+        }
+
+        let position = ctx.admission.position(&ctx.config.id).unwrap_or_default();
+        info!(
+            message = "job queued until there are enough free slots",
+            job_id = *ctx.config.id,
+            slots = self.slots,
+            priority = ctx.config.priority,
+            position
+        );
+        record_queued_event(
+            &ctx.db,
+            &ctx.config.id,
+            format!(
+                "Waiting for {} free slots to schedule the job ({} jobs ahead in the queue)",
+                self.slots, position
+            ),
+        )
+        .await;
+
+        loop {
+            tokio::select! {
+                msg = ctx.rx.recv() => {
+                    match msg {
+                        Some(JobMessage::ConfigUpdate(c)) => {
+                            if c.stop_mode != StopMode::none {
+                                ctx.admission.remove(&ctx.config.id);
+                                stop_if_desired_non_running!(self, &c);
+                            }
+
+                            // the job's slots or priority may have changed, so it's re-queued
+                            // (keeping its place) by scheduling with the new config
+                            return Ok(Transition::next(*self, Scheduling {}));
+                        }
+                        Some(msg) => {
+                            ctx.handle(msg)?;
+                        }
+                        None => {
+                            panic!("Job message channel closed: {}", ctx.config.id);
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            }
+
+            let free_slots = ctx
+                .scheduler
+                .free_slots(&ctx.config.id, &ctx.config.node_selector)
+                .await;
+            if ctx
+                .admission
+                .can_admit(&ctx.config.id, ctx.config.priority, self.slots, free_slots)
+            {
+                return Ok(Transition::next(*self, Scheduling {}));
+            }
+        }
+    }
+}
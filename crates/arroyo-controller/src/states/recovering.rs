@@ -1,14 +1,84 @@
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
 use anyhow::bail;
+use arroyo_rpc::api_types::pipelines::RestartStrategy;
 use arroyo_rpc::grpc::rpc::StopMode;
 use tokio::time::timeout;
 use tracing::{info, warn};
 
+use crate::states::stop_if_desired_non_running;
+use crate::JobMessage;
+
 use super::{compiling::Compiling, JobContext, State, StateError, Transition};
 
+/// Chooses how long to wait before restarting a job after a failure, given the number of
+/// consecutive restarts so far and the times of its previous failures, or returns the reason
+/// that the job should be failed instead
+pub fn restart_delay(
+    strategy: &RestartStrategy,
+    restarts: i32,
+    allowed_restarts: i32,
+    failures: &mut VecDeque<Instant>,
+    now: Instant,
+) -> Result<Duration, String> {
+    let check_allowed_restarts = || {
+        if allowed_restarts != -1 && restarts >= allowed_restarts {
+            Err("Job has restarted too many times".to_string())
+        } else {
+            Ok(())
+        }
+    };
+
+    match strategy {
+        RestartStrategy::FixedDelay { delay_micros } => {
+            check_allowed_restarts()?;
+            Ok(Duration::from_micros(*delay_micros))
+        }
+        RestartStrategy::ExponentialBackoff {
+            initial_delay_micros,
+            max_delay_micros,
+            multiplier,
+        } => {
+            check_allowed_restarts()?;
+            let delay = (*initial_delay_micros as f64) * multiplier.powi(restarts.max(0));
+            Ok(Duration::from_micros(
+                delay.min(*max_delay_micros as f64) as u64
+            ))
+        }
+        RestartStrategy::FailureRate {
+            max_failures,
+            interval_micros,
+            delay_micros,
+        } => {
+            let interval = Duration::from_micros(*interval_micros);
+            failures.push_back(now);
+            while failures
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > interval)
+            {
+                failures.pop_front();
+            }
+
+            if failures.len() > *max_failures as usize {
+                Err(format!(
+                    "Job failed {} times within {:?}, more than the {} allowed by its restart strategy",
+                    failures.len(),
+                    interval,
+                    max_failures
+                ))
+            } else {
+                Ok(Duration::from_micros(*delay_micros))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct Recovering {}
+pub struct Recovering {
+    /// How long to wait after tearing down the existing cluster before restarting
+    pub delay: Duration,
+}
 
 impl Recovering {
     // tries, with increasing levels of force, to tear down the existing cluster
@@ -91,11 +161,83 @@ impl State for Recovering {
     }
 
     async fn next(mut self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
-        // tear down the existing cluster
-        if let Err(e) = Self::cleanup(ctx).await {
-            return Err(ctx.retryable(self, "failed to tear down existing cluster", e, 10));
+        // tear down the existing cluster (unless we already did before being retried)
+        if ctx.job_controller.is_some() {
+            if let Err(e) = Self::cleanup(ctx).await {
+                return Err(ctx.retryable(self, "failed to tear down existing cluster", e, 10));
+            }
+            // the old job is gone, so a stop while we're waiting only needs to clean up workers
+            ctx.job_controller = None;
         }
 
-        Ok(Transition::next(*self, Compiling))
+        // wait out the restart delay, unless the job is stopped in the meantime
+        let restart_at = ctx.last_transitioned_at + self.delay;
+        if restart_at > Instant::now() {
+            info!(
+                message = "waiting before restarting job",
+                job_id = *ctx.config.id,
+                delay = format!("{:?}", self.delay)
+            );
+        }
+
+        loop {
+            tokio::select! {
+                msg = ctx.rx.recv() => {
+                    match msg {
+                        Some(JobMessage::ConfigUpdate(c)) => {
+                            stop_if_desired_non_running!(self, &c);
+                        }
+                        Some(msg) => {
+                            ctx.handle(msg)?;
+                        }
+                        None => {
+                            panic!("job queue shut down");
+                        }
+                    }
+                }
+                _ = tokio::time::sleep_until(restart_at.into()) => {
+                    return Ok(Transition::next(*self, Compiling));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_delay() {
+        let mut failures = VecDeque::new();
+        let now = Instant::now();
+
+        let backoff = RestartStrategy::ExponentialBackoff {
+            initial_delay_micros: 1_000_000,
+            max_delay_micros: 10_000_000,
+            multiplier: 2.0,
+        };
+        let delays: Vec<_> = (0..6)
+            .map(|restarts| restart_delay(&backoff, restarts, 20, &mut failures, now).unwrap())
+            .map(|d| d.as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+        assert!(restart_delay(&backoff, 20, 20, &mut failures, now).is_err());
+        assert!(restart_delay(&backoff, 20, -1, &mut failures, now).is_ok());
+
+        let rate = RestartStrategy::FailureRate {
+            max_failures: 2,
+            interval_micros: 60_000_000,
+            delay_micros: 5_000_000,
+        };
+        let at = |secs| now + Duration::from_secs(secs);
+        assert_eq!(
+            restart_delay(&rate, 0, 0, &mut failures, at(0)),
+            Ok(Duration::from_secs(5))
+        );
+        assert!(restart_delay(&rate, 1, 0, &mut failures, at(30)).is_ok());
+        // the first failure has fallen out of the interval
+        assert!(restart_delay(&rate, 2, 0, &mut failures, at(90)).is_ok());
+        assert!(restart_delay(&rate, 3, 0, &mut failures, at(100)).is_err());
     }
 }
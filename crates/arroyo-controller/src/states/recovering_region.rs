@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use arroyo_rpc::config::config;
+use arroyo_rpc::grpc::api;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::states::stopping::{StopBehavior, Stopping};
+use crate::types::public::StopMode;
+use crate::JobMessage;
+
+use super::recovering::Recovering;
+use super::running::Running;
+use super::scheduling::{
+    compute_assignments, handle_worker_connect, slots_for_job, start_execution, start_pipeline_req,
+};
+use super::{JobContext, State, StateError, Transition};
+
+/// Restarts a single failover region of a running job from the last checkpoint, while the rest of
+/// the job keeps running. If the region can't be restarted, the whole job is recovered instead.
+#[derive(Debug)]
+pub struct RecoveringRegion {
+    pub region: usize,
+    /// How long to wait after stopping the region's workers before restarting them
+    pub delay: Duration,
+}
+
+impl RecoveringRegion {
+    // Returns false if the job was stopped while the region was restarting
+    async fn restart_region<'a>(&self, ctx: &mut JobContext<'a>) -> anyhow::Result<bool> {
+        let job_controller = ctx.job_controller.as_mut().unwrap();
+        job_controller.stop_failover_region(self.region).await?;
+
+        let failover_region = job_controller.failover_region(self.region);
+        let worker_group = format!("r{}-{}", self.region, failover_region.restarts);
+        let program = ctx.program.subprogram(&failover_region.operators);
+        let restore_epoch = job_controller.restore_epoch();
+
+        info!(
+            message = "restarting failover region",
+            job_id = *ctx.config.id,
+            region = self.region,
+            restore_epoch,
+            delay = format!("{:?}", self.delay)
+        );
+
+        // wait out the restart delay, while the rest of the job keeps running
+        let restart_at = ctx.last_transitioned_at + self.delay;
+        while restart_at > Instant::now() {
+            tokio::select! {
+                msg = ctx.rx.recv() => {
+                    if !Self::handle_message(ctx, msg).await? {
+                        return Ok(false);
+                    }
+                }
+                _ = tokio::time::sleep_until(restart_at.into()) => {}
+            }
+        }
+
+        let slots_needed = slots_for_job(&program);
+        if let Err(e) = ctx
+            .scheduler
+            .start_workers(start_pipeline_req(ctx, slots_needed, Some(worker_group)))
+            .await
+        {
+            return Err(anyhow!("failed to start workers: {:?}", e));
+        }
+
+        let mut workers = HashMap::new();
+        let worker_connects = Arc::new(Mutex::new(HashMap::new()));
+        let mut handles = vec![];
+
+        let startup_time = *config().pipeline.worker_startup_time;
+        let start = Instant::now();
+        while workers.values().map(|w| w.slots).sum::<usize>() < slots_needed {
+            let timeout = startup_time
+                .checked_sub(start.elapsed())
+                .unwrap_or(Duration::ZERO);
+
+            tokio::select! {
+                msg = ctx.rx.recv() => {
+                    match msg {
+                        Some(msg @ JobMessage::WorkerConnect { .. }) => {
+                            handle_worker_connect(msg, &mut workers, worker_connects.clone(), &mut handles, ctx)
+                                .await
+                                .map_err(|e| anyhow!("{:?}", e))?;
+                        }
+                        msg => {
+                            if !Self::handle_message(ctx, msg).await? {
+                                return Ok(false);
+                            }
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    return Err(anyhow!("timed out after {:?} while waiting for worker startup", startup_time));
+                }
+            }
+        }
+
+        for h in handles {
+            h.await?;
+        }
+
+        let assignments = compute_assignments(workers.values().collect(), &program);
+        let arrow_program = api::ArrowProgram::from(program.clone());
+        let plans = workers
+            .keys()
+            .map(|id| (*id, (arrow_program.clone(), assignments.clone())))
+            .collect();
+
+        // the rest of the job has moved on from the checkpoint we're restoring, so the region's
+        // tasks need to pick up at the epoch of the next barrier rather than the one after it
+        let next_epoch = ctx.job_controller.as_ref().unwrap().next_epoch();
+
+        let worker_connects = Arc::try_unwrap(worker_connects).unwrap().into_inner();
+        let worker_connects = start_execution(
+            ctx.config.id.clone(),
+            worker_connects,
+            &plans,
+            restore_epoch,
+            Some(next_epoch),
+            false,
+        )
+        .await?;
+
+        let task_startup_time = *config().pipeline.task_startup_time;
+        let start = Instant::now();
+        let mut started_tasks = HashSet::new();
+        while started_tasks.len() < program.task_count() {
+            let timeout = task_startup_time
+                .checked_sub(start.elapsed())
+                .unwrap_or(Duration::ZERO);
+
+            tokio::select! {
+                msg = ctx.rx.recv() => {
+                    match msg {
+                        Some(JobMessage::TaskStarted {
+                            operator_id,
+                            operator_subtask,
+                            ..
+                        }) => {
+                            started_tasks.insert((operator_id, operator_subtask));
+                        }
+                        msg => {
+                            if !Self::handle_message(ctx, msg).await? {
+                                return Ok(false);
+                            }
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    return Err(anyhow!("timed out after {:?} while waiting for tasks to start", task_startup_time));
+                }
+            }
+        }
+
+        ctx.job_controller
+            .as_mut()
+            .unwrap()
+            .restore_failover_region(self.region, worker_connects, &assignments);
+
+        info!(
+            message = "restarted failover region",
+            job_id = *ctx.config.id,
+            region = self.region,
+            duration = ctx.last_transitioned_at.elapsed().as_secs_f32()
+        );
+
+        Ok(true)
+    }
+
+    // Handles a message for the parts of the job that are still running; returns false if the job
+    // is being stopped
+    async fn handle_message<'a>(
+        ctx: &mut JobContext<'a>,
+        msg: Option<JobMessage>,
+    ) -> anyhow::Result<bool> {
+        match msg {
+            Some(JobMessage::ConfigUpdate(c)) => {
+                if c.stop_mode != StopMode::none {
+                    return Ok(false);
+                }
+                ctx.job_controller.as_mut().unwrap().update_config(c);
+                Ok(true)
+            }
+            Some(JobMessage::RunningMessage(msg)) => {
+                // the rest of the job's workers keep heartbeating and reporting on their tasks;
+                // any failures are picked up once we're back in running
+                ctx.job_controller
+                    .as_mut()
+                    .unwrap()
+                    .handle_message(msg)
+                    .await?;
+                Ok(true)
+            }
+            Some(msg) => {
+                ctx.handle(msg).map_err(|e| anyhow!("{:?}", e))?;
+                Ok(true)
+            }
+            None => {
+                panic!("job queue shut down");
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl State for RecoveringRegion {
+    fn name(&self) -> &'static str {
+        "RecoveringRegion"
+    }
+
+    async fn next(self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
+        match self.restart_region(ctx).await {
+            Ok(true) => Ok(Transition::next(*self, Running {})),
+            Ok(false) => {
+                // part of the job isn't running, so it can't be stopped gracefully
+                Ok(Transition::next(
+                    *self,
+                    Stopping {
+                        stop_mode: StopBehavior::StopWorkers,
+                    },
+                ))
+            }
+            Err(e) => {
+                warn!(
+                    message = "failed to restart failover region; recovering the whole job",
+                    job_id = *ctx.config.id,
+                    region = self.region,
+                    error = format!("{:?}", e)
+                );
+                Ok(Transition::next(
+                    *self,
+                    Recovering {
+                        delay: Duration::ZERO,
+                    },
+                ))
+            }
+        }
+    }
+}
@@ -1,12 +1,14 @@
 use std::time::{Duration, Instant};
 
+use anyhow::anyhow;
 use time::OffsetDateTime;
 use tokio::time::MissedTickBehavior;
 
 use tracing::error;
 
 use crate::states::finishing::Finishing;
-use crate::states::recovering::Recovering;
+use crate::states::recovering::{restart_delay, Recovering};
+use crate::states::recovering_region::RecoveringRegion;
 use crate::states::rescaling::Rescaling;
 use crate::states::restarting::Restarting;
 use crate::states::{fatal, stop_if_desired_running};
@@ -109,6 +111,24 @@ impl State for Running {
                                 Finishing {}
                             ))
                         },
+                        Ok(ControllerProgress::RegionFailed(region)) => {
+                            let delay = match restart_delay(
+                                &ctx.restart_strategy(),
+                                ctx.status.restarts,
+                                pipeline_config.allowed_restarts,
+                                &mut ctx.failure_times,
+                                Instant::now(),
+                            ) {
+                                Ok(delay) => delay,
+                                Err(message) => return Err(fatal(message, anyhow!("failover region {} failed", region))),
+                            };
+
+                            ctx.status.restart_delay = Some(delay.as_micros() as i64);
+                            return Ok(Transition::next(
+                                *self,
+                                RecoveringRegion { region, delay }
+                            ))
+                        },
                         Err(err) => {
                             error!(message = "error while running", error = format!("{:?}", err), job_id = *ctx.config.id);
                             log_event("running_error", json!({
@@ -123,15 +143,21 @@ impl State for Running {
                                 return Err(fatal("Job encountered a fatal error; see worker logs for details", err));
                             }
 
-                            if pipeline_config.allowed_restarts != -1 && ctx.status.restarts >= pipeline_config.allowed_restarts {
-                                return Err(fatal(
-                                    "Job has restarted too many times",
-                                    err
-                                ));
-                            }
+                            let delay = match restart_delay(
+                                &ctx.restart_strategy(),
+                                ctx.status.restarts,
+                                pipeline_config.allowed_restarts,
+                                &mut ctx.failure_times,
+                                Instant::now(),
+                            ) {
+                                Ok(delay) => delay,
+                                Err(message) => return Err(fatal(message, err)),
+                            };
+
+                            ctx.status.restart_delay = Some(delay.as_micros() as i64);
                             return Ok(Transition::next(
                                 *self,
-                                Recovering {}
+                                Recovering { delay }
                             ))
                         }
                     }
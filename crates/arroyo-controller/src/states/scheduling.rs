@@ -12,19 +12,28 @@ use tokio::{select, sync::Mutex, task::JoinHandle};
 use tonic::{transport::Channel, Request};
 use tracing::{error, info, warn};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, Context};
 use arroyo_datastream::logical::LogicalProgram;
-use arroyo_rpc::config::config;
+use arroyo_rpc::config::{config, FailoverStrategy};
 use arroyo_rpc::grpc::api;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_state::{
     committing_state::CommittingState,
+    compatibility::{OperatorStateStatus, StateCompatibilityReport},
     tables::{global_keyed_map::GlobalKeyedTable, ErasedTable},
     BackingStore, StateBackend,
 };
+use arroyo_worker::engine::operator_tables;
+use cornucopia_async::DatabaseSource;
+use time::OffsetDateTime;
 
 use crate::job_controller::job_metrics::JobMetrics;
+use crate::leader;
+use crate::JobConfig;
 use crate::{
-    job_controller::JobController, queries::controller_queries, states::stop_if_desired_non_running,
+    job_controller::{FailoverRegion, JobController},
+    queries::controller_queries,
+    states::stop_if_desired_non_running,
 };
 use crate::{schedulers::SchedulerError, JobMessage};
 use crate::{
@@ -32,19 +41,131 @@ use crate::{
     states::{fatal, StateError},
 };
 
+use super::queued::Queued;
 use super::{running::Running, JobContext, State, Transition};
 
 #[derive(Debug, Clone)]
-struct WorkerStatus {
+pub(super) struct WorkerStatus {
     id: WorkerId,
     data_address: String,
-    slots: usize,
+    pub(super) slots: usize,
 }
 
 #[derive(Debug)]
 pub struct Scheduling {}
 
-fn slots_for_job(job: &LogicalProgram) -> usize {
+// Checks that the state in the savepoint can be restored into the job's operators, before any of
+// it is copied. Returns the operators whose state is incompatible but should be dropped, as the
+// job was configured with `drop_incompatible_state`.
+async fn check_savepoint_compatibility(
+    program: &LogicalProgram,
+    config: &JobConfig,
+    url: &str,
+) -> anyhow::Result<HashSet<String>> {
+    let tables = operator_tables(program).await?;
+    let checkpoint = StateBackend::load_savepoint_metadata(url, &config.state_mapping)
+        .await
+        .with_context(|| format!("failed to read savepoint at {}", url))?;
+
+    let report = StateCompatibilityReport::check(&tables, &checkpoint);
+    info!(
+        message = "checked savepoint state compatibility",
+        job_id = *config.id,
+        url,
+        report = report.to_string()
+    );
+
+    if report.is_compatible() {
+        return Ok(HashSet::new());
+    }
+
+    if !config.drop_incompatible_state {
+        bail!(
+            "the pipeline is incompatible with the state of some operators in the savepoint:\n{}",
+            report
+        );
+    }
+
+    let dropped: HashSet<_> = report
+        .operators
+        .iter()
+        .filter(|(_, status)| matches!(status, OperatorStateStatus::Incompatible(_)))
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    warn!(
+        message = "starting operators with incompatible state from empty state",
+        job_id = *config.id,
+        url,
+        operators = ?dropped
+    );
+
+    Ok(dropped)
+}
+
+// Copies the state from a savepoint into a new checkpoint for this job, which will then be
+// picked up as the latest successful checkpoint when the job is started
+async fn restore_savepoint(
+    db: &DatabaseSource,
+    config: &JobConfig,
+    url: &str,
+    skip_operators: &HashSet<String>,
+) -> anyhow::Result<()> {
+    let c = db.client().await?;
+    let last_epoch = controller_queries::fetch_last_checkpoint_epoch(&c, &*config.id)
+        .await?
+        .into_iter()
+        .next()
+        .unwrap_or(0);
+    let epoch = last_epoch as u32 + 1;
+
+    info!(
+        message = "restoring savepoint",
+        job_id = *config.id,
+        url,
+        epoch
+    );
+
+    StateBackend::restore_savepoint(
+        url,
+        &config.id,
+        epoch,
+        &config.state_mapping,
+        skip_operators,
+    )
+    .await?;
+
+    let checkpoint_id = generate_id(IdTypes::Checkpoint);
+    let (lease_holder, lease_term) = leader::lease_fence();
+    let created = controller_queries::execute_create_checkpoint(
+        &c,
+        &checkpoint_id,
+        &config.organization_id,
+        &*config.id,
+        &StateBackend::name().to_string(),
+        &(epoch as i32),
+        &(epoch as i32),
+        &OffsetDateTime::now_utc(),
+        &lease_holder,
+        &lease_term,
+    )
+    .await?;
+    leader::check_fenced(created, "create checkpoint")?;
+
+    let updated = controller_queries::execute_commit_checkpoint(
+        &c,
+        &OffsetDateTime::now_utc(),
+        &checkpoint_id,
+        &lease_holder,
+        &lease_term,
+    )
+    .await?;
+    leader::check_fenced(updated, "commit checkpoint")
+}
+
+// each slot runs at most one subtask of every operator, so a job needs as many slots as the
+// parallelism of its largest operator
+pub(super) fn slots_for_job(job: &LogicalProgram) -> usize {
     job.graph
         .node_weights()
         .map(|n| n.parallelism)
@@ -52,7 +173,7 @@ fn slots_for_job(job: &LogicalProgram) -> usize {
         .unwrap_or(0)
 }
 
-fn compute_assignments(
+pub(super) fn compute_assignments(
     workers: Vec<&WorkerStatus>,
     program: &LogicalProgram,
 ) -> Vec<TaskAssignment> {
@@ -80,7 +201,132 @@ fn compute_assignments(
     assignments
 }
 
-async fn handle_worker_connect<'a>(
+// Splits the workers between the failover regions, so that each region gets workers of its own
+// with enough slots to run it, or returns None if that isn't possible
+fn isolate_regions<'a>(
+    workers: &[&'a WorkerStatus],
+    slots_needed: &[usize],
+) -> Option<Vec<Vec<&'a WorkerStatus>>> {
+    let mut free = workers.to_vec();
+    free.sort_by_key(|w| (w.slots, w.id.0));
+
+    let mut regions: Vec<usize> = (0..slots_needed.len()).collect();
+    regions.sort_by_key(|r| std::cmp::Reverse(slots_needed[*r]));
+
+    let mut assigned = vec![vec![]; slots_needed.len()];
+    for region in regions {
+        let mut needed = slots_needed[region];
+        while needed > 0 {
+            // prefer the smallest worker that fits the rest of the region, otherwise take the
+            // largest one and keep going
+            let idx = free
+                .iter()
+                .position(|w| w.slots >= needed)
+                .or_else(|| free.len().checked_sub(1))?;
+            let worker = free.remove(idx);
+            needed = needed.saturating_sub(worker.slots);
+            assigned[region].push(worker);
+        }
+    }
+
+    Some(assigned)
+}
+
+pub(super) fn start_pipeline_req(
+    ctx: &JobContext,
+    slots: usize,
+    worker_group: Option<String>,
+) -> StartPipelineReq {
+    StartPipelineReq {
+        program: ctx.program.clone(),
+        wasm_path: "".to_string(),
+        job_id: ctx.config.id.clone(),
+        run_id: ctx.status.run_id,
+        name: ctx.config.pipeline_name.clone(),
+        hash: ctx.program.get_hash(),
+        slots,
+        env_vars: [(
+            "ARROYO__CHECKPOINT_URL".to_string(),
+            config().checkpoint_url.clone(),
+        )]
+        .into_iter()
+        .collect(),
+        worker_group,
+        node_selector: ctx.config.node_selector.clone(),
+    }
+}
+
+/// Sends each worker the program and task assignments it should run, returning the workers'
+/// clients once they have all accepted them
+pub(super) async fn start_execution(
+    job_id: Arc<String>,
+    worker_connects: HashMap<WorkerId, WorkerGrpcClient<Channel>>,
+    plans: &HashMap<WorkerId, (api::ArrowProgram, Vec<TaskAssignment>)>,
+    restore_epoch: Option<u32>,
+    next_epoch: Option<u32>,
+    check_state_compatibility: bool,
+) -> anyhow::Result<HashMap<WorkerId, WorkerGrpcClient<Channel>>> {
+    let tasks: Vec<_> = worker_connects
+        .into_iter()
+        .filter_map(|(id, mut c)| {
+            let Some((program, assignments)) = plans.get(&id).cloned() else {
+                // any extra workers are left idle until the job is stopped
+                warn!(
+                    message = "worker is not needed to run the job",
+                    job_id = *job_id,
+                    worker_id = id.0
+                );
+                return None;
+            };
+            let job_id = job_id.clone();
+            Some(tokio::spawn(async move {
+                info!(
+                    message = "starting execution on worker",
+                    job_id = *job_id,
+                    worker_id = id.0
+                );
+                for i in 0..10 {
+                    match c
+                        .start_execution(Request::new(StartExecutionReq {
+                            restore_epoch,
+                            program: Some(program.clone()),
+                            tasks: assignments.clone(),
+                            check_state_compatibility,
+                            next_epoch,
+                        }))
+                        .await
+                    {
+                        Ok(_) => {
+                            return (id, c);
+                        }
+                        Err(e) => {
+                            error!(
+                                message = "failed to start execution on worker",
+                                job_id = *job_id,
+                                worker_id = id.0,
+                                attempt = i,
+                                error = format!("{:?}", e)
+                            );
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+
+                panic!("Failed to start execution on workers {:?}", id);
+            }))
+        })
+        .collect();
+
+    let mut worker_connects = HashMap::new();
+    for t in tasks {
+        let (id, c) = t.await?;
+        worker_connects.insert(id, c);
+    }
+
+    Ok(worker_connects)
+}
+
+pub(super) async fn handle_worker_connect<'a>(
     msg: JobMessage,
     workers: &mut HashMap<WorkerId, WorkerStatus>,
     worker_connects: Arc<Mutex<HashMap<WorkerId, WorkerGrpcClient<Channel>>>>,
@@ -88,6 +334,40 @@ async fn handle_worker_connect<'a>(
     ctx: &mut JobContext<'a>,
 ) -> Result<(), StateError> {
     match msg {
+        JobMessage::WorkerConnect {
+            worker_id,
+            node_id,
+            slots,
+            run_id,
+            tasks,
+            ..
+        } if !tasks.is_empty() => {
+            // a worker from a previous leader that re-registered too late to be adopted; it's
+            // stopped so that it doesn't keep running alongside the new workers
+            warn!(
+                message = "stopping worker left over from a previous leader",
+                job_id = *ctx.config.id,
+                worker_id = worker_id.0,
+                run_id
+            );
+            ctx.scheduler
+                .adopt_worker(ctx.config.id.clone(), run_id, worker_id, node_id, slots)
+                .await;
+            if run_id != ctx.status.run_id {
+                if let Err(e) = ctx
+                    .scheduler
+                    .stop_workers(&ctx.config.id, Some(run_id), true)
+                    .await
+                {
+                    warn!(
+                        message = "failed to stop worker left over from a previous leader",
+                        job_id = *ctx.config.id,
+                        worker_id = worker_id.0,
+                        error = format!("{:?}", e)
+                    );
+                }
+            }
+        }
         JobMessage::WorkerConnect {
             worker_id,
             rpc_address,
@@ -155,61 +435,35 @@ async fn handle_worker_connect<'a>(
 }
 
 impl Scheduling {
+    // Returns false if the scheduler doesn't have enough free slots to start the workers
     async fn start_workers<'a>(
-        self: Box<Self>,
+        &self,
         ctx: &mut JobContext<'a>,
         slots_needed: usize,
-    ) -> Result<Box<Self>, StateError> {
-        let start = Instant::now();
-        loop {
-            match ctx
-                .scheduler
-                .start_workers(StartPipelineReq {
-                    program: ctx.program.clone(),
-                    wasm_path: "".to_string(),
-                    job_id: ctx.config.id.clone(),
-                    run_id: ctx.status.run_id,
-                    name: ctx.config.pipeline_name.clone(),
-                    hash: ctx.program.get_hash(),
-                    slots: slots_needed,
-                    env_vars: [(
-                        "ARROYO__CHECKPOINT_URL".to_string(),
-                        config().checkpoint_url.clone(),
-                    )]
-                    .into_iter()
-                    .collect(),
-                })
-                .await
-            {
-                Ok(_) => break,
-                Err(SchedulerError::NotEnoughSlots { slots_needed: s }) => {
-                    warn!(
-                        message = "not enough slots for job",
-                        job_id = *ctx.config.id,
-                        slots_for_job = slots_needed,
-                        slots_needed = s
-                    );
-                    if start.elapsed() > *config().pipeline.worker_startup_time {
-                        return Err(fatal(
-                            "Not enough slots to schedule job",
-                            anyhow!("scheduler error -- needed {} slots", slots_needed),
-                        ));
-                    }
-                }
-                Err(SchedulerError::Other(s)) => {
-                    return Err(ctx.retryable(
-                        self,
-                        "encountered error during scheduling",
-                        anyhow::anyhow!("scheduling error: {}", s),
-                        10,
-                    ));
-                }
+        worker_group: Option<String>,
+    ) -> Result<bool, StateError> {
+        match ctx
+            .scheduler
+            .start_workers(start_pipeline_req(ctx, slots_needed, worker_group))
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(SchedulerError::NotEnoughSlots { slots_needed: s }) => {
+                warn!(
+                    message = "not enough slots for job",
+                    job_id = *ctx.config.id,
+                    slots_for_job = slots_needed,
+                    slots_needed = s
+                );
+                Ok(false)
             }
-
-            tokio::time::sleep(Duration::from_secs(1)).await;
+            Err(SchedulerError::Other(s)) => Err(ctx.retryable(
+                Box::new(Scheduling {}),
+                "encountered error during scheduling",
+                anyhow::anyhow!("scheduling error: {}", s),
+                10,
+            )),
         }
-
-        Ok(self)
     }
 }
 
@@ -236,8 +490,96 @@ impl State for Scheduling {
         ctx.program
             .update_parallelism(&ctx.config.parallelism_overrides);
 
-        let slots_needed: usize = slots_for_job(&*ctx.program);
-        self = self.start_workers(ctx, slots_needed).await?;
+        if let Err(e) = ctx.program.validate_parallelism() {
+            return Err(fatal("Invalid parallelism for pipeline", e));
+        }
+
+        // with region failover, each failover region runs on its own workers so that it can be
+        // restarted independently; preview pipelines are never restarted, so don't need this
+        let regions: Vec<(HashSet<String>, LogicalProgram)> = if config().pipeline.failover_strategy
+            == FailoverStrategy::Region
+            && ctx.config.ttl.is_none()
+        {
+            ctx.program
+                .failover_regions()
+                .into_iter()
+                .map(|operators| {
+                    let program = ctx.program.subprogram(&operators);
+                    (operators, program)
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        let region_slots: Vec<usize> = regions.iter().map(|(_, p)| slots_for_job(p)).collect();
+
+        let slots_needed: usize = if regions.len() > 1 {
+            region_slots.iter().sum()
+        } else {
+            slots_for_job(&*ctx.program)
+        };
+
+        // wait in the queue if the cluster doesn't have room for the job, or if other jobs are
+        // already waiting ahead of it
+        let free_slots = ctx
+            .scheduler
+            .free_slots(&ctx.config.id, &ctx.config.node_selector)
+            .await;
+        if !ctx.admission.can_admit(
+            &ctx.config.id,
+            ctx.config.priority,
+            slots_needed,
+            free_slots,
+        ) {
+            return Ok(Transition::next(
+                *self,
+                Queued {
+                    slots: slots_needed,
+                },
+            ));
+        }
+
+        // the job holds its place at the head of the queue until its workers have started (or
+        // scheduling fails), so that if the slots were taken in the meantime it waits for them in
+        // the same place
+        let started = async {
+            if regions.len() > 1 {
+                for (region, slots) in region_slots.iter().enumerate() {
+                    if !self
+                        .start_workers(ctx, *slots, Some(format!("r{}-0", region)))
+                        .await?
+                    {
+                        return Ok(false);
+                    }
+                }
+                Ok::<_, StateError>(true)
+            } else {
+                self.start_workers(ctx, slots_needed, None).await
+            }
+        }
+        .await;
+
+        if !matches!(started, Ok(false)) {
+            ctx.admission.remove(&ctx.config.id);
+        }
+
+        if !started? {
+            // the slots were taken by someone else in the meantime; release any we did get so
+            // that they're available to the jobs ahead of us
+            if let Err(e) = ctx.scheduler.stop_workers(&ctx.config.id, None, true).await {
+                warn!(
+                    message = "failed to stop workers before queueing job",
+                    job_id = *ctx.config.id,
+                    error = format!("{:?}", e)
+                );
+            }
+            return Ok(Transition::next(
+                *self,
+                Queued {
+                    slots: slots_needed,
+                },
+            ));
+        }
 
         // wait for them to connect and make outbound RPC connections
         let mut workers = HashMap::new();
@@ -246,6 +588,7 @@ impl State for Scheduling {
 
         let config = &config().pipeline;
 
+        let mut isolated_regions = None;
         let start = Instant::now();
         loop {
             let timeout = config
@@ -269,13 +612,34 @@ impl State for Scheduling {
                     }
                 }
                 _ = tokio::time::sleep(timeout) => {
+                    if regions.len() > 1 && workers.values().map(|w| w.slots).sum::<usize>() >= slots_needed {
+                        // the workers we got can't be split between the regions, but can still
+                        // run the job together
+                        warn!(
+                            message = "could not run failover regions on separate workers; failures will restart the whole job",
+                            job_id = *ctx.config.id
+                        );
+                        break;
+                    }
+
                     return Err(ctx.retryable(self,
                         "timed out while waiting for workers to start",
                         anyhow!("timed out after {:?} while waiting for worker startup", *config.worker_startup_time), 3));
                 }
             }
 
-            if workers.values().map(|w| w.slots).sum::<usize>() >= slots_needed {
+            if regions.len() > 1 {
+                let worker_list: Vec<_> = workers.values().collect();
+                if let Some(isolated) = isolate_regions(&worker_list, &region_slots) {
+                    isolated_regions = Some(
+                        isolated
+                            .into_iter()
+                            .map(|ws| ws.into_iter().cloned().collect::<Vec<_>>())
+                            .collect::<Vec<_>>(),
+                    );
+                    break;
+                }
+            } else if workers.values().map(|w| w.slots).sum::<usize>() >= slots_needed {
                 break;
             }
         }
@@ -288,6 +652,28 @@ impl State for Scheduling {
 
         // Compute assignments and send to workers
 
+        if let Some(url) = &ctx.config.restore_from {
+            let skip_operators =
+                match check_savepoint_compatibility(ctx.program, &ctx.config, url).await {
+                    Ok(skip_operators) => skip_operators,
+                    Err(e) => {
+                        return Err(fatal(
+                            format!("Failed to restore pipeline from savepoint at {}", url),
+                            e,
+                        ));
+                    }
+                };
+
+            if let Err(e) = restore_savepoint(&ctx.db, &ctx.config, url, &skip_operators).await {
+                return Err(fatal(
+                    format!("Failed to restore pipeline from savepoint at {}", url),
+                    e,
+                ));
+            }
+            // restore_from is cleared once the job is running (see `execute_state`); until then,
+            // a retried schedule restores the savepoint again into a new epoch
+        }
+
         // TODO: better error handling
 
         #[derive(Clone, Debug)]
@@ -298,15 +684,58 @@ impl State for Scheduling {
             needs_commits: bool,
         }
 
-        let checkpoint_info = controller_queries::fetch_last_successful_checkpoint(
-            &ctx.db.client().await.unwrap(),
-            &*ctx.config.id,
-        )
-        .await
-        .unwrap()
-        .into_iter()
-        .next()
-        .map(|r| {
+        let checkpoint = if let Some(epoch) = ctx.config.restore_epoch {
+            // roll back to a specific earlier checkpoint; the later ones are marked as failed
+            // below, so they will not be picked up by subsequent restarts
+            let checkpoint = match async {
+                anyhow::Ok(
+                    controller_queries::fetch_successful_checkpoint_at_epoch(
+                        &ctx.db.client().await?,
+                        &*ctx.config.id,
+                        &(epoch as i32),
+                    )
+                    .await?,
+                )
+            }
+            .await
+            {
+                Ok(checkpoints) => checkpoints.into_iter().next(),
+                Err(e) => {
+                    return Err(ctx.retryable(self, "failed to fetch checkpoint", e, 10));
+                }
+            };
+
+            let Some(checkpoint) = checkpoint else {
+                return Err(fatal(
+                    format!(
+                        "Failed to restore job; checkpoint {} is not available (it may have failed or been cleaned up)",
+                        epoch
+                    ),
+                    anyhow!("no successful checkpoint for epoch {}", epoch),
+                ));
+            };
+
+            Some(checkpoint)
+        } else {
+            match async {
+                anyhow::Ok(
+                    controller_queries::fetch_last_successful_checkpoint(
+                        &ctx.db.client().await?,
+                        &*ctx.config.id,
+                    )
+                    .await?,
+                )
+            }
+            .await
+            {
+                Ok(checkpoints) => checkpoints.into_iter().next(),
+                Err(e) => {
+                    return Err(ctx.retryable(self, "failed to fetch last checkpoint", e, 10));
+                }
+            }
+        };
+
+        let checkpoint_info = checkpoint.map(|r| {
             info!(
                 message = "restoring checkpoint",
                 job_id = *ctx.config.id,
@@ -328,13 +757,27 @@ impl State for Scheduling {
                 .as_ref()
                 .map(|checkpoint_info| checkpoint_info.epoch)
                 .unwrap_or(0);
-            controller_queries::execute_mark_failed(
-                &ctx.db.client().await.unwrap(),
-                &*ctx.config.id,
-                &(last_epoch as i32 + 1),
-            )
+            if let Err(e) = async {
+                let (lease_holder, lease_term) = leader::lease_fence();
+                controller_queries::execute_mark_failed(
+                    &ctx.db.client().await?,
+                    &*ctx.config.id,
+                    &(last_epoch as i32 + 1),
+                    &lease_holder,
+                    &lease_term,
+                )
+                .await?;
+                anyhow::Ok(())
+            }
             .await
-            .unwrap();
+            {
+                return Err(ctx.retryable(
+                    self,
+                    "failed to mark in-progress checkpoints as failed",
+                    e,
+                    10,
+                ));
+            }
         }
 
         let mut committing_state = None;
@@ -356,8 +799,42 @@ impl State for Scheduling {
                     )
                 })?;
 
-            if let Err(e) = StateBackend::prepare_checkpoint_load(&metadata).await {
-                return Err(ctx.retryable(self, "failed to prepare checkpoint for loading", e, 10));
+            // when rolling back to an earlier checkpoint, the data written by the later ones has to
+            // be removed so that it isn't mixed in with the new checkpoints that will reuse those
+            // epochs; otherwise anything left over from in-progress checkpoints is overwritten or
+            // removed by normal cleanup
+            if ctx.config.restore_epoch.is_some() {
+                if let Err(e) = async {
+                    leader::ensure_leader(&ctx.db).await?;
+                    StateBackend::prepare_checkpoint_load(&metadata).await
+                }
+                .await
+                {
+                    return Err(ctx.retryable(
+                        self,
+                        "failed to prepare checkpoint for loading",
+                        e,
+                        10,
+                    ));
+                }
+
+                // only roll back once; subsequent restarts should resume from the latest checkpoint
+                if let Err(e) = async {
+                    let (lease_holder, lease_term) = leader::lease_fence();
+                    let updated = controller_queries::execute_clear_restore_epoch(
+                        &ctx.db.client().await?,
+                        &*ctx.config.id,
+                        &lease_holder,
+                        &lease_term,
+                    )
+                    .await?;
+                    leader::check_fenced(updated, "clear restore epoch")
+                }
+                .await
+                {
+                    return Err(ctx.retryable(self, "failed to clear restore epoch", e, 10));
+                }
+                ctx.config.restore_epoch = None;
             }
             metadata.min_epoch = min_epoch;
             if needs_commits {
@@ -442,65 +919,48 @@ impl State for Scheduling {
                 })?;
         }
 
-        let assignments = compute_assignments(workers.values().collect(), &*ctx.program);
-        let worker_connects = Arc::try_unwrap(worker_connects).unwrap().into_inner();
         let program = api::ArrowProgram::from(ctx.program.clone());
-        let tasks: Vec<_> = worker_connects
-            .into_iter()
-            .map(|(id, mut c)| {
-                let assignments = assignments.clone();
-
-                let job_id = ctx.config.id.clone();
-                let restore_epoch = checkpoint_info.as_ref().map(|info| info.epoch);
-                let program = program.clone();
-                tokio::spawn(async move {
-                    info!(
-                        message = "starting execution on worker",
-                        job_id = *job_id,
-                        worker_id = id.0
-                    );
-                    for i in 0..10 {
-                        match c
-                            .start_execution(Request::new(StartExecutionReq {
-                                restore_epoch,
-                                program: Some(program.clone()),
-                                tasks: assignments.clone(),
-                            }))
-                            .await
-                        {
-                            Ok(_) => {
-                                return (id, c);
-                            }
-                            Err(e) => {
-                                error!(
-                                    message = "failed to start execution on worker",
-                                    job_id = *job_id,
-                                    worker_id = id.0,
-                                    attempt = i,
-                                    error = format!("{:?}", e)
-                                );
-                            }
-                        }
-                        tokio::time::sleep(Duration::from_millis(100)).await;
-                    }
-
-                    panic!("Failed to start execution on workers {:?}", id);
-                })
-            })
-            .collect();
-
-        let mut worker_connects = HashMap::new();
-        for t in tasks {
-            match t.await {
-                Ok((id, c)) => {
-                    worker_connects.insert(id, c);
-                }
-                Err(e) => {
-                    return Err(fatal("Failed to start cluster for pipeline", e.into()));
+        let mut plans = HashMap::new();
+        let mut failover_regions = vec![];
+        if let Some(isolated) = &isolated_regions {
+            for ((operators, region_program), region_workers) in regions.iter().zip(isolated) {
+                let assignments =
+                    compute_assignments(region_workers.iter().collect(), region_program);
+                let region_program = api::ArrowProgram::from(region_program.clone());
+                for w in region_workers {
+                    plans.insert(w.id, (region_program.clone(), assignments.clone()));
                 }
+
+                failover_regions.push(FailoverRegion {
+                    operators: operators.clone(),
+                    workers: region_workers.iter().map(|w| w.id).collect(),
+                    restarts: 0,
+                });
+            }
+        } else {
+            let assignments = compute_assignments(workers.values().collect(), &*ctx.program);
+            for id in workers.keys() {
+                plans.insert(*id, (program.clone(), assignments.clone()));
             }
         }
 
+        let worker_connects = Arc::try_unwrap(worker_connects).unwrap().into_inner();
+        let worker_connects = match start_execution(
+            ctx.config.id.clone(),
+            worker_connects,
+            &plans,
+            checkpoint_info.as_ref().map(|info| info.epoch),
+            None,
+            ctx.config.restore_from.is_some(),
+        )
+        .await
+        {
+            Ok(worker_connects) => worker_connects,
+            Err(e) => {
+                return Err(fatal("Failed to start cluster for pipeline", e));
+            }
+        };
+
         // wait until all tasks are running
         let start = Instant::now();
         let mut started_tasks = HashSet::new();
@@ -572,6 +1032,11 @@ impl State for Scheduling {
                 .expect("failed to send commit messages");
         }
 
+        controller.set_failover_regions(failover_regions);
+        for (_, assignments) in plans.values() {
+            controller.set_task_workers(assignments);
+        }
+
         ctx.job_controller = Some(controller);
         Ok(Transition::next(*self, Running {}))
     }
@@ -1,6 +1,6 @@
 use datafusion_proto::protobuf::ArrowType;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use arrow_schema::DataType;
 use arroyo_rpc::api_types::pipelines::{PipelineEdge, PipelineGraph, PipelineNode};
 use arroyo_rpc::df::ArroyoSchema;
@@ -8,7 +8,7 @@ use arroyo_rpc::grpc::api;
 use arroyo_rpc::grpc::api::{
     ArrowDylibUdfConfig, ArrowProgram, ArrowProgramConfig, ConnectorOp, EdgeType,
 };
-use petgraph::graph::DiGraph;
+use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::prelude::EdgeRef;
 use petgraph::Direction;
 use prost::Message;
@@ -219,6 +219,142 @@ impl LogicalProgram {
         }
     }
 
+    /// Returns the operators connected to `idx` (including itself) through forward edges, which
+    /// must all have the same parallelism
+    pub fn forward_chain(&self, idx: NodeIndex) -> HashSet<NodeIndex> {
+        let mut chain = HashSet::new();
+        let mut stack = vec![idx];
+        while let Some(idx) = stack.pop() {
+            if !chain.insert(idx) {
+                continue;
+            }
+
+            for direction in [Direction::Incoming, Direction::Outgoing] {
+                for edge in self.graph.edges_directed(idx, direction) {
+                    if edge.weight().edge_type == LogicalEdgeType::Forward {
+                        stack.push(if direction == Direction::Incoming {
+                            edge.source()
+                        } else {
+                            edge.target()
+                        });
+                    }
+                }
+            }
+        }
+        chain
+    }
+
+    /// Expands a map of per-operator parallelism overrides so that it also covers the operators
+    /// forward-connected to each overridden operator, returning an error if an operator doesn't
+    /// exist or if two overrides conflict
+    pub fn expand_parallelism_overrides(
+        &self,
+        overrides: &HashMap<String, usize>,
+    ) -> anyhow::Result<HashMap<String, usize>> {
+        let mut expanded: HashMap<String, (usize, &str)> = HashMap::new();
+
+        for (operator_id, parallelism) in overrides {
+            if *parallelism == 0 {
+                bail!(
+                    "parallelism for operator {} must be at least 1",
+                    operator_id
+                );
+            }
+
+            let idx = self
+                .operator_index(operator_id)
+                .ok_or_else(|| anyhow!("no operator with id {} in the pipeline", operator_id))?;
+
+            for idx in self.forward_chain(NodeIndex::new(idx as usize)) {
+                let id = self.graph[idx].operator_id.clone();
+                match expanded.get(&id) {
+                    Some((p, from)) if p != parallelism => {
+                        bail!(
+                            "conflicting parallelism for operators {} ({}) and {} ({}), which are \
+                            connected by forward edges and must have the same parallelism",
+                            from,
+                            p,
+                            operator_id,
+                            parallelism
+                        );
+                    }
+                    _ => {
+                        expanded.insert(id, (*parallelism, operator_id));
+                    }
+                }
+            }
+        }
+
+        Ok(expanded.into_iter().map(|(k, (p, _))| (k, p)).collect())
+    }
+
+    /// Checks that every operator has a parallelism of at least 1, and that operators connected
+    /// by forward edges have the same parallelism
+    pub fn validate_parallelism(&self) -> anyhow::Result<()> {
+        for node in self.graph.node_weights() {
+            if node.parallelism == 0 {
+                bail!("operator {} has a parallelism of 0", node.operator_id);
+            }
+        }
+
+        for edge in self.graph.edge_references() {
+            let from = &self.graph[edge.source()];
+            let to = &self.graph[edge.target()];
+            if edge.weight().edge_type == LogicalEdgeType::Forward
+                && from.parallelism != to.parallelism
+            {
+                bail!(
+                    "operators {} and {} are connected by a forward edge but have different \
+                    parallelism ({} != {})",
+                    from.operator_id,
+                    to.operator_id,
+                    from.parallelism,
+                    to.parallelism
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Splits the program into failover regions: sets of operators that exchange data with each
+    /// other, directly or indirectly, but not with any operator outside the set. A failure in one
+    /// region can be recovered by restarting only that region's tasks. Regions are returned in
+    /// the order of their first operator in the graph.
+    pub fn failover_regions(&self) -> Vec<HashSet<String>> {
+        let mut regions: Vec<HashSet<String>> = vec![];
+        let mut visited = HashSet::new();
+
+        for start in self.graph.node_indices() {
+            if visited.contains(&start) {
+                continue;
+            }
+
+            let mut region = HashSet::new();
+            let mut stack = vec![start];
+            while let Some(idx) = stack.pop() {
+                if !visited.insert(idx) {
+                    continue;
+                }
+                region.insert(self.graph[idx].operator_id.clone());
+                stack.extend(self.graph.neighbors_undirected(idx));
+            }
+            regions.push(region);
+        }
+
+        regions
+    }
+
+    /// Returns a program containing only the given operators and the edges between them
+    pub fn subprogram(&self, operators: &HashSet<String>) -> LogicalProgram {
+        let graph = self.graph.filter_map(
+            |_, node| operators.contains(&node.operator_id).then(|| node.clone()),
+            |_, edge| Some(edge.clone()),
+        );
+
+        LogicalProgram::new(graph, self.program_config.clone())
+    }
+
     pub fn task_count(&self) -> usize {
         // TODO: this can be cached
         self.graph.node_weights().map(|nw| nw.parallelism).sum()
@@ -466,3 +602,116 @@ impl From<LogicalProgram> for ArrowProgram {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn program(operators: &[&str], edges: &[(&str, &str)]) -> LogicalProgram {
+        let schema = ArroyoSchema::from_fields(vec![]);
+        let mut graph = LogicalGraph::new();
+        let indices: HashMap<&str, NodeIndex> = operators
+            .iter()
+            .map(|op| {
+                let idx = graph.add_node(LogicalNode {
+                    operator_id: op.to_string(),
+                    description: op.to_string(),
+                    operator_name: OperatorName::ArrowValue,
+                    operator_config: vec![],
+                    parallelism: 2,
+                });
+                (*op, idx)
+            })
+            .collect();
+
+        for (from, to) in edges {
+            graph.add_edge(
+                indices[from],
+                indices[to],
+                LogicalEdge::project_all(LogicalEdgeType::Shuffle, schema.clone()),
+            );
+        }
+
+        LogicalProgram::new(graph, ProgramConfig::default())
+    }
+
+    fn operators(ops: &[&str]) -> HashSet<String> {
+        ops.iter().map(|op| op.to_string()).collect()
+    }
+
+    #[test]
+    fn test_failover_regions_of_disconnected_branches() {
+        let program = program(
+            &["a_source", "b_source", "a_map", "b_map", "a_sink", "b_sink"],
+            &[
+                ("a_source", "a_map"),
+                ("a_map", "a_sink"),
+                ("b_source", "b_map"),
+                ("b_map", "b_sink"),
+            ],
+        );
+
+        assert_eq!(
+            program.failover_regions(),
+            vec![
+                operators(&["a_source", "a_map", "a_sink"]),
+                operators(&["b_source", "b_map", "b_sink"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failover_regions_of_connected_branches() {
+        // a diamond: the branches split and rejoin
+        let diamond = program(
+            &["source", "left", "right", "join"],
+            &[
+                ("source", "left"),
+                ("source", "right"),
+                ("left", "join"),
+                ("right", "join"),
+            ],
+        );
+        assert_eq!(
+            diamond.failover_regions(),
+            vec![operators(&["source", "left", "right", "join"])]
+        );
+
+        // two sources that are joined are connected, even though neither reaches the other
+        let join = program(
+            &["left", "right", "join", "sink"],
+            &[("left", "join"), ("right", "join"), ("join", "sink")],
+        );
+        assert_eq!(
+            join.failover_regions(),
+            vec![operators(&["left", "right", "join", "sink"])]
+        );
+
+        let single = program(&["source"], &[]);
+        assert_eq!(single.failover_regions(), vec![operators(&["source"])]);
+    }
+
+    #[test]
+    fn test_subprogram() {
+        let program = program(
+            &["a_source", "b_source", "a_sink", "b_sink"],
+            &[("a_source", "a_sink"), ("b_source", "b_sink")],
+        );
+
+        let region = operators(&["b_source", "b_sink"]);
+        let subprogram = program.subprogram(&region);
+
+        assert_eq!(subprogram.graph.node_count(), 2);
+        assert_eq!(subprogram.graph.edge_count(), 1);
+        assert_eq!(subprogram.task_count(), 4);
+        assert_eq!(
+            subprogram
+                .operator_indices
+                .keys()
+                .cloned()
+                .collect::<HashSet<_>>(),
+            region
+        );
+        assert_eq!(subprogram.failover_regions(), vec![region]);
+    }
+}
@@ -2,13 +2,14 @@ use std::collections::HashMap;
 use std::sync::{Arc, OnceLock, RwLock};
 
 use arroyo_types::{
-    TaskInfo, BATCHES_RECV, BATCHES_SENT, BYTES_RECV, BYTES_SENT, DESERIALIZATION_ERRORS,
-    MESSAGES_RECV, MESSAGES_SENT,
+    TaskInfo, BATCHES_RECV, BATCHES_SENT, BUSY_TIME, BYTES_RECV, BYTES_SENT,
+    DESERIALIZATION_ERRORS, MESSAGES_RECV, MESSAGES_SENT, SOURCE_LAG,
 };
 use lazy_static::lazy_static;
 use prometheus::{
-    labels, register_histogram, register_int_counter_vec, register_int_gauge, Histogram,
-    HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts,
+    labels, register_histogram, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts,
 };
 
 pub fn gauge_for_task(
@@ -85,6 +86,28 @@ lazy_static! {
         &TASK_METRIC_LABELS
     )
     .unwrap();
+    pub static ref BUSY_TIME_COUNTER: IntCounterVec = register_int_counter_vec!(
+        BUSY_TIME,
+        "Microseconds this subtask has spent processing data",
+        &TASK_METRIC_LABELS
+    )
+    .unwrap();
+    pub static ref SOURCE_LAG_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        SOURCE_LAG,
+        "How far this source subtask is behind the end of its input, in the source's units \
+         (messages for Kafka, milliseconds for Kinesis)",
+        &TASK_METRIC_LABELS
+    )
+    .unwrap();
+}
+
+/// The gauge that sources report their lag with
+pub fn source_lag_gauge(task_info: &TaskInfo) -> IntGauge {
+    SOURCE_LAG_GAUGE.with_label_values(&[
+        &task_info.operator_id,
+        &task_info.task_index.to_string(),
+        &task_info.operator_name,
+    ])
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
@@ -96,10 +119,11 @@ pub enum TaskCounters {
     BytesReceived,
     BytesSent,
     DeserializationErrors,
+    BusyTime,
 }
 
 impl TaskCounters {
-    pub fn variants() -> [TaskCounters; 7] {
+    pub fn variants() -> [TaskCounters; 8] {
         use TaskCounters::*;
 
         [
@@ -110,6 +134,7 @@ impl TaskCounters {
             BytesReceived,
             BytesSent,
             DeserializationErrors,
+            BusyTime,
         ]
     }
 }
@@ -125,6 +150,7 @@ impl TaskCounters {
             TaskCounters::BytesReceived => &BYTES_RECEIVED_COUNTER,
             TaskCounters::BytesSent => &BYTES_SENT_COUNTER,
             TaskCounters::DeserializationErrors => &DESERIALIZATION_ERRORS_COUNTER,
+            TaskCounters::BusyTime => &BUSY_TIME_COUNTER,
         }
     }
 
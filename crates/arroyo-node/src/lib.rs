@@ -22,11 +22,10 @@ use arroyo_types::{to_millis, NodeId, WorkerId, JOB_ID_ENV, RUN_ID_ENV};
 use lazy_static::lazy_static;
 use prometheus::{register_gauge, Gauge};
 use rand::random;
-use std::process::exit;
 use tokio::sync::mpsc::{channel, Sender};
 use tokio::{process::Command, select};
 use tonic::{Request, Response, Status};
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 
 lazy_static! {
     static ref WORKERS: Gauge = register_gauge!(
@@ -46,6 +45,7 @@ pub struct WorkerStatus {
 
 pub struct NodeServer {
     id: NodeId,
+    task_slots: usize,
     worker_finished_tx: Sender<WorkerFinishedReq>,
     workers: Arc<Mutex<HashMap<WorkerId, WorkerStatus>>>,
 }
@@ -81,7 +81,6 @@ impl NodeServer {
 
         info!("Starting worker for job {}", req.job_id);
 
-        // TODO: Check that we have enough slots to schedule this
         let slots = req.slots;
         let state = Arc::clone(&self.workers);
         let worker_id = WorkerId(random());
@@ -90,6 +89,28 @@ impl NodeServer {
 
         let mut workers = self.workers.lock().unwrap();
 
+        // the controller may have a stale view of our slots (for example, after a new controller
+        // becomes leader), so we check them here as well rather than overcommitting
+        let used_slots: usize = workers
+            .values()
+            .filter(|w| w.running)
+            .map(|w| w.slots)
+            .sum();
+        let free_slots = self.task_slots.saturating_sub(used_slots);
+        if slots as usize > free_slots {
+            warn!(
+                message = "not enough slots to start worker",
+                job_id = req.job_id,
+                slots,
+                free_slots
+            );
+            return Err(Status::resource_exhausted(format!(
+                "not enough free slots on node: requested {}, but only {} are free",
+                slots, free_slots
+            ))
+            .into());
+        }
+
         let mut command = Command::new(current_exe().expect("Could not get path of worker binary"));
 
         for (env, value) in req.env_vars {
@@ -160,10 +181,13 @@ impl NodeGrpc for NodeServer {
     ) -> Result<Response<StartWorkerResp>, Status> {
         let req = request.into_inner();
 
-        let worker_id = self
-            .start_worker_int(req)
-            .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+        let worker_id =
+            self.start_worker_int(req)
+                .await
+                .map_err(|e| match e.downcast::<Status>() {
+                    Ok(status) => status,
+                    Err(e) => Status::internal(e.to_string()),
+                })?;
 
         Ok(Response::new(StartWorkerResp {
             worker_id: worker_id.0,
@@ -245,6 +269,7 @@ pub async fn start_server(guard: ShutdownGuard) -> NodeId {
 
     let server = NodeServer {
         id: node_id,
+        task_slots: config.node.task_slots as usize,
         workers: Arc::new(Mutex::new(HashMap::new())),
         worker_finished_tx,
     };
@@ -287,25 +312,34 @@ pub async fn start_server(guard: ShutdownGuard) -> NodeId {
         loop {
             match ControllerGrpcClient::connect(config.controller_endpoint()).await {
                 Ok(mut controller) => {
-                    controller
+                    // this may be a standby controller, or the previous leader may have gone away;
+                    // either way, we reconnect and register again with whichever is now the leader
+                    if let Err(e) = controller
                         .register_node(Request::new(RegisterNodeReq {
                             node_id: node_id.0,
                             task_slots: config.node.task_slots as u64,
                             addr: req_addr.clone(),
+                            labels: config.node.labels.clone().into_iter().collect(),
                         }))
                         .await
-                        .unwrap();
+                    {
+                        if attempts % 50 == 0 {
+                            warn!("failed to register with controller: {:?}", e);
+                        }
+                        attempts += 1;
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
 
                     info!("Connected to controller");
+                    attempts = 0;
                     loop {
                         select! {
                             _ = tokio::time::sleep(Duration::from_secs(5)) => {},
                             msg = worker_finished_rx.recv() => {
-                                controller.worker_finished(Request::new(msg.unwrap())).await
-                                .unwrap_or_else(|err| {
-                                    error!("shutting down: controller failed to report finished worker with {:?}", err);
-                                    exit(1);
-                                });
+                                if let Err(err) = controller.worker_finished(Request::new(msg.unwrap())).await {
+                                    warn!("failed to report finished worker to controller: {:?}", err);
+                                }
                             }
                         }
 
@@ -316,7 +350,8 @@ pub async fn start_server(guard: ShutdownGuard) -> NodeId {
                             }))
                             .await
                         {
-                            bail!("shutting down: controller failed heartbeat with {:?}", e);
+                            warn!("controller failed heartbeat, reconnecting: {:?}", e);
+                            break;
                         }
                     }
                 }
@@ -10,11 +10,12 @@ use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::rpc::{CheckpointMetadata, TableConfig, TaskCheckpointEventType};
 use arroyo_rpc::schema_resolver::SchemaResolver;
-use arroyo_rpc::{get_hasher, CompactionResult, ControlMessage, ControlResp};
+use arroyo_rpc::{get_hasher, CompactionResult, ControlMessage, ControlResp, StateQuery};
 use arroyo_state::tables::table_manager::TableManager;
 use arroyo_state::{BackingStore, StateBackend};
 use arroyo_types::{
-    from_micros, ArrowMessage, CheckpointBarrier, SourceError, TaskInfo, UserError, Watermark,
+    from_micros, ArrowMessage, CheckpointBarrier, SignalMessage, SourceError, TaskInfo, UserError,
+    Watermark,
 };
 use datafusion::common::hash_utils;
 use rand::Rng;
@@ -26,7 +27,7 @@ use std::time::{Instant, SystemTime};
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::{unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender};
 use tokio::sync::Notify;
-use tracing::warn;
+use tracing::{info, warn};
 
 pub type QueueItem = ArrowMessage;
 
@@ -84,11 +85,15 @@ impl WatermarkHolder {
 }
 
 /// A wrapper for an UnboundedSender<QueueItem> that bounds by the number of rows within
-/// a batch rather than the number of batches
+/// a batch rather than the number of batches.
+///
+/// Unaligned checkpoint barriers are sent on a separate priority lane that isn't bounded, so
+/// that they overtake any queued data.
 #[derive(Clone)]
 pub struct BatchSender {
     size: u32,
     tx: UnboundedSender<QueueItem>,
+    priority_tx: UnboundedSender<QueueItem>,
     queued_messages: Arc<AtomicU32>,
     queued_bytes: Arc<AtomicU64>,
     notify: Arc<Notify>,
@@ -110,8 +115,17 @@ fn message_bytes(item: &QueueItem) -> u64 {
     }
 }
 
+#[inline]
+fn is_priority(item: &QueueItem) -> bool {
+    matches!(item, QueueItem::Signal(SignalMessage::Barrier(b)) if b.unaligned)
+}
+
 impl BatchSender {
     pub async fn send(&self, item: QueueItem) -> Result<(), SendError<QueueItem>> {
+        if is_priority(&item) {
+            return self.priority_tx.send(item);
+        }
+
         // Ensure that every message is sendable, even if it's bigger than our max size
         let count = message_count(&item, self.size);
         loop {
@@ -162,6 +176,7 @@ impl BatchSender {
 pub struct BatchReceiver {
     size: u32,
     rx: UnboundedReceiver<QueueItem>,
+    priority_rx: Option<UnboundedReceiver<QueueItem>>,
     queued_messages: Arc<AtomicU32>,
     queued_bytes: Arc<AtomicU64>,
     notify: Arc<Notify>,
@@ -169,7 +184,16 @@ pub struct BatchReceiver {
 
 impl BatchReceiver {
     pub async fn recv(&mut self) -> Option<QueueItem> {
-        let item = self.rx.recv().await;
+        let item = match &mut self.priority_rx {
+            Some(priority_rx) => tokio::select! {
+                biased;
+                Some(item) = priority_rx.recv() => {
+                    return Some(item);
+                }
+                item = self.rx.recv() => item,
+            },
+            None => self.rx.recv().await,
+        };
         if let Some(item) = &item {
             let count = message_count(item, self.size);
             self.queued_messages.fetch_sub(count, Ordering::SeqCst);
@@ -179,10 +203,17 @@ impl BatchReceiver {
         }
         item
     }
+
+    /// Takes the priority lane, so that it can be read independently of the queued data; after
+    /// this, [`BatchReceiver::recv`] only returns items from the data lane
+    pub fn take_priority(&mut self) -> Option<UnboundedReceiver<QueueItem>> {
+        self.priority_rx.take()
+    }
 }
 
 pub fn batch_bounded(size: u32) -> (BatchSender, BatchReceiver) {
     let (tx, rx) = unbounded_channel();
+    let (priority_tx, priority_rx) = unbounded_channel();
     let notify = Arc::new(Notify::new());
     let queued_messages = Arc::new(AtomicU32::new(0));
     let queued_bytes = Arc::new(AtomicU64::new(0));
@@ -190,6 +221,7 @@ pub fn batch_bounded(size: u32) -> (BatchSender, BatchReceiver) {
         BatchSender {
             size,
             tx,
+            priority_tx,
             queued_messages: queued_messages.clone(),
             queued_bytes: queued_bytes.clone(),
             notify: notify.clone(),
@@ -197,6 +229,7 @@ pub fn batch_bounded(size: u32) -> (BatchSender, BatchReceiver) {
         BatchReceiver {
             size,
             rx,
+            priority_rx: Some(priority_rx),
             notify,
             queued_bytes,
             queued_messages,
@@ -418,6 +451,7 @@ impl ArrowContext {
     pub async fn new(
         task_info: TaskInfo,
         restore_from: Option<CheckpointMetadata>,
+        next_epoch: Option<u32>,
         control_rx: Receiver<ControlMessage>,
         control_tx: Sender<ControlResp>,
         input_partitions: usize,
@@ -428,27 +462,39 @@ impl ArrowContext {
         tables: HashMap<String, TableConfig>,
     ) -> Self {
         let (watermark, metadata) = if let Some(metadata) = restore_from {
-            let (watermark, operator_metadata) = {
-                let metadata = StateBackend::load_operator_metadata(
-                    &task_info.job_id,
-                    &task_info.operator_id,
-                    metadata.epoch,
-                )
-                .await
-                .expect("lookup should succeed")
-                .expect("require metadata");
-                (
-                    metadata
+            let operator_metadata = StateBackend::load_operator_metadata(
+                &task_info.job_id,
+                &task_info.operator_id,
+                metadata.epoch,
+            )
+            .await
+            .expect("lookup should succeed");
+
+            match operator_metadata {
+                Some(operator_metadata) => (
+                    operator_metadata
                         .operator_metadata
                         .as_ref()
                         .unwrap()
                         .min_watermark
                         .map(from_micros),
-                    metadata,
-                )
-            };
-
-            (watermark, Some(operator_metadata))
+                    Some(operator_metadata),
+                ),
+                None if !metadata.operator_ids.contains(&task_info.operator_id) => {
+                    // operators that weren't in the checkpoint (which the compatibility check
+                    // reports as new) start empty
+                    info!(
+                        message = "no state for operator in checkpoint; starting empty",
+                        operator_id = task_info.operator_id,
+                        epoch = metadata.epoch
+                    );
+                    (None, None)
+                }
+                None => panic!(
+                    "missing metadata for operator {} in checkpoint {}",
+                    task_info.operator_id, metadata.epoch
+                ),
+            }
         } else {
             (None, None)
         };
@@ -485,10 +531,15 @@ impl ArrowContext {
             m.for_task(&task_info, |_| {});
         }
 
-        let table_manager =
-            TableManager::new(task_info.clone(), tables, control_tx.clone(), metadata)
-                .await
-                .expect("should be able to create TableManager");
+        let table_manager = TableManager::new(
+            task_info.clone(),
+            tables,
+            control_tx.clone(),
+            metadata,
+            next_epoch,
+        )
+        .await
+        .expect("should be able to create TableManager");
 
         Self {
             task_info: task_info.clone(),
@@ -629,6 +680,15 @@ impl ArrowContext {
             .expect("should be able to load compacted");
     }
 
+    pub async fn query_state(&mut self, query: StateQuery) {
+        let result = self
+            .table_manager
+            .query_state(&query.table, query.key.as_ref(), query.limit)
+            .await;
+        // the requester may have stopped waiting, in which case there's nobody to respond to
+        let _ = query.tx.send(result);
+    }
+
     pub fn initialize_deserializer(
         &mut self,
         format: Format,
@@ -881,4 +941,70 @@ mod tests {
 
         assert_eq!(tx.capacity(), 8);
     }
+
+    #[tokio::test]
+    async fn test_unaligned_barriers_overtake_data() {
+        let (tx, mut rx) = batch_bounded(8);
+        let msg = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("x", DataType::Int64, false)])),
+            vec![Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5, 6, 7, 8]))],
+        )
+        .unwrap();
+
+        tx.send(ArrowMessage::Data(msg.clone())).await.unwrap();
+        assert_eq!(tx.capacity(), 0);
+
+        // the queue is full, but the barrier should still go through
+        let barrier = CheckpointBarrier {
+            epoch: 1,
+            min_epoch: 0,
+            timestamp: SystemTime::now(),
+            then_stop: false,
+            unaligned: true,
+        };
+        tx.send(ArrowMessage::Signal(SignalMessage::Barrier(barrier)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            ArrowMessage::Signal(SignalMessage::Barrier(barrier))
+        );
+        assert_eq!(rx.recv().await.unwrap(), ArrowMessage::Data(msg));
+        assert_eq!(tx.capacity(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_take_priority() {
+        let (tx, mut rx) = batch_bounded(8);
+        let mut priority_rx = rx.take_priority().unwrap();
+        assert!(rx.take_priority().is_none());
+
+        let barrier = CheckpointBarrier {
+            epoch: 1,
+            min_epoch: 0,
+            timestamp: SystemTime::now(),
+            then_stop: false,
+            unaligned: true,
+        };
+        tx.send(ArrowMessage::Signal(SignalMessage::BarrierPosition(
+            barrier,
+        )))
+        .await
+        .unwrap();
+        tx.send(ArrowMessage::Signal(SignalMessage::Barrier(barrier)))
+            .await
+            .unwrap();
+
+        // the barrier only goes to the priority lane, and the position only to the data lane
+        assert_eq!(
+            priority_rx.recv().await.unwrap(),
+            ArrowMessage::Signal(SignalMessage::Barrier(barrier))
+        );
+        assert!(priority_rx.try_recv().is_err());
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            ArrowMessage::Signal(SignalMessage::BarrierPosition(barrier))
+        );
+    }
 }
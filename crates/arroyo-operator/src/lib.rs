@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::future::Future;
 use std::ops::Sub;
@@ -111,6 +112,118 @@ impl CheckpointCounter {
     }
 }
 
+#[derive(Debug, Default)]
+struct InFlightEpoch {
+    pending: HashSet<usize>,
+    batches: HashMap<usize, Vec<RecordBatch>>,
+}
+
+/// Records the data that unaligned checkpoint barriers overtake. Once the first barrier for an
+/// epoch has been received (and the operator's state checkpointed), everything read from an input
+/// until that input's [`SignalMessage::BarrierPosition`] is data that the upstream operator sent
+/// before its own checkpoint, and so needs to be stored as part of this one. If a position
+/// arrives before any barrier for its epoch, it starts the checkpoint in the barrier's place.
+#[derive(Debug)]
+pub struct InFlightRecorder {
+    inputs: usize,
+    last_started: Option<u32>,
+    epochs: BTreeMap<u32, InFlightEpoch>,
+}
+
+impl InFlightRecorder {
+    pub fn new(inputs: usize) -> Self {
+        Self {
+            inputs,
+            last_started: None,
+            epochs: BTreeMap::new(),
+        }
+    }
+
+    /// Starts recording for the barrier's epoch, returning false if it has already been started
+    /// by a barrier from another input
+    pub fn start(&mut self, barrier: &CheckpointBarrier, closed: &HashSet<usize>) -> bool {
+        if self.last_started.is_some_and(|e| e >= barrier.epoch) {
+            return false;
+        }
+        self.last_started = Some(barrier.epoch);
+        self.epochs.insert(
+            barrier.epoch,
+            InFlightEpoch {
+                pending: (0..self.inputs).filter(|i| !closed.contains(i)).collect(),
+                batches: HashMap::new(),
+            },
+        );
+        true
+    }
+
+    pub fn is_recording(&self) -> bool {
+        !self.epochs.is_empty()
+    }
+
+    /// The epoch of the last checkpoint that was started
+    pub fn last_started(&self) -> Option<u32> {
+        self.last_started
+    }
+
+    /// Whether input `idx` has yet to reach the position of the barrier for any epoch
+    pub fn is_pending(&self, idx: usize) -> bool {
+        self.epochs.values().any(|e| e.pending.contains(&idx))
+    }
+
+    /// Whether input `idx` has yet to reach the position of the barrier for `epoch`
+    pub fn waits_on(&self, idx: usize, epoch: u32) -> bool {
+        self.epochs
+            .get(&epoch)
+            .is_some_and(|e| e.pending.contains(&idx))
+    }
+
+    pub fn record(&mut self, idx: usize, batch: &RecordBatch) {
+        for epoch in self.epochs.values_mut() {
+            if epoch.pending.contains(&idx) {
+                epoch.batches.entry(idx).or_default().push(batch.clone());
+            }
+        }
+    }
+
+    /// Records a batch from input `idx` for just `epoch`, if it's still waiting on that input
+    pub fn record_epoch(&mut self, epoch: u32, idx: usize, batch: &RecordBatch) {
+        if let Some(state) = self.epochs.get_mut(&epoch) {
+            if state.pending.contains(&idx) {
+                state.batches.entry(idx).or_default().push(batch.clone());
+            }
+        }
+    }
+
+    /// Marks that input `idx` has reached the position of the barrier for `epoch`, returning the
+    /// epoch's in-flight data once all inputs have
+    pub fn mark(&mut self, idx: usize, epoch: u32) -> Option<HashMap<usize, Vec<RecordBatch>>> {
+        let state = self.epochs.get_mut(&epoch)?;
+        state.pending.remove(&idx);
+        if state.pending.is_empty() {
+            Some(self.epochs.remove(&epoch).unwrap().batches)
+        } else {
+            None
+        }
+    }
+
+    /// Stops waiting on input `idx`, which has closed, returning any epochs that are now complete
+    pub fn close(&mut self, idx: usize) -> Vec<(u32, HashMap<usize, Vec<RecordBatch>>)> {
+        let completed: Vec<u32> = self
+            .epochs
+            .iter_mut()
+            .filter_map(|(epoch, state)| {
+                state.pending.remove(&idx);
+                state.pending.is_empty().then_some(*epoch)
+            })
+            .collect();
+
+        completed
+            .into_iter()
+            .map(|epoch| (epoch, self.epochs.remove(&epoch).unwrap().batches))
+            .collect()
+    }
+}
+
 #[allow(unused)]
 pub struct RunContext<St: Stream<Item = (usize, ArrowMessage)> + Send + Sync> {
     pub task_info: TaskInfoRef,
@@ -188,3 +301,200 @@ impl RateLimiter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int64Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+
+    fn batch(value: i64) -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("x", DataType::Int64, false)])),
+            vec![Arc::new(Int64Array::from(vec![value]))],
+        )
+        .unwrap()
+    }
+
+    fn barrier(epoch: u32) -> CheckpointBarrier {
+        CheckpointBarrier {
+            epoch,
+            min_epoch: 0,
+            timestamp: SystemTime::now(),
+            then_stop: false,
+            unaligned: true,
+        }
+    }
+
+    #[test]
+    fn test_in_flight_recorder() {
+        let mut recorder = InFlightRecorder::new(2);
+        assert!(!recorder.is_recording());
+
+        assert!(recorder.start(&barrier(1), &HashSet::new()));
+        // the barrier arriving on the other input doesn't start the checkpoint again
+        assert!(!recorder.start(&barrier(1), &HashSet::new()));
+        assert!(recorder.is_recording());
+
+        recorder.record(0, &batch(1));
+        recorder.record(1, &batch(2));
+        assert!(recorder.mark(0, 1).is_none());
+
+        // data after the barrier's position on input 0 isn't in flight
+        recorder.record(0, &batch(3));
+        recorder.record(1, &batch(4));
+
+        let in_flight = recorder.mark(1, 1).unwrap();
+        assert_eq!(in_flight.get(&0).unwrap(), &vec![batch(1)]);
+        assert_eq!(in_flight.get(&1).unwrap(), &vec![batch(2), batch(4)]);
+        assert!(!recorder.is_recording());
+
+        // inputs that close no longer hold up the checkpoint
+        assert!(recorder.start(&barrier(2), &HashSet::new()));
+        recorder.record(1, &batch(5));
+        assert!(recorder.mark(1, 2).is_none());
+        let completed = recorder.close(0);
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].0, 2);
+        assert_eq!(completed[0].1.get(&1).unwrap(), &vec![batch(5)]);
+
+        // and closed inputs aren't waited on in later epochs
+        assert!(recorder.start(&barrier(3), &HashSet::from([0])));
+        assert!(recorder.mark(1, 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_in_flight_recorder_multiple_inputs() {
+        let mut recorder = InFlightRecorder::new(3);
+        assert!(recorder.start(&barrier(1), &HashSet::new()));
+
+        recorder.record(0, &batch(1));
+        recorder.record(1, &batch(2));
+        recorder.record(2, &batch(3));
+        assert!(recorder.mark(1, 1).is_none());
+
+        recorder.record(0, &batch(4));
+        recorder.record(1, &batch(5));
+        assert!(recorder.mark(0, 1).is_none());
+
+        // positions for other epochs don't affect this one
+        assert!(recorder.mark(2, 0).is_none());
+        recorder.record(2, &batch(6));
+
+        let in_flight = recorder.mark(2, 1).unwrap();
+        assert_eq!(in_flight.get(&0).unwrap(), &vec![batch(1), batch(4)]);
+        assert_eq!(in_flight.get(&1).unwrap(), &vec![batch(2)]);
+        assert_eq!(in_flight.get(&2).unwrap(), &vec![batch(3), batch(6)]);
+        assert!(!recorder.is_recording());
+    }
+
+    #[test]
+    fn test_in_flight_recorder_close_mid_epoch() {
+        let mut recorder = InFlightRecorder::new(3);
+        assert!(recorder.start(&barrier(1), &HashSet::new()));
+
+        recorder.record(0, &batch(1));
+        recorder.record(1, &batch(2));
+        assert!(recorder.mark(0, 1).is_none());
+
+        // input 1 closes before its position arrives; what it sent until then is still in flight
+        assert!(recorder.close(1).is_empty());
+        assert!(recorder.is_recording());
+
+        recorder.record(2, &batch(3));
+        let in_flight = recorder.mark(2, 1).unwrap();
+        assert_eq!(in_flight.get(&0).unwrap(), &vec![batch(1)]);
+        assert_eq!(in_flight.get(&1).unwrap(), &vec![batch(2)]);
+        assert_eq!(in_flight.get(&2).unwrap(), &vec![batch(3)]);
+
+        // closing the last pending input completes the epoch
+        assert!(recorder.start(&barrier(2), &HashSet::from([1])));
+        recorder.record(0, &batch(4));
+        assert!(recorder.mark(0, 2).is_none());
+        let completed = recorder.close(2);
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].0, 2);
+        assert_eq!(completed[0].1.get(&0).unwrap(), &vec![batch(4)]);
+        assert!(!completed[0].1.contains_key(&2));
+        assert!(!recorder.is_recording());
+    }
+
+    #[test]
+    fn test_in_flight_recorder_overlapping_epochs() {
+        let mut recorder = InFlightRecorder::new(2);
+        assert!(recorder.start(&barrier(1), &HashSet::new()));
+        recorder.record(0, &batch(1));
+        assert!(recorder.mark(0, 1).is_none());
+
+        // the barrier for epoch 2 overtakes the position for epoch 1 on input 1
+        assert!(recorder.start(&barrier(2), &HashSet::new()));
+        // a late barrier for an earlier epoch doesn't start it again
+        assert!(!recorder.start(&barrier(1), &HashSet::new()));
+
+        // data on input 1 is before both of its positions, so it's in flight for both epochs,
+        // while data on input 0 is only in flight for epoch 2
+        recorder.record(0, &batch(2));
+        recorder.record(1, &batch(3));
+
+        let first = recorder.mark(1, 1).unwrap();
+        assert_eq!(first.get(&0).unwrap(), &vec![batch(1)]);
+        assert_eq!(first.get(&1).unwrap(), &vec![batch(3)]);
+        assert!(recorder.is_recording());
+
+        recorder.record(1, &batch(4));
+        assert!(recorder.mark(0, 2).is_none());
+        recorder.record(0, &batch(5));
+
+        let second = recorder.mark(1, 2).unwrap();
+        assert_eq!(second.get(&0).unwrap(), &vec![batch(2)]);
+        assert_eq!(second.get(&1).unwrap(), &vec![batch(3), batch(4)]);
+        assert!(!recorder.is_recording());
+    }
+    #[test]
+    fn test_in_flight_recorder_position_before_barrier() {
+        let mut recorder = InFlightRecorder::new(2);
+
+        // the position on input 0 arrives before any barrier, so it starts the checkpoint, and
+        // nothing on input 0 was in flight
+        assert!(recorder.start(&barrier(1), &HashSet::new()));
+        assert!(recorder.mark(0, 1).is_none());
+        recorder.record(0, &batch(1));
+        recorder.record(1, &batch(2));
+
+        // the barrier itself arrives later
+        assert!(!recorder.start(&barrier(1), &HashSet::new()));
+
+        let in_flight = recorder.mark(1, 1).unwrap();
+        assert!(!in_flight.contains_key(&0));
+        assert_eq!(in_flight.get(&1).unwrap(), &vec![batch(2)]);
+    }
+
+    #[test]
+    fn test_in_flight_recorder_read_ahead() {
+        let mut recorder = InFlightRecorder::new(2);
+        assert!(recorder.last_started().is_none());
+        assert!(recorder.start(&barrier(1), &HashSet::new()));
+        assert_eq!(recorder.last_started(), Some(1));
+        assert!(recorder.waits_on(0, 1));
+        assert!(!recorder.waits_on(0, 2));
+
+        recorder.record(0, &batch(1));
+        assert!(recorder.mark(0, 1).is_none());
+        assert!(!recorder.is_pending(0));
+        assert!(recorder.is_pending(1));
+
+        // batches read ahead on input 1 are in flight for a checkpoint started after them, but
+        // input 0 has already reached its position for epoch 1
+        recorder.record(1, &batch(2));
+        assert!(recorder.start(&barrier(2), &HashSet::new()));
+        recorder.record_epoch(2, 1, &batch(2));
+        assert!(recorder.is_pending(0));
+
+        let first = recorder.mark(1, 1).unwrap();
+        assert_eq!(first.get(&1).unwrap(), &vec![batch(2)]);
+        assert!(recorder.mark(0, 2).is_none());
+        let second = recorder.mark(1, 2).unwrap();
+        assert!(!second.contains_key(&0));
+        assert_eq!(second.get(&1).unwrap(), &vec![batch(2)]);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::context::{ArrowContext, BatchReceiver};
 use crate::inq_reader::InQReader;
 use crate::udfs::{ArroyoUdaf, UdafArg};
-use crate::{CheckpointCounter, ControlOutcome, SourceFinishType};
+use crate::{CheckpointCounter, ControlOutcome, InFlightRecorder, SourceFinishType};
 use anyhow::anyhow;
 use arrow::array::RecordBatch;
 use arrow::datatypes::DataType;
@@ -22,13 +22,14 @@ use datafusion::logical_expr::{
 };
 use dlopen2::wrapper::Container;
 use futures::future::OptionFuture;
+use futures::Stream;
 use std::any::Any;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::future::Future;
 use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::Barrier;
 use tokio_stream::StreamExt;
 use tracing::{debug, error, info, warn, Instrument};
@@ -145,9 +146,40 @@ async fn run_checkpoint(checkpoint_barrier: CheckpointBarrier, ctx: &mut ArrowCo
     )))
     .await;
 
+    if checkpoint_barrier.unaligned {
+        // the barrier skips ahead of any queued data, so downstream operators also need to know
+        // where in the stream it was sent
+        ctx.broadcast(ArrowMessage::Signal(SignalMessage::BarrierPosition(
+            checkpoint_barrier,
+        )))
+        .await;
+    }
+
     checkpoint_barrier.then_stop
 }
 
+async fn run_unaligned_checkpoint<O: ArrowOperator + ?Sized>(
+    operator: &mut O,
+    checkpoint_barrier: CheckpointBarrier,
+    ctx: &mut ArrowContext,
+) -> bool {
+    ctx.send_checkpoint_event(
+        checkpoint_barrier,
+        TaskCheckpointEventType::StartedCheckpointing,
+    )
+    .await;
+
+    operator.handle_checkpoint(checkpoint_barrier, ctx).await;
+
+    ctx.send_checkpoint_event(
+        checkpoint_barrier,
+        TaskCheckpointEventType::FinishedOperatorSetup,
+    )
+    .await;
+
+    run_checkpoint(checkpoint_barrier, ctx).await
+}
+
 #[async_trait]
 pub trait SourceOperator: Send + 'static {
     fn name(&self) -> String;
@@ -175,7 +207,16 @@ pub trait SourceOperator: Send + 'static {
         )
         .await;
 
-        run_checkpoint(checkpoint_barrier, ctx).await
+        let stop = run_checkpoint(checkpoint_barrier, ctx).await;
+
+        if checkpoint_barrier.unaligned {
+            // sources have no inputs, so there's never any in-flight data
+            ctx.table_manager
+                .write_in_flight(checkpoint_barrier.epoch, 0, HashMap::new())
+                .await;
+        }
+
+        stop
     }
 }
 
@@ -193,9 +234,9 @@ async fn operator_run_behavior(
         ctx.task_info.operator_name, ctx.task_info.task_index
     );
 
-    let task_info = ctx.task_info.clone();
     let name = this.name();
     let mut counter = CheckpointCounter::new(in_qs.len());
+    let mut in_flight = InFlightRecorder::new(in_qs.len());
     let mut closed: HashSet<usize> = HashSet::new();
     let mut sel = InQReader::new();
     let in_partitions = in_qs.len();
@@ -211,12 +252,85 @@ async fn operator_run_behavior(
     let mut blocked = vec![];
     let mut final_message = None;
 
+    // data that was in flight when an unaligned checkpoint we're restoring from was taken comes
+    // before anything we read from our inputs
+    let restored_in_flight = ctx
+        .table_manager
+        .take_in_flight(in_partitions)
+        .expect("should be able to restore in-flight data");
+    for (idx, batches) in restored_in_flight {
+        for batch in batches {
+            this.process_batch_index(idx, in_partitions, batch, ctx)
+                .await;
+        }
+    }
+
     let mut ticks = 0u64;
     let mut interval =
         tokio::time::interval(this.tick_interval().unwrap_or(Duration::from_secs(60)));
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
+    // messages read ahead of their barrier positions during an unaligned checkpoint, and the
+    // inputs we stopped reading from while doing so
+    let mut read_ahead = VecDeque::new();
+    let mut paused = vec![];
+    let mut read_ahead_epoch = None;
+
     loop {
+        if let Some((idx, message)) = read_ahead.pop_front() {
+            // these have already been recorded as in-flight data
+            let outcome = handle_input_message(
+                this,
+                &name,
+                idx,
+                message,
+                false,
+                &mut counter,
+                &mut in_flight,
+                &mut closed,
+                in_partitions,
+                ctx,
+            )
+            .await;
+            if let Some(message) = run_ends(outcome) {
+                final_message = message;
+                break;
+            }
+            if counter.all_clear() {
+                for q in blocked.drain(..) {
+                    sel.push(q);
+                }
+            }
+            continue;
+        }
+
+        for (idx, s) in paused.drain(..) {
+            if counter.is_blocked(idx) {
+                blocked.push(s);
+            } else {
+                sel.push(s);
+            }
+        }
+
+        if in_flight.is_recording() && in_flight.last_started() != read_ahead_epoch {
+            read_ahead_epoch = in_flight.last_started();
+            if read_ahead_in_flight(
+                this,
+                &mut sel,
+                &mut read_ahead,
+                &mut paused,
+                &mut in_flight,
+                &mut closed,
+                in_partitions,
+                ctx,
+            )
+            .await
+            {
+                break;
+            }
+            continue;
+        }
+
         let operator_future: OptionFuture<_> = this.future_to_poll().into();
         tokio::select! {
             Some(control_message) = ctx.control_rx.recv() => {
@@ -226,41 +340,10 @@ async fn operator_run_behavior(
             p = sel.next() => {
                 match p {
                     Some(((idx, message), s)) => {
-                        let local_idx = idx;
-
-                        debug!("[{}] Handling message {}-{}, {:?}",
-                            ctx.task_info.operator_name, 0, local_idx, message);
-
-                        match message {
-                            ArrowMessage::Data(record) => {
-                                TaskCounters::BatchesReceived.for_task(&ctx.task_info, |c| c.inc());
-                                TaskCounters::MessagesReceived.for_task(&ctx.task_info, |c| c.inc_by(record.num_rows() as u64));
-                                TaskCounters::BytesReceived.for_task(&ctx.task_info, |c| c.inc_by(record.get_array_memory_size() as u64));
-                                this.process_batch_index(idx, in_partitions, record, ctx)
-                                    .instrument(tracing::trace_span!("handle_fn",
-                                        name,
-                                        operator_id = task_info.operator_id,
-                                        subtask_idx = task_info.task_index)
-                                ).await;
-                            }
-                            ArrowMessage::Signal(signal) => {
-                                match this.handle_control_message(idx, &signal, &mut counter, &mut closed, in_partitions, ctx).await {
-                                    ControlOutcome::Continue => {}
-                                    ControlOutcome::Stop => {
-                                        // just stop; the stop will have already been broadcast for example by
-                                        // a final checkpoint
-                                        break;
-                                    }
-                                    ControlOutcome::Finish => {
-                                        final_message = Some(SignalMessage::EndOfData);
-                                        break;
-                                    }
-                                    ControlOutcome::StopAndSendStop => {
-                                        final_message = Some(SignalMessage::Stop);
-                                        break;
-                                    }
-                                }
-                            }
+                        let outcome = handle_input_message(this, &name, idx, message, true, &mut counter, &mut in_flight, &mut closed, in_partitions, ctx).await;
+                        if let Some(message) = run_ends(outcome) {
+                            final_message = message;
+                            break;
                         }
 
                         if counter.is_blocked(idx){
@@ -293,6 +376,170 @@ async fn operator_run_behavior(
     final_message
 }
 
+/// Returns the message to finish with if the outcome ends the run loop
+fn run_ends(outcome: ControlOutcome) -> Option<Option<SignalMessage>> {
+    match outcome {
+        ControlOutcome::Continue => None,
+        // just stop; the stop will have already been broadcast for example by a final checkpoint
+        ControlOutcome::Stop => Some(None),
+        ControlOutcome::Finish => Some(Some(SignalMessage::EndOfData)),
+        ControlOutcome::StopAndSendStop => Some(Some(SignalMessage::Stop)),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_input_message(
+    this: &mut Box<dyn ArrowOperator + Send>,
+    name: &str,
+    idx: usize,
+    message: ArrowMessage,
+    record: bool,
+    counter: &mut CheckpointCounter,
+    in_flight: &mut InFlightRecorder,
+    closed: &mut HashSet<usize>,
+    in_partitions: usize,
+    ctx: &mut ArrowContext,
+) -> ControlOutcome {
+    debug!(
+        "[{}] Handling message {}-{}, {:?}",
+        ctx.task_info.operator_name, 0, idx, message
+    );
+
+    match message {
+        ArrowMessage::Data(record_batch) => {
+            TaskCounters::BatchesReceived.for_task(&ctx.task_info, |c| c.inc());
+            TaskCounters::MessagesReceived
+                .for_task(&ctx.task_info, |c| c.inc_by(record_batch.num_rows() as u64));
+            TaskCounters::BytesReceived.for_task(&ctx.task_info, |c| {
+                c.inc_by(record_batch.get_array_memory_size() as u64)
+            });
+            if record && in_flight.is_recording() {
+                in_flight.record(idx, &record_batch);
+            }
+            let task_info = ctx.task_info.clone();
+            let start = Instant::now();
+            this.process_batch_index(idx, in_partitions, record_batch, ctx)
+                .instrument(tracing::trace_span!(
+                    "handle_fn",
+                    name,
+                    operator_id = task_info.operator_id,
+                    subtask_idx = task_info.task_index
+                ))
+                .await;
+            TaskCounters::BusyTime
+                .for_task(&task_info, |c| c.inc_by(start.elapsed().as_micros() as u64));
+            ControlOutcome::Continue
+        }
+        ArrowMessage::Signal(signal) => {
+            this.handle_control_message(
+                idx,
+                &signal,
+                counter,
+                in_flight,
+                closed,
+                in_partitions,
+                ctx,
+            )
+            .await
+        }
+    }
+}
+
+/// Once an unaligned checkpoint has started, the data queued on each input ahead of the
+/// barrier's position is in flight. Rather than waiting for it to be processed, we read each
+/// input up to the position into `read_ahead`, writing out the in-flight data for the epoch as
+/// soon as every input has reached it, so that the checkpoint can complete without waiting for
+/// the queues to drain. Inputs that we stop reading from are moved to `paused`. Returns true if
+/// the operator should stop.
+#[allow(clippy::too_many_arguments)]
+async fn read_ahead_in_flight<St: Stream<Item = (usize, ArrowMessage)> + Unpin>(
+    this: &mut Box<dyn ArrowOperator + Send>,
+    sel: &mut InQReader<St>,
+    read_ahead: &mut VecDeque<(usize, ArrowMessage)>,
+    paused: &mut Vec<(usize, St)>,
+    in_flight: &mut InFlightRecorder,
+    closed: &mut HashSet<usize>,
+    in_partitions: usize,
+    ctx: &mut ArrowContext,
+) -> bool {
+    // inputs that have reached an aligned barrier, which can't be read past until it's processed
+    let mut held = HashSet::new();
+
+    while in_flight.is_recording() {
+        let p = tokio::select! {
+            Some(control_message) = ctx.control_rx.recv() => {
+                this.handle_controller_message(control_message, ctx).await;
+                continue;
+            }
+            p = sel.next() => p,
+        };
+
+        let Some(((idx, message), s)) = p else {
+            break;
+        };
+
+        match message {
+            ArrowMessage::Data(batch) => {
+                in_flight.record(idx, &batch);
+                read_ahead.push_back((idx, ArrowMessage::Data(batch)));
+            }
+            ArrowMessage::Signal(SignalMessage::BarrierPosition(t))
+                if in_flight.waits_on(idx, t.epoch) =>
+            {
+                if let Some(batches) = in_flight.mark(idx, t.epoch) {
+                    ctx.table_manager
+                        .write_in_flight(t.epoch, in_partitions, batches)
+                        .await;
+                }
+            }
+            ArrowMessage::Signal(SignalMessage::Barrier(t)) if t.unaligned => {
+                if in_flight.start(&t, closed) {
+                    if run_unaligned_checkpoint(this.as_mut(), t, ctx).await {
+                        return true;
+                    }
+                    // nothing we've read ahead has been processed yet, so it's in flight for
+                    // this epoch as well
+                    for (i, message) in read_ahead.iter() {
+                        if let ArrowMessage::Data(batch) = message {
+                            in_flight.record_epoch(t.epoch, *i, batch);
+                        }
+                    }
+                    let (resumed, still_paused): (Vec<_>, Vec<_>) = paused
+                        .drain(..)
+                        .partition(|(i, _)| in_flight.is_pending(*i) && !held.contains(i));
+                    *paused = still_paused;
+                    for (_, q) in resumed {
+                        sel.push(q);
+                    }
+                }
+            }
+            ArrowMessage::Signal(signal @ (SignalMessage::Stop | SignalMessage::EndOfData)) => {
+                closed.insert(idx);
+                for (epoch, batches) in in_flight.close(idx) {
+                    ctx.table_manager
+                        .write_in_flight(epoch, in_partitions, batches)
+                        .await;
+                }
+                read_ahead.push_back((idx, ArrowMessage::Signal(signal)));
+            }
+            ArrowMessage::Signal(signal) => {
+                if matches!(signal, SignalMessage::Barrier(_)) {
+                    held.insert(idx);
+                }
+                read_ahead.push_back((idx, ArrowMessage::Signal(signal)));
+            }
+        }
+
+        if in_flight.is_pending(idx) && !held.contains(&idx) {
+            sel.push(s);
+        } else {
+            paused.push((idx, s));
+        }
+    }
+
+    false
+}
+
 #[async_trait::async_trait]
 pub trait ArrowOperator: Send + 'static {
     async fn handle_watermark_int(&mut self, watermark: Watermark, ctx: &mut ArrowContext) {
@@ -336,15 +583,20 @@ pub trait ArrowOperator: Send + 'static {
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
+            ControlMessage::QueryState(query) => {
+                ctx.query_state(query).await;
+            }
             ControlMessage::NoOp => {}
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn handle_control_message(
         &mut self,
         idx: usize,
         message: &SignalMessage,
         counter: &mut CheckpointCounter,
+        in_flight: &mut InFlightRecorder,
         closed: &mut HashSet<usize>,
         in_partitions: usize,
         ctx: &mut ArrowContext,
@@ -359,6 +611,16 @@ pub trait ArrowOperator: Send + 'static {
                     idx
                 );
 
+                if t.unaligned {
+                    // unaligned barriers don't wait for the barriers on the other inputs; we
+                    // checkpoint on the first one, and record the data it overtook until we see
+                    // where it was sent on each input
+                    if in_flight.start(t, closed) && run_unaligned_checkpoint(self, *t, ctx).await {
+                        return ControlOutcome::Stop;
+                    }
+                    return ControlOutcome::Continue;
+                }
+
                 if counter.all_clear() {
                     ctx.control_tx
                         .send(ControlResp::CheckpointEvent(arroyo_rpc::CheckpointEvent {
@@ -414,14 +676,37 @@ pub trait ArrowOperator: Send + 'static {
                     self.handle_watermark_int(watermark, ctx).await;
                 }
             }
+            SignalMessage::BarrierPosition(t) => {
+                // between workers, barriers are sent on a separate connection from the data, so
+                // the position may arrive first; the upstream operator has already checkpointed,
+                // so we have to as well before processing anything that comes after it
+                if in_flight.start(t, closed) && run_unaligned_checkpoint(self, *t, ctx).await {
+                    return ControlOutcome::Stop;
+                }
+                if let Some(batches) = in_flight.mark(idx, t.epoch) {
+                    ctx.table_manager
+                        .write_in_flight(t.epoch, in_partitions, batches)
+                        .await;
+                }
+            }
             SignalMessage::Stop => {
                 closed.insert(idx);
+                for (epoch, batches) in in_flight.close(idx) {
+                    ctx.table_manager
+                        .write_in_flight(epoch, in_partitions, batches)
+                        .await;
+                }
                 if closed.len() == in_partitions {
                     return ControlOutcome::StopAndSendStop;
                 }
             }
             SignalMessage::EndOfData => {
                 closed.insert(idx);
+                for (epoch, batches) in in_flight.close(idx) {
+                    ctx.table_manager
+                        .write_in_flight(epoch, in_partitions, batches)
+                        .await;
+                }
                 if closed.len() == in_partitions {
                     return ControlOutcome::Finish;
                 }
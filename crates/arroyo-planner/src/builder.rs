@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
@@ -22,7 +22,10 @@ use datafusion::physical_expr::PhysicalExpr;
 use datafusion::physical_plan::ExecutionPlan;
 use datafusion::physical_planner::{DefaultPhysicalPlanner, ExtensionPlanner, PhysicalPlanner};
 use datafusion_proto::protobuf::{PhysicalExprNode, PhysicalPlanNode};
+use petgraph::algo::toposort;
 use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
 use tokio::runtime::Builder;
 use tokio::sync::oneshot;
 
@@ -50,6 +53,7 @@ pub(crate) struct PlanToGraphVisitor<'a> {
     // In post_visit each node should clean up its vec and push its index to the last vec, if present.
     traversal: Vec<Vec<NodeIndex>>,
     planner: Planner<'a>,
+    parallelism_hints: HashMap<NodeIndex, usize>,
 }
 
 impl<'a> PlanToGraphVisitor<'a> {
@@ -60,12 +64,13 @@ impl<'a> PlanToGraphVisitor<'a> {
             named_nodes: Default::default(),
             traversal: vec![],
             planner: Planner::new(schema_provider),
+            parallelism_hints: Default::default(),
         }
     }
 }
 
 pub(crate) struct Planner<'a> {
-    schema_provider: &'a ArroyoSchemaProvider,
+    pub(crate) schema_provider: &'a ArroyoSchemaProvider,
     planner: DefaultPhysicalPlanner,
     session_state: SessionState,
 }
@@ -278,7 +283,14 @@ impl<'a> PlanToGraphVisitor<'a> {
         Ok(())
     }
 
-    pub fn into_graph(self) -> LogicalGraph {
+    /// Returns the nodes whose parallelism was set in the query (for example with the
+    /// `parallelism` option of a connector table)
+    pub fn take_parallelism_hints(&mut self) -> HashMap<NodeIndex, usize> {
+        std::mem::take(&mut self.parallelism_hints)
+    }
+
+    pub fn into_graph(mut self) -> LogicalGraph {
+        assign_stable_operator_ids(&mut self.graph);
         self.graph
     }
 
@@ -310,6 +322,9 @@ impl<'a> PlanToGraphVisitor<'a> {
             .plan_node(&self.planner, self.graph.node_count(), input_schemas)
             .map_err(|e| e.context("planning extension"))?;
         let node_index = self.graph.add_node(node);
+        if let Some(parallelism) = extension.parallelism() {
+            self.parallelism_hints.insert(node_index, parallelism);
+        }
         self.add_index_to_traversal(node_index);
         for (source, edge) in input_nodes.into_iter().zip(edges.into_iter()) {
             self.graph.add_edge(source, node_index, edge);
@@ -323,6 +338,82 @@ impl<'a> PlanToGraphVisitor<'a> {
     }
 }
 
+/// Replaces the index-based operator ids assigned during planning with ids derived from the
+/// structure of the query, so that unrelated changes to a query (like adding another INSERT)
+/// don't change the ids of existing operators and their state can be restored after an upgrade.
+///
+/// The id of each operator is a hash of its kind and the ids (and edge types) of its inputs;
+/// operators that would otherwise share an id are disambiguated by their planning order.
+fn assign_stable_operator_ids(graph: &mut LogicalGraph) {
+    let order = toposort(&*graph, None).expect("planned graph should not have cycles");
+
+    let mut used: HashSet<String> = HashSet::new();
+    for idx in order {
+        let mut inputs: Vec<_> = graph
+            .edges_directed(idx, Direction::Incoming)
+            .map(|e| {
+                (
+                    graph.node_weight(e.source()).unwrap().operator_id.clone(),
+                    e.weight().edge_type.to_string(),
+                )
+            })
+            .collect();
+        inputs.sort();
+
+        let node = graph.node_weight_mut(idx).unwrap();
+        let prefix = match node.operator_id.rsplit_once('_') {
+            Some((prefix, index)) if index.chars().all(|c| c.is_ascii_digit()) => {
+                prefix.to_string()
+            }
+            _ => node.operator_id.clone(),
+        };
+
+        let mut hash = Fnv1a::default();
+        hash.write(prefix.as_bytes());
+        hash.write(node.operator_name.to_string().as_bytes());
+        for (id, edge_type) in &inputs {
+            hash.write(id.as_bytes());
+            hash.write(edge_type.as_bytes());
+        }
+
+        let base = format!("{}_{:08x}", prefix, hash.finish_u32());
+        let mut id = base.clone();
+        let mut n = 1;
+        while used.contains(&id) {
+            id = format!("{}_{}", base, n);
+            n += 1;
+        }
+        used.insert(id.clone());
+        node.operator_id = id;
+    }
+}
+
+/// 64-bit FNV-1a; used instead of std's hasher because operator ids need to be the same across
+/// Rust versions and platforms
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        // separator, so that ("ab", "c") and ("a", "bc") hash differently
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+
+    fn finish_u32(&self) -> u32 {
+        (self.0 ^ (self.0 >> 32)) as u32
+    }
+}
+
 impl<'a> TreeNodeVisitor for PlanToGraphVisitor<'a> {
     type Node = LogicalPlan;
 
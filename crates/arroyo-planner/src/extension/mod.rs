@@ -58,6 +58,10 @@ pub(crate) trait ArroyoExtension: Debug {
     fn transparent(&self) -> bool {
         false
     }
+    // the parallelism requested for this node in the query, if any
+    fn parallelism(&self) -> Option<usize> {
+        None
+    }
 }
 
 pub(crate) struct NodeWithIncomingEdges {
@@ -143,6 +143,7 @@ impl ArroyoExtension for UpdatingAggregateExtension {
             physical_plan_type: Some(PhysicalPlanType::Aggregate(Box::new(combine_aggregate))),
         };
 
+        let options = &planner.schema_provider.planning_options;
         let config = UpdatingAggregateOperator {
             name: "UpdatingAggregate".to_string(),
             partial_schema: Some(partial_schema.into()),
@@ -155,6 +156,11 @@ impl ArroyoExtension for UpdatingAggregateExtension {
                 .pipeline
                 .update_aggregate_flush_interval
                 .as_micros() as u64,
+            ttl_micros: options
+                .updating_aggregate_ttl
+                .unwrap_or(*config().pipeline.update_aggregate_ttl)
+                .as_micros() as u64,
+            retract_on_expire: options.updating_aggregate_retract_on_expire,
         };
         let node = LogicalNode {
             operator_id: format!("updating_aggregate_{}", index),
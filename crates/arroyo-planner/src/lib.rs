@@ -18,6 +18,7 @@ mod test;
 
 use anyhow::bail;
 use arrow::array::ArrayRef;
+use arrow::compute::kernels::cast_utils::parse_interval_month_day_nano;
 use arrow::datatypes::{self, DataType};
 use arrow_schema::Schema;
 use arroyo_datastream::WindowType;
@@ -29,6 +30,9 @@ use datafusion::physical_plan::functions::make_scalar_function;
 
 use datafusion::prelude::create_udf;
 
+use datafusion::sql::sqlparser::ast::{
+    Expr as SqlExpr, Interval, ObjectName, Statement, Value as SqlValue,
+};
 use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
 use datafusion::sql::sqlparser::parser::Parser;
 use datafusion::sql::{planner::ContextProvider, TableReference};
@@ -54,6 +58,7 @@ use arroyo_rpc::api_types::connections::ConnectionProfile;
 use datafusion::common::DataFusionError;
 use std::collections::HashSet;
 use std::fmt::Debug;
+use std::str::FromStr;
 
 use crate::json::get_json_functions;
 use crate::rewriters::{SourceMetadataVisitor, TimeWindowUdfChecker, UnnestRewriter};
@@ -69,6 +74,7 @@ use arroyo_udf_host::ParsedUdfFile;
 use datafusion::execution::FunctionRegistry;
 use datafusion::logical_expr;
 use datafusion::logical_expr::expr_rewriter::FunctionRewrite;
+use petgraph::graph::NodeIndex;
 use std::time::{Duration, SystemTime};
 use std::{collections::HashMap, sync::Arc};
 use syn::Item;
@@ -96,6 +102,7 @@ pub struct ArroyoSchemaProvider {
     config_options: datafusion::config::ConfigOptions,
     pub dylib_udfs: HashMap<String, DylibUdfConfig>,
     pub function_rewriters: Vec<Arc<dyn FunctionRewrite + Send + Sync>>,
+    pub planning_options: PlanningOptions,
 }
 
 impl ArroyoSchemaProvider {
@@ -397,6 +404,89 @@ impl FunctionRegistry for ArroyoSchemaProvider {
     }
 }
 
+/// Options that can be set for a query with `SET <option> = <value>` statements
+#[derive(Clone, Debug, Default)]
+pub struct PlanningOptions {
+    /// How long keys in updating aggregates are kept in state after they were last updated
+    pub updating_aggregate_ttl: Option<Duration>,
+    /// Whether updating aggregates emit a retraction when a key is evicted from state
+    pub updating_aggregate_retract_on_expire: bool,
+    /// Parallelism for all operators in the query, overriding the pipeline's default
+    pub parallelism: Option<usize>,
+    /// Parallelism for individual operators, keyed by operator id; set with
+    /// `SET parallelism.<operator_id> = <n>`
+    pub operator_parallelism: HashMap<String, usize>,
+}
+
+fn parse_parallelism(name: &str, value: &SqlExpr) -> Result<usize> {
+    match value {
+        SqlExpr::Value(SqlValue::Number(n, _)) => match usize::from_str(n) {
+            Ok(p) if p > 0 => Ok(p),
+            _ => plan_err!("{} must be a positive integer, not {}", name, n),
+        },
+        _ => plan_err!("expected a positive integer for {}, not {}", name, value),
+    }
+}
+
+impl PlanningOptions {
+    fn set(&mut self, variable: &ObjectName, value: &[SqlExpr]) -> Result<()> {
+        let name = variable.to_string().to_lowercase();
+        let [value] = value else {
+            return plan_err!("expected a single value for {}", name);
+        };
+
+        if let [prefix, operator_id] = &variable.0[..] {
+            if prefix.value.eq_ignore_ascii_case("parallelism") {
+                let parallelism = parse_parallelism(&name, value)?;
+                self.operator_parallelism
+                    .insert(operator_id.value.clone(), parallelism);
+                return Ok(());
+            }
+        }
+
+        match name.as_str() {
+            "parallelism" => {
+                self.parallelism = Some(parse_parallelism(&name, value)?);
+            }
+            "updating_aggregate.ttl" => {
+                let interval = match value {
+                    SqlExpr::Value(SqlValue::SingleQuotedString(s)) => s,
+                    SqlExpr::Interval(Interval {
+                        value,
+                        leading_field: None,
+                        ..
+                    }) => match value.as_ref() {
+                        SqlExpr::Value(SqlValue::SingleQuotedString(s)) => s,
+                        _ => return plan_err!("invalid interval for {}: {}", name, value),
+                    },
+                    _ => return plan_err!("expected an interval for {}, not {}", name, value),
+                };
+                let ttl = interval_month_day_nanos_to_duration(
+                    parse_interval_month_day_nano(interval).map_err(|e| {
+                        DataFusionError::Plan(format!(
+                            "invalid interval '{}' for {}: {}",
+                            interval, name, e
+                        ))
+                    })?,
+                );
+                if ttl.is_zero() {
+                    return plan_err!("{} must be greater than 0", name);
+                }
+                self.updating_aggregate_ttl = Some(ttl);
+            }
+            "updating_aggregate.retract_on_expire" => {
+                let SqlExpr::Value(SqlValue::Boolean(retract)) = value else {
+                    return plan_err!("expected true or false for {}, not {}", name, value);
+                };
+                self.updating_aggregate_retract_on_expire = *retract;
+            }
+            _ => return plan_err!("unknown option '{}'", name),
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SqlConfig {
     pub default_parallelism: usize,
@@ -518,13 +608,17 @@ pub fn rewrite_plan(
 pub async fn parse_and_get_arrow_program(
     query: String,
     mut schema_provider: ArroyoSchemaProvider,
-    // TODO: use config
-    _config: SqlConfig,
+    config: SqlConfig,
 ) -> Result<CompiledSql> {
     let dialect = PostgreSqlDialect {};
     let mut inserts = vec![];
     for statement in Parser::parse_sql(&dialect, &query)? {
-        if let Some(table) = Table::try_from_statement(&statement, &schema_provider)? {
+        if let Statement::SetVariable {
+            variable, value, ..
+        } = &statement
+        {
+            schema_provider.planning_options.set(variable, value)?;
+        } else if let Some(table) = Table::try_from_statement(&statement, &schema_provider)? {
             schema_provider.insert_table(table);
         } else {
             inserts.push(Insert::try_from_statement(
@@ -601,20 +695,58 @@ pub async fn parse_and_get_arrow_program(
     for extension in extensions {
         plan_to_graph_visitor.add_plan(extension)?;
     }
+    let parallelism_hints = plan_to_graph_visitor.take_parallelism_hints();
     let graph = plan_to_graph_visitor.into_graph();
-    let program = LogicalProgram::new(
+    let mut program = LogicalProgram::new(
         graph,
         ProgramConfig {
             udf_dylibs: schema_provider.dylib_udfs.clone(),
         },
     );
 
+    set_parallelism(
+        &mut program,
+        &config,
+        &schema_provider.planning_options,
+        parallelism_hints,
+    )?;
+
     Ok(CompiledSql {
         program,
         connection_ids: used_connections.into_iter().collect(),
     })
 }
 
+/// Sets the parallelism of each operator in the program. In increasing order of precedence, this
+/// comes from the default parallelism, a `SET parallelism` statement, the `parallelism` option of
+/// connector tables and `SET parallelism.<operator_id>` statements. Because operators connected by
+/// forward edges must have the same parallelism, a hint for one operator applies to all of them.
+fn set_parallelism(
+    program: &mut LogicalProgram,
+    config: &SqlConfig,
+    options: &PlanningOptions,
+    hints: HashMap<NodeIndex, usize>,
+) -> Result<()> {
+    let default = options.parallelism.unwrap_or(config.default_parallelism);
+    for node in program.graph.node_weights_mut() {
+        node.parallelism = default;
+    }
+
+    let table_hints: HashMap<String, usize> = hints
+        .into_iter()
+        .map(|(idx, p)| (program.graph[idx].operator_id.clone(), p))
+        .collect();
+
+    for overrides in [&table_hints, &options.operator_parallelism] {
+        let expanded = program
+            .expand_parallelism_overrides(overrides)
+            .map_err(|e| DataFusionError::Plan(format!("invalid parallelism: {}", e)))?;
+        program.update_parallelism(&expanded);
+    }
+
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct TestStruct {
     pub non_nullable_i32: i32,
@@ -1,6 +1,8 @@
 use crate::extension::join::JoinExtension;
 use crate::extension::key_calculation::KeyCalculationExtension;
 use crate::plan::WindowDetectingVisitor;
+use crate::rewriters::AsyncUdfRewriter;
+use crate::ArroyoSchemaProvider;
 use arrow_schema::DataType;
 use arroyo_datastream::WindowType;
 use arroyo_rpc::IS_RETRACT_FIELD;
@@ -12,15 +14,17 @@ use datafusion::common::{
 use datafusion::logical_expr;
 use datafusion::logical_expr::expr::{Alias, ScalarFunction};
 use datafusion::logical_expr::{
-    BinaryExpr, BuiltinScalarFunction, Case, Expr, ExprSchemable, Extension, Join, LogicalPlan,
-    Operator, Projection,
+    build_join_schema, BinaryExpr, BuiltinScalarFunction, Case, Expr, ExprSchemable, Extension,
+    Join, LogicalPlan, Operator, Projection,
 };
 use datafusion::prelude::{get_field, lit};
 use std::sync::Arc;
 
-pub(crate) struct JoinRewriter {}
+pub(crate) struct JoinRewriter<'a> {
+    pub(crate) schema_provider: &'a ArroyoSchemaProvider,
+}
 
-impl JoinRewriter {
+impl<'a> JoinRewriter<'a> {
     fn check_join_windowing(join: &Join) -> Result<bool> {
         let left_window = WindowDetectingVisitor::get_window(&join.left)?;
         let right_window = WindowDetectingVisitor::get_window(&join.right)?;
@@ -76,6 +80,46 @@ impl JoinRewriter {
         Ok(())
     }
 
+    /// Moves any async UDF calls in the join keys into async UDF nodes on the corresponding
+    /// input, so that the join itself only compares plain columns. Returns the new inputs and
+    /// key pairs, or `None` if the keys contain no async calls.
+    fn split_async_join_keys(
+        &self,
+        left: Arc<LogicalPlan>,
+        right: Arc<LogicalPlan>,
+        on: &[(Expr, Expr)],
+    ) -> Result<Option<(Arc<LogicalPlan>, Arc<LogicalPlan>, Vec<(Expr, Expr)>)>> {
+        // both sides share one list of calls so that their result columns have distinct names
+        let mut calls = vec![];
+        let left_keys = on
+            .iter()
+            .map(|(l, _)| {
+                AsyncUdfRewriter::split_async(l.clone(), self.schema_provider, &mut calls)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let right_start = calls.len();
+        let right_keys = on
+            .iter()
+            .map(|(_, r)| {
+                AsyncUdfRewriter::split_async(r.clone(), self.schema_provider, &mut calls)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if calls.is_empty() {
+            return Ok(None);
+        }
+
+        let right_calls = calls.split_off(right_start);
+        let left = AsyncUdfRewriter::chain_calls(self.schema_provider, left, calls)?;
+        let right = AsyncUdfRewriter::chain_calls(self.schema_provider, right, right_calls)?;
+
+        Ok(Some((
+            left,
+            right,
+            left_keys.into_iter().zip(right_keys).collect(),
+        )))
+    }
+
     fn create_join_key_plan(
         &self,
         input: Arc<LogicalPlan>,
@@ -123,7 +167,9 @@ impl JoinRewriter {
         if timestamp_fields.len() != 2 {
             return not_impl_err!("join must have two timestamp fields");
         }
-        schema_with_timestamp.retain(|field| field.name() != "_timestamp");
+        schema_with_timestamp.retain(|field| {
+            field.name() != "_timestamp" && !AsyncUdfRewriter::is_async_result(field)
+        });
         let mut projection_expr = schema_with_timestamp
             .iter()
             .map(|field| {
@@ -239,7 +285,7 @@ impl TreeNodeRewriter for StructEqRewriter {
     }
 }
 
-impl TreeNodeRewriter for JoinRewriter {
+impl<'a> TreeNodeRewriter for JoinRewriter<'a> {
     type Node = LogicalPlan;
 
     fn f_up(&mut self, node: Self::Node) -> Result<Transformed<Self::Node>> {
@@ -263,6 +309,30 @@ impl TreeNodeRewriter for JoinRewriter {
         };
         Self::check_updating(&left, &right)?;
 
+        if let Some(filter) = &filter {
+            let mut calls = vec![];
+            AsyncUdfRewriter::split_async(filter.clone(), self.schema_provider, &mut calls)?;
+            if !calls.is_empty() {
+                return plan_err!(
+                    "async UDFs are only supported in equality join conditions (in {})",
+                    filter.canonical_name()
+                );
+            }
+        }
+
+        let (left, right, on, schema) =
+            match self.split_async_join_keys(left.clone(), right.clone(), &on)? {
+                Some((left, right, on)) => {
+                    let schema = Arc::new(build_join_schema(
+                        left.schema(),
+                        right.schema(),
+                        &join_type,
+                    )?);
+                    (left, right, on, schema)
+                }
+                None => (left, right, on, schema),
+            };
+
         let (left_expressions, right_expressions): (Vec<_>, Vec<_>) =
             on.clone().into_iter().unzip();
 
@@ -300,7 +300,10 @@ impl<'a> TreeNodeRewriter for ArroyoRewriter<'a> {
                 return AggregateRewriter {}.f_up(LogicalPlan::Aggregate(aggregate));
             }
             LogicalPlan::Join(join) => {
-                return JoinRewriter {}.f_up(LogicalPlan::Join(join));
+                return JoinRewriter {
+                    schema_provider: self.schema_provider,
+                }
+                .f_up(LogicalPlan::Join(join));
             }
             LogicalPlan::TableScan(table_scan) => {
                 return SourceRewriter {
@@ -308,7 +311,9 @@ impl<'a> TreeNodeRewriter for ArroyoRewriter<'a> {
                 }
                 .f_up(LogicalPlan::TableScan(table_scan));
             }
-            LogicalPlan::Filter(_) => {}
+            LogicalPlan::Filter(_) => {
+                return AsyncUdfRewriter::new(self.schema_provider).f_up(node);
+            }
             LogicalPlan::Window(_) => {
                 return WindowFunctionRewriter {}.f_up(node);
             }
@@ -19,14 +19,14 @@ use datafusion::common::tree_node::{
     Transformed, TreeNode, TreeNodeRecursion, TreeNodeRewriter, TreeNodeVisitor,
 };
 use datafusion::common::{
-    plan_err, Column, DFField, DFSchema, DataFusionError, OwnedTableReference, Result as DFResult,
-    ScalarValue,
+    plan_err, Column, DFField, DFSchema, DFSchemaRef, DataFusionError, OwnedTableReference,
+    Result as DFResult, ScalarValue,
 };
 use datafusion::logical_expr;
 use datafusion::logical_expr::expr::ScalarFunction;
 use datafusion::logical_expr::{
-    BinaryExpr, Expr, Extension, LogicalPlan, Projection, ScalarFunctionDefinition, TableScan,
-    Unnest,
+    BinaryExpr, Expr, Extension, Filter, LogicalPlan, Projection, ScalarFunctionDefinition,
+    TableScan, Unnest,
 };
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
@@ -453,18 +453,28 @@ pub struct AsyncUdfRewriter<'a> {
     provider: &'a ArroyoSchemaProvider,
 }
 
-type AsyncSplitResult = (String, AsyncOptions, Vec<Expr>);
+/// A single async UDF call site that has been extracted from an expression and replaced
+/// by a reference to the column `result_field`
+pub(crate) struct AsyncCall {
+    name: String,
+    opts: AsyncOptions,
+    args: Vec<Expr>,
+    result_field: String,
+}
 
 impl<'a> AsyncUdfRewriter<'a> {
     pub fn new(provider: &'a ArroyoSchemaProvider) -> Self {
         Self { provider }
     }
 
-    fn split_async(
+    /// Replaces every async UDF call in `expr` with a column reference to its result, appending the
+    /// extracted calls to `calls`. Calls are discovered bottom-up, so a call that depends on the result
+    /// of another (nested) async call always comes after it.
+    pub(crate) fn split_async(
         expr: Expr,
         provider: &ArroyoSchemaProvider,
-    ) -> DFResult<(Expr, Option<AsyncSplitResult>)> {
-        let mut c: Option<(String, AsyncOptions, Vec<Expr>)> = None;
+        calls: &mut Vec<AsyncCall>,
+    ) -> DFResult<Expr> {
         let expr = expr.transform_up_mut(&mut |e| {
             if let Expr::ScalarFunction(ScalarFunction {
                 func_def: ScalarFunctionDefinition::UDF(udf),
@@ -474,78 +484,189 @@ impl<'a> AsyncUdfRewriter<'a> {
                 if let Some(UdfType::Async(opts)) =
                     provider.udf_defs.get(udf.name()).map(|udf| udf.udf_type)
                 {
-                    if c.replace((udf.name().to_string(), opts, args.clone()))
-                        .is_some()
-                    {
-                        return plan_err!(
-                            "multiple async calls in the same expression, which is not allowed"
-                        );
-                    }
+                    let result_field = format!("{}_{}", ASYNC_RESULT_FIELD, calls.len());
+                    calls.push(AsyncCall {
+                        name: udf.name().to_string(),
+                        opts,
+                        args: args.clone(),
+                        result_field: result_field.clone(),
+                    });
                     return Ok(Transformed::yes(Expr::Column(Column::new_unqualified(
-                        ASYNC_RESULT_FIELD,
+                        result_field,
                     ))));
                 }
             }
             Ok(Transformed::no(e))
         })?;
 
-        Ok((expr.data, c))
+        Ok(expr.data)
     }
-}
 
-impl<'a> TreeNodeRewriter for AsyncUdfRewriter<'a> {
-    type Node = LogicalPlan;
+    /// Builds a chain of async UDF nodes on top of `input`, one per call, each of which passes
+    /// through all of its input columns and appends its result as `call.result_field`
+    pub(crate) fn chain_calls(
+        provider: &ArroyoSchemaProvider,
+        mut input: Arc<LogicalPlan>,
+        calls: Vec<AsyncCall>,
+    ) -> DFResult<Arc<LogicalPlan>> {
+        for call in calls {
+            input = Arc::new(Self::async_node(provider, input, call, None)?);
+        }
+        Ok(input)
+    }
 
-    fn f_up(&mut self, node: Self::Node) -> DFResult<Transformed<Self::Node>> {
-        let LogicalPlan::Projection(mut projection) = node else {
-            for e in node.expressions() {
-                if let (_, Some((udf, _, _))) = Self::split_async(e.clone(), self.provider)? {
-                    return plan_err!(
-                        "async UDFs are only supported in projections, but {udf} was called in another context"
-                    );
-                }
+    fn async_node(
+        provider: &ArroyoSchemaProvider,
+        input: Arc<LogicalPlan>,
+        call: AsyncCall,
+        projection: Option<(Vec<Expr>, DFSchemaRef)>,
+    ) -> DFResult<LogicalPlan> {
+        let udf = provider.dylib_udfs.get(&call.name).unwrap().clone();
+
+        let (final_exprs, final_schema) = match projection {
+            Some(projection) => projection,
+            None => {
+                let mut exprs: Vec<_> = input
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|f| Expr::Column(f.qualified_column()))
+                    .collect();
+                exprs.push(
+                    Expr::Column(Column::new_unqualified(ASYNC_RESULT_FIELD))
+                        .alias(&call.result_field),
+                );
+
+                let mut fields = input.schema().fields().clone();
+                fields.push(DFField::new_unqualified(
+                    &call.result_field,
+                    udf.return_type.clone(),
+                    true,
+                ));
+
+                (
+                    exprs,
+                    Arc::new(DFSchema::new_with_metadata(
+                        fields,
+                        input.schema().metadata().clone(),
+                    )?),
+                )
             }
-            return Ok(Transformed::no(node));
         };
 
-        let mut args = None;
+        Ok(LogicalPlan::Extension(Extension {
+            node: Arc::new(AsyncUDFExtension {
+                input,
+                name: call.name,
+                udf,
+                arg_exprs: call.args,
+                final_exprs,
+                ordered: call.opts.ordered,
+                max_concurrency: call.opts.max_concurrency,
+                timeout: call.opts.timeout,
+                final_schema,
+            }),
+        }))
+    }
 
-        for e in projection.expr.iter_mut() {
-            let (new_e, Some(udf)) = Self::split_async(e.clone(), self.provider)? else {
-                continue;
-            };
+    pub(crate) fn is_async_result(field: &DFField) -> bool {
+        field.qualifier().is_none() && field.name().starts_with(ASYNC_RESULT_FIELD)
+    }
 
-            if let Some((prev, _, _)) = args.replace(udf) {
-                return plan_err!(
-                    "Projection contains multiple async UDFs, which is not supported \
-                    \n(hint: two async UDFs calls, {} and {}, appear in the same SELECT statement)",
-                    prev,
-                    args.unwrap().0
-                );
-            }
+    /// Returns a projection over `input` that drops the intermediate async result columns
+    pub(crate) fn drop_async_results(input: LogicalPlan) -> DFResult<LogicalPlan> {
+        let exprs = input
+            .schema()
+            .fields()
+            .iter()
+            .filter(|f| !Self::is_async_result(f))
+            .map(|f| Expr::Column(f.qualified_column()))
+            .collect();
 
-            *e = new_e;
+        Ok(LogicalPlan::Projection(Projection::try_new(
+            exprs,
+            Arc::new(input),
+        )?))
+    }
+
+    fn rewrite_projection(&self, mut projection: Projection) -> DFResult<Transformed<LogicalPlan>> {
+        let mut calls = vec![];
+        for e in projection.expr.iter_mut() {
+            *e = Self::split_async(e.clone(), self.provider, &mut calls)?;
         }
 
-        let Some((name, opts, args)) = args else {
+        let Some(last) = calls.pop() else {
             return Ok(Transformed::no(LogicalPlan::Projection(projection)));
         };
 
-        let udf = self.provider.dylib_udfs.get(&name).unwrap().clone();
+        // the final call in the chain computes the projection directly, so its result is read
+        // from the operator's result field rather than a named intermediate column
+        let last_field = last.result_field.clone();
+        let final_exprs = projection
+            .expr
+            .into_iter()
+            .map(|e| {
+                Ok(e.transform_up_mut(&mut |e| {
+                    Ok(match &e {
+                        Expr::Column(c) if c.relation.is_none() && c.name == last_field => {
+                            Transformed::yes(Expr::Column(Column::new_unqualified(
+                                ASYNC_RESULT_FIELD,
+                            )))
+                        }
+                        _ => Transformed::no(e),
+                    })
+                })?
+                .data)
+            })
+            .collect::<DFResult<Vec<_>>>()?;
 
-        Ok(Transformed::yes(LogicalPlan::Extension(Extension {
-            node: Arc::new(AsyncUDFExtension {
-                input: projection.input,
-                name,
-                udf,
-                arg_exprs: args,
-                final_exprs: projection.expr,
-                ordered: opts.ordered,
-                max_concurrency: opts.max_concurrency,
-                timeout: opts.timeout,
-                final_schema: projection.schema,
-            }),
-        })))
+        let input = Self::chain_calls(self.provider, projection.input, calls)?;
+
+        Ok(Transformed::yes(Self::async_node(
+            self.provider,
+            input,
+            last,
+            Some((final_exprs, projection.schema)),
+        )?))
+    }
+
+    fn rewrite_filter(&self, filter: Filter) -> DFResult<Transformed<LogicalPlan>> {
+        let mut calls = vec![];
+        let predicate = Self::split_async(filter.predicate.clone(), self.provider, &mut calls)?;
+
+        if calls.is_empty() {
+            return Ok(Transformed::no(LogicalPlan::Filter(filter)));
+        }
+
+        let input = Self::chain_calls(self.provider, filter.input, calls)?;
+        let filter = LogicalPlan::Filter(Filter::try_new(predicate, input)?);
+
+        Ok(Transformed::yes(Self::drop_async_results(filter)?))
+    }
+}
+
+impl<'a> TreeNodeRewriter for AsyncUdfRewriter<'a> {
+    type Node = LogicalPlan;
+
+    fn f_up(&mut self, node: Self::Node) -> DFResult<Transformed<Self::Node>> {
+        match node {
+            LogicalPlan::Projection(projection) => self.rewrite_projection(projection),
+            LogicalPlan::Filter(filter) => self.rewrite_filter(filter),
+            node => {
+                for e in node.expressions() {
+                    let mut calls = vec![];
+                    Self::split_async(e.clone(), self.provider, &mut calls)?;
+                    if let Some(call) = calls.first() {
+                        return plan_err!(
+                            "async UDFs are only supported in projections, filters and join keys, \
+                            but {} was called in another context",
+                            call.name
+                        );
+                    }
+                }
+                Ok(Transformed::no(node))
+            }
+        }
     }
 }
 
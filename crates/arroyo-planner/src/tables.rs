@@ -71,6 +71,9 @@ pub struct ConnectorTable {
     pub event_time_field: Option<String>,
     pub watermark_field: Option<String>,
     pub idle_time: Option<Duration>,
+    /// Parallelism for the source or sink operators of this table, set with the `parallelism`
+    /// option; otherwise the pipeline's default parallelism is used
+    pub parallelism: Option<usize>,
 
     pub inferred_fields: Option<Vec<DFField>>,
 }
@@ -181,6 +184,7 @@ impl From<Connection> for ConnectorTable {
             event_time_field: None,
             watermark_field: None,
             idle_time: DEFAULT_IDLE_TIME,
+            parallelism: None,
             inferred_fields: None,
         }
     }
@@ -267,6 +271,14 @@ impl ConnectorTable {
         )
         .map_err(|e| DataFusionError::Plan(format!("could not create connection schema: {}", e)))?;
 
+        let parallelism = match options.remove("parallelism") {
+            Some(p) => match usize::from_str(&p) {
+                Ok(p) if p > 0 => Some(p),
+                _ => return plan_err!("parallelism must be a positive number, not '{}'", p),
+            },
+            None => None,
+        };
+
         let connection = connector
             .from_options(name, options, Some(&schema), connection_profile)
             .map_err(|e| DataFusionError::Plan(e.to_string()))?;
@@ -276,6 +288,7 @@ impl ConnectorTable {
             table.fields = fields;
         }
 
+        table.parallelism = parallelism;
         table.event_time_field = options.remove("event_time_field");
         table.watermark_field = options.remove("watermark_field");
 
@@ -5,8 +5,11 @@ use arroyo_connectors::{
     nexmark::{NexmarkConnector, NexmarkTable},
     EmptyConfig,
 };
+use arroyo_datastream::logical::OperatorName;
 use arroyo_operator::connector::Connector;
 use arroyo_udf_host::parse::NullableType;
+use petgraph::graph::NodeIndex;
+use std::collections::HashSet;
 use test_log::test;
 
 use crate::{parse_and_get_program, ArroyoSchemaProvider, SqlConfig};
@@ -58,3 +61,101 @@ async fn test_udf() {
         .await
         .unwrap();
 }
+
+async fn operator_ids(sql: &str) -> HashSet<String> {
+    parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap()
+        .program
+        .graph
+        .node_weights()
+        .map(|n| n.operator_id.clone())
+        .collect()
+}
+
+#[test(tokio::test)]
+async fn test_stable_operator_ids() {
+    let first = "CREATE TABLE auctions (auction BIGINT) WITH (
+        connector = 'single_file',
+        path = '/tmp/auctions.json',
+        format = 'json',
+        type = 'sink'
+    );
+    INSERT INTO auctions SELECT bid.auction FROM nexmark WHERE bid IS NOT NULL;";
+
+    let second = format!(
+        "{}
+    CREATE TABLE counts (count BIGINT) WITH (
+        connector = 'single_file',
+        path = '/tmp/counts.json',
+        format = 'json',
+        type = 'sink'
+    );
+    INSERT INTO counts SELECT count(*) FROM nexmark GROUP BY tumble(interval '1 second');",
+        first
+    );
+
+    let first_ids = operator_ids(first).await;
+    assert_eq!(first_ids, operator_ids(first).await);
+
+    let second_ids = operator_ids(&second).await;
+    assert!(
+        first_ids.is_subset(&second_ids),
+        "{:?} should be a subset of {:?}",
+        first_ids,
+        second_ids
+    );
+}
+
+#[test(tokio::test)]
+async fn test_parallelism_hints() {
+    let sql = "SET parallelism = 3;
+    CREATE TABLE counts (count BIGINT) WITH (
+        connector = 'single_file',
+        path = '/tmp/counts.json',
+        format = 'json',
+        type = 'sink',
+        parallelism = '2'
+    );
+    INSERT INTO counts SELECT count(*) FROM nexmark GROUP BY tumble(interval '1 second');";
+
+    let program = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap()
+        .program;
+    program.validate_parallelism().unwrap();
+
+    for node in program.graph.node_weights() {
+        match node.operator_name {
+            OperatorName::ConnectorSink => assert_eq!(node.parallelism, 2),
+            OperatorName::ConnectorSource => assert_eq!(node.parallelism, 3),
+            _ => {}
+        }
+    }
+
+    let sink_id = program
+        .graph
+        .node_weights()
+        .find(|n| n.operator_name == OperatorName::ConnectorSink)
+        .unwrap()
+        .operator_id
+        .clone();
+    let program = parse_and_get_program(
+        &format!("SET parallelism.{} = 5;\n{}", sink_id, sql),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap()
+    .program;
+    let sink = program.operator_index(&sink_id).unwrap();
+    assert_eq!(program.graph[NodeIndex::new(sink as usize)].parallelism, 5);
+
+    assert!(parse_and_get_program(
+        &format!("SET parallelism.not_an_operator = 5;\n{}", sql),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .is_err());
+}
@@ -0,0 +1,15 @@
+create table logs (
+  ip TEXT,
+  status INT
+) with (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  format = 'json',
+  type = 'source',
+  topic ='logs',
+  'source.offset' = 'latest'
+);
+
+SELECT ip, get_city(ip) as city
+FROM logs
+WHERE get_city(ip) = 'Berlin' AND status = 200;
@@ -0,0 +1,26 @@
+create table logs (
+  ip TEXT
+) with (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  format = 'json',
+  type = 'source',
+  topic ='logs',
+  'source.offset' = 'latest'
+);
+
+create table cities (
+  name TEXT,
+  population BIGINT
+) with (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  format = 'json',
+  type = 'source',
+  topic ='cities',
+  'source.offset' = 'latest'
+);
+
+SELECT logs.ip, cities.population
+FROM logs
+JOIN cities ON get_city(logs.ip) = cities.name;
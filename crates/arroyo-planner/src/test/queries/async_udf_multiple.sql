@@ -0,0 +1,14 @@
+create table logs (
+  src_ip TEXT,
+  dst_ip TEXT
+) with (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  format = 'json',
+  type = 'source',
+  topic ='logs',
+  'source.offset' = 'latest'
+);
+
+SELECT get_city(src_ip) as src_city, get_city(dst_ip) as dst_city, get_city(get_city(src_ip)) as nested
+FROM logs;
@@ -0,0 +1,4 @@
+--fail=Error during planning: unknown option 'updating_aggregate.size'
+SET updating_aggregate.size = '7 days';
+
+SELECT bid.bidder, count(*) FROM nexmark GROUP BY 1;
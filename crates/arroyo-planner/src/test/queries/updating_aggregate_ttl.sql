@@ -0,0 +1,4 @@
+SET updating_aggregate.ttl = '7 days';
+SET updating_aggregate.retract_on_expire = true;
+
+SELECT bid.bidder, count(*) FROM nexmark GROUP BY 1;
@@ -8,6 +8,11 @@ pub struct Checkpoint {
     pub backend: String,
     pub start_time: u64,
     pub finish_time: Option<u64>,
+    /// Bytes of new state written for this epoch, not including files carried over from
+    /// earlier checkpoints
+    pub bytes: u64,
+    /// Why the checkpoint failed, if it did
+    pub failure_reason: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -43,3 +48,30 @@ pub struct OperatorCheckpointGroup {
     pub bytes: u64,
     pub subtasks: Vec<SubtaskCheckpointGroup>,
 }
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SavepointState {
+    Requested,
+    InProgress,
+    Ready,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Savepoint {
+    pub id: String,
+    pub url: String,
+    pub state: SavepointState,
+    pub epoch: Option<u32>,
+    pub created_at: u64,
+    pub finish_time: Option<u64>,
+    pub failure_message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SavepointPost {
+    pub url: String,
+}
@@ -15,6 +15,8 @@ pub enum MetricName {
     Backpressure,
     TxQueueSize,
     TxQueueRem,
+    BusyTime,
+    SourceLag,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
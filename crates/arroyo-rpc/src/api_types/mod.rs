@@ -11,6 +11,7 @@ pub mod checkpoints;
 pub mod connections;
 pub mod metrics;
 pub mod pipelines;
+pub mod state;
 pub mod udfs;
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -35,6 +36,7 @@ pub struct PaginatedCollection<T> {
     ConnectorCollection = NonPaginatedCollection<Connector>,
     ConnectionProfileCollection = NonPaginatedCollection<ConnectionProfile>,
     GlobalUdfCollection = NonPaginatedCollection<GlobalUdf>,
+    SavepointCollection = NonPaginatedCollection<Savepoint>,
 )]
 pub struct NonPaginatedCollection<T> {
     pub data: Vec<T>,
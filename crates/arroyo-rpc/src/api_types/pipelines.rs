@@ -1,6 +1,7 @@
 use crate::api_types::udfs::Udf;
 use crate::grpc as grpc_proto;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use utoipa::ToSchema;
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -23,8 +24,53 @@ pub struct PipelinePost {
     pub name: String,
     pub query: String,
     pub udfs: Option<Vec<Udf>>,
+    /// Default parallelism for the operators in the pipeline
     pub parallelism: u64,
+    /// Parallelism for individual operators, keyed by node id; these take precedence over the
+    /// default parallelism and any parallelism hints in the query
+    pub parallelism_overrides: Option<HashMap<String, u64>>,
     pub checkpoint_interval_micros: Option<u64>,
+    /// A savepoint id or URL to restore the pipeline's state from
+    pub restore_from: Option<String>,
+    /// Maps operator ids in the pipeline to the ids of the operators in the savepoint whose
+    /// state they should be restored from
+    pub state_mapping: Option<HashMap<String, String>>,
+    /// When restoring, operators whose state is incompatible with the savepoint start with
+    /// empty state instead of failing the restore
+    pub drop_incompatible_state: Option<bool>,
+    /// How the pipeline is restarted after failures; defaults to the cluster's configured
+    /// strategy
+    pub restart_strategy: Option<RestartStrategy>,
+    /// Labels that nodes must have for the pipeline's workers to be scheduled on them; only
+    /// used by the node and Kubernetes schedulers
+    pub node_selector: Option<HashMap<String, String>>,
+    /// When the cluster doesn't have enough free slots, queued pipelines with higher priorities
+    /// are scheduled first; defaults to 0
+    pub priority: Option<i32>,
+}
+
+/// How a pipeline is restarted after it fails
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RestartStrategy {
+    /// Waits the same amount of time before every restart
+    #[serde(rename_all = "camelCase")]
+    FixedDelay { delay_micros: u64 },
+    /// Multiplies the delay by `multiplier` after each consecutive restart, up to the max delay
+    #[serde(rename_all = "camelCase")]
+    ExponentialBackoff {
+        initial_delay_micros: u64,
+        max_delay_micros: u64,
+        multiplier: f64,
+    },
+    /// Waits a fixed delay before each restart, but fails the pipeline once it has failed
+    /// `maxFailures` times within the interval
+    #[serde(rename_all = "camelCase")]
+    FailureRate {
+        max_failures: u32,
+        interval_micros: u64,
+        delay_micros: u64,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -39,15 +85,37 @@ pub struct PreviewPost {
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PipelinePatch {
+    /// Sets the parallelism of every operator in the pipeline
     pub parallelism: Option<u64>,
+    /// Sets the parallelism of individual operators, keyed by node id; applied after
+    /// `parallelism`
+    pub parallelism_overrides: Option<HashMap<String, u64>>,
     pub checkpoint_interval_micros: Option<u64>,
     pub stop: Option<StopType>,
+    /// A savepoint id or URL to restore the pipeline's state from; this is applied the next time
+    /// the pipeline is started, so a running pipeline must be stopped or restarted for it to
+    /// take effect
+    pub restore_from: Option<String>,
+    /// Maps operator ids in the pipeline to the ids of the operators in the savepoint whose
+    /// state they should be restored from
+    pub state_mapping: Option<HashMap<String, String>>,
+    /// When restoring, operators whose state is incompatible with the savepoint start with
+    /// empty state instead of failing the restore
+    pub drop_incompatible_state: Option<bool>,
+    pub restart_strategy: Option<RestartStrategy>,
+    /// Replaces the pipeline's node selector; takes effect the next time its workers are
+    /// scheduled
+    pub node_selector: Option<HashMap<String, String>>,
+    pub priority: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PipelineRestart {
     pub force: Option<bool>,
+    /// Restore from the checkpoint with this epoch instead of the latest one; later
+    /// checkpoints are discarded
+    pub checkpoint_epoch: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -114,6 +182,8 @@ pub struct Job {
     pub finish_time: Option<u64>,
     pub tasks: Option<u64>,
     pub failure_message: Option<String>,
+    /// How long the controller is waiting before restarting the job after a failure
+    pub restart_delay_micros: Option<u64>,
     pub created_at: u64,
 }
 
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "snake_case")]
+pub struct StateQueryParams {
+    /// JSON object mapping the key columns of the table to the values to look up; if not set,
+    /// all entries are returned
+    pub key: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StateQueryResult {
+    pub operator_id: String,
+    pub table: String,
+    pub rows: Vec<serde_json::Value>,
+}
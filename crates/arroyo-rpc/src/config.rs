@@ -1,3 +1,4 @@
+use crate::api_types::pipelines::RestartStrategy;
 use arc_swap::ArcSwapOption;
 use figment::providers::{Env, Format, Json, Toml, Yaml};
 use figment::Figment;
@@ -218,6 +219,9 @@ pub struct Config {
     /// Process scheduler configuration
     pub process_scheduler: ProcessSchedulerConfig,
 
+    /// Node scheduler configuration
+    pub node_scheduler: NodeSchedulerConfig,
+
     // Kubernetes scheduler configuration
     pub kubernetes_scheduler: KubernetesSchedulerConfig,
 
@@ -227,9 +231,22 @@ pub struct Config {
     /// URL of an object store or filesystem for storing checkpoints
     pub checkpoint_url: String,
 
+    /// Backend used to hold operator state and write it to checkpoints
+    pub state_backend: StateBackendType,
+
     /// Default interval for checkpointing
     pub default_checkpoint_interval: HumanReadableDuration,
 
+    /// Client-side encryption for checkpoint data and metadata; if not set, checkpoints are
+    /// written unencrypted
+    pub checkpoint_encryption: Option<CheckpointEncryptionConfig>,
+
+    /// Local disk cache for objects read from remote storage, like checkpoint files
+    pub storage_cache: StorageCacheConfig,
+
+    /// Retry policy for transient errors from object stores
+    pub storage_retry: StorageRetryConfig,
+
     /// The endpoint of the controller, used by other services to connect to it. This must be set
     /// if running the controller on a separate machine from the other services or on a separate
     /// process with a non-standard port.
@@ -279,6 +296,47 @@ pub struct ApiConfig {
 
     /// The HTTP port for the API service in run mode; defaults to a random port
     pub run_http_port: Option<u16>,
+
+    /// Limits on the pipelines that organizations can run; unset limits are unlimited
+    #[serde(default)]
+    pub quotas: QuotaConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct QuotaConfig {
+    /// Maximum number of pipelines that can be running at once, not counting previews
+    pub max_running_jobs: Option<u32>,
+
+    /// Maximum parallelism for any operator of a pipeline
+    pub max_parallelism: Option<u32>,
+
+    /// Maximum number of operators in a pipeline
+    pub max_operators: Option<u32>,
+
+    /// Overrides of the limits above for individual organizations, keyed by organization id
+    #[serde(default)]
+    pub organizations: BTreeMap<String, OrganizationQuota>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct OrganizationQuota {
+    pub max_running_jobs: Option<u32>,
+    pub max_parallelism: Option<u32>,
+    pub max_operators: Option<u32>,
+}
+
+impl QuotaConfig {
+    /// The limits for an organization, falling back to the defaults for any it doesn't override
+    pub fn for_organization(&self, organization_id: &str) -> OrganizationQuota {
+        let o = self.organizations.get(organization_id);
+        OrganizationQuota {
+            max_running_jobs: o.and_then(|o| o.max_running_jobs).or(self.max_running_jobs),
+            max_parallelism: o.and_then(|o| o.max_parallelism).or(self.max_parallelism),
+            max_operators: o.and_then(|o| o.max_operators).or(self.max_operators),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -292,6 +350,30 @@ pub struct ControllerConfig {
 
     /// The scheduler to use
     pub scheduler: Scheduler,
+
+    /// Leader election, for running multiple controllers against the same database
+    pub leader_election: LeaderElectionConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LeaderElectionConfig {
+    /// Whether to elect a leader among the controllers; only the leader runs pipelines, while the
+    /// others wait to take over if it fails, adopting the pipelines that were running.
+    ///
+    /// Leader election requires a Postgres database. A SQLite database can only be used by a
+    /// single controller, so with SQLite this setting is ignored and the controller always acts
+    /// as the leader.
+    pub enabled: bool,
+
+    /// How long a leader holds its lease without renewing it before a standby can take over. This
+    /// should be well below `pipeline.worker-heartbeat-timeout`, as running workers shut down if
+    /// they can't reach a leader for that long, after which their pipelines are restarted rather
+    /// than adopted
+    pub lease_duration: HumanReadableDuration,
+
+    /// How often the leader renews its lease, and standbys try to acquire it
+    pub renew_interval: HumanReadableDuration,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -302,6 +384,10 @@ pub struct CompactionConfig {
 
     /// The number of outstanding checkpoints that will trigger compaction
     pub checkpoints_to_compact: u32,
+
+    /// Files at least this large (in MiB) that belong to a single subtask and have no expired
+    /// rows are carried over into the compacted checkpoint as they are, rather than rewritten
+    pub reuse_file_size_mb: u64,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -355,6 +441,14 @@ pub struct WorkerConfig {
 
     /// Size of the queues between nodes in the dataflow graph
     pub queue_size: u32,
+
+    /// Local directory for the worker's data, like state that has been spilled to disk
+    pub data_dir: PathBuf,
+
+    /// Memory (in MiB) that each task may use for window and join state before it starts
+    /// spilling the least-recently-used data to `data-dir`; if unset, state is kept in memory
+    #[serde(default)]
+    pub state_memory_budget_mb: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -371,6 +465,11 @@ pub struct NodeConfig {
 
     /// Number of task slots for this node
     pub task_slots: u32,
+
+    /// Labels for this node, which pipelines can select with node selectors when run with the
+    /// node scheduler
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
 }
 
 impl NodeConfig {
@@ -397,6 +496,83 @@ pub enum DefaultSink {
     Stdout,
 }
 
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum StateBackendType {
+    /// All state is held in memory, and checkpointed as parquet files in the checkpoint storage
+    Parquet,
+    /// Keyed state for updating aggregates is held in an embedded key-value store under the
+    /// worker's `data-dir`, so that it doesn't need to fit in memory. Checkpoints are written in
+    /// the same format as the parquet backend.
+    Kv,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StorageCacheConfig {
+    /// Whether to cache objects read from remote object stores on local disk, so that they
+    /// don't need to be downloaded again (for example, when a job is restored)
+    pub enabled: bool,
+
+    /// Local directory for the cache; this may be shared across restarts of the process
+    pub path: PathBuf,
+
+    /// Maximum size (in MiB) of the cache, beyond which the least-recently-used objects are
+    /// evicted
+    pub max_size_mb: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StorageRetryConfig {
+    /// Maximum number of attempts for each storage operation, including the first
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, which doubles (with jitter) for each subsequent retry
+    pub base_delay: HumanReadableDuration,
+
+    /// Maximum delay between retries
+    pub max_delay: HumanReadableDuration,
+
+    /// Time after which a single attempt of a storage operation is abandoned and retried; if
+    /// unset, attempts never time out
+    pub operation_timeout: Option<HumanReadableDuration>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CheckpointEncryptionConfig {
+    /// Path to a file containing a base64-encoded 256-bit key
+    pub key_file: Option<PathBuf>,
+
+    /// Name of an environment variable containing a base64-encoded 256-bit key
+    pub key_env: Option<String>,
+}
+
+impl CheckpointEncryptionConfig {
+    /// Reads the base64-encoded key from the configured file or environment variable
+    pub fn read_key(&self) -> anyhow::Result<String> {
+        match (&self.key_file, &self.key_env) {
+            (Some(path), None) => fs::read_to_string(path).map_err(|e| {
+                anyhow::anyhow!(
+                    "failed to read checkpoint encryption key from {}: {}",
+                    path.display(),
+                    e
+                )
+            }),
+            (None, Some(var)) => std::env::var(var).map_err(|_| {
+                anyhow::anyhow!(
+                    "checkpoint encryption key environment variable {} is not set",
+                    var
+                )
+            }),
+            _ => anyhow::bail!(
+                "exactly one of checkpoint-encryption.key-file and checkpoint-encryption.key-env must be set"
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct PipelineConfig {
@@ -409,7 +585,12 @@ pub struct PipelineConfig {
     /// How often to flush aggregates
     pub update_aggregate_flush_interval: HumanReadableDuration,
 
-    /// How many restarts to allow before moving to failed (-1 for infinite)
+    /// How long keys in updating aggregates are kept in state after they were last updated;
+    /// can be overridden per query with `SET updating_aggregate.ttl = '<interval>'`
+    pub update_aggregate_ttl: HumanReadableDuration,
+
+    /// How many restarts to allow before moving to failed (-1 for infinite); applies to the
+    /// fixed-delay and exponential-backoff restart strategies
     pub allowed_restarts: i32,
 
     /// After this amount of time, we consider the job to be healthy and reset the restarts counter
@@ -428,7 +609,139 @@ pub struct PipelineConfig {
     #[serde(default)]
     pub default_sink: DefaultSink,
 
+    /// Number of recent checkpoints to retain for each job; a pipeline can be restarted from
+    /// any of these, older checkpoints are compacted away
+    pub checkpoints_to_keep: u32,
+
+    /// Whether checkpoint barriers should overtake queued data rather than waiting behind it;
+    /// the data they overtake is stored as part of the checkpoint and replayed on restore.
+    /// This keeps checkpoint times low when the pipeline is backpressured.
+    pub unaligned_checkpoints: bool,
+
+    /// How long a checkpoint may take before it is considered failed
+    pub checkpoint_timeout: HumanReadableDuration,
+
+    /// Maximum number of checkpoints that may be in progress at once; a new checkpoint is
+    /// started once the checkpoint interval has passed, even if earlier ones haven't finished
+    pub max_concurrent_checkpoints: u32,
+
+    /// Number of consecutive checkpoint failures (including timeouts) that are tolerated before
+    /// the job is restarted; the data written for failed checkpoints is removed by the next
+    /// checkpoint cleanup
+    pub tolerable_checkpoint_failures: u32,
+
     pub compaction: CompactionConfig,
+
+    pub autoscaler: AutoscalerConfig,
+
+    /// How failed pipelines are restarted; can be overridden per pipeline
+    pub restart_strategy: RestartStrategyConfig,
+
+    /// Which tasks are restarted when a task or worker fails: `full` restarts the whole pipeline,
+    /// while `region` runs each independent part of the pipeline on its own workers and restarts
+    /// only the part that failed
+    pub failover_strategy: FailoverStrategy,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailoverStrategy {
+    Full,
+    Region,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartStrategyType {
+    FixedDelay,
+    ExponentialBackoff,
+    FailureRate,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RestartStrategyConfig {
+    /// The strategy to use: fixed-delay, exponential-backoff or failure-rate
+    pub strategy: RestartStrategyType,
+
+    /// Time to wait before restarting for the fixed-delay and failure-rate strategies, and
+    /// before the first restart for exponential-backoff
+    pub delay: HumanReadableDuration,
+
+    /// Maximum time to wait before restarting for exponential-backoff
+    pub max_delay: HumanReadableDuration,
+
+    /// Factor by which the exponential-backoff delay grows with each consecutive restart
+    pub backoff_multiplier: f64,
+
+    /// For failure-rate, the number of failures within the failure interval after which the
+    /// pipeline is failed rather than restarted
+    pub max_failures: u32,
+
+    /// For failure-rate, the interval over which failures are counted
+    pub failure_interval: HumanReadableDuration,
+}
+
+impl RestartStrategyConfig {
+    pub fn strategy(&self) -> RestartStrategy {
+        match self.strategy {
+            RestartStrategyType::FixedDelay => RestartStrategy::FixedDelay {
+                delay_micros: self.delay.as_micros() as u64,
+            },
+            RestartStrategyType::ExponentialBackoff => RestartStrategy::ExponentialBackoff {
+                initial_delay_micros: self.delay.as_micros() as u64,
+                max_delay_micros: self.max_delay.as_micros() as u64,
+                multiplier: self.backoff_multiplier,
+            },
+            RestartStrategyType::FailureRate => RestartStrategy::FailureRate {
+                max_failures: self.max_failures,
+                interval_micros: self.failure_interval.as_micros() as u64,
+                delay_micros: self.delay.as_micros() as u64,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AutoscalerConfig {
+    /// Whether the controller should automatically rescale running pipelines based on
+    /// backpressure, busy time and source lag
+    pub enabled: bool,
+
+    /// The autoscaler will not scale a pipeline below this parallelism
+    pub min_parallelism: usize,
+
+    /// The autoscaler will not scale a pipeline above this parallelism
+    pub max_parallelism: usize,
+
+    /// Average backpressure (between 0 and 1) of the most backpressured operator at or above
+    /// which the pipeline is scaled up
+    pub scale_up_threshold: f64,
+
+    /// Average backpressure of the most backpressured operator at or below which the pipeline
+    /// is scaled down
+    pub scale_down_threshold: f64,
+
+    /// Fraction of time (between 0 and 1) the busiest operator spends processing data at or
+    /// above which the pipeline is scaled up
+    pub scale_up_busy_threshold: f64,
+
+    /// Fraction of time the busiest operator spends processing data at or below which the
+    /// pipeline may be scaled down
+    pub scale_down_busy_threshold: f64,
+
+    /// Factor by which parallelism is multiplied when scaling up
+    pub scale_up_factor: f64,
+
+    /// Factor by which parallelism is multiplied when scaling down
+    pub scale_down_factor: f64,
+
+    /// Window over which backpressure and busy time are averaged, and source lag is compared
+    pub window: HumanReadableDuration,
+
+    /// Minimum time after a pipeline is (re)scheduled before the autoscaler may rescale it
+    pub cooldown: HumanReadableDuration,
 }
 
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone)]
@@ -486,6 +799,43 @@ pub enum Scheduler {
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ProcessSchedulerConfig {
     pub slots_per_process: u32,
+
+    /// How `resources` applies to each worker process: per-slot scales the limits by the number
+    /// of slots in the process, while per-pod applies them to each process as-is
+    pub resource_mode: ResourceMode,
+
+    /// Resource limits for worker processes, in the same format as for Kubernetes; only `limits`
+    /// are used. On Linux these are enforced with cgroup v2 if `cgroup-parent` is set, otherwise
+    /// the memory limit is enforced with rlimits.
+    #[serde(default)]
+    pub resources: ResourceRequirements,
+
+    /// The cgroup (relative to /sys/fs/cgroup) under which worker cgroups are created. It must
+    /// already exist, be delegated to the controller (e.g., by systemd with `Delegate=yes`), and
+    /// be dedicated to the workers. If unset, cgroups aren't used, and only the memory limit is
+    /// enforced, with rlimits.
+    pub cgroup_parent: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NodeSchedulerConfig {
+    /// How a job's workers are placed on the nodes that match its node selector
+    pub placement: NodePlacement,
+
+    /// If enabled, no two workers for the same job are placed on the same node; jobs wait to be
+    /// scheduled until enough distinct nodes have free slots
+    pub anti_affinity: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodePlacement {
+    /// Fills the nodes with the most free slots first, using as few workers as possible
+    Pack,
+    /// Spreads the job's slots evenly across the nodes, preferring the nodes with the fewest
+    /// slots already scheduled for the job
+    Spread,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -610,6 +960,7 @@ impl<'de> Deserialize<'de> for HumanReadableDuration {
             "s" | "secs" | "seconds" => Duration::from_secs(n),
             "m" | "mins" | "minutes" => Duration::from_secs(n * 60),
             "h" | "hrs" | "hours" => Duration::from_secs(n * 60 * 60),
+            "d" | "days" => Duration::from_secs(n * 60 * 60 * 24),
             x => return Err(de::Error::custom(format!("unknown time unit '{}'", x))),
         };
 
@@ -711,6 +1062,37 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_quota_config() {
+        figment::Jail::expect_with(|jail| {
+            jail.create_file(
+                "arroyo.toml",
+                r#"
+            [api.quotas]
+            max-running-jobs = 5
+            max-parallelism = 16
+
+            [api.quotas.organizations.big-org]
+            max-running-jobs = 50
+            "#,
+            )
+            .unwrap();
+
+            let config: Config = load_config(&[]).extract().unwrap();
+
+            let quota = config.api.quotas.for_organization("org");
+            assert_eq!(quota.max_running_jobs, Some(5));
+            assert_eq!(quota.max_parallelism, Some(16));
+            assert_eq!(quota.max_operators, None);
+
+            let quota = config.api.quotas.for_organization("big-org");
+            assert_eq!(quota.max_running_jobs, Some(50));
+            assert_eq!(quota.max_parallelism, Some(16));
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_legacy_config() {
         figment::Jail::expect_with(|jail| {
@@ -13,13 +13,14 @@ use crate::formats::{BadData, Format, Framing};
 use crate::grpc::rpc::{LoadCompactedDataReq, SubtaskCheckpointMetadata};
 use anyhow::Result;
 use arrow::row::{OwnedRow, RowConverter, Rows, SortField};
-use arrow_array::{Array, ArrayRef, BooleanArray};
+use arrow_array::{Array, ArrayRef, BooleanArray, RecordBatch};
 use arrow_schema::DataType;
 use arroyo_types::{CheckpointBarrier, HASH_SEEDS};
 use grpc::rpc::{StopMode, TableCheckpointMetadata, TaskCheckpointEventType};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::oneshot;
 use tonic::{
     metadata::{Ascii, MetadataValue},
     service::Interceptor,
@@ -70,9 +71,22 @@ pub enum ControlMessage {
     LoadCompacted {
         compacted: CompactionResult,
     },
+    QueryState(StateQuery),
     NoOp,
 }
 
+/// A read-only lookup of the current contents of one of an operator's state tables
+#[derive(Debug)]
+pub struct StateQuery {
+    pub table: String,
+    /// maps the key columns of the table to the values to look up; if not set, all entries
+    /// are returned
+    pub key: Option<serde_json::Map<String, Value>>,
+    pub limit: usize,
+    /// receives the matching entries, or None if the subtask does not own the key
+    pub tx: oneshot::Sender<Result<Option<RecordBatch>>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct CompactionResult {
     pub operator_id: String,
@@ -105,6 +105,7 @@ async fn checkpoint(ctx: &mut SmokeTestContext<'_>, epoch: u32) {
         min_epoch: 0,
         timestamp: SystemTime::now(),
         then_stop: false,
+        unaligned: false,
     };
 
     for source in ctx.engine.source_controls() {
@@ -252,6 +253,7 @@ async fn run_and_checkpoint(job_id: Arc<String>, program: Program, checkpoint_in
     let (running_engine, mut control_rx) = engine
         .start(StreamConfig {
             restore_epoch: None,
+            next_epoch: None,
         })
         .await;
     info!("Smoke test checkpointing enabled");
@@ -296,6 +298,7 @@ async fn finish_from_checkpoint(job_id: &str, program: Program) {
     let (running_engine, mut control_rx) = engine
         .start(StreamConfig {
             restore_epoch: Some(3),
+            next_epoch: None,
         })
         .await;
 
@@ -358,6 +361,7 @@ async fn run_completely(
     let (running_engine, mut control_rx) = engine
         .start(StreamConfig {
             restore_epoch: None,
+            next_epoch: None,
         })
         .await;
 
@@ -11,8 +11,9 @@ use arroyo_rpc::grpc::{
     rpc,
     rpc::{
         CheckpointMetadata, OperatorCheckpointMetadata, OperatorMetadata,
-        SubtaskCheckpointMetadata, TableCheckpointMetadata, TableConfig, TableEnum,
-        TableSubtaskCheckpointMetadata, TaskCheckpointCompletedReq, TaskCheckpointEventReq,
+        SubtaskCheckpointMetadata, SubtaskInFlightData, TableCheckpointMetadata, TableConfig,
+        TableEnum, TableSubtaskCheckpointMetadata, TaskCheckpointCompletedReq,
+        TaskCheckpointEventReq,
     },
 };
 use arroyo_types::{from_micros, to_micros};
@@ -20,6 +21,7 @@ use tracing::{debug, warn};
 
 use crate::{
     committing_state::CommittingState,
+    parquet::compute_file_refs,
     tables::{
         expiring_time_key_map::ExpiringTimeKeyTable, global_keyed_map::GlobalKeyedTable,
         ErasedTable,
@@ -34,6 +36,7 @@ pub struct CheckpointState {
     epoch: u32,
     min_epoch: u32,
     start_time: SystemTime,
+    bytes: u64,
     operators: usize,
     operators_checkpointed: usize,
     operator_state: HashMap<String, OperatorState>,
@@ -54,6 +57,7 @@ pub struct OperatorState {
     pub finish_time: Option<SystemTime>,
     table_state: HashMap<String, TableState>,
     watermarks: Vec<Option<SystemTime>>,
+    in_flight: HashMap<u32, SubtaskInFlightData>,
 }
 
 impl OperatorState {
@@ -65,6 +69,7 @@ impl OperatorState {
             finish_time: None,
             table_state: HashMap::new(),
             watermarks: vec![],
+            in_flight: HashMap::new(),
         }
     }
 
@@ -101,6 +106,14 @@ impl OperatorState {
                 .subtask_tables
                 .insert(table_metadata.subtask_index, table_metadata);
         }
+        if !c.in_flight.is_empty() {
+            self.in_flight.insert(
+                c.subtask_index,
+                SubtaskInFlightData {
+                    inputs: c.in_flight,
+                },
+            );
+        }
 
         if self.subtasks == self.subtasks_checkpointed {
             let (table_configs, table_metadatas) = self
@@ -160,6 +173,7 @@ impl CheckpointState {
             epoch,
             min_epoch,
             start_time: SystemTime::now(),
+            bytes: 0,
             operators: tasks_per_operator.len(),
             operators_checkpointed: 0,
             operator_state: tasks_per_operator
@@ -176,10 +190,19 @@ impl CheckpointState {
         &self.checkpoint_id
     }
 
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
     pub fn start_time(&self) -> SystemTime {
         self.start_time
     }
 
+    /// Total bytes of new data written by all subtasks for this checkpoint
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
     pub fn checkpoint_event(&mut self, c: TaskCheckpointEventReq) -> anyhow::Result<()> {
         debug!(message = "Checkpoint event", checkpoint_id = self.checkpoint_id, event_type = ?c.event_type(), subtask_index = c.subtask_index, operator_id = ?c.operator_id);
 
@@ -274,6 +297,7 @@ impl CheckpointState {
                 }
             });
         detail.bytes = Some(metadata.bytes);
+        self.bytes += metadata.bytes;
 
         let operator_state = self
             .operator_state
@@ -325,22 +349,44 @@ impl CheckpointState {
                         .insert(table.clone(), committing_data);
                 }
             }
-            StateBackend::write_operator_checkpoint_metadata(OperatorCheckpointMetadata {
+            let mut operator_metadata = OperatorCheckpointMetadata {
                 start_time: to_micros(operator_state.start_time.unwrap()),
                 finish_time: to_micros(operator_state.finish_time.unwrap()),
                 table_checkpoint_metadata,
                 table_configs,
                 operator_metadata: Some(OperatorMetadata {
                     job_id: self.job_id.to_string(),
-                    operator_id: c.operator_id,
+                    operator_id: c.operator_id.clone(),
                     epoch: self.epoch,
                     min_watermark,
                     max_watermark,
                     parallelism: operator_state.subtasks_checkpointed as u64,
                 }),
-            })
-            .await
-            .expect("Should be able to write operator checkpoint metadata");
+                file_refs: HashMap::new(),
+                in_flight: std::mem::take(&mut operator_state.in_flight),
+            };
+
+            // files may be carried over from the previous epoch, so we build on its references;
+            // if that epoch's checkpoint failed we use the most recent one before it instead
+            let mut previous = None;
+            for epoch in (self.min_epoch..self.epoch).rev() {
+                previous =
+                    StateBackend::load_operator_metadata(&self.job_id, &c.operator_id, epoch)
+                        .await?;
+                if previous.is_some() {
+                    break;
+                }
+            }
+            operator_metadata.file_refs = compute_file_refs(
+                previous.as_ref(),
+                &operator_metadata,
+                self.epoch,
+                self.min_epoch,
+            )?;
+
+            StateBackend::write_operator_checkpoint_metadata(operator_metadata)
+                .await
+                .expect("Should be able to write operator checkpoint metadata");
         }
         Ok(())
     }
@@ -370,6 +416,7 @@ impl CheckpointState {
                 .keys()
                 .map(|key| key.to_string())
                 .collect(),
+            bytes: self.bytes,
         })
         .await?;
         Ok(())
@@ -0,0 +1,299 @@
+use arroyo_rpc::grpc::rpc::{
+    ExpiringKeyedTimeTableConfig, GlobalKeyedTableConfig, OperatorCheckpointMetadata, TableConfig,
+    TableEnum,
+};
+use prost::Message;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+
+/// What will happen to an operator's state when a program is restored from a checkpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperatorStateStatus {
+    /// The operator's state in the checkpoint will be restored
+    Reused,
+    /// There is no state for the operator in the checkpoint, so it will start empty
+    New,
+    /// The operator has state in the checkpoint, but it can't be used by the new operator
+    Incompatible(Vec<String>),
+}
+
+/// Compares the state tables of the operators in a program against the state that was written
+/// for them in a checkpoint, to determine whether the program can be restored from it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateCompatibilityReport {
+    pub operators: BTreeMap<String, OperatorStateStatus>,
+    /// operators that have state in the checkpoint but don't exist in the new program; their
+    /// state will not be restored
+    pub dropped: BTreeSet<String>,
+}
+
+impl StateCompatibilityReport {
+    /// `tables` holds the table configs of each operator in the new program, keyed by operator
+    /// id, and `checkpoint` the metadata of each operator in the checkpoint being restored
+    pub fn check(
+        tables: &HashMap<String, HashMap<String, TableConfig>>,
+        checkpoint: &HashMap<String, OperatorCheckpointMetadata>,
+    ) -> Self {
+        let mut report = Self::default();
+
+        for (operator_id, tables) in tables {
+            let status = match checkpoint.get(operator_id) {
+                Some(metadata) => {
+                    let reasons = incompatibilities(tables, &metadata.table_configs);
+                    if reasons.is_empty() {
+                        OperatorStateStatus::Reused
+                    } else {
+                        OperatorStateStatus::Incompatible(reasons)
+                    }
+                }
+                None => OperatorStateStatus::New,
+            };
+            report.operators.insert(operator_id.clone(), status);
+        }
+
+        report.dropped = checkpoint
+            .keys()
+            .filter(|id| !tables.contains_key(*id))
+            .cloned()
+            .collect();
+
+        report
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        !self
+            .operators
+            .values()
+            .any(|s| matches!(s, OperatorStateStatus::Incompatible(_)))
+    }
+}
+
+fn incompatibilities(
+    new_tables: &HashMap<String, TableConfig>,
+    old_tables: &HashMap<String, TableConfig>,
+) -> Vec<String> {
+    let mut reasons = vec![];
+
+    let mut names: Vec<_> = old_tables.keys().collect();
+    names.sort();
+
+    for name in names {
+        let old = &old_tables[name];
+        let Some(new) = new_tables.get(name) else {
+            reasons.push(format!("table '{}' is not used by the new operator", name));
+            continue;
+        };
+
+        if old.table_type != new.table_type {
+            reasons.push(format!(
+                "table '{}' changed type from {:?} to {:?}",
+                name,
+                old.table_type(),
+                new.table_type()
+            ));
+            continue;
+        }
+
+        match new.table_type() {
+            TableEnum::ExpiringKeyedTimeTable => {
+                match (
+                    ExpiringKeyedTimeTableConfig::decode(&old.config[..]),
+                    ExpiringKeyedTimeTableConfig::decode(&new.config[..]),
+                ) {
+                    (Ok(old), Ok(new)) => {
+                        if old.schema != new.schema {
+                            reasons.push(format!("the schema of table '{}' has changed", name));
+                        }
+                        // generational tables keep a generation column in their files
+                        if old.generational != new.generational {
+                            reasons.push(format!(
+                                "table '{}' changed from {}generational to {}generational",
+                                name,
+                                if old.generational { "" } else { "non-" },
+                                if new.generational { "" } else { "non-" },
+                            ));
+                        }
+                    }
+                    _ => {
+                        reasons.push(format!("invalid config for table '{}'", name));
+                    }
+                }
+            }
+            TableEnum::GlobalKeyValue => {
+                match (
+                    GlobalKeyedTableConfig::decode(&old.config[..]),
+                    GlobalKeyedTableConfig::decode(&new.config[..]),
+                ) {
+                    (Ok(old), Ok(new)) => {
+                        // the values of global tables are opaque to us, but two-phase commit
+                        // tables store pre-commit data that the new operator must be able to
+                        // commit
+                        if old.uses_two_phase_commit != new.uses_two_phase_commit {
+                            reasons.push(format!(
+                                "table '{}' changed whether it uses two-phase commit",
+                                name
+                            ));
+                        }
+                    }
+                    _ => {
+                        reasons.push(format!("invalid config for table '{}'", name));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    reasons
+}
+
+impl Display for StateCompatibilityReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (operator_id, status) in &self.operators {
+            match status {
+                OperatorStateStatus::Reused => writeln!(f, "  {}: reused", operator_id)?,
+                OperatorStateStatus::New => writeln!(f, "  {}: new", operator_id)?,
+                OperatorStateStatus::Incompatible(reasons) => writeln!(
+                    f,
+                    "  {}: incompatible ({})",
+                    operator_id,
+                    reasons.join("; ")
+                )?,
+            }
+        }
+        for operator_id in &self.dropped {
+            writeln!(f, "  {}: dropped", operator_id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{global_table_config, timestamp_table_config};
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use arroyo_rpc::df::ArroyoSchema;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn schema(fields: Vec<Field>) -> ArroyoSchema {
+        let mut fields = fields;
+        fields.push(Field::new(
+            "_timestamp",
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            false,
+        ));
+        ArroyoSchema::from_schema_keys(Arc::new(Schema::new(fields)), vec![]).unwrap()
+    }
+
+    fn timestamp_tables(retention: Duration, schema: ArroyoSchema) -> HashMap<String, TableConfig> {
+        HashMap::from([(
+            "t".to_string(),
+            timestamp_table_config("t", "tumbling", retention, false, schema),
+        )])
+    }
+
+    fn two_phase_tables(uses_two_phase_commit: bool) -> HashMap<String, TableConfig> {
+        HashMap::from([(
+            "k".to_string(),
+            TableConfig {
+                table_type: TableEnum::GlobalKeyValue.into(),
+                config: GlobalKeyedTableConfig {
+                    table_name: "k".to_string(),
+                    description: "sink".to_string(),
+                    uses_two_phase_commit,
+                    ttl_micros: 0,
+                }
+                .encode_to_vec(),
+            },
+        )])
+    }
+
+    fn checkpoint(tables: HashMap<String, TableConfig>) -> OperatorCheckpointMetadata {
+        OperatorCheckpointMetadata {
+            table_configs: tables,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_state_compatibility() {
+        let old_schema = schema(vec![Field::new("count", DataType::Int64, false)]);
+        let new_schema = schema(vec![Field::new("count", DataType::Utf8, false)]);
+
+        let tables = HashMap::from([
+            ("source_1".to_string(), global_table_config("s", "source")),
+            (
+                "tumbling_2".to_string(),
+                timestamp_tables(Duration::ZERO, old_schema.clone()),
+            ),
+            (
+                "tumbling_3".to_string(),
+                timestamp_tables(Duration::ZERO, new_schema),
+            ),
+            ("join_4".to_string(), global_table_config("j", "join")),
+            ("sink_6".to_string(), two_phase_tables(true)),
+            (
+                "tumbling_7".to_string(),
+                HashMap::from([(
+                    "t".to_string(),
+                    timestamp_table_config(
+                        "t",
+                        "tumbling",
+                        Duration::ZERO,
+                        true,
+                        old_schema.clone(),
+                    ),
+                )]),
+            ),
+        ]);
+
+        let checkpoints = HashMap::from([
+            (
+                "source_1".to_string(),
+                checkpoint(global_table_config("s", "source")),
+            ),
+            (
+                "tumbling_2".to_string(),
+                checkpoint(timestamp_tables(
+                    Duration::from_secs(10),
+                    old_schema.clone(),
+                )),
+            ),
+            (
+                "tumbling_3".to_string(),
+                checkpoint(timestamp_tables(Duration::ZERO, old_schema.clone())),
+            ),
+            (
+                "sink_5".to_string(),
+                checkpoint(global_table_config("k", "sink")),
+            ),
+            ("sink_6".to_string(), checkpoint(two_phase_tables(false))),
+            (
+                "tumbling_7".to_string(),
+                checkpoint(timestamp_tables(Duration::ZERO, old_schema.clone())),
+            ),
+        ]);
+
+        let report = StateCompatibilityReport::check(&tables, &checkpoints);
+
+        assert_eq!(report.operators["source_1"], OperatorStateStatus::Reused);
+        assert_eq!(report.operators["tumbling_2"], OperatorStateStatus::Reused);
+        assert!(matches!(
+            report.operators["tumbling_3"],
+            OperatorStateStatus::Incompatible(_)
+        ));
+        assert_eq!(report.operators["join_4"], OperatorStateStatus::New);
+        assert!(matches!(
+            report.operators["sink_6"],
+            OperatorStateStatus::Incompatible(_)
+        ));
+        assert!(matches!(
+            report.operators["tumbling_7"],
+            OperatorStateStatus::Incompatible(_)
+        ));
+        assert_eq!(report.dropped, BTreeSet::from(["sink_5".to_string()]));
+        assert!(!report.is_compatible());
+    }
+}
@@ -0,0 +1,230 @@
+//! Offline inspection of the checkpoints written by the [`ParquetBackend`](crate::parquet::ParquetBackend)
+
+use crate::parquet::{base_path, checkpoint_storage, metadata_path, operator_path, ParquetStats};
+use anyhow::{anyhow, bail, Context, Result};
+use arrow_array::RecordBatch;
+use arroyo_rpc::grpc::rpc::{
+    CheckpointMetadata, ExpiringKeyedTimeTableCheckpointMetadata,
+    GlobalKeyedTableTaskCheckpointMetadata, OperatorCheckpointMetadata, TableCheckpointMetadata,
+    TableEnum,
+};
+use arroyo_storage::StorageProvider;
+use arroyo_types::from_micros;
+use futures::TryStreamExt;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use prost::Message;
+use std::collections::BTreeSet;
+
+/// A data file belonging to a table in a checkpoint
+#[derive(Debug)]
+pub struct TableFile {
+    pub path: String,
+    /// only available for tables that record them, like expiring keyed time tables
+    pub stats: Option<ParquetStats>,
+}
+
+/// Summary of the data in a table in a checkpoint
+#[derive(Debug)]
+pub struct TableStats {
+    pub name: String,
+    pub table_type: TableEnum,
+    pub files: usize,
+    pub bytes: u64,
+    pub rows: u64,
+    pub stats: Option<ParquetStats>,
+}
+
+pub struct CheckpointInspector {
+    storage: StorageProvider,
+    url: String,
+    job_id: String,
+}
+
+impl CheckpointInspector {
+    /// `url` is the checkpoint URL the job was run with, under which each job's checkpoints
+    /// are stored
+    pub async fn new(url: &str, job_id: &str) -> Result<Self> {
+        let storage = checkpoint_storage(url).await?;
+
+        Ok(Self {
+            storage,
+            url: url.trim_end_matches('/').to_string(),
+            job_id: job_id.to_string(),
+        })
+    }
+
+    /// Returns the epochs for which there is a complete checkpoint
+    pub async fn epochs(&self) -> Result<Vec<u32>> {
+        let checkpoints_url = format!("{}/{}/checkpoints", self.url, self.job_id);
+        let storage = StorageProvider::for_url(&checkpoints_url)
+            .await
+            .with_context(|| format!("failed to construct storage for URL {}", checkpoints_url))?;
+
+        let mut epochs = BTreeSet::new();
+        let mut paths = Box::pin(storage.list(true).await?);
+        while let Some(path) = paths.try_next().await? {
+            let parts: Vec<_> = path.parts().map(|p| p.as_ref().to_string()).collect();
+            // the top-level metadata file is only written once the checkpoint is complete
+            if let [.., dir, file] = &parts[..] {
+                if file == "metadata" {
+                    if let Some(epoch) =
+                        dir.strip_prefix("checkpoint-").and_then(|e| e.parse().ok())
+                    {
+                        epochs.insert(epoch);
+                    }
+                }
+            }
+        }
+
+        Ok(epochs.into_iter().collect())
+    }
+
+    /// Returns the most recent epoch with a complete checkpoint
+    pub async fn latest_epoch(&self) -> Result<u32> {
+        self.epochs()
+            .await?
+            .last()
+            .copied()
+            .ok_or_else(|| anyhow!("no checkpoints found for job {}", self.job_id))
+    }
+
+    pub async fn checkpoint(&self, epoch: u32) -> Result<CheckpointMetadata> {
+        let data = self
+            .storage
+            .get_if_present(metadata_path(&base_path(&self.job_id, epoch)))
+            .await?
+            .ok_or_else(|| anyhow!("no checkpoint for epoch {}", epoch))?;
+        Ok(CheckpointMetadata::decode(&data[..])?)
+    }
+
+    pub async fn operator(
+        &self,
+        epoch: u32,
+        operator_id: &str,
+    ) -> Result<OperatorCheckpointMetadata> {
+        let data = self
+            .storage
+            .get_if_present(metadata_path(&operator_path(
+                &self.job_id,
+                epoch,
+                operator_id,
+            )))
+            .await?
+            .ok_or_else(|| {
+                anyhow!(
+                    "no metadata for operator {} in checkpoint {}",
+                    operator_id,
+                    epoch
+                )
+            })?;
+        Ok(OperatorCheckpointMetadata::decode(&data[..])?)
+    }
+
+    /// Returns the files that make up a table's data as of a checkpoint
+    pub fn table_files(metadata: &TableCheckpointMetadata) -> Result<Vec<TableFile>> {
+        Ok(match metadata.table_type() {
+            TableEnum::MissingTableType => bail!("table has no type"),
+            TableEnum::GlobalKeyValue => {
+                GlobalKeyedTableTaskCheckpointMetadata::decode(&metadata.data[..])?
+                    .files
+                    .into_iter()
+                    .map(|path| TableFile { path, stats: None })
+                    .collect()
+            }
+            TableEnum::ExpiringKeyedTimeTable => {
+                ExpiringKeyedTimeTableCheckpointMetadata::decode(&metadata.data[..])?
+                    .files
+                    .into_iter()
+                    .map(|file| TableFile {
+                        path: file.file,
+                        stats: Some(ParquetStats {
+                            min_timestamp: from_micros(file.min_timestamp_micros),
+                            max_timestamp: from_micros(file.max_timestamp_micros),
+                            min_routing_key: file.min_routing_key,
+                            max_routing_key: file.max_routing_key,
+                        }),
+                    })
+                    .collect()
+            }
+        })
+    }
+
+    pub async fn table_stats(&self, epoch: u32, operator_id: &str) -> Result<Vec<TableStats>> {
+        let operator = self.operator(epoch, operator_id).await?;
+
+        let mut tables: Vec<_> = operator.table_checkpoint_metadata.iter().collect();
+        tables.sort_by_key(|(name, _)| *name);
+
+        let mut result = vec![];
+        for (name, metadata) in tables {
+            let mut table = TableStats {
+                name: name.clone(),
+                table_type: metadata.table_type(),
+                files: 0,
+                bytes: 0,
+                rows: 0,
+                stats: None,
+            };
+
+            for file in Self::table_files(metadata)? {
+                let data = self
+                    .storage
+                    .get(file.path.as_str())
+                    .await
+                    .with_context(|| format!("failed to read checkpoint file {}", file.path))?;
+                let reader = SerializedFileReader::new(data.clone())
+                    .with_context(|| format!("invalid parquet file {}", file.path))?;
+
+                table.files += 1;
+                table.bytes += data.len() as u64;
+                table.rows += reader.metadata().file_metadata().num_rows() as u64;
+                if let Some(stats) = file.stats {
+                    table
+                        .stats
+                        .get_or_insert_with(ParquetStats::default)
+                        .merge(stats);
+                }
+            }
+
+            result.push(table);
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the contents of a table as of a checkpoint, as it was written to storage
+    pub async fn read_table(
+        &self,
+        epoch: u32,
+        operator_id: &str,
+        table: &str,
+    ) -> Result<Vec<RecordBatch>> {
+        let operator = self.operator(epoch, operator_id).await?;
+        let metadata = operator
+            .table_checkpoint_metadata
+            .get(table)
+            .ok_or_else(|| {
+                anyhow!(
+                    "operator {} has no table '{}' in checkpoint {}",
+                    operator_id,
+                    table,
+                    epoch
+                )
+            })?;
+
+        let mut batches = vec![];
+        for file in Self::table_files(metadata)? {
+            let data = self
+                .storage
+                .get(file.path.as_str())
+                .await
+                .with_context(|| format!("failed to read checkpoint file {}", file.path))?;
+            for batch in ParquetRecordBatchReaderBuilder::try_new(data)?.build()? {
+                batches.push(batch?);
+            }
+        }
+
+        Ok(batches)
+    }
+}
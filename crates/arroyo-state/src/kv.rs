@@ -0,0 +1,337 @@
+//! A state backend that keeps keyed state in an embedded key-value store on the worker's local
+//! disk rather than in memory, so that operators with large keyspaces (like updating aggregates)
+//! aren't limited by the worker's memory.
+//!
+//! The local store is only a working copy: on checkpoint, changes are written to the checkpoint
+//! storage in the same format as the [`ParquetBackend`], and on restore the local store is
+//! rebuilt from the checkpoint. This means a pipeline can be switched between backends across
+//! restarts.
+
+use crate::parquet::ParquetBackend;
+use crate::tables::expiring_time_key_map::{LastValue, LastValueStore};
+use crate::{BackingStore, BINCODE_CONFIG};
+use anyhow::{Context, Result};
+use arroyo_rpc::config::config;
+use arroyo_rpc::grpc::rpc::{CheckpointMetadata, OperatorCheckpointMetadata};
+use arroyo_types::{from_nanos, to_nanos, TaskInfo};
+use redb::{Database, Durability, ReadableTable, TableDefinition, WriteTransaction};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+pub struct KvBackend;
+
+#[async_trait::async_trait]
+impl BackingStore for KvBackend {
+    fn name() -> &'static str {
+        "kv"
+    }
+
+    async fn load_checkpoint_metadata(job_id: &str, epoch: u32) -> Result<CheckpointMetadata> {
+        ParquetBackend::load_checkpoint_metadata(job_id, epoch).await
+    }
+
+    async fn load_operator_metadata(
+        job_id: &str,
+        operator_id: &str,
+        epoch: u32,
+    ) -> Result<Option<OperatorCheckpointMetadata>> {
+        ParquetBackend::load_operator_metadata(job_id, operator_id, epoch).await
+    }
+
+    async fn write_operator_checkpoint_metadata(
+        metadata: OperatorCheckpointMetadata,
+    ) -> Result<()> {
+        ParquetBackend::write_operator_checkpoint_metadata(metadata).await
+    }
+
+    async fn write_checkpoint_metadata(metadata: CheckpointMetadata) -> Result<()> {
+        ParquetBackend::write_checkpoint_metadata(metadata).await
+    }
+
+    async fn prepare_checkpoint_load(metadata: &CheckpointMetadata) -> Result<()> {
+        // local stores are cleared by the workers when they're opened, so there's nothing to do
+        // beyond removing the checkpoint data from later epochs
+        ParquetBackend::prepare_checkpoint_load(metadata).await
+    }
+
+    async fn cleanup_checkpoint(
+        metadata: CheckpointMetadata,
+        old_min_epoch: u32,
+        new_min_epoch: u32,
+    ) -> Result<()> {
+        ParquetBackend::cleanup_checkpoint(metadata, old_min_epoch, new_min_epoch).await
+    }
+
+    async fn cleanup_failed_checkpoints(
+        metadata: CheckpointMetadata,
+        failed_epochs: &[u32],
+    ) -> Result<()> {
+        ParquetBackend::cleanup_failed_checkpoints(metadata, failed_epochs).await
+    }
+
+    async fn write_savepoint(job_id: &str, epoch: u32, url: &str) -> Result<()> {
+        ParquetBackend::write_savepoint(job_id, epoch, url).await
+    }
+
+    async fn restore_savepoint(
+        url: &str,
+        job_id: &str,
+        epoch: u32,
+        operator_mapping: &HashMap<String, String>,
+        skip_operators: &HashSet<String>,
+    ) -> Result<CheckpointMetadata> {
+        ParquetBackend::restore_savepoint(url, job_id, epoch, operator_mapping, skip_operators)
+            .await
+    }
+
+    async fn load_savepoint_metadata(
+        url: &str,
+        operator_mapping: &HashMap<String, String>,
+    ) -> Result<HashMap<String, OperatorCheckpointMetadata>> {
+        ParquetBackend::load_savepoint_metadata(url, operator_mapping).await
+    }
+}
+
+const VALUES: TableDefinition<&[u8], &[u8]> = TableDefinition::new("values");
+// keyed by the big-endian timestamp in nanos followed by the key, so that iteration order is
+// timestamp order
+const EXPIRATIONS: TableDefinition<&[u8], ()> = TableDefinition::new("expirations");
+
+fn expiration_key(timestamp: SystemTime, key: &[u8]) -> Vec<u8> {
+    let mut expiration_key = Vec::with_capacity(16 + key.len());
+    expiration_key.extend_from_slice(&to_nanos(timestamp).to_be_bytes());
+    expiration_key.extend_from_slice(key);
+    expiration_key
+}
+
+fn split_expiration_key(expiration_key: &[u8]) -> (SystemTime, &[u8]) {
+    let (timestamp, key) = expiration_key.split_at(16);
+    (
+        from_nanos(u128::from_be_bytes(timestamp.try_into().unwrap())),
+        key,
+    )
+}
+
+/// A [`LastValueStore`] backed by a [redb](https://docs.rs/redb) database in the worker's data
+/// dir
+pub(crate) struct KvLastValueStore {
+    path: PathBuf,
+    db: Database,
+    len: usize,
+}
+
+impl Debug for KvLastValueStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KvLastValueStore")
+            .field("path", &self.path)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl KvLastValueStore {
+    /// Opens the store for `table` in the given task. Any existing data is removed, as it may
+    /// include changes made after the checkpoint that the task is being restored from; the
+    /// store is repopulated from the checkpoint instead.
+    pub(crate) fn open(task_info: &TaskInfo, table: &str) -> Result<Self> {
+        let dir = config()
+            .worker
+            .data_dir
+            .join("kv")
+            .join(&task_info.job_id)
+            .join(format!(
+                "{}-{}",
+                task_info.operator_id, task_info.task_index
+            ));
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create state directory {}", dir.display()))?;
+
+        let path = dir.join(format!("{}.redb", table));
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove old state at {}", path.display()))?;
+        }
+
+        let db = Database::create(&path)
+            .with_context(|| format!("failed to create state store at {}", path.display()))?;
+
+        let store = Self { path, db, len: 0 };
+        let txn = store.begin_write()?;
+        txn.open_table(VALUES)?;
+        txn.open_table(EXPIRATIONS)?;
+        txn.commit()?;
+
+        Ok(store)
+    }
+
+    fn begin_write(&self) -> Result<WriteTransaction> {
+        let mut txn = self.db.begin_write()?;
+        // the store is rebuilt from the checkpoint after a failure, so there's no need to pay
+        // for syncing every write to disk
+        txn.set_durability(Durability::None);
+        Ok(txn)
+    }
+}
+
+impl Drop for KvLastValueStore {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn decode(value: &[u8]) -> Result<LastValue> {
+    Ok(bincode::decode_from_slice(value, BINCODE_CONFIG)?.0)
+}
+
+impl LastValueStore for KvLastValueStore {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Cow<'_, LastValue>>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(VALUES)?;
+        let value = table
+            .get(key)?
+            .map(|value| decode(value.value()))
+            .transpose()?;
+        Ok(value.map(Cow::Owned))
+    }
+
+    fn put(
+        &mut self,
+        key: &[u8],
+        value: LastValue,
+        previous_timestamp: Option<SystemTime>,
+    ) -> Result<()> {
+        let txn = self.begin_write()?;
+        {
+            let mut values = txn.open_table(VALUES)?;
+            values.insert(
+                key,
+                bincode::encode_to_vec(&value, BINCODE_CONFIG)?.as_slice(),
+            )?;
+
+            if previous_timestamp != Some(value.timestamp) {
+                let mut expirations = txn.open_table(EXPIRATIONS)?;
+                if let Some(previous_timestamp) = previous_timestamp {
+                    expirations.remove(expiration_key(previous_timestamp, key).as_slice())?;
+                }
+                expirations.insert(expiration_key(value.timestamp, key).as_slice(), ())?;
+            }
+        }
+        txn.commit()?;
+
+        if previous_timestamp.is_none() {
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    fn earliest_timestamp(&self) -> Result<Option<SystemTime>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(EXPIRATIONS)?;
+        let earliest = table
+            .first()?
+            .map(|(expiration_key, _)| split_expiration_key(expiration_key.value()).0);
+        Ok(earliest)
+    }
+
+    fn remove_before(&mut self, cutoff: SystemTime) -> Result<Vec<(Vec<u8>, LastValue)>> {
+        let end = to_nanos(cutoff).to_be_bytes();
+        let txn = self.begin_write()?;
+        let mut removed = vec![];
+        {
+            let mut expirations = txn.open_table(EXPIRATIONS)?;
+            let mut values = txn.open_table(VALUES)?;
+
+            let expired: Vec<Vec<u8>> = expirations
+                .range(..end.as_slice())?
+                .map(|entry| Ok(entry?.0.value().to_vec()))
+                .collect::<Result<_>>()?;
+
+            for expiration_key in expired {
+                expirations.remove(expiration_key.as_slice())?;
+                let key = split_expiration_key(&expiration_key).1;
+                if let Some(value) = values.remove(key)? {
+                    removed.push((key.to_vec(), decode(value.value())?));
+                }
+            }
+        }
+        txn.commit()?;
+
+        self.len -= removed.len();
+        Ok(removed)
+    }
+
+    fn entries(&self, limit: usize) -> Result<Vec<(Vec<u8>, LastValue)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(VALUES)?;
+        let entries = table
+            .iter()?
+            .take(limit)
+            .map(|entry| {
+                let (key, value) = entry?;
+                Ok((key.value().to_vec(), decode(value.value())?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tables::expiring_time_key_map::MemoryLastValueStore;
+    use arroyo_types::get_test_task_info;
+    use std::time::Duration;
+
+    fn value(timestamp: u64, generation: u64) -> LastValue {
+        LastValue {
+            value_row_bytes: vec![generation as u8],
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp),
+            generation,
+        }
+    }
+
+    fn check_store(store: &mut dyn LastValueStore) {
+        assert!(store.is_empty());
+
+        store.put(b"a", value(10, 0), None).unwrap();
+        store.put(b"b", value(5, 0), None).unwrap();
+        store
+            .put(b"a", value(20, 1), Some(value(10, 0).timestamp))
+            .unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(b"a").unwrap().unwrap().generation, 1);
+        assert!(store.get(b"c").unwrap().is_none());
+        assert_eq!(
+            store.earliest_timestamp().unwrap(),
+            Some(value(5, 0).timestamp)
+        );
+
+        // a's old timestamp of 10 no longer counts, so only b is expired
+        let removed = store.remove_before(value(15, 0).timestamp).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].0, b"b");
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.entries(10).unwrap().len(), 1);
+
+        let removed = store.remove_before(value(25, 0).timestamp).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(store.is_empty());
+        assert_eq!(store.earliest_timestamp().unwrap(), None);
+    }
+
+    #[test]
+    fn test_last_value_stores() {
+        check_store(&mut MemoryLastValueStore::default());
+
+        let task_info = get_test_task_info();
+        check_store(&mut KvLastValueStore::open(&task_info, "test").unwrap());
+    }
+}
@@ -9,19 +9,24 @@ use async_trait::async_trait;
 use bincode::config::Configuration;
 use bincode::{Decode, Encode};
 
+use arroyo_rpc::config::{config, StateBackendType};
 use arroyo_rpc::df::ArroyoSchema;
 use prost::Message;
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::ops::RangeInclusive;
 use std::time::{Duration, SystemTime};
 
 pub mod checkpoint_state;
 pub mod committing_state;
+pub mod compatibility;
+pub mod inspect;
+pub mod kv;
 mod metrics;
 pub mod parquet;
 pub(crate) mod schemas;
+pub mod spill;
 pub mod tables;
 
 pub const BINCODE_CONFIG: Configuration = bincode::config::standard();
@@ -31,7 +36,16 @@ pub const FULL_KEY_RANGE: RangeInclusive<u64> = 0..=u64::MAX;
 pub enum StateMessage {
     Checkpoint(CheckpointMessage),
     Compaction(HashMap<String, TableCheckpointMetadata>),
-    TableData { table: String, data: TableData },
+    TableData {
+        table: String,
+        data: TableData,
+    },
+    /// The data overtaken by the barrier of an unaligned checkpoint, by input index
+    InFlight {
+        epoch: u32,
+        input_partitions: usize,
+        batches: HashMap<usize, Vec<RecordBatch>>,
+    },
 }
 #[derive(Debug)]
 pub struct CheckpointMessage {
@@ -39,20 +53,125 @@ pub struct CheckpointMessage {
     time: SystemTime,
     watermark: Option<SystemTime>,
     then_stop: bool,
+    unaligned: bool,
 }
 
 #[derive(Debug)]
 pub enum TableData {
     RecordBatch(RecordBatch),
-    CommitData { data: Vec<u8> },
-    KeyedData { key: Vec<u8>, value: Vec<u8> },
+    CommitData {
+        data: Vec<u8>,
+    },
+    KeyedData {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        // when the value was last changed, which is only tracked for tables with a TTL
+        updated_at: Option<SystemTime>,
+    },
 }
 
-pub type StateBackend = parquet::ParquetBackend;
+/// The [`BackingStore`] selected by the `state-backend` config
+pub struct StateBackend;
+
+macro_rules! dispatch {
+    ($method:ident($($arg:expr),*)) => {
+        match config().state_backend {
+            StateBackendType::Parquet => parquet::ParquetBackend::$method($($arg),*).await,
+            StateBackendType::Kv => kv::KvBackend::$method($($arg),*).await,
+        }
+    };
+}
+
+#[async_trait]
+impl BackingStore for StateBackend {
+    async fn prepare_checkpoint_load(metadata: &CheckpointMetadata) -> Result<()> {
+        dispatch!(prepare_checkpoint_load(metadata))
+    }
+
+    async fn load_checkpoint_metadata(job_id: &str, epoch: u32) -> Result<CheckpointMetadata> {
+        dispatch!(load_checkpoint_metadata(job_id, epoch))
+    }
+
+    async fn load_operator_metadata(
+        job_id: &str,
+        operator_id: &str,
+        epoch: u32,
+    ) -> Result<Option<OperatorCheckpointMetadata>> {
+        dispatch!(load_operator_metadata(job_id, operator_id, epoch))
+    }
+
+    fn name() -> &'static str {
+        match config().state_backend {
+            StateBackendType::Parquet => parquet::ParquetBackend::name(),
+            StateBackendType::Kv => kv::KvBackend::name(),
+        }
+    }
+
+    async fn write_operator_checkpoint_metadata(
+        metadata: OperatorCheckpointMetadata,
+    ) -> Result<()> {
+        dispatch!(write_operator_checkpoint_metadata(metadata))
+    }
+
+    async fn write_checkpoint_metadata(metadata: CheckpointMetadata) -> Result<()> {
+        dispatch!(write_checkpoint_metadata(metadata))
+    }
+
+    async fn cleanup_checkpoint(
+        metadata: CheckpointMetadata,
+        old_min_epoch: u32,
+        new_min_epoch: u32,
+    ) -> Result<()> {
+        dispatch!(cleanup_checkpoint(metadata, old_min_epoch, new_min_epoch))
+    }
+
+    async fn cleanup_failed_checkpoints(
+        metadata: CheckpointMetadata,
+        failed_epochs: &[u32],
+    ) -> Result<()> {
+        dispatch!(cleanup_failed_checkpoints(metadata, failed_epochs))
+    }
+
+    async fn write_savepoint(job_id: &str, epoch: u32, url: &str) -> Result<()> {
+        dispatch!(write_savepoint(job_id, epoch, url))
+    }
+
+    async fn restore_savepoint(
+        url: &str,
+        job_id: &str,
+        epoch: u32,
+        operator_mapping: &HashMap<String, String>,
+        skip_operators: &HashSet<String>,
+    ) -> Result<CheckpointMetadata> {
+        dispatch!(restore_savepoint(
+            url,
+            job_id,
+            epoch,
+            operator_mapping,
+            skip_operators
+        ))
+    }
+
+    async fn load_savepoint_metadata(
+        url: &str,
+        operator_mapping: &HashMap<String, String>,
+    ) -> Result<HashMap<String, OperatorCheckpointMetadata>> {
+        dispatch!(load_savepoint_metadata(url, operator_mapping))
+    }
+}
 
 pub fn global_table_config(
     name: impl Into<String>,
     description: impl Into<String>,
+) -> HashMap<String, TableConfig> {
+    global_table_config_with_ttl(name, description, None)
+}
+
+/// Config for a global table whose entries are dropped once they haven't been updated for `ttl`
+pub fn global_table_config_with_ttl(
+    name: impl Into<String>,
+    description: impl Into<String>,
+    ttl: Option<Duration>,
 ) -> HashMap<String, TableConfig> {
     let name = name.into();
     single_item_hash_map(
@@ -63,6 +182,7 @@ pub fn global_table_config(
                 table_name: name,
                 description: description.into(),
                 uses_two_phase_commit: false,
+                ttl_micros: ttl.map(|ttl| ttl.as_micros() as u64).unwrap_or_default(),
             }
             .encode_to_vec(),
         },
@@ -153,6 +273,36 @@ pub trait BackingStore {
         old_min_epoch: u32,
         new_min_epoch: u32,
     ) -> Result<()>;
+
+    /// deletes the data written by the checkpoints for `failed_epochs`, other than files that
+    /// are referenced by the completed checkpoint `metadata`, which must be for a later epoch
+    async fn cleanup_failed_checkpoints(
+        metadata: CheckpointMetadata,
+        failed_epochs: &[u32],
+    ) -> Result<()>;
+
+    /// copies all of the data needed to restore the given checkpoint to a self-contained
+    /// savepoint at `url`
+    async fn write_savepoint(job_id: &str, epoch: u32, url: &str) -> Result<()>;
+
+    /// copies the savepoint at `url` into the checkpoint storage for `job_id`, so that
+    /// it can be restored as the checkpoint for `epoch`. `operator_mapping` maps operator ids
+    /// in the new program to the ids of operators in the savepoint whose state they should take;
+    /// operators in `skip_operators` (by their new ids) are left out, so they start empty.
+    async fn restore_savepoint(
+        url: &str,
+        job_id: &str,
+        epoch: u32,
+        operator_mapping: &HashMap<String, String>,
+        skip_operators: &HashSet<String>,
+    ) -> Result<CheckpointMetadata>;
+
+    /// loads the operator metadata of the savepoint at `url`, keyed by the ids of the operators
+    /// in the new program that would take their state (see `restore_savepoint`)
+    async fn load_savepoint_metadata(
+        url: &str,
+        operator_mapping: &HashMap<String, String>,
+    ) -> Result<HashMap<String, OperatorCheckpointMetadata>>;
 }
 
 pub fn hash_key<K: Hash>(key: &K) -> u64 {
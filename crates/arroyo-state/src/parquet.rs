@@ -4,11 +4,14 @@ use crate::tables::{CompactionConfig, ErasedTable};
 use crate::BackingStore;
 use anyhow::{bail, Context, Result};
 use arroyo_rpc::grpc::rpc::{
-    CheckpointMetadata, OperatorCheckpointMetadata, TableCheckpointMetadata,
+    CheckpointMetadata, FileReferences, OperatorCheckpointMetadata, TableCheckpointMetadata,
+    TableConfig,
 };
-use arroyo_storage::StorageProvider;
+use arroyo_storage::{EncryptionKey, StorageProvider};
+use arroyo_types::from_micros;
 use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
+use once_cell::sync::OnceCell;
 
 use arroyo_rpc::config::config;
 use arroyo_rpc::grpc::rpc;
@@ -26,28 +29,185 @@ pub const GENERATIONS_TO_COMPACT: u32 = 1; // only compact generation 0 files
 async fn get_storage_provider() -> anyhow::Result<StorageProvider> {
     // TODO: this should be encoded in the config so that the controller doesn't need
     // to be synchronized with the workers
-    let storage_url = &config().checkpoint_url;
+    checkpoint_storage(&config().checkpoint_url).await
+}
+
+fn checkpoint_encryption_key() -> Result<Option<EncryptionKey>> {
+    static KEY: OnceCell<Option<EncryptionKey>> = OnceCell::new();
+    KEY.get_or_try_init(|| {
+        let Some(encryption) = &config().checkpoint_encryption else {
+            return Ok(None);
+        };
 
-    StorageProvider::for_url(storage_url).await.context(format!(
-        "failed to construct checkpoint backend for URL {}",
-        storage_url
-    ))
+        Ok(Some(EncryptionKey::from_base64(&encryption.read_key()?)?))
+    })
+    .cloned()
+}
+
+/// Constructs a storage provider for checkpoint data under `url`, which encrypts and decrypts
+/// all objects if checkpoint encryption is configured
+pub(crate) async fn checkpoint_storage(url: &str) -> Result<StorageProvider> {
+    let storage = StorageProvider::for_url(url)
+        .await
+        .with_context(|| format!("failed to construct checkpoint backend for URL {}", url))?;
+
+    Ok(match checkpoint_encryption_key()? {
+        Some(key) => storage.with_encryption(key),
+        None => storage,
+    })
 }
 
 pub struct ParquetBackend;
 
-fn base_path(job_id: &str, epoch: u32) -> String {
+pub(crate) fn base_path(job_id: &str, epoch: u32) -> String {
     format!("{}/checkpoints/checkpoint-{:0>7}", job_id, epoch)
 }
 
-fn metadata_path(path: &str) -> String {
+pub(crate) fn metadata_path(path: &str) -> String {
     format!("{}/metadata", path)
 }
 
-fn operator_path(job_id: &str, epoch: u32, operator: &str) -> String {
+pub(crate) fn operator_path(job_id: &str, epoch: u32, operator: &str) -> String {
     format!("{}/operator-{}", base_path(job_id, epoch), operator)
 }
 
+/// The file holding the data that the barrier for an unaligned checkpoint overtook on one of a
+/// subtask's inputs
+pub(crate) fn in_flight_path(
+    job_id: &str,
+    epoch: u32,
+    operator: &str,
+    subtask: usize,
+    input: usize,
+) -> String {
+    format!(
+        "{}/in-flight-{:0>3}-{:0>3}",
+        operator_path(job_id, epoch, operator),
+        subtask,
+        input
+    )
+}
+
+fn savepoint_operator_path(operator: &str) -> String {
+    format!("operator-{}", operator)
+}
+
+/// Files in a savepoint are stored under `data/`, keeping their path relative to the
+/// checkpoint directory of the job they were written by
+fn savepoint_file_path(job_id: &str, file: &str) -> String {
+    let prefix = format!("{}/checkpoints/", job_id);
+    format!("data/{}", file.strip_prefix(&prefix).unwrap_or(file))
+}
+
+fn table_files(config: TableConfig, metadata: TableCheckpointMetadata) -> Result<HashSet<String>> {
+    match config.table_type() {
+        rpc::TableEnum::MissingTableType => bail!("should have table type"),
+        rpc::TableEnum::GlobalKeyValue => GlobalKeyedTable::files_to_keep(config, metadata),
+        rpc::TableEnum::ExpiringKeyedTimeTable => {
+            ExpiringTimeKeyTable::files_to_keep(config, metadata)
+        }
+    }
+}
+
+/// Returns all of the data files referenced by an operator's checkpoint
+pub(crate) fn operator_files(metadata: &OperatorCheckpointMetadata) -> Result<HashSet<String>> {
+    let mut files = HashSet::new();
+    for (table, table_metadata) in &metadata.table_checkpoint_metadata {
+        let table_config = metadata
+            .table_configs
+            .get(table)
+            .ok_or_else(|| anyhow::anyhow!("missing table config for table {}", table))?;
+        files.extend(table_files(table_config.clone(), table_metadata.clone())?);
+    }
+    for subtask in metadata.in_flight.values() {
+        files.extend(subtask.inputs.iter().map(|input| input.file.clone()));
+    }
+    Ok(files)
+}
+
+/// Computes the file references for an operator's checkpoint at `epoch` by adding its files to
+/// the references of the previous epoch's checkpoint, dropping epochs before `min_epoch` which
+/// are no longer retained
+pub(crate) fn compute_file_refs(
+    previous: Option<&OperatorCheckpointMetadata>,
+    metadata: &OperatorCheckpointMetadata,
+    epoch: u32,
+    min_epoch: u32,
+) -> Result<HashMap<String, FileReferences>> {
+    let mut refs: HashMap<String, FileReferences> = previous
+        .map(|previous| previous.file_refs.clone())
+        .unwrap_or_default();
+
+    for file_refs in refs.values_mut() {
+        file_refs.epochs.retain(|e| *e >= min_epoch && *e < epoch);
+    }
+    refs.retain(|_, file_refs| !file_refs.epochs.is_empty());
+
+    for file in operator_files(metadata)? {
+        refs.entry(file).or_default().epochs.push(epoch);
+    }
+
+    Ok(refs)
+}
+
+fn relocate_table_files(
+    config: TableConfig,
+    metadata: TableCheckpointMetadata,
+    relocate: &dyn Fn(&str) -> String,
+) -> Result<TableCheckpointMetadata> {
+    match config.table_type() {
+        rpc::TableEnum::MissingTableType => bail!("should have table type"),
+        rpc::TableEnum::GlobalKeyValue => {
+            GlobalKeyedTable::relocate_files(config, metadata, relocate)
+        }
+        rpc::TableEnum::ExpiringKeyedTimeTable => {
+            ExpiringTimeKeyTable::relocate_files(config, metadata, relocate)
+        }
+    }
+}
+
+/// Copies all of the table and in-flight files referenced by `metadata` from `from` to `to`, and rewrites
+/// the metadata to point at the new locations
+async fn copy_operator_files(
+    from: &StorageProvider,
+    to: &StorageProvider,
+    metadata: &mut OperatorCheckpointMetadata,
+    relocate: &(dyn Fn(&str) -> String + Sync),
+) -> Result<()> {
+    for (table, table_metadata) in metadata.table_checkpoint_metadata.iter_mut() {
+        let table_config = metadata
+            .table_configs
+            .get(table)
+            .ok_or_else(|| anyhow::anyhow!("missing table config for table {}", table))?
+            .clone();
+
+        for file in table_files(table_config.clone(), table_metadata.clone())? {
+            to.copy_from(from, &file, relocate(&file))
+                .await
+                .with_context(|| format!("failed to copy checkpoint file {}", file))?;
+        }
+
+        *table_metadata = relocate_table_files(table_config, table_metadata.clone(), relocate)?;
+    }
+
+    for input in metadata
+        .in_flight
+        .values_mut()
+        .flat_map(|subtask| subtask.inputs.iter_mut())
+    {
+        let relocated = relocate(&input.file);
+        to.copy_from(from, &input.file, relocated.clone())
+            .await
+            .with_context(|| format!("failed to copy in-flight data {}", input.file))?;
+        input.file = relocated;
+    }
+
+    // references are to the old locations, and for epochs that are meaningless at the destination
+    metadata.file_refs.clear();
+
+    Ok(())
+}
+
 #[async_trait::async_trait]
 impl BackingStore for ParquetBackend {
     fn name() -> &'static str {
@@ -102,7 +262,97 @@ impl BackingStore for ParquetBackend {
         Ok(())
     }
 
-    async fn prepare_checkpoint_load(_metadata: &CheckpointMetadata) -> anyhow::Result<()> {
+    async fn prepare_checkpoint_load(metadata: &CheckpointMetadata) -> anyhow::Result<()> {
+        // only called when rolling back to an earlier checkpoint: remove everything written for
+        // later epochs so that it can't be mixed in with the new checkpoints that will reuse them
+        let checkpoints_url = format!(
+            "{}/{}/checkpoints",
+            config().checkpoint_url.trim_end_matches('/'),
+            metadata.job_id
+        );
+        let storage_client = StorageProvider::for_url(&checkpoints_url)
+            .await
+            .with_context(|| format!("failed to construct storage for URL {}", checkpoints_url))?;
+
+        let mut to_delete = vec![];
+        {
+            let mut paths = Box::pin(storage_client.list(true).await?);
+            while let Some(path) = paths.try_next().await? {
+                let epoch = path.parts().find_map(|part| {
+                    part.as_ref()
+                        .strip_prefix("checkpoint-")
+                        .and_then(|e| e.parse::<u32>().ok())
+                });
+                if epoch.is_some_and(|epoch| epoch > metadata.epoch) {
+                    to_delete.push(path);
+                }
+            }
+        }
+
+        if !to_delete.is_empty() {
+            info!(
+                message = "Removing data from later epochs",
+                job_id = metadata.job_id,
+                epoch = metadata.epoch,
+                files = to_delete.len()
+            );
+        }
+
+        for path in to_delete {
+            storage_client.delete_if_present(path.to_string()).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn cleanup_failed_checkpoints(
+        metadata: CheckpointMetadata,
+        failed_epochs: &[u32],
+    ) -> Result<()> {
+        info!(
+            message = "Cleaning failed checkpoints",
+            job_id = metadata.job_id,
+            failed_epochs = format!("{:?}", failed_epochs),
+        );
+
+        for operator_id in &metadata.operator_ids {
+            // subtasks may have carried files from the failed epochs over into this one
+            let Some(operator_metadata) =
+                Self::load_operator_metadata(&metadata.job_id, operator_id, metadata.epoch).await?
+            else {
+                bail!(
+                    "missing metadata for operator {} in completed checkpoint {}",
+                    operator_id,
+                    metadata.epoch
+                );
+            };
+            let mut paths_to_keep = operator_files(&operator_metadata)?;
+            paths_to_keep.extend(
+                operator_metadata
+                    .file_refs
+                    .into_iter()
+                    .filter(|(_, refs)| refs.epochs.iter().any(|e| !failed_epochs.contains(e)))
+                    .map(|(file, _)| file),
+            );
+
+            for epoch in failed_epochs {
+                if *epoch >= metadata.epoch {
+                    bail!(
+                        "failed checkpoint {} is not before the completed checkpoint {}",
+                        epoch,
+                        metadata.epoch
+                    );
+                }
+                Self::cleanup_failed_operator(
+                    &metadata.job_id,
+                    operator_id,
+                    *epoch,
+                    &paths_to_keep,
+                )
+                .await?;
+            }
+        }
+
         Ok(())
     }
 
@@ -126,6 +376,7 @@ impl BackingStore for ParquetBackend {
                     operator_id.clone(),
                     old_min_epoch,
                     min_epoch,
+                    metadata.epoch,
                 )
             })
             .collect();
@@ -163,6 +414,178 @@ impl BackingStore for ParquetBackend {
         Self::write_checkpoint_metadata(metadata).await?;
         Ok(())
     }
+
+    async fn write_savepoint(job_id: &str, epoch: u32, url: &str) -> Result<()> {
+        info!(message = "Writing savepoint", job_id, epoch, url);
+        let job_storage = get_storage_provider().await?;
+        let savepoint_storage = checkpoint_storage(url).await?;
+
+        let metadata = Self::load_checkpoint_metadata(job_id, epoch).await?;
+
+        for operator_id in &metadata.operator_ids {
+            let Some(mut operator_metadata) =
+                Self::load_operator_metadata(job_id, operator_id, epoch).await?
+            else {
+                bail!(
+                    "missing metadata for operator {} in checkpoint {}",
+                    operator_id,
+                    epoch
+                );
+            };
+
+            copy_operator_files(
+                &job_storage,
+                &savepoint_storage,
+                &mut operator_metadata,
+                &|file: &str| savepoint_file_path(job_id, file),
+            )
+            .await?;
+
+            savepoint_storage
+                .put(
+                    metadata_path(&savepoint_operator_path(operator_id)),
+                    operator_metadata.encode_to_vec(),
+                )
+                .await?;
+        }
+
+        // the top-level metadata is written last, so its presence marks a complete savepoint
+        savepoint_storage
+            .put("metadata", metadata.encode_to_vec())
+            .await?;
+
+        info!(message = "Finished writing savepoint", job_id, epoch, url);
+        Ok(())
+    }
+
+    async fn restore_savepoint(
+        url: &str,
+        job_id: &str,
+        epoch: u32,
+        operator_mapping: &HashMap<String, String>,
+        skip_operators: &HashSet<String>,
+    ) -> Result<CheckpointMetadata> {
+        info!(message = "Restoring savepoint", job_id, epoch, url);
+        let job_storage = get_storage_provider().await?;
+        let savepoint_storage = checkpoint_storage(url).await?;
+
+        let mut metadata = load_savepoint_checkpoint_metadata(&savepoint_storage, url).await?;
+        let renames = savepoint_renames(&metadata, operator_mapping)?;
+
+        let savepoint_path = format!("{}/savepoint", base_path(job_id, epoch));
+
+        let mut operator_ids = vec![];
+        for (operator_id, new_operator_id) in renames {
+            if skip_operators.contains(&new_operator_id) {
+                continue;
+            }
+
+            let mut operator_metadata =
+                load_savepoint_operator_metadata(&savepoint_storage, &operator_id).await?;
+
+            copy_operator_files(
+                &savepoint_storage,
+                &job_storage,
+                &mut operator_metadata,
+                &|file: &str| format!("{}/{}", savepoint_path, file),
+            )
+            .await?;
+
+            let inner = operator_metadata
+                .operator_metadata
+                .as_mut()
+                .ok_or_else(|| anyhow::anyhow!("missing operator metadata"))?;
+            inner.job_id = job_id.to_string();
+            inner.operator_id = new_operator_id.clone();
+            inner.epoch = epoch;
+            operator_metadata.file_refs =
+                compute_file_refs(None, &operator_metadata, epoch, epoch)?;
+
+            Self::write_operator_checkpoint_metadata(operator_metadata).await?;
+            operator_ids.push(new_operator_id);
+        }
+
+        metadata.operator_ids = operator_ids;
+        metadata.job_id = job_id.to_string();
+        metadata.epoch = epoch;
+        metadata.min_epoch = epoch;
+        Self::write_checkpoint_metadata(metadata.clone()).await?;
+
+        Ok(metadata)
+    }
+
+    async fn load_savepoint_metadata(
+        url: &str,
+        operator_mapping: &HashMap<String, String>,
+    ) -> Result<HashMap<String, OperatorCheckpointMetadata>> {
+        let savepoint_storage = checkpoint_storage(url).await?;
+        let metadata = load_savepoint_checkpoint_metadata(&savepoint_storage, url).await?;
+
+        let mut operators = HashMap::new();
+        for (operator_id, new_operator_id) in savepoint_renames(&metadata, operator_mapping)? {
+            operators.insert(
+                new_operator_id,
+                load_savepoint_operator_metadata(&savepoint_storage, &operator_id).await?,
+            );
+        }
+
+        Ok(operators)
+    }
+}
+
+async fn load_savepoint_checkpoint_metadata(
+    savepoint_storage: &StorageProvider,
+    url: &str,
+) -> Result<CheckpointMetadata> {
+    let data = savepoint_storage
+        .get_if_present("metadata")
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("no complete savepoint found at {}", url))?;
+    Ok(CheckpointMetadata::decode(&data[..])?)
+}
+
+async fn load_savepoint_operator_metadata(
+    savepoint_storage: &StorageProvider,
+    operator_id: &str,
+) -> Result<OperatorCheckpointMetadata> {
+    let data = savepoint_storage
+        .get(metadata_path(&savepoint_operator_path(operator_id)))
+        .await
+        .with_context(|| format!("missing metadata for operator {} in savepoint", operator_id))?;
+    Ok(OperatorCheckpointMetadata::decode(&data[..])?)
+}
+
+/// Returns the operators in the savepoint whose state will be restored, along with the ids of
+/// the operators in the new program that will take it. `operator_mapping` goes from new ids to
+/// savepoint ids; unmapped operators keep their ids.
+fn savepoint_renames(
+    metadata: &CheckpointMetadata,
+    operator_mapping: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>> {
+    let mut renames = HashMap::new();
+    for (new_id, old_id) in operator_mapping {
+        if !metadata.operator_ids.contains(old_id) {
+            bail!(
+                "operator {} (mapped to {}) does not exist in the savepoint",
+                old_id,
+                new_id
+            );
+        }
+        if renames.insert(old_id.clone(), new_id.clone()).is_some() {
+            bail!("operator {} is mapped more than once", old_id);
+        }
+    }
+
+    Ok(metadata
+        .operator_ids
+        .iter()
+        .filter_map(|operator_id| match renames.get(operator_id) {
+            Some(new_id) => Some((operator_id.clone(), new_id.clone())),
+            // this id has been mapped to another operator's state, which replaces its own
+            None if operator_mapping.contains_key(operator_id) => None,
+            None => Some((operator_id.clone(), operator_id.clone())),
+        })
+        .collect())
 }
 
 impl ParquetBackend {
@@ -173,6 +596,8 @@ impl ParquetBackend {
         epoch: u32,
     ) -> Result<HashMap<String, TableCheckpointMetadata>> {
         let min_files_to_compact = config().pipeline.compaction.checkpoints_to_compact as usize;
+        let reuse_file_bytes =
+            (config().pipeline.compaction.reuse_file_size_mb * 1024 * 1024) as usize;
 
         let operator_checkpoint_metadata =
             Self::load_operator_metadata(&job_id, &operator_id, epoch)
@@ -183,6 +608,7 @@ impl ParquetBackend {
             storage_provider,
             compact_generations: vec![0].into_iter().collect(),
             min_compaction_epochs: min_files_to_compact,
+            reuse_file_bytes,
         };
         let operator_metadata = operator_checkpoint_metadata.operator_metadata.unwrap();
 
@@ -221,37 +647,34 @@ impl ParquetBackend {
         Ok(result)
     }
 
-    /// Delete files no longer referenced by the new min epoch
+    /// Delete files from the epochs being cleaned up that are no longer referenced by any
+    /// retained checkpoint
     pub async fn cleanup_operator(
         job_id: String,
         operator_id: String,
         old_min_epoch: u32,
         new_min_epoch: u32,
+        epoch: u32,
     ) -> Result<String> {
-        let operator_metadata = Self::load_operator_metadata(&job_id, &operator_id, new_min_epoch)
-            .await?
-            .expect("expect new_min_epoch metadata to still be present");
-        let paths_to_keep: HashSet<String> = operator_metadata
-            .table_checkpoint_metadata
-            .iter()
-            .flat_map(|(table_name, metadata)| {
-                let table_config = operator_metadata
-                    .table_configs
-                    .get(table_name)
-                    .unwrap()
-                    .clone();
-
-                match table_config.table_type() {
-                    rpc::TableEnum::MissingTableType => todo!("should handle error"),
-                    rpc::TableEnum::GlobalKeyValue => {
-                        GlobalKeyedTable::files_to_keep(table_config, metadata.clone()).unwrap()
-                    }
-                    rpc::TableEnum::ExpiringKeyedTimeTable => {
-                        ExpiringTimeKeyTable::files_to_keep(table_config, metadata.clone()).unwrap()
-                    }
-                }
-            })
-            .collect();
+        // the checkpoint for new_min_epoch may have failed, in which case everything that's
+        // needed is referenced by the latest epoch
+        let mut paths_to_keep =
+            match Self::load_operator_metadata(&job_id, &operator_id, new_min_epoch).await? {
+                Some(operator_metadata) => operator_files(&operator_metadata)?,
+                None => HashSet::new(),
+            };
+
+        // files can be carried over unchanged into later epochs, so anything that is still
+        // referenced by a retained epoch must be kept
+        if let Some(latest) = Self::load_operator_metadata(&job_id, &operator_id, epoch).await? {
+            paths_to_keep.extend(
+                latest
+                    .file_refs
+                    .into_iter()
+                    .filter(|(_, refs)| refs.epochs.iter().any(|e| *e >= new_min_epoch))
+                    .map(|(file, _)| file),
+            );
+        }
 
         let mut deleted_paths = HashSet::new();
         let storage_client = get_storage_provider().await?;
@@ -260,34 +683,19 @@ impl ParquetBackend {
             let Some(metadata) =
                 Self::load_operator_metadata(&job_id, &operator_id, epoch_to_remove).await?
             else {
+                // the checkpoint failed before this operator finished, so there's no record of
+                // what it wrote
+                Self::cleanup_failed_operator(
+                    &job_id,
+                    &operator_id,
+                    epoch_to_remove,
+                    &paths_to_keep,
+                )
+                .await?;
                 continue;
             };
 
-            // delete any files that are not in the new min epoch
-            for file in metadata
-                .table_checkpoint_metadata
-                .iter()
-                // TODO: factor this out
-                .flat_map(|(table_name, metadata)| {
-                    let table_config = operator_metadata
-                        .table_configs
-                        .get(table_name)
-                        .ok_or_else(|| anyhow::anyhow!("missing table config for operator {}, table {}, metadata is {:?}, operator_metadata is {:?}",
-                         operator_id, table_name, metadata, operator_metadata)).unwrap()
-                        .clone();
-
-                    match table_config.table_type() {
-                        rpc::TableEnum::MissingTableType => todo!("should handle error"),
-                        rpc::TableEnum::GlobalKeyValue => {
-                            GlobalKeyedTable::files_to_keep(table_config, metadata.clone()).unwrap()
-                        }
-                        rpc::TableEnum::ExpiringKeyedTimeTable => {
-                            ExpiringTimeKeyTable::files_to_keep(table_config, metadata.clone())
-                                .unwrap()
-                        }
-                    }
-                })
-            {
+            for file in operator_files(&metadata)? {
                 if !paths_to_keep.contains(&file) && !deleted_paths.contains(&file) {
                     deleted_paths.insert(file.clone());
                     storage_client.delete_if_present(file).await?;
@@ -297,10 +705,64 @@ impl ParquetBackend {
 
         Ok(operator_id)
     }
+
+    /// Deletes the partial files written by an operator for a checkpoint that failed, other than
+    /// those carried over into retained checkpoints
+    async fn cleanup_failed_operator(
+        job_id: &str,
+        operator_id: &str,
+        epoch: u32,
+        paths_to_keep: &HashSet<String>,
+    ) -> Result<()> {
+        let operator_dir = operator_path(job_id, epoch, operator_id);
+        let operator_url = format!(
+            "{}/{}",
+            config().checkpoint_url.trim_end_matches('/'),
+            operator_dir
+        );
+        let storage_client = StorageProvider::for_url(&operator_url)
+            .await
+            .with_context(|| format!("failed to construct storage for URL {}", operator_url))?;
+
+        let mut to_delete = vec![];
+        {
+            let mut paths = Box::pin(storage_client.list(true).await?);
+            while let Some(path) = paths.try_next().await? {
+                // depending on the store, listed paths either include the storage prefix or are
+                // relative to the operator directory, while our references are relative to the
+                // checkpoint URL
+                let path = path.to_string();
+                let reference = match path.find(&operator_dir) {
+                    Some(i) => path[i..].to_string(),
+                    None => format!("{}/{}", operator_dir, path),
+                };
+                if !paths_to_keep.contains(&reference) {
+                    to_delete.push(path);
+                }
+            }
+        }
+
+        if !to_delete.is_empty() {
+            info!(
+                message = "Removing files from failed checkpoint",
+                job_id,
+                operator_id,
+                epoch,
+                files = to_delete.len()
+            );
+        }
+
+        for path in to_delete {
+            storage_client.delete_if_present(path).await?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
 pub struct ParquetStats {
+    pub min_timestamp: SystemTime,
     pub max_timestamp: SystemTime,
     pub min_routing_key: u64,
     pub max_routing_key: u64,
@@ -309,6 +771,7 @@ pub struct ParquetStats {
 impl Default for ParquetStats {
     fn default() -> Self {
         Self {
+            min_timestamp: from_micros(u64::MAX),
             max_timestamp: SystemTime::UNIX_EPOCH,
             min_routing_key: u64::MAX,
             max_routing_key: u64::MIN,
@@ -318,8 +781,229 @@ impl Default for ParquetStats {
 
 impl ParquetStats {
     pub fn merge(&mut self, other: ParquetStats) {
+        self.min_timestamp = self.min_timestamp.min(other.min_timestamp);
         self.max_timestamp = self.max_timestamp.max(other.max_timestamp);
         self.min_routing_key = self.min_routing_key.min(other.min_routing_key);
         self.max_routing_key = self.max_routing_key.max(other.max_routing_key);
     }
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::global_table_config;
+    use arroyo_rpc::grpc::rpc::{
+        GlobalKeyedTableTaskCheckpointMetadata, OperatorMetadata, TableEnum,
+    };
+
+    fn checkpoint(files: &[&str]) -> OperatorCheckpointMetadata {
+        OperatorCheckpointMetadata {
+            table_checkpoint_metadata: HashMap::from([(
+                "s".to_string(),
+                TableCheckpointMetadata {
+                    table_type: TableEnum::GlobalKeyValue.into(),
+                    data: GlobalKeyedTableTaskCheckpointMetadata {
+                        files: files.iter().map(|f| f.to_string()).collect(),
+                        commit_data_by_subtask: HashMap::new(),
+                    }
+                    .encode_to_vec(),
+                },
+            )]),
+            table_configs: global_table_config("s", "source"),
+            ..Default::default()
+        }
+    }
+
+    fn epochs(metadata: &OperatorCheckpointMetadata, file: &str) -> Vec<u32> {
+        metadata
+            .file_refs
+            .get(file)
+            .map(|refs| refs.epochs.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_file_refs() {
+        let mut first = checkpoint(&["a", "b"]);
+        first.file_refs = compute_file_refs(None, &first, 1, 1).unwrap();
+
+        // b is carried over unchanged, while a is replaced by c
+        let mut second = checkpoint(&["b", "c"]);
+        second.file_refs = compute_file_refs(Some(&first), &second, 2, 1).unwrap();
+        assert_eq!(epochs(&second, "a"), vec![1]);
+        assert_eq!(epochs(&second, "b"), vec![1, 2]);
+        assert_eq!(epochs(&second, "c"), vec![2]);
+
+        // once epoch 1 is no longer retained, a is unreferenced
+        let mut third = checkpoint(&["b", "c"]);
+        third.file_refs = compute_file_refs(Some(&second), &third, 3, 2).unwrap();
+        assert!(!third.file_refs.contains_key("a"));
+        assert_eq!(epochs(&third, "b"), vec![2, 3]);
+
+        // rewriting an epoch (e.g., after rolling back) replaces the references it had before
+        let rolled_back = checkpoint(&["b"]);
+        let refs = compute_file_refs(Some(&second), &rolled_back, 2, 1).unwrap();
+        assert_eq!(refs.get("b").unwrap().epochs, vec![1, 2]);
+        assert!(!refs.contains_key("c"));
+    }
+
+    /// Points the checkpoint storage at a temporary directory shared by the tests, which use
+    /// their own job ids
+    pub(crate) async fn checkpoint_test_storage() -> StorageProvider {
+        static URL: OnceCell<String> = OnceCell::new();
+        let url = URL.get_or_init(|| {
+            let dir = std::env::temp_dir()
+                .join(format!("arroyo-checkpoint-test-{}", rand::random::<u64>()));
+            let url = format!("file://{}", dir.to_str().unwrap());
+            config();
+            arroyo_rpc::config::update(|c| c.checkpoint_url = url.clone());
+            url
+        });
+        StorageProvider::for_url(url).await.unwrap()
+    }
+
+    /// Writes the operator's checkpoint for `epoch`, which references `files`, along with any of
+    /// the files that don't exist yet
+    async fn write_operator_checkpoint(
+        storage: &StorageProvider,
+        job_id: &str,
+        epoch: u32,
+        files: &[&str],
+    ) {
+        for file in files {
+            if storage.get_if_present(*file).await.unwrap().is_none() {
+                storage.put(*file, b"data".to_vec()).await.unwrap();
+            }
+        }
+
+        let mut previous = None;
+        for e in (1..epoch).rev() {
+            previous = ParquetBackend::load_operator_metadata(job_id, "op", e)
+                .await
+                .unwrap();
+            if previous.is_some() {
+                break;
+            }
+        }
+
+        let mut metadata = checkpoint(files);
+        metadata.operator_metadata = Some(OperatorMetadata {
+            job_id: job_id.to_string(),
+            operator_id: "op".to_string(),
+            epoch,
+            ..Default::default()
+        });
+        metadata.file_refs = compute_file_refs(previous.as_ref(), &metadata, epoch, 1).unwrap();
+        ParquetBackend::write_operator_checkpoint_metadata(metadata)
+            .await
+            .unwrap();
+    }
+
+    fn file(job_id: &str, epoch: u32, name: &str) -> String {
+        format!("{}/{}", operator_path(job_id, epoch, "op"), name)
+    }
+
+    async fn exists(storage: &StorageProvider, path: &str) -> bool {
+        storage.get_if_present(path).await.unwrap().is_some()
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_failed_checkpoints() {
+        let storage = checkpoint_test_storage().await;
+        let job_id = format!("failed-{}", rand::random::<u64>());
+        let (a, b, c) = (
+            file(&job_id, 1, "a"),
+            file(&job_id, 2, "b"),
+            file(&job_id, 2, "c"),
+        );
+
+        write_operator_checkpoint(&storage, &job_id, 1, &[&a]).await;
+        // the checkpoint for epoch 2 failed after this operator finished, and the subtask that
+        // wrote b carried it over into epoch 3
+        write_operator_checkpoint(&storage, &job_id, 2, &[&a, &b, &c]).await;
+        // while epoch 4 failed before the operator finished, leaving a partial file behind
+        let partial = file(&job_id, 4, "partial");
+        storage.put(&partial, b"data".to_vec()).await.unwrap();
+        write_operator_checkpoint(&storage, &job_id, 3, &[&a, &b]).await;
+        write_operator_checkpoint(&storage, &job_id, 5, &[&a, &b]).await;
+
+        let completed = CheckpointMetadata {
+            job_id: job_id.clone(),
+            epoch: 5,
+            operator_ids: vec!["op".to_string()],
+            ..Default::default()
+        };
+        ParquetBackend::cleanup_failed_checkpoints(completed.clone(), &[2, 4])
+            .await
+            .unwrap();
+
+        assert!(exists(&storage, &a).await);
+        assert!(exists(&storage, &b).await);
+        assert!(!exists(&storage, &c).await);
+        assert!(!exists(&storage, &partial).await);
+        assert!(ParquetBackend::load_operator_metadata(&job_id, "op", 2)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(ParquetBackend::load_operator_metadata(&job_id, "op", 3)
+            .await
+            .unwrap()
+            .is_some());
+
+        // only epochs before the completed checkpoint can be cleaned up
+        assert!(ParquetBackend::cleanup_failed_checkpoints(completed, &[5])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_keeps_carried_over_files() {
+        let storage = checkpoint_test_storage().await;
+        let job_id = format!("carried-{}", rand::random::<u64>());
+        let (a, b, c) = (
+            file(&job_id, 1, "a"),
+            file(&job_id, 1, "b"),
+            file(&job_id, 3, "c"),
+        );
+
+        write_operator_checkpoint(&storage, &job_id, 1, &[&a, &b]).await;
+        // the checkpoint for epoch 2 failed without writing anything, and b is carried over
+        // from epoch 1 into epoch 3
+        write_operator_checkpoint(&storage, &job_id, 3, &[&b, &c]).await;
+
+        ParquetBackend::cleanup_operator(job_id.clone(), "op".to_string(), 1, 2, 3)
+            .await
+            .unwrap();
+
+        assert!(!exists(&storage, &a).await);
+        assert!(exists(&storage, &b).await);
+        assert!(exists(&storage, &c).await);
+    }
+
+    #[test]
+    fn test_savepoint_renames() {
+        let metadata = CheckpointMetadata {
+            operator_ids: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            ..Default::default()
+        };
+
+        // b takes a's state, which replaces b's own
+        let mapping = HashMap::from([("b".to_string(), "a".to_string())]);
+        assert_eq!(
+            savepoint_renames(&metadata, &mapping).unwrap(),
+            vec![
+                ("a".to_string(), "b".to_string()),
+                ("c".to_string(), "c".to_string())
+            ]
+        );
+
+        let missing = HashMap::from([("b".to_string(), "d".to_string())]);
+        assert!(savepoint_renames(&metadata, &missing).is_err());
+
+        let twice = HashMap::from([
+            ("b".to_string(), "a".to_string()),
+            ("d".to_string(), "a".to_string()),
+        ]);
+        assert!(savepoint_renames(&metadata, &twice).is_err());
+    }
+}
@@ -148,8 +148,10 @@ impl SchemaWithHashAndOperation {
             .as_any()
             .downcast_ref::<TimestampNanosecondArray>()
             .ok_or_else(|| anyhow!("should be able to extract timestamp array"))?;
+        let min_timestamp_nanos = min(timestamp_array).expect("should have min timestamp");
         let max_timestamp_nanos = max(timestamp_array).expect("should have max timestamp");
         Ok(ParquetStats {
+            min_timestamp: from_nanos(min_timestamp_nanos as u128),
             max_timestamp: from_nanos(max_timestamp_nanos as u128),
             min_routing_key: hash_min,
             max_routing_key: hash_max,
@@ -174,12 +176,14 @@ impl SchemaWithHashAndOperation {
 
         let hash_min = min(&hash_array).unwrap();
         let hash_max = max(&hash_array).unwrap();
-        let max_timestamp_nanos: i64 = max(record_batch
+        let timestamp_array = record_batch
             .column(self.memory_schema.timestamp_index)
-            .as_primitive::<TimestampNanosecondType>())
-        .unwrap();
+            .as_primitive::<TimestampNanosecondType>();
+        let min_timestamp_nanos: i64 = min(timestamp_array).unwrap();
+        let max_timestamp_nanos: i64 = max(timestamp_array).unwrap();
 
         let batch_stats = ParquetStats {
+            min_timestamp: from_nanos(min_timestamp_nanos as u128),
             max_timestamp: from_nanos(max_timestamp_nanos as u128),
             min_routing_key: hash_min,
             max_routing_key: hash_max,
@@ -0,0 +1,526 @@
+//! Bounded-memory storage for the record batches that window and join operators hold between
+//! checkpoints. Each task gets a [`MemoryBudget`], shared by all of its [`SpillableMap`]s; once
+//! the task is over budget, the least-recently-used entries are written out as Arrow IPC files
+//! under the worker's data dir and read back when they're next needed. File I/O runs on tokio's
+//! blocking pool so that it doesn't stall the operator's task.
+
+use anyhow::{Context, Result};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow_array::RecordBatch;
+use arroyo_metrics::gauge_for_task;
+use arroyo_rpc::config::config;
+use arroyo_types::TaskInfo;
+use prometheus::IntGauge;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tracing::{debug, warn};
+
+/// Once a task goes over its budget, entries are spilled until usage falls below this fraction of
+/// the budget, so that we don't end up spilling on every insert
+const SPILL_LOW_WATERMARK: f64 = 0.8;
+
+pub type MemoryBudgetRef = Arc<MemoryBudget>;
+
+/// Tracks the memory used by a task's in-memory state, and how much of it has been spilled to disk
+pub struct MemoryBudget {
+    limit: Option<usize>,
+    memory: AtomicUsize,
+    disk: AtomicUsize,
+    dir: PathBuf,
+    next_file: AtomicU64,
+    memory_gauge: Option<IntGauge>,
+    disk_gauge: Option<IntGauge>,
+}
+
+impl Debug for MemoryBudget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryBudget")
+            .field("limit", &self.limit)
+            .field("memory", &self.memory_bytes())
+            .field("disk", &self.disk_bytes())
+            .field("dir", &self.dir)
+            .finish()
+    }
+}
+
+impl MemoryBudget {
+    /// A budget that never spills
+    pub fn unbounded() -> MemoryBudgetRef {
+        Arc::new(Self {
+            limit: None,
+            memory: AtomicUsize::new(0),
+            disk: AtomicUsize::new(0),
+            dir: std::env::temp_dir(),
+            next_file: AtomicU64::new(0),
+            memory_gauge: None,
+            disk_gauge: None,
+        })
+    }
+
+    /// Creates the budget for a task from the worker config. Any files left over in the task's
+    /// spill directory from a previous run are removed, as the state will be restored from the
+    /// checkpoint instead.
+    pub async fn for_task(task_info: &TaskInfo) -> MemoryBudgetRef {
+        let worker_config = &config().worker;
+        let dir = worker_config
+            .data_dir
+            .join("spill")
+            .join(&task_info.job_id)
+            .join(format!(
+                "{}-{}",
+                task_info.operator_id, task_info.task_index
+            ));
+
+        if tokio::fs::try_exists(&dir).await.unwrap_or(false) {
+            if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+                warn!("failed to clean up spill directory {:?}: {:?}", dir, e);
+            }
+        }
+
+        Arc::new(Self {
+            limit: worker_config
+                .state_memory_budget_mb
+                .map(|mb| mb as usize * 1024 * 1024),
+            memory: AtomicUsize::new(0),
+            disk: AtomicUsize::new(0),
+            dir,
+            next_file: AtomicU64::new(0),
+            memory_gauge: gauge_for_task(
+                task_info,
+                "arroyo_worker_state_memory_bytes",
+                "Bytes of window and join state held in memory",
+                HashMap::new(),
+            ),
+            disk_gauge: gauge_for_task(
+                task_info,
+                "arroyo_worker_state_spilled_bytes",
+                "Bytes of window and join state spilled to local disk",
+                HashMap::new(),
+            ),
+        })
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.memory.load(Ordering::Relaxed)
+    }
+
+    pub fn disk_bytes(&self) -> usize {
+        self.disk.load(Ordering::Relaxed)
+    }
+
+    /// Whether the task is using more memory than it's been allotted
+    pub fn over_budget(&self) -> bool {
+        self.limit
+            .map(|limit| self.memory_bytes() > limit)
+            .unwrap_or(false)
+    }
+
+    fn above_low_watermark(&self) -> bool {
+        self.limit
+            .map(|limit| self.memory_bytes() as f64 > limit as f64 * SPILL_LOW_WATERMARK)
+            .unwrap_or(false)
+    }
+
+    fn add_memory(&self, bytes: usize) {
+        let total = self.memory.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if let Some(gauge) = &self.memory_gauge {
+            gauge.set(total as i64);
+        }
+    }
+
+    fn release_memory(&self, bytes: usize) {
+        let total = self.memory.fetch_sub(bytes, Ordering::Relaxed) - bytes;
+        if let Some(gauge) = &self.memory_gauge {
+            gauge.set(total as i64);
+        }
+    }
+
+    fn add_disk(&self, bytes: usize) {
+        let total = self.disk.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if let Some(gauge) = &self.disk_gauge {
+            gauge.set(total as i64);
+        }
+    }
+
+    fn release_disk(&self, bytes: usize) {
+        let total = self.disk.fetch_sub(bytes, Ordering::Relaxed) - bytes;
+        if let Some(gauge) = &self.disk_gauge {
+            gauge.set(total as i64);
+        }
+    }
+
+    async fn next_path(&self) -> Result<PathBuf> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("failed to create spill directory {:?}", self.dir))?;
+        let id = self.next_file.fetch_add(1, Ordering::Relaxed);
+        Ok(self.dir.join(format!("{:010}.arrow", id)))
+    }
+}
+
+#[derive(Debug)]
+struct SpillFile {
+    path: PathBuf,
+    bytes: usize,
+}
+
+#[derive(Default)]
+struct Entry {
+    batches: Vec<RecordBatch>,
+    memory_bytes: usize,
+    // batches that were written out before the in-memory ones
+    files: Vec<SpillFile>,
+    last_access: u64,
+}
+
+/// An ordered map of record batches whose contents are spilled to local disk when the task is
+/// over its [`MemoryBudget`]. Spilled entries are read back transparently on access.
+pub struct SpillableMap<K: Ord + Clone> {
+    budget: MemoryBudgetRef,
+    entries: BTreeMap<K, Entry>,
+    clock: u64,
+}
+
+impl<K: Ord + Clone + Debug> Debug for SpillableMap<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.entries.iter().map(|(k, entry)| {
+                (
+                    k,
+                    format!(
+                        "{} batches in memory, {} spilled files",
+                        entry.batches.len(),
+                        entry.files.len()
+                    ),
+                )
+            }))
+            .finish()
+    }
+}
+
+impl<K: Ord + Clone> SpillableMap<K> {
+    pub fn new(budget: MemoryBudgetRef) -> Self {
+        Self {
+            budget,
+            entries: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    pub fn push(&mut self, key: K, batch: RecordBatch) {
+        let bytes = batch.get_array_memory_size();
+        let access = self.tick();
+        let entry = self.entries.entry(key).or_default();
+        entry.batches.push(batch);
+        entry.memory_bytes += bytes;
+        entry.last_access = access;
+        self.budget.add_memory(bytes);
+    }
+
+    pub fn extend(&mut self, key: K, batches: impl IntoIterator<Item = RecordBatch>) {
+        for batch in batches {
+            self.push(key.clone(), batch);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.keys()
+    }
+
+    pub fn first_key(&self) -> Option<&K> {
+        self.entries.keys().next()
+    }
+
+    /// Returns the batches for `key`, loading any that were spilled back into memory
+    pub async fn get(&mut self, key: &K) -> Result<Option<&[RecordBatch]>> {
+        let access = self.tick();
+        let Some(entry) = self.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        entry.last_access = access;
+        if !entry.files.is_empty() {
+            let mut batches = vec![];
+            for file in entry.files.drain(..) {
+                batches.extend(read_file(&file.path).await?);
+                delete_file(&file.path).await;
+                self.budget.release_disk(file.bytes);
+            }
+            let bytes: usize = batches.iter().map(|b| b.get_array_memory_size()).sum();
+            batches.append(&mut entry.batches);
+            entry.batches = batches;
+            entry.memory_bytes += bytes;
+            self.budget.add_memory(bytes);
+        }
+        Ok(Some(&entry.batches))
+    }
+
+    /// Reads the batches for `key` without changing where they're stored
+    pub async fn read(&self, key: &K) -> Result<Vec<RecordBatch>> {
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(vec![]);
+        };
+        let mut batches = vec![];
+        for file in &entry.files {
+            batches.extend(read_file(&file.path).await?);
+        }
+        batches.extend(entry.batches.iter().cloned());
+        Ok(batches)
+    }
+
+    pub async fn remove(&mut self, key: &K) -> Result<Option<Vec<RecordBatch>>> {
+        let Some(entry) = self.entries.remove(key) else {
+            return Ok(None);
+        };
+        Ok(Some(self.take_entry(entry).await?))
+    }
+
+    pub async fn pop_first(&mut self) -> Result<Option<(K, Vec<RecordBatch>)>> {
+        let Some((key, entry)) = self.entries.pop_first() else {
+            return Ok(None);
+        };
+        Ok(Some((key, self.take_entry(entry).await?)))
+    }
+
+    /// Drops all entries with keys before `key`
+    pub fn remove_before(&mut self, key: &K) {
+        let retained = self.entries.split_off(key);
+        let dropped = std::mem::replace(&mut self.entries, retained);
+        self.drop_entries(dropped.into_values());
+    }
+
+    /// Drops all entries whose keys don't match the predicate
+    pub fn retain(&mut self, mut f: impl FnMut(&K) -> bool) {
+        let to_drop: Vec<_> = self.entries.keys().filter(|k| !f(k)).cloned().collect();
+        let dropped: Vec<_> = to_drop
+            .iter()
+            .filter_map(|key| self.entries.remove(key))
+            .collect();
+        self.drop_entries(dropped);
+    }
+
+    /// If the task is over budget, spills the least-recently-used entries until it's back under
+    pub async fn spill_if_needed(&mut self) -> Result<()> {
+        if !self.budget.over_budget() {
+            return Ok(());
+        }
+
+        let mut by_access: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| !entry.batches.is_empty())
+            .map(|(k, entry)| (entry.last_access, k.clone()))
+            .collect();
+        by_access.sort_by_key(|(access, _)| *access);
+
+        for (_, key) in by_access {
+            if !self.budget.above_low_watermark() {
+                break;
+            }
+            self.spill(&key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes all in-memory batches for `key` to disk
+    pub async fn spill(&mut self, key: &K) -> Result<()> {
+        let Some(entry) = self.entries.get_mut(key) else {
+            return Ok(());
+        };
+        if entry.batches.is_empty() {
+            return Ok(());
+        }
+
+        let path = self.budget.next_path().await?;
+        let bytes = write_file(&path, &entry.batches).await?;
+        debug!(
+            "spilled {} batches ({} bytes in memory) to {:?}",
+            entry.batches.len(),
+            entry.memory_bytes,
+            path
+        );
+
+        entry.batches.clear();
+        self.budget.release_memory(entry.memory_bytes);
+        entry.memory_bytes = 0;
+        entry.files.push(SpillFile { path, bytes });
+        self.budget.add_disk(bytes);
+        Ok(())
+    }
+
+    /// Spills every entry to disk
+    pub async fn spill_all(&mut self) -> Result<()> {
+        let keys: Vec<_> = self.entries.keys().cloned().collect();
+        for key in keys {
+            self.spill(&key).await?;
+        }
+        Ok(())
+    }
+
+    async fn take_entry(&mut self, mut entry: Entry) -> Result<Vec<RecordBatch>> {
+        let mut batches = vec![];
+        for file in entry.files.drain(..) {
+            batches.extend(read_file(&file.path).await?);
+            delete_file(&file.path).await;
+            self.budget.release_disk(file.bytes);
+        }
+        batches.append(&mut entry.batches);
+        self.budget.release_memory(entry.memory_bytes);
+        Ok(batches)
+    }
+
+    /// Releases the given entries, deleting their spilled files in the background
+    fn drop_entries(&self, entries: impl IntoIterator<Item = Entry>) {
+        let mut paths = vec![];
+        for entry in entries {
+            for file in entry.files {
+                self.budget.release_disk(file.bytes);
+                paths.push(file.path);
+            }
+            self.budget.release_memory(entry.memory_bytes);
+        }
+        remove_files(paths);
+    }
+}
+
+impl<K: Ord + Clone> Drop for SpillableMap<K> {
+    fn drop(&mut self) {
+        let entries = std::mem::take(&mut self.entries);
+        self.drop_entries(entries.into_values());
+    }
+}
+
+async fn write_file(path: &Path, batches: &[RecordBatch]) -> Result<usize> {
+    let path = path.to_path_buf();
+    let batches = batches.to_vec();
+    tokio::task::spawn_blocking(move || {
+        let file = File::create(&path)
+            .with_context(|| format!("failed to create spill file {:?}", path))?;
+        let mut writer = FileWriter::try_new(BufWriter::new(file), &batches[0].schema())?;
+        for batch in &batches {
+            writer.write(batch)?;
+        }
+        writer.finish()?;
+        Ok(std::fs::metadata(&path)?.len() as usize)
+    })
+    .await?
+}
+
+async fn read_file(path: &Path) -> Result<Vec<RecordBatch>> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let file =
+            File::open(&path).with_context(|| format!("failed to open spill file {:?}", path))?;
+        FileReader::try_new(BufReader::new(file), None)?
+            .map(|batch| Ok(batch?))
+            .collect()
+    })
+    .await?
+}
+
+async fn delete_file(path: &Path) {
+    if let Err(e) = tokio::fs::remove_file(path).await {
+        warn!("failed to remove spill file {:?}: {:?}", path, e);
+    }
+}
+
+/// Deletes spill files from a synchronous context (e.g., when a map is dropped), on the blocking
+/// pool if we're inside a runtime
+fn remove_files(paths: Vec<PathBuf>) {
+    if paths.is_empty() {
+        return;
+    }
+    let remove = move || {
+        for path in paths {
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("failed to remove spill file {:?}: {:?}", path, e);
+            }
+        }
+    };
+    match Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(remove);
+        }
+        Err(_) => remove(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow_array::UInt64Array;
+    use arrow_schema::{DataType, Field, Schema};
+
+    fn batch(n: u64) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("v", DataType::UInt64, false)]));
+        RecordBatch::try_new(schema, vec![Arc::new(UInt64Array::from_iter_values(0..n))]).unwrap()
+    }
+
+    fn budget(limit: usize) -> MemoryBudgetRef {
+        Arc::new(MemoryBudget {
+            limit: Some(limit),
+            memory: AtomicUsize::new(0),
+            disk: AtomicUsize::new(0),
+            dir: std::env::temp_dir().join(format!("arroyo-spill-test-{}", rand::random::<u64>())),
+            next_file: AtomicU64::new(0),
+            memory_gauge: None,
+            disk_gauge: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_spill_and_read_back() {
+        let batch_size = batch(1000).get_array_memory_size();
+        let budget = budget(batch_size * 2);
+        let mut map = SpillableMap::new(budget.clone());
+
+        map.push(1, batch(1000));
+        map.push(2, batch(1000));
+        map.push(3, batch(1000));
+        assert!(budget.over_budget());
+
+        map.spill_if_needed().await.unwrap();
+        assert!(!budget.over_budget());
+        assert!(budget.disk_bytes() > 0);
+
+        // the least recently used entries are spilled first
+        assert!(map.entries[&1].batches.is_empty());
+        assert_eq!(map.entries[&3].batches.len(), 1);
+
+        assert_eq!(map.read(&1).await.unwrap()[0].num_rows(), 1000);
+        assert_eq!(map.get(&1).await.unwrap().unwrap()[0].num_rows(), 1000);
+        assert!(map.entries[&1].files.is_empty());
+
+        map.remove_before(&3);
+        assert_eq!(map.len(), 1);
+        drop(map);
+        assert_eq!(budget.memory_bytes(), 0);
+        assert_eq!(budget.disk_bytes(), 0);
+
+        let _ = tokio::fs::remove_dir_all(&budget.dir).await;
+    }
+}
@@ -1,20 +1,25 @@
 use std::{
+    any::Any,
+    borrow::Cow,
     collections::{BTreeMap, HashMap, HashSet},
+    fmt::Debug,
     mem,
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
-use anyhow::{anyhow, bail, Ok, Result};
+use anyhow::{anyhow, bail, Result};
 use arrow::compute::{concat_batches, filter_record_batch, kernels::aggregate, take};
 use arrow::row::OwnedRow;
 use arrow_array::{
     cast::AsArray,
     types::{TimestampNanosecondType, UInt64Type},
-    BooleanArray, PrimitiveArray, RecordBatch, TimestampNanosecondArray, UInt64Array,
+    ArrayRef, BooleanArray, PrimitiveArray, RecordBatch, TimestampNanosecondArray, UInt64Array,
 };
 use arrow_ord::{partition::partition, sort::sort_to_indices};
+use arrow_schema::{Schema, SchemaRef};
 use arroyo_rpc::{
+    config::{config, StateBackendType},
     df::server_for_hash_array,
     grpc::rpc::{
         ExpiringKeyedTimeSubtaskCheckpointMetadata, ExpiringKeyedTimeTableCheckpointMetadata,
@@ -27,22 +32,30 @@ use arroyo_types::{
     from_micros, from_nanos, print_time, server_for_hash, to_micros, to_nanos, TaskInfoRef,
 };
 
+use bincode::{Decode, Encode};
 use futures::{StreamExt, TryStreamExt};
 use parquet::{
     arrow::{async_reader::ParquetObjectReader, AsyncArrowWriter, ParquetRecordBatchStreamBuilder},
     basic::{Compression, ZstdLevel},
     file::properties::WriterProperties,
 };
+use serde_json::{Map, Value as JsonValue};
 use tokio::{io::AsyncWrite, sync::mpsc::Sender};
 
 use crate::{
-    parquet::ParquetStats, schemas::SchemaWithHashAndOperation, CheckpointMessage, StateMessage,
-    TableData,
+    kv::KvLastValueStore,
+    parquet::ParquetStats,
+    schemas::SchemaWithHashAndOperation,
+    spill::{MemoryBudgetRef, SpillableMap},
+    CheckpointMessage, StateMessage, TableData,
 };
 use arroyo_rpc::df::{ArroyoSchema, ArroyoSchemaRef};
 use tracing::debug;
 
-use super::{table_checkpoint_path, CompactionConfig, Table, TableEpochCheckpointer};
+use super::{
+    decode_key, table_checkpoint_path, CompactionConfig, QueryableView, Table,
+    TableEpochCheckpointer,
+};
 
 #[derive(Debug, Clone)]
 pub struct ExpiringTimeKeyTable {
@@ -59,11 +72,12 @@ impl ExpiringTimeKeyTable {
         &self,
         state_tx: Sender<StateMessage>,
         watermark: Option<SystemTime>,
+        memory_budget: MemoryBudgetRef,
     ) -> Result<ExpiringTimeKeyView> {
         let cutoff = self.get_cutoff(watermark);
         let files = self.get_files_with_filtering(cutoff);
 
-        let mut data: BTreeMap<SystemTime, Vec<RecordBatch>> = BTreeMap::new();
+        let mut data = SpillableMap::new(memory_budget);
         let timestamp_index = self.schema.timestamp_index();
         let batches_by_timestamp = self
             .call_on_filtered_batches(files, |batch| {
@@ -102,7 +116,8 @@ impl ExpiringTimeKeyTable {
 
         for (timestamp, batch) in batches_by_timestamp {
             if cutoff <= timestamp {
-                data.entry(timestamp).or_default().push(batch)
+                data.push(timestamp, batch);
+                data.spill_if_needed().await?;
             }
         }
 
@@ -185,11 +200,12 @@ impl ExpiringTimeKeyTable {
         &self,
         state_tx: Sender<StateMessage>,
         watermark: Option<SystemTime>,
+        memory_budget: MemoryBudgetRef,
     ) -> Result<KeyTimeView> {
         let cutoff = self.get_cutoff(watermark);
         let files = self.get_files_with_filtering(cutoff);
 
-        let mut view = KeyTimeView::new(self.clone(), state_tx)?;
+        let mut view = KeyTimeView::new(self.clone(), state_tx, memory_budget)?;
         let batches_to_add = self
             .call_on_filtered_batches(files, |batch| {
                 let timestamp_array: &PrimitiveArray<TimestampNanosecondType> = batch
@@ -209,7 +225,7 @@ impl ExpiringTimeKeyTable {
             })
             .await?;
         for batch in batches_to_add {
-            view.insert_internal(batch)?;
+            view.insert_internal(batch).await?;
         }
         Ok(view)
     }
@@ -368,6 +384,18 @@ impl Table for ExpiringTimeKeyTable {
             .map(|file: ParquetTimeFile| file.file)
             .collect())
     }
+
+    fn relocate_files(
+        _config: Self::ConfigMessage,
+        mut checkpoint: Self::TableCheckpointMessage,
+        relocate: &dyn Fn(&str) -> String,
+    ) -> Result<Self::TableCheckpointMessage> {
+        for file in &mut checkpoint.files {
+            file.file = relocate(&file.file);
+        }
+        Ok(checkpoint)
+    }
+
     fn apply_compacted_checkpoint(
         &self,
         epoch: u32,
@@ -397,7 +425,19 @@ impl Table for ExpiringTimeKeyTable {
         let mut epochs_in_generation: HashMap<u64, HashSet<u32>> = HashMap::new();
         let mut files_by_generation: BTreeMap<u64, HashMap<String, ParquetTimeFile>> =
             BTreeMap::new();
+        let cutoff = operator_metadata.min_watermark.map(|min_micros| {
+            from_micros(min_micros) - Duration::from_micros(config.retention_micros)
+        });
+        let mut expired_files = 0;
         for file in current_metadata.files {
+            // files whose rows have all expired can be dropped, regardless of their generation
+            if cutoff
+                .map(|cutoff| from_micros(file.max_timestamp_micros) < cutoff)
+                .unwrap_or(false)
+            {
+                expired_files += 1;
+                continue;
+            }
             if compaction_config
                 .compact_generations
                 .contains(&file.generation)
@@ -433,6 +473,7 @@ impl Table for ExpiringTimeKeyTable {
                 files_by_generation
                     .remove(&generation)
                     .expect("will have been populated"),
+                compaction_config.reuse_file_bytes,
             )
             .await?;
             files.extend(
@@ -442,10 +483,84 @@ impl Table for ExpiringTimeKeyTable {
             );
             return Ok(Some(ExpiringKeyedTimeTableCheckpointMetadata { files }));
         }
+
+        // files that are mostly made up of expired rows are rewritten without them, so that
+        // long-lived keys don't keep expired rows around until the rest of their file expires
+        if let Some(cutoff) = cutoff.map(to_micros) {
+            let rewrite = files_by_generation.iter().find_map(|(generation, files)| {
+                let files: Vec<_> = files
+                    .values()
+                    .filter(|file| mostly_expired(file, cutoff))
+                    .map(|file| file.file.clone())
+                    .collect();
+                (!files.is_empty()).then_some((*generation, files))
+            });
+
+            if let Some((generation, file_names)) = rewrite {
+                let generation_files = files_by_generation
+                    .get_mut(&generation)
+                    .expect("will have been populated");
+                let to_rewrite: HashMap<_, _> = file_names
+                    .into_iter()
+                    .filter_map(|name| generation_files.remove_entry(&name))
+                    .collect();
+                let epoch = to_rewrite
+                    .values()
+                    .map(|file| file.epoch)
+                    .max()
+                    .expect("will have files to rewrite");
+                debug!(
+                    message = "rewriting files with expired rows",
+                    table = config.table_name,
+                    files = to_rewrite.len(),
+                    generation
+                );
+                let mut files = TimeTableCompactor::compact_files(
+                    config.table_name,
+                    epoch,
+                    generation,
+                    compaction_config.storage_provider.clone(),
+                    state_schema,
+                    Duration::from_micros(config.retention_micros),
+                    operator_metadata,
+                    to_rewrite,
+                    compaction_config.reuse_file_bytes,
+                )
+                .await?;
+                files.extend(
+                    files_by_generation
+                        .into_values()
+                        .flat_map(|files| files.into_values()),
+                );
+                return Ok(Some(ExpiringKeyedTimeTableCheckpointMetadata { files }));
+            }
+        }
+
+        if expired_files > 0 {
+            debug!(
+                message = "dropping expired files",
+                table = config.table_name,
+                expired_files
+            );
+            return Ok(Some(ExpiringKeyedTimeTableCheckpointMetadata {
+                files: files_by_generation
+                    .into_values()
+                    .flat_map(|files| files.into_values())
+                    .collect(),
+            }));
+        }
         Ok(None)
     }
 }
 
+/// Whether at least half of the time range covered by the file is older than the cutoff. Files
+/// written before min timestamps were tracked have a min of 0, so are rewritten once.
+fn mostly_expired(file: &ParquetTimeFile, cutoff_micros: u64) -> bool {
+    file.min_timestamp_micros < cutoff_micros
+        && cutoff_micros - file.min_timestamp_micros
+            >= file.max_timestamp_micros.saturating_sub(cutoff_micros)
+}
+
 struct CompactedFileWriter {
     file_name: String,
     schema: SchemaWithHashAndOperation,
@@ -472,6 +587,7 @@ impl TimeTableCompactor {
         retention: Duration,
         operator_metadata: &OperatorMetadata,
         files: HashMap<String, ParquetTimeFile>,
+        reuse_file_bytes: usize,
     ) -> Result<Vec<ParquetTimeFile>> {
         let mut compactor = Self {
             table,
@@ -483,6 +599,7 @@ impl TimeTableCompactor {
         let cutoff = operator_metadata
             .min_watermark
             .map(|min_micros| from_micros(min_micros) - retention);
+        let mut reused = vec![];
         for (file_name, file) in files {
             let max_file_timestamp = from_micros(file.max_timestamp_micros);
             if cutoff
@@ -491,19 +608,31 @@ impl TimeTableCompactor {
             {
                 continue;
             }
-            let reader = ParquetObjectReader::new(
-                compactor.storage_provider.get_backing_store(),
-                compactor
-                    .storage_provider
-                    .get_backing_store()
-                    .head(&(file_name.clone().into()))
-                    .await?,
-            );
+            let object_meta = compactor
+                .storage_provider
+                .get_backing_store()
+                .head(&(file_name.clone().into()))
+                .await?;
             let first_partition =
                 server_for_hash(file.min_routing_key, operator_metadata.parallelism as usize);
             let last_partition =
                 server_for_hash(file.max_routing_key, operator_metadata.parallelism as usize);
             let multiple_partitions = first_partition != last_partition;
+
+            // a large file whose rows would all be copied into a single compacted file is kept
+            // as it is, so that compaction doesn't rewrite long-lived state every time it runs
+            let has_expired_rows = cutoff
+                .map(|cutoff| from_micros(file.min_timestamp_micros) < cutoff)
+                .unwrap_or(false);
+            if !multiple_partitions && !has_expired_rows && object_meta.size >= reuse_file_bytes {
+                reused.push(ParquetTimeFile { generation, ..file });
+                continue;
+            }
+
+            let reader = ParquetObjectReader::new(
+                compactor.storage_provider.get_backing_store(),
+                object_meta,
+            );
             let reader_builder = ParquetRecordBatchStreamBuilder::new(reader).await?;
             let mut stream = reader_builder.build()?;
             // projection to trim the metadata fields. Should probably be factored out.
@@ -552,7 +681,9 @@ impl TimeTableCompactor {
                 }
             }
         }
-        compactor.finish(epoch, generation).await
+        let mut files = compactor.finish(epoch, generation).await?;
+        files.extend(reused);
+        Ok(files)
     }
 
     async fn write_batch(&mut self, partition: usize, record_batch: RecordBatch) -> Result<()> {
@@ -626,6 +757,7 @@ impl CompactedFileWriter {
             max_routing_key: stats.max_routing_key,
             max_timestamp_micros: to_micros(stats.max_timestamp),
             generation,
+            min_timestamp_micros: to_micros(stats.min_timestamp),
         })
     }
 }
@@ -739,6 +871,7 @@ impl TableEpochCheckpointer for ExpiringTimeKeyTableCheckpointer {
                 max_routing_key: stats.max_routing_key,
                 max_timestamp_micros: to_micros(stats.max_timestamp),
                 generation: 0,
+                min_timestamp_micros: to_micros(stats.min_timestamp),
             };
             files.push(file)
         }
@@ -788,14 +921,16 @@ impl ExpiringTimeKeyTableCheckpointer {
 #[derive(Debug)]
 pub struct ExpiringTimeKeyView {
     parent: ExpiringTimeKeyTable,
-    flushed_batches_by_max_timestamp: BTreeMap<SystemTime, Vec<RecordBatch>>,
+    // batches that have been sent to the checkpointer, which are spilled to disk if the task is
+    // over its memory budget
+    flushed_batches_by_max_timestamp: SpillableMap<SystemTime>,
     batches_to_flush: BTreeMap<SystemTime, Vec<RecordBatch>>,
     state_tx: Sender<StateMessage>,
 }
 
 impl ExpiringTimeKeyView {
     pub async fn flush(&mut self, watermark: Option<SystemTime>) -> Result<()> {
-        while let Some((max_timestamp, mut batches)) = self.batches_to_flush.pop_first() {
+        while let Some((max_timestamp, batches)) = self.batches_to_flush.pop_first() {
             if watermark
                 .map(|watermark| max_timestamp < watermark - self.parent.retention)
                 .unwrap_or(false)
@@ -811,16 +946,15 @@ impl ExpiringTimeKeyView {
                     .await?;
             }
             self.flushed_batches_by_max_timestamp
-                .entry(max_timestamp)
-                .or_default()
-                .append(&mut batches);
+                .extend(max_timestamp, batches);
         }
         if let Some(watermark) = watermark {
             let cutoff = watermark - self.parent.retention;
-            self.flushed_batches_by_max_timestamp =
-                self.flushed_batches_by_max_timestamp.split_off(&cutoff);
+            self.flushed_batches_by_max_timestamp.remove_before(&cutoff);
         }
-        Ok(())
+        self.flushed_batches_by_max_timestamp
+            .spill_if_needed()
+            .await
     }
 
     pub fn insert(&mut self, max_timestamp: SystemTime, batch: RecordBatch) {
@@ -830,44 +964,58 @@ impl ExpiringTimeKeyView {
             .push(batch);
     }
 
-    pub fn all_batches_for_watermark(
+    pub async fn all_batches_for_watermark(
         &self,
         watermark: Option<SystemTime>,
-    ) -> impl Iterator<Item = (&SystemTime, &Vec<RecordBatch>)> {
+    ) -> Result<Vec<(SystemTime, Vec<RecordBatch>)>> {
         // TODO: decide how to manage hash range ownership. Previously this was done by iterating over the contents of the record batch.
         // Should we use statistics?
         let cutoff = watermark
             .map(|watermark| watermark - self.parent.retention)
             .unwrap_or_else(|| SystemTime::UNIX_EPOCH);
         debug!("CUTOFF IS {}", print_time(cutoff));
-        let flushed_range = self.flushed_batches_by_max_timestamp.range(cutoff..);
-        let buffered_range = self.batches_to_flush.range(cutoff..);
-        flushed_range.chain(buffered_range)
+        let mut result = vec![];
+        for timestamp in self
+            .flushed_batches_by_max_timestamp
+            .keys()
+            .filter(|t| **t >= cutoff)
+        {
+            result.push((
+                *timestamp,
+                self.flushed_batches_by_max_timestamp
+                    .read(timestamp)
+                    .await?,
+            ));
+        }
+        for (timestamp, batches) in self.batches_to_flush.range(cutoff..) {
+            result.push((*timestamp, batches.clone()));
+        }
+        Ok(result)
     }
 
-    pub fn expire_timestamp(&mut self, timestamp: SystemTime) -> Vec<RecordBatch> {
-        let flushed_batches = self.flushed_batches_by_max_timestamp.remove(&timestamp);
+    pub async fn expire_timestamp(&mut self, timestamp: SystemTime) -> Result<Vec<RecordBatch>> {
+        let flushed_batches = self
+            .flushed_batches_by_max_timestamp
+            .remove(&timestamp)
+            .await?;
         let buffered_batches = self.batches_to_flush.remove(&timestamp);
-        match (flushed_batches, buffered_batches) {
+        Ok(match (flushed_batches, buffered_batches) {
             (None, None) => vec![],
             (None, Some(batches)) | (Some(batches), None) => batches,
             (Some(mut flushed_batches), Some(mut buffered_batches)) => {
                 flushed_batches.append(&mut buffered_batches);
                 flushed_batches
             }
-        }
+        })
     }
 
     pub async fn flush_timestamp(&mut self, bin_start: SystemTime) -> Result<()> {
         let Some(batches_to_flush) = self.batches_to_flush.remove(&bin_start) else {
             return Ok(());
         };
-        let flushed_vec = self
-            .flushed_batches_by_max_timestamp
-            .entry(bin_start)
-            .or_default();
         for batch in batches_to_flush {
-            flushed_vec.push(batch.clone());
+            self.flushed_batches_by_max_timestamp
+                .push(bin_start, batch.clone());
             self.state_tx
                 .send(StateMessage::TableData {
                     table: self.parent.table_name.to_string(),
@@ -875,13 +1023,15 @@ impl ExpiringTimeKeyView {
                 })
                 .await?;
         }
-        Ok(())
+        self.flushed_batches_by_max_timestamp
+            .spill_if_needed()
+            .await
     }
 
     pub fn get_min_time(&self) -> Option<SystemTime> {
         match (
             self.batches_to_flush.keys().next(),
-            self.flushed_batches_by_max_timestamp.keys().next(),
+            self.flushed_batches_by_max_timestamp.first_key(),
         ) {
             (None, None) => None,
             (None, Some(time)) | (Some(time), None) => Some(*time),
@@ -890,11 +1040,94 @@ impl ExpiringTimeKeyView {
     }
 }
 
+#[async_trait::async_trait]
+impl QueryableView for ExpiringTimeKeyView {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn key_columns(&self, key: &Map<String, JsonValue>) -> Result<Option<Vec<ArrayRef>>> {
+        let schema = self.parent.schema.memory_schema();
+        match &schema.key_indices {
+            Some(key_indices) => Ok(Some(decode_key(&schema.schema, key_indices, key)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn query(
+        &mut self,
+        key: Option<&Map<String, JsonValue>>,
+        limit: usize,
+    ) -> Result<RecordBatch> {
+        let schema = self.parent.schema.memory_schema();
+        let key_filter = match key {
+            Some(key) => {
+                let Some(key_indices) = &schema.key_indices else {
+                    bail!("table '{}' is not keyed", self.parent.table_name);
+                };
+                let converter = schema.converter(false)?;
+                let key_row =
+                    converter.convert_columns(&decode_key(&schema.schema, key_indices, key)?)?;
+                Some((key_indices, converter, key_row))
+            }
+            None => None,
+        };
+
+        // spilled batches are read one timestamp at a time, so that we only go to disk for as much
+        // as the limit needs
+        let flushed: Vec<_> = self
+            .flushed_batches_by_max_timestamp
+            .keys()
+            .cloned()
+            .collect();
+        let buffered: Vec<_> = self.batches_to_flush.keys().cloned().collect();
+
+        let mut batches = vec![];
+        let mut rows = 0;
+        'outer: for (timestamp, is_flushed) in flushed
+            .into_iter()
+            .map(|t| (t, true))
+            .chain(buffered.into_iter().map(|t| (t, false)))
+        {
+            let group = if is_flushed {
+                self.flushed_batches_by_max_timestamp
+                    .read(&timestamp)
+                    .await?
+            } else {
+                self.batches_to_flush[&timestamp].clone()
+            };
+            for batch in group {
+                if rows >= limit {
+                    break 'outer;
+                }
+                let batch = match &key_filter {
+                    Some((key_indices, converter, key_row)) => {
+                        let key_batch = batch.project(key_indices)?;
+                        let key_rows =
+                            converter.convert_all_columns(key_batch.columns(), batch.num_rows())?;
+                        let matches: BooleanArray = key_rows
+                            .iter()
+                            .map(|row| Some(row == key_row.row()))
+                            .collect();
+                        filter_record_batch(&batch, &matches)?
+                    }
+                    None => batch,
+                };
+                rows += batch.num_rows();
+                batches.push(batch);
+            }
+        }
+
+        let batch = concat_batches(&schema.schema, &batches)?;
+        Ok(batch.slice(0, batch.num_rows().min(limit)))
+    }
+}
+
 #[derive(Debug)]
 pub struct KeyTimeView {
     key_converter: Converter,
     parent: ExpiringTimeKeyTable,
-    keyed_data: HashMap<Vec<u8>, BatchData>,
+    keyed_data: SpillableMap<Vec<u8>>,
     schema: ArroyoSchemaRef,
     value_schema: ArroyoSchemaRef,
     // indices of schema that aren't keys, used for projection
@@ -902,14 +1135,12 @@ pub struct KeyTimeView {
     state_tx: Sender<StateMessage>,
 }
 
-#[derive(Debug)]
-enum BatchData {
-    SingleBatch(RecordBatch),
-    BatchVec(Vec<RecordBatch>),
-}
-
 impl KeyTimeView {
-    fn new(parent: ExpiringTimeKeyTable, state_tx: Sender<StateMessage>) -> Result<Self> {
+    fn new(
+        parent: ExpiringTimeKeyTable,
+        state_tx: Sender<StateMessage>,
+        memory_budget: MemoryBudgetRef,
+    ) -> Result<Self> {
         let schema = parent.schema.memory_schema();
         let key_converter = schema.converter(false)?;
         let value_schema = Arc::new(schema.schema_without_keys()?);
@@ -917,7 +1148,7 @@ impl KeyTimeView {
         Ok(Self {
             key_converter,
             parent,
-            keyed_data: HashMap::new(),
+            keyed_data: SpillableMap::new(memory_budget),
             schema,
             value_indices,
             value_schema,
@@ -925,21 +1156,19 @@ impl KeyTimeView {
         })
     }
 
-    pub fn get_batch(&mut self, row: &[u8]) -> Result<Option<&RecordBatch>> {
-        if !self.keyed_data.contains_key(row) {
+    pub async fn get_batch(&mut self, row: &[u8]) -> Result<Option<RecordBatch>> {
+        let key = row.to_vec();
+        let Some(batches) = self.keyed_data.get(&key).await? else {
             return Ok(None);
-        }
-        let Some(value) = self.keyed_data.get_mut(row) else {
-            unreachable!("just checked")
         };
-        if let BatchData::BatchVec(batches) = value {
-            let coalesced_batches = concat_batches(&self.value_schema.schema, batches.iter())?;
-            *value = BatchData::SingleBatch(coalesced_batches);
+        if let [batch] = batches {
+            return Ok(Some(batch.clone()));
         }
-        let Some(BatchData::SingleBatch(single_batch)) = self.keyed_data.get(row) else {
-            unreachable!("just inserted")
-        };
-        Ok(Some(single_batch))
+        // coalesce the batches for the key so that future lookups don't need to
+        let coalesced_batch = concat_batches(&self.value_schema.schema, batches)?;
+        self.keyed_data.remove(&key).await?;
+        self.keyed_data.push(key, coalesced_batch.clone());
+        Ok(Some(coalesced_batch))
     }
 
     pub async fn write_batch_to_state(&mut self, batch: RecordBatch) -> Result<()> {
@@ -959,10 +1188,10 @@ impl KeyTimeView {
                 data: TableData::RecordBatch(batch.clone()),
             })
             .await?;
-        Ok(self.insert_internal(batch)?)
+        self.insert_internal(batch).await
     }
 
-    fn insert_internal(&mut self, batch: RecordBatch) -> Result<Vec<OwnedRow>> {
+    async fn insert_internal(&mut self, batch: RecordBatch) -> Result<Vec<OwnedRow>> {
         let sorted_batch = self.schema.sort(batch, false)?;
         let value_batch = sorted_batch.project(&self.value_indices)?;
         let mut rows = vec![];
@@ -978,31 +1207,83 @@ impl KeyTimeView {
                     .to_vec()
             };
             let key_row = self.key_converter.convert_columns(&key_columns)?;
-            let contents = self.keyed_data.get_mut(key_row.as_ref());
-            rows.push(key_row.clone());
-            let batch = match contents {
-                Some(BatchData::BatchVec(vec)) => {
-                    vec.push(value_batch);
-                    continue;
-                }
-                None => {
-                    self.keyed_data.insert(
-                        key_row.as_ref().to_vec(),
-                        BatchData::SingleBatch(value_batch),
-                    );
-                    continue;
-                }
-                Some(BatchData::SingleBatch(single_batch)) => single_batch.clone(),
-            };
-            self.keyed_data.insert(
-                key_row.as_ref().to_vec(),
-                BatchData::BatchVec(vec![batch, value_batch]),
-            );
+            self.keyed_data.push(key_row.as_ref().to_vec(), value_batch);
+            rows.push(key_row);
         }
+        self.keyed_data.spill_if_needed().await?;
         Ok(rows)
     }
 }
 
+#[async_trait::async_trait]
+impl QueryableView for KeyTimeView {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn key_columns(&self, key: &Map<String, JsonValue>) -> Result<Option<Vec<ArrayRef>>> {
+        match &self.schema.key_indices {
+            Some(key_indices) => Ok(Some(decode_key(&self.schema.schema, key_indices, key)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn query(
+        &mut self,
+        key: Option<&Map<String, JsonValue>>,
+        limit: usize,
+    ) -> Result<RecordBatch> {
+        let key_indices = self.schema.key_indices.clone().unwrap_or_default();
+        let schema = Arc::new(Schema::new(
+            key_indices
+                .iter()
+                .map(|i| self.schema.schema.field(*i).clone())
+                .chain(
+                    self.value_schema
+                        .schema
+                        .fields()
+                        .iter()
+                        .map(|f| f.as_ref().clone()),
+                )
+                .collect::<Vec<_>>(),
+        ));
+
+        let keys: Vec<Vec<u8>> = match key {
+            Some(key) => {
+                let key_row = self.key_converter.convert_columns(&decode_key(
+                    &self.schema.schema,
+                    &key_indices,
+                    key,
+                )?)?;
+                vec![key_row.as_ref().to_vec()]
+            }
+            None => self.keyed_data.keys().cloned().collect(),
+        };
+
+        let mut batches = vec![];
+        let mut rows = 0;
+        for key_row in keys {
+            if rows >= limit {
+                break;
+            }
+            for value_batch in self.keyed_data.read(&key_row).await? {
+                if rows >= limit {
+                    break;
+                }
+                let mut columns =
+                    self.key_converter
+                        .convert_raw_rows(vec![key_row.as_slice(); value_batch.num_rows()])?;
+                columns.extend(value_batch.columns().iter().cloned());
+                batches.push(RecordBatch::try_new(schema.clone(), columns)?);
+                rows += value_batch.num_rows();
+            }
+        }
+
+        let batch = concat_batches(&schema, &batches)?;
+        Ok(batch.slice(0, batch.num_rows().min(limit)))
+    }
+}
+
 #[derive(Debug)]
 pub struct LastKeyValueView {
     parent: ExpiringTimeKeyTable,
@@ -1010,16 +1291,112 @@ pub struct LastKeyValueView {
     value_converter: Converter,
     value_indices: Vec<usize>,
     // indices of schema that aren't keys, used for projection
-    backing_map: HashMap<Vec<u8>, Value>,
-    expirations: BTreeMap<SystemTime, HashSet<Vec<u8>>>,
+    store: Box<dyn LastValueStore>,
     state_tx: Sender<StateMessage>,
     key_indices: Vec<usize>,
 }
-#[derive(Debug)]
-struct Value {
-    value_row_bytes: Vec<u8>,
-    timestamp: SystemTime,
-    generation: u64,
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub(crate) struct LastValue {
+    pub(crate) value_row_bytes: Vec<u8>,
+    pub(crate) timestamp: SystemTime,
+    pub(crate) generation: u64,
+}
+
+/// Holds the latest value for each key of a [`LastKeyValueView`], indexed by timestamp so that
+/// expired keys can be found
+pub(crate) trait LastValueStore: Debug + Send {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Cow<'_, LastValue>>>;
+
+    /// Sets the value for `key`, where `previous_timestamp` is the timestamp of the value it
+    /// replaces, if any
+    fn put(
+        &mut self,
+        key: &[u8],
+        value: LastValue,
+        previous_timestamp: Option<SystemTime>,
+    ) -> Result<()>;
+
+    fn earliest_timestamp(&self) -> Result<Option<SystemTime>>;
+
+    /// Removes and returns every entry with a timestamp before `cutoff`
+    fn remove_before(&mut self, cutoff: SystemTime) -> Result<Vec<(Vec<u8>, LastValue)>>;
+
+    /// Returns up to `limit` entries, in no particular order
+    fn entries(&self, limit: usize) -> Result<Vec<(Vec<u8>, LastValue)>>;
+}
+
+/// A [`LastValueStore`] that keeps everything in memory, used by the parquet backend
+#[derive(Debug, Default)]
+pub(crate) struct MemoryLastValueStore {
+    values: HashMap<Vec<u8>, LastValue>,
+    expirations: BTreeMap<SystemTime, HashSet<Vec<u8>>>,
+}
+
+impl LastValueStore for MemoryLastValueStore {
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Cow<'_, LastValue>>> {
+        Ok(self.values.get(key).map(Cow::Borrowed))
+    }
+
+    fn put(
+        &mut self,
+        key: &[u8],
+        value: LastValue,
+        previous_timestamp: Option<SystemTime>,
+    ) -> Result<()> {
+        if previous_timestamp != Some(value.timestamp) {
+            if let Some(previous_timestamp) = previous_timestamp {
+                if let Some(keys) = self.expirations.get_mut(&previous_timestamp) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.expirations.remove(&previous_timestamp);
+                    }
+                }
+            }
+            self.expirations
+                .entry(value.timestamp)
+                .or_default()
+                .insert(key.to_vec());
+        }
+        self.values.insert(key.to_vec(), value);
+        Ok(())
+    }
+
+    fn earliest_timestamp(&self) -> Result<Option<SystemTime>> {
+        Ok(self.expirations.first_key_value().map(|(t, _)| *t))
+    }
+
+    fn remove_before(&mut self, cutoff: SystemTime) -> Result<Vec<(Vec<u8>, LastValue)>> {
+        let mut to_delete = self.expirations.split_off(&cutoff);
+        mem::swap(&mut self.expirations, &mut to_delete);
+        Ok(to_delete
+            .into_values()
+            .flatten()
+            .filter_map(|key| {
+                let value = self.values.remove(&key)?;
+                Some((key, value))
+            })
+            .collect())
+    }
+
+    fn entries(&self, limit: usize) -> Result<Vec<(Vec<u8>, LastValue)>> {
+        Ok(self
+            .values
+            .iter()
+            .take(limit)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
 }
 
 impl LastKeyValueView {
@@ -1035,14 +1412,18 @@ impl LastKeyValueView {
             .into_iter()
             .filter(|index| *index != generation_index)
             .collect();
-        let backing_map = HashMap::new();
-        let expirations = BTreeMap::new();
+        let store: Box<dyn LastValueStore> = match config().state_backend {
+            StateBackendType::Parquet => Box::<MemoryLastValueStore>::default(),
+            StateBackendType::Kv => Box::new(KvLastValueStore::open(
+                &parent.task_info,
+                &parent.table_name,
+            )?),
+        };
         Ok(Self {
             key_converter,
             value_converter,
             value_indices,
-            backing_map,
-            expirations,
+            store,
             parent,
             state_tx,
             key_indices: schema.key_indices.as_ref().unwrap().clone(),
@@ -1056,30 +1437,26 @@ impl LastKeyValueView {
         &self,
         batch: &RecordBatch,
     ) -> Result<Option<(RecordBatch, BooleanArray)>> {
-        if self.backing_map.is_empty() {
+        if self.store.is_empty() {
             return Ok(None);
         }
         let key_batch: RecordBatch = batch.project(&self.key_indices)?;
         let key_rows = self
             .key_converter
             .convert_all_columns(key_batch.columns(), key_batch.num_rows())?;
-        let capacity = batch.num_rows().min(self.backing_map.len());
+        let capacity = batch.num_rows().min(self.store.len());
         let mut prior_values = Vec::with_capacity(capacity);
         let mut prior_timestamp_builder = TimestampNanosecondArray::builder(capacity);
         let mut prior_row_filter = BooleanArray::builder(capacity);
         for i in 0..batch.num_rows() {
-            match self.backing_map.get(key_rows.row(i).as_ref()) {
+            match self.store.get(key_rows.row(i).as_ref())? {
                 None => {
                     prior_row_filter.append_value(false);
                 }
-                Some(Value {
-                    value_row_bytes,
-                    timestamp,
-                    generation: _,
-                }) => {
+                Some(value) => {
                     prior_row_filter.append_value(true);
-                    prior_timestamp_builder.append_value(to_nanos(*timestamp) as i64);
-                    prior_values.push(value_row_bytes.as_slice());
+                    prior_timestamp_builder.append_value(to_nanos(value.timestamp) as i64);
+                    prior_values.push(value);
                 }
             }
         }
@@ -1089,7 +1466,12 @@ impl LastKeyValueView {
             return Ok(None);
         }
         let mut columns = filtered_key_batch.columns().to_vec();
-        let value_columns = self.value_converter.convert_raw_rows(prior_values)?;
+        let value_columns = self.value_converter.convert_raw_rows(
+            prior_values
+                .iter()
+                .map(|value| value.value_row_bytes.as_slice())
+                .collect(),
+        )?;
         columns.extend(value_columns);
         columns.push(Arc::new(prior_timestamp_builder.finish()));
 
@@ -1170,84 +1552,454 @@ impl LastKeyValueView {
         timestamp: SystemTime,
         generation: Option<u64>,
     ) -> Result<(SystemTime, u64)> {
-        match self.backing_map.get_mut(key_row) {
-            None => {
-                let generation = generation.unwrap_or_default();
-                self.backing_map.insert(
-                    key_row.to_vec(),
-                    Value {
-                        value_row_bytes: value_row.to_vec(),
-                        timestamp,
-                        generation,
-                    },
-                );
-                self.expirations
-                    .entry(timestamp)
-                    .or_default()
-                    .insert(key_row.to_vec());
-                Ok((timestamp, generation))
-            }
-            Some(Value {
-                value_row_bytes: old_value,
-                timestamp: existing_timestamp,
-                generation: current_generation,
-            }) => {
-                match generation {
+        let existing = self
+            .store
+            .get(key_row)?
+            .map(|value| (value.timestamp, value.generation));
+
+        let (timestamp, generation) = match existing {
+            None => (timestamp, generation.unwrap_or_default()),
+            Some((existing_timestamp, current_generation)) => {
+                let generation = match generation {
                     Some(generation) => {
                         // this handles out of order backfills, we only want the largest generation.
-                        if generation < *current_generation {
-                            return Ok((*existing_timestamp, *current_generation));
+                        if generation < current_generation {
+                            return Ok((existing_timestamp, current_generation));
                         }
-                        *current_generation = generation;
                         generation
                     }
                     // if it is new data, bump the generation by 1.
-                    None => {
-                        *current_generation += 1;
-                        *current_generation
-                    }
+                    None => current_generation + 1,
                 };
-
-                if *existing_timestamp < timestamp {
-                    self.expirations
-                        .get_mut(existing_timestamp)
-                        .unwrap()
-                        .remove(key_row);
-                    self.expirations
-                        .entry(timestamp)
-                        .or_default()
-                        .insert(key_row.to_vec());
-                    *existing_timestamp = timestamp;
-                }
-                *old_value = value_row.to_vec();
-                Ok((*existing_timestamp, *current_generation))
+                (existing_timestamp.max(timestamp), generation)
             }
-        }
+        };
+
+        self.store.put(
+            key_row,
+            LastValue {
+                value_row_bytes: value_row.to_vec(),
+                timestamp,
+                generation,
+            },
+            existing.map(|(existing_timestamp, _)| existing_timestamp),
+        )?;
+        Ok((timestamp, generation))
     }
 
-    pub fn would_expire(&mut self, watermark: Option<SystemTime>) -> bool {
+    pub fn would_expire(&mut self, watermark: Option<SystemTime>) -> Result<bool> {
         let Some(watermark) = watermark else {
-            return false;
+            return Ok(false);
         };
         let cutoff = watermark - self.parent.retention;
-        let Some((earliest_timestamp, _value)) = self.expirations.first_key_value() else {
-            return false;
+        let Some(earliest_timestamp) = self.store.earliest_timestamp()? else {
+            return Ok(false);
         };
-        *earliest_timestamp < cutoff
+        Ok(earliest_timestamp < cutoff)
     }
 
     pub fn expire(&mut self, watermark: Option<SystemTime>) -> Result<()> {
+        self.expire_entries(watermark)?;
+        Ok(())
+    }
+
+    /// Expires entries like [`Self::expire`], returning the values that were removed as a batch
+    /// of `schema`, which should have the key columns, followed by the value columns and the
+    /// timestamp
+    pub fn expire_returning(
+        &mut self,
+        watermark: Option<SystemTime>,
+        schema: SchemaRef,
+    ) -> Result<Option<RecordBatch>> {
+        let expired = self.expire_entries(watermark)?;
+        if expired.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.entries_to_batch(&expired, schema)?))
+    }
+
+    fn entries_to_batch(
+        &self,
+        entries: &[(Vec<u8>, LastValue)],
+        schema: SchemaRef,
+    ) -> Result<RecordBatch> {
+        let mut timestamps = TimestampNanosecondArray::builder(entries.len());
+        for (_, value) in entries {
+            timestamps.append_value(to_nanos(value.timestamp) as i64);
+        }
+
+        let mut columns = self
+            .key_converter
+            .convert_raw_rows(entries.iter().map(|(key, _)| key.as_slice()).collect())?;
+        columns.extend(
+            self.value_converter.convert_raw_rows(
+                entries
+                    .iter()
+                    .map(|(_, value)| value.value_row_bytes.as_slice())
+                    .collect(),
+            )?,
+        );
+        columns.push(Arc::new(timestamps.finish()));
+
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+
+    fn expire_entries(
+        &mut self,
+        watermark: Option<SystemTime>,
+    ) -> Result<Vec<(Vec<u8>, LastValue)>> {
         let Some(watermark) = watermark else {
-            return Ok(());
+            return Ok(vec![]);
         };
-        let cutoff = watermark - self.parent.retention;
-        let mut to_delete = self.expirations.split_off(&cutoff);
-        mem::swap(&mut self.expirations, &mut to_delete);
-        for keys in to_delete.values() {
-            for key in keys {
-                self.backing_map.remove(key);
+        self.store.remove_before(watermark - self.parent.retention)
+    }
+}
+
+#[async_trait::async_trait]
+impl QueryableView for LastKeyValueView {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn key_columns(&self, key: &Map<String, JsonValue>) -> Result<Option<Vec<ArrayRef>>> {
+        let schema = self.parent.schema.memory_schema();
+        Ok(Some(decode_key(&schema.schema, &self.key_indices, key)?))
+    }
+
+    async fn query(
+        &mut self,
+        key: Option<&Map<String, JsonValue>>,
+        limit: usize,
+    ) -> Result<RecordBatch> {
+        let memory_schema = self.parent.schema.memory_schema();
+        let indices: Vec<_> = self
+            .key_indices
+            .iter()
+            .chain(self.value_indices.iter())
+            .chain(std::iter::once(&memory_schema.timestamp_index))
+            .cloned()
+            .collect();
+        let schema = Arc::new(memory_schema.schema.project(&indices)?);
+
+        let entries: Vec<_> = match key {
+            Some(key) => {
+                let key_row = self.key_converter.convert_columns(&decode_key(
+                    &memory_schema.schema,
+                    &self.key_indices,
+                    key,
+                )?)?;
+                self.store
+                    .get(key_row.as_ref())?
+                    .map(|value| (key_row.as_ref().to_vec(), value.into_owned()))
+                    .into_iter()
+                    .collect()
             }
+            None => self.store.entries(limit)?,
+        };
+
+        self.entries_to_batch(&entries, schema)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow_array::{Int64Array, StringArray};
+    use arrow_schema::{DataType, Field, TimeUnit};
+    use arroyo_storage::StorageProvider;
+    use arroyo_types::get_test_task_info;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn secs(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn schema() -> ArroyoSchema {
+        ArroyoSchema::new_keyed(
+            Arc::new(Schema::new(vec![
+                Field::new("k", DataType::Utf8, false),
+                Field::new("v", DataType::Int64, false),
+                Field::new(
+                    "_timestamp",
+                    DataType::Timestamp(TimeUnit::Nanosecond, None),
+                    false,
+                ),
+            ])),
+            2,
+            vec![0],
+        )
+    }
+
+    fn table_config(generational: bool) -> ExpiringKeyedTimeTableConfig {
+        ExpiringKeyedTimeTableConfig {
+            table_name: "t".to_string(),
+            description: "test".to_string(),
+            retention_micros: Duration::from_secs(10).as_micros() as u64,
+            generational,
+            schema: Some(schema().into()),
         }
-        Ok(())
+    }
+
+    async fn table(generational: bool) -> ExpiringTimeKeyTable {
+        let dir = std::env::temp_dir().join(format!(
+            "arroyo-expiring-table-test-{}",
+            rand::random::<u64>()
+        ));
+        let storage = StorageProvider::for_url(&format!("file://{}", dir.to_str().unwrap()))
+            .await
+            .unwrap();
+        ExpiringTimeKeyTable::from_config(
+            table_config(generational),
+            Arc::new(get_test_task_info()),
+            Arc::new(storage),
+            None,
+        )
+        .unwrap()
+    }
+
+    fn batch(rows: &[(&str, i64, u64)]) -> RecordBatch {
+        RecordBatch::try_new(
+            schema().schema,
+            vec![
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.0))),
+                Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.1))),
+                Arc::new(TimestampNanosecondArray::from_iter_values(
+                    rows.iter().map(|r| to_nanos(secs(r.2)) as i64),
+                )),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn keys(batch: &RecordBatch) -> Vec<&str> {
+        batch
+            .column(0)
+            .as_string::<i32>()
+            .iter()
+            .flatten()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_last_key_value_expiration() {
+        let table = table(true).await;
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        let mut view = table.get_last_key_value_view(tx, None).await.unwrap();
+
+        view.insert_batch(batch(&[("a", 1, 5), ("b", 2, 20)]))
+            .await
+            .unwrap();
+        view.insert_batch(batch(&[("a", 3, 8)])).await.unwrap();
+
+        // the retention is 10 seconds, and a was last updated at 8
+        assert!(!view.would_expire(Some(secs(15))).unwrap());
+        assert!(view.would_expire(Some(secs(20))).unwrap());
+
+        // the expired entries are returned with their latest value, so they can be retracted
+        let expired = view
+            .expire_returning(Some(secs(20)), schema().schema)
+            .unwrap()
+            .unwrap();
+        assert_eq!(keys(&expired), vec!["a"]);
+        assert_eq!(
+            expired
+                .column(1)
+                .as_primitive::<arrow_array::types::Int64Type>()
+                .value(0),
+            3
+        );
+        assert_eq!(
+            expired
+                .column(2)
+                .as_primitive::<TimestampNanosecondType>()
+                .value(0),
+            to_nanos(secs(8)) as i64
+        );
+
+        let (current, filter) = view
+            .get_current_matching_values(&batch(&[("a", 0, 0), ("b", 0, 0)]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(keys(&current), vec!["b"]);
+        assert_eq!(filter, BooleanArray::from(vec![false, true]));
+
+        view.expire(Some(secs(40))).unwrap();
+        assert!(!view.would_expire(Some(secs(40))).unwrap());
+        assert!(view
+            .expire_returning(Some(secs(40)), schema().schema)
+            .unwrap()
+            .is_none());
+        assert!(view
+            .get_current_matching_values(&batch(&[("b", 0, 0)]))
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compaction_drops_expired_rows() {
+        let table = table(false).await;
+        let mut checkpointer = table.epoch_checkpointer(1, None).unwrap();
+        checkpointer
+            .insert_data(TableData::RecordBatch(batch(&[("a", 1, 5), ("b", 2, 100)])))
+            .await
+            .unwrap();
+        let (metadata, _) = checkpointer
+            .finish(&CheckpointMessage {
+                epoch: 1,
+                time: SystemTime::now(),
+                watermark: Some(secs(100)),
+                then_stop: false,
+                unaligned: false,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(metadata.files.len(), 1);
+        assert_eq!(metadata.files[0].min_timestamp_micros, to_micros(secs(5)));
+
+        let compaction_config = CompactionConfig {
+            storage_provider: table.storage_provider.clone(),
+            compact_generations: HashSet::from([0]),
+            min_compaction_epochs: 2,
+            reuse_file_bytes: usize::MAX,
+        };
+        let operator_metadata = |watermark| OperatorMetadata {
+            job_id: table.task_info.job_id.clone(),
+            operator_id: table.task_info.operator_id.clone(),
+            epoch: 2,
+            min_watermark: Some(to_micros(secs(watermark))),
+            max_watermark: Some(to_micros(secs(watermark))),
+            parallelism: 1,
+        };
+        let checkpoint = ExpiringKeyedTimeTableCheckpointMetadata {
+            files: metadata.files,
+        };
+
+        // with a cutoff of 40 most of the file is still live, so it's left alone
+        assert!(ExpiringTimeKeyTable::compact_data(
+            table_config(false),
+            &compaction_config,
+            &operator_metadata(50),
+            checkpoint.clone(),
+        )
+        .await
+        .unwrap()
+        .is_none());
+
+        // with a cutoff of 60 most of the file has expired, so it's rewritten without a's row
+        let compacted = ExpiringTimeKeyTable::compact_data(
+            table_config(false),
+            &compaction_config,
+            &operator_metadata(70),
+            checkpoint,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(compacted.files.len(), 1);
+        let file = &compacted.files[0];
+        assert_eq!(file.generation, 0);
+        assert_eq!(file.min_timestamp_micros, to_micros(secs(100)));
+
+        let contents = table.storage_provider.get(&file.file).await.unwrap();
+        let batches: Vec<_> = ParquetRecordBatchReaderBuilder::try_new(contents)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let rows: Vec<_> = batches.iter().flat_map(keys).collect();
+        assert_eq!(rows, vec!["b"]);
+
+        // the rewritten file is no longer mostly expired, so it isn't rewritten again
+        assert!(ExpiringTimeKeyTable::compact_data(
+            table_config(false),
+            &compaction_config,
+            &operator_metadata(70),
+            compacted,
+        )
+        .await
+        .unwrap()
+        .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compaction_reuses_large_files() {
+        let table = table(false).await;
+        let checkpoint = |epoch| CheckpointMessage {
+            epoch,
+            time: SystemTime::now(),
+            watermark: Some(secs(60)),
+            then_stop: false,
+            unaligned: false,
+        };
+
+        let mut checkpointer = table.epoch_checkpointer(1, None).unwrap();
+        checkpointer
+            .insert_data(TableData::RecordBatch(batch(&[("a", 1, 50)])))
+            .await
+            .unwrap();
+        let (first, _) = checkpointer.finish(&checkpoint(1)).await.unwrap().unwrap();
+
+        let mut checkpointer = table.epoch_checkpointer(2, Some(first)).unwrap();
+        checkpointer
+            .insert_data(TableData::RecordBatch(batch(&[("b", 2, 60)])))
+            .await
+            .unwrap();
+        let (second, _) = checkpointer.finish(&checkpoint(2)).await.unwrap().unwrap();
+        assert_eq!(second.files.len(), 2);
+
+        let operator_metadata = OperatorMetadata {
+            job_id: table.task_info.job_id.clone(),
+            operator_id: table.task_info.operator_id.clone(),
+            epoch: 2,
+            min_watermark: Some(to_micros(secs(60))),
+            max_watermark: Some(to_micros(secs(60))),
+            parallelism: 1,
+        };
+        let checkpoint = ExpiringKeyedTimeTableCheckpointMetadata {
+            files: second.files.clone(),
+        };
+        let compaction_config = |reuse_file_bytes| CompactionConfig {
+            storage_provider: table.storage_provider.clone(),
+            compact_generations: HashSet::from([0]),
+            min_compaction_epochs: 2,
+            reuse_file_bytes,
+        };
+
+        // small files are merged into a new file
+        let compacted = ExpiringTimeKeyTable::compact_data(
+            table_config(false),
+            &compaction_config(usize::MAX),
+            &operator_metadata,
+            checkpoint.clone(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(compacted.files.len(), 1);
+        assert!(second
+            .files
+            .iter()
+            .all(|f| f.file != compacted.files[0].file));
+
+        // while files over the size threshold are moved to the next generation without being
+        // rewritten
+        let compacted = ExpiringTimeKeyTable::compact_data(
+            table_config(false),
+            &compaction_config(0),
+            &operator_metadata,
+            checkpoint,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let mut files: Vec<_> = compacted.files.iter().map(|f| f.file.clone()).collect();
+        files.sort();
+        let mut original: Vec<_> = second.files.iter().map(|f| f.file.clone()).collect();
+        original.sort();
+        assert_eq!(files, original);
+        assert!(compacted.files.iter().all(|f| f.generation == 1));
     }
 }
@@ -1,13 +1,17 @@
 use crate::{CheckpointMessage, StateMessage, TableData};
 use anyhow::{anyhow, bail, Result};
-use arrow_array::{BinaryArray, RecordBatch};
-use arrow_schema::{DataType, Field, Schema};
+use arrow_array::cast::AsArray;
+use arrow_array::types::TimestampNanosecondType;
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, RecordBatch, StringArray, TimestampNanosecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
 use arroyo_rpc::grpc::rpc::{
     GlobalKeyedTableSubtaskCheckpointMetadata, GlobalKeyedTableTaskCheckpointMetadata,
     OperatorMetadata, TableEnum,
 };
 use arroyo_storage::StorageProviderRef;
-use arroyo_types::{to_micros, Data, Key, TaskInfoRef};
+use arroyo_types::{from_nanos, to_nanos, Data, Key, TaskInfoRef};
 use bincode::config;
 
 use once_cell::sync::Lazy;
@@ -19,21 +23,32 @@ use parquet::{
 };
 use tracing::info;
 
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::any::Any;
 use std::iter::Zip;
 
 use arroyo_rpc::grpc::rpc::GlobalKeyedTableConfig;
-use std::time::SystemTime;
 use std::{
     collections::{BTreeMap, HashMap},
     sync::Arc,
+    time::{Duration, SystemTime},
 };
 use tokio::sync::mpsc::Sender;
 
-use super::{table_checkpoint_path, CompactionConfig, Table, TableEpochCheckpointer};
+use super::{
+    table_checkpoint_path, CompactionConfig, QueryableView, Table, TableEpochCheckpointer,
+};
 static GLOBAL_KEY_VALUE_SCHEMA: Lazy<Arc<Schema>> = Lazy::new(|| {
     let fields = vec![
         Field::new("key", DataType::Binary, false), // non-nullable BinaryArray for 'key'
         Field::new("value", DataType::Binary, false), // non-nullable BinaryArray for 'value'
+        // when the value was last changed, used to expire entries; missing from older files
+        Field::new(
+            "updated_at",
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            true,
+        ),
     ];
     Arc::new(Schema::new(fields))
 });
@@ -44,6 +59,7 @@ pub struct GlobalKeyedTable {
     pub task_info: TaskInfoRef,
     storage_provider: StorageProviderRef,
     pub files: Vec<String>,
+    ttl: Option<Duration>,
 }
 
 impl GlobalKeyedTable {
@@ -72,18 +88,38 @@ impl GlobalKeyedTable {
         &self,
         state_tx: Sender<StateMessage>,
     ) -> anyhow::Result<GlobalKeyedView<K, V>> {
+        let now = SystemTime::now();
+        let cutoff = self.ttl.map(|ttl| now - ttl);
         let mut data = HashMap::new();
+        let mut updated_at = HashMap::new();
         for file in &self.files {
             let contents = self.storage_provider.get(file).await?;
             let reader = ParquetRecordBatchReaderBuilder::try_new(contents)?.build()?;
             for batch in reader {
-                for (key, value) in self.get_key_value_iterator(&batch?)? {
+                let batch = batch?;
+                // files written before entries could expire don't have update times, so they're
+                // treated as having just been updated
+                let updated_at_column = batch
+                    .column_by_name("updated_at")
+                    .map(|column| column.as_primitive::<TimestampNanosecondType>());
+                for (i, (key, value)) in self.get_key_value_iterator(&batch)?.enumerate() {
                     let key =
                         key.ok_or_else(|| anyhow!("unexpected null key from record batch"))?;
                     let value =
                         value.ok_or_else(|| anyhow!("unexpected null value from record batch"))?;
+                    let key: K = bincode::decode_from_slice(key, config::standard())?.0;
+                    if let Some(cutoff) = cutoff {
+                        let entry_updated_at = updated_at_column
+                            .filter(|column| column.is_valid(i))
+                            .map(|column| from_nanos(column.value(i) as u128))
+                            .unwrap_or(now);
+                        if entry_updated_at < cutoff {
+                            continue;
+                        }
+                        updated_at.insert(key.clone(), entry_updated_at);
+                    }
                     data.insert(
-                        bincode::decode_from_slice(key, config::standard())?.0,
+                        key,
                         bincode::decode_from_slice(value, config::standard())?.0,
                     );
                 }
@@ -92,6 +128,8 @@ impl GlobalKeyedTable {
         Ok(GlobalKeyedView {
             table_name: self.table_name.to_string(),
             data,
+            updated_at,
+            ttl: self.ttl,
             state_tx,
         })
     }
@@ -110,7 +148,7 @@ impl Table for GlobalKeyedTable {
     fn epoch_checkpointer(
         &self,
         epoch: u32,
-        _previous_metadata: Option<Self::TableSubtaskCheckpointMetadata>,
+        previous_metadata: Option<Self::TableSubtaskCheckpointMetadata>,
     ) -> Result<Self::Checkpointer> {
         Ok(Self::Checkpointer {
             table_name: self.table_name.clone(),
@@ -119,6 +157,9 @@ impl Table for GlobalKeyedTable {
             storage_provider: self.storage_provider.clone(),
             commit_data: None,
             latest_values: BTreeMap::new(),
+            ttl: self.ttl,
+            previous_file: previous_metadata
+                .and_then(|metadata| metadata.file.zip(metadata.content_digest)),
         })
     }
 
@@ -135,6 +176,7 @@ impl Table for GlobalKeyedTable {
             files: checkpoint_message
                 .map(|checkpoint| checkpoint.files)
                 .unwrap_or_default(),
+            ttl: (config.ttl_micros > 0).then(|| Duration::from_micros(config.ttl_micros)),
         })
     }
 
@@ -193,6 +235,18 @@ impl Table for GlobalKeyedTable {
     ) -> Result<std::collections::HashSet<String>> {
         Ok(checkpoint.files.into_iter().collect())
     }
+
+    fn relocate_files(
+        _config: Self::ConfigMessage,
+        mut checkpoint: Self::TableCheckpointMessage,
+        relocate: &dyn Fn(&str) -> String,
+    ) -> Result<Self::TableCheckpointMessage> {
+        for file in &mut checkpoint.files {
+            *file = relocate(file);
+        }
+        Ok(checkpoint)
+    }
+
     fn committing_data(
         config: Self::ConfigMessage,
         table_metadata: Self::TableCheckpointMessage,
@@ -228,8 +282,32 @@ pub struct GlobalKeyedCheckpointer {
     epoch: u32,
     task_info: TaskInfoRef,
     storage_provider: StorageProviderRef,
-    latest_values: BTreeMap<Vec<u8>, Vec<u8>>,
+    // keys and values along with when the value was last changed
+    latest_values: BTreeMap<Vec<u8>, (Vec<u8>, Option<SystemTime>)>,
     commit_data: Option<Vec<u8>>,
+    ttl: Option<Duration>,
+    // the file written by this subtask in the previous epoch and the digest of its contents
+    previous_file: Option<(String, Vec<u8>)>,
+}
+
+/// SHA-256 digest of the values that will be written to a checkpoint file; each field is length
+/// prefixed so that different entries can't produce the same input
+fn content_digest(values: &BTreeMap<Vec<u8>, (Vec<u8>, Option<SystemTime>)>) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for (key, (value, updated_at)) in values {
+        hasher.update((key.len() as u64).to_le_bytes());
+        hasher.update(key);
+        hasher.update((value.len() as u64).to_le_bytes());
+        hasher.update(value);
+        match updated_at {
+            Some(t) => {
+                hasher.update([1]);
+                hasher.update(to_nanos(*t).to_le_bytes());
+            }
+            None => hasher.update([0]),
+        }
+    }
+    hasher.finalize().to_vec()
 }
 
 #[async_trait::async_trait]
@@ -249,28 +327,60 @@ impl TableEpochCheckpointer for GlobalKeyedCheckpointer {
                 }
                 self.commit_data = Some(data);
             }
-            TableData::KeyedData { key, value } => {
-                self.latest_values.insert(key, value);
+            TableData::KeyedData {
+                key,
+                value,
+                updated_at,
+            } => {
+                self.latest_values.insert(key, (value, updated_at));
             }
         }
         Ok(())
     }
 
     async fn finish(
-        self,
+        mut self,
         _checkpoint: &CheckpointMessage,
     ) -> Result<Option<(Self::SubTableCheckpointMessage, usize)>> {
-        let _start_time = to_micros(SystemTime::now());
-        let (keys, values): (Vec<_>, Vec<_>) = self
-            .latest_values
-            .iter()
-            .map(|(k, v)| (k.as_slice(), v.as_slice()))
-            .unzip();
-        let key_array = BinaryArray::from_vec(keys);
-        let value_array = BinaryArray::from_vec(values);
+        if let Some(ttl) = self.ttl {
+            let cutoff = SystemTime::now() - ttl;
+            self.latest_values
+                .retain(|_, (_, updated_at)| !updated_at.is_some_and(|t| t < cutoff));
+        }
+
+        let content_digest = content_digest(&self.latest_values);
+
+        // if nothing has changed since the last epoch, reference the existing file rather than
+        // writing out an identical copy
+        if let Some((file, previous_digest)) = self.previous_file {
+            if previous_digest == content_digest {
+                return Ok(Some((
+                    GlobalKeyedTableSubtaskCheckpointMetadata {
+                        subtask_index: self.task_info.task_index as u32,
+                        commit_data: self.commit_data,
+                        file: Some(file),
+                        content_digest: Some(content_digest),
+                    },
+                    0,
+                )));
+            }
+        }
+
+        let key_array = BinaryArray::from_iter_values(self.latest_values.keys());
+        let value_array =
+            BinaryArray::from_iter_values(self.latest_values.values().map(|(value, _)| value));
+        let updated_at_array = TimestampNanosecondArray::from_iter(
+            self.latest_values
+                .values()
+                .map(|(_, updated_at)| updated_at.map(|t| to_nanos(t) as i64)),
+        );
         let batch = RecordBatch::try_new(
             GLOBAL_KEY_VALUE_SCHEMA.clone(),
-            vec![Arc::new(key_array), Arc::new(value_array)],
+            vec![
+                Arc::new(key_array),
+                Arc::new(value_array),
+                Arc::new(updated_at_array),
+            ],
         )?;
 
         let props = WriterProperties::builder()
@@ -292,12 +402,12 @@ impl TableEpochCheckpointer for GlobalKeyedCheckpointer {
             false,
         );
         self.storage_provider.put(&path, parquet_bytes).await?;
-        let _finish_time = to_micros(SystemTime::now());
         Ok(Some((
             GlobalKeyedTableSubtaskCheckpointMetadata {
                 subtask_index: self.task_info.task_index as u32,
                 commit_data: self.commit_data,
                 file: Some(path),
+                content_digest: Some(content_digest),
             },
             bytes as usize,
         )))
@@ -315,6 +425,8 @@ impl TableEpochCheckpointer for GlobalKeyedCheckpointer {
 pub struct GlobalKeyedView<K: Key, V: Data> {
     table_name: String,
     data: HashMap<K, V>,
+    updated_at: HashMap<K, SystemTime>,
+    ttl: Option<Duration>,
     state_tx: Sender<StateMessage>,
 }
 
@@ -323,23 +435,63 @@ impl<K: Key, V: Data> GlobalKeyedView<K, V> {
         Self {
             table_name,
             data,
+            updated_at: HashMap::new(),
+            ttl: None,
             state_tx,
         }
     }
+
     pub async fn insert(&mut self, key: K, value: V) {
+        let value_bytes = bincode::encode_to_vec(&value, config::standard()).unwrap();
+        let updated_at = self.ttl.map(|_| {
+            // operators re-insert their state every checkpoint, so only a changed value counts as
+            // an update for the purposes of expiration
+            let unchanged = self.data.get(&key).is_some_and(|current| {
+                bincode::encode_to_vec(current, config::standard()).unwrap() == value_bytes
+            });
+            match self.updated_at.get(&key) {
+                Some(updated_at) if unchanged => *updated_at,
+                _ => SystemTime::now(),
+            }
+        });
         self.state_tx
             .send(StateMessage::TableData {
                 table: self.table_name.clone(),
                 data: TableData::KeyedData {
                     key: bincode::encode_to_vec(&key, config::standard()).unwrap(),
-                    value: bincode::encode_to_vec(&value, config::standard()).unwrap(),
+                    value: value_bytes,
+                    updated_at,
                 },
             })
             .await
             .unwrap();
+        if let Some(updated_at) = updated_at {
+            self.updated_at.insert(key.clone(), updated_at);
+        }
         self.data.insert(key, value);
     }
 
+    /// Removes and returns the entries that haven't been updated within the table's TTL
+    pub fn expire(&mut self) -> Vec<(K, V)> {
+        let Some(ttl) = self.ttl else {
+            return vec![];
+        };
+        let cutoff = SystemTime::now() - ttl;
+        let expired: Vec<_> = self
+            .updated_at
+            .iter()
+            .filter(|(_, updated_at)| **updated_at < cutoff)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|key| {
+                self.updated_at.remove(&key);
+                self.data.remove(&key).map(|value| (key, value))
+            })
+            .collect()
+    }
+
     pub fn get_all(&self) -> &HashMap<K, V> {
         &self.data
     }
@@ -348,3 +500,236 @@ impl<K: Key, V: Data> GlobalKeyedView<K, V> {
         self.data.get(key)
     }
 }
+
+#[async_trait::async_trait]
+impl<K: Key, V: Data> QueryableView for GlobalKeyedView<K, V> {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn key_columns(&self, _key: &Map<String, Value>) -> Result<Option<Vec<ArrayRef>>> {
+        // global tables are not partitioned, so every subtask may hold a matching entry
+        Ok(None)
+    }
+
+    async fn query(
+        &mut self,
+        key: Option<&Map<String, Value>>,
+        limit: usize,
+    ) -> Result<RecordBatch> {
+        // the keys and values are arbitrary rust types, so they're returned in their debug format
+        let key = match key {
+            Some(key) => match (key.len(), key.get("key")) {
+                (1, Some(Value::String(s))) => Some(s.clone()),
+                (1, Some(v)) => Some(v.to_string()),
+                _ => bail!("keys for global tables must be of the form {{\"key\": <key>}}"),
+            },
+            None => None,
+        };
+
+        let (keys, values): (Vec<_>, Vec<_>) = self
+            .data
+            .iter()
+            .map(|(k, v)| (format!("{:?}", k), format!("{:?}", v)))
+            .filter(|(k, _)| key.as_ref().map(|key| key == k).unwrap_or(true))
+            .take(limit)
+            .unzip();
+
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("key", DataType::Utf8, false),
+                Field::new("value", DataType::Utf8, false),
+            ])),
+            vec![
+                Arc::new(StringArray::from(keys)),
+                Arc::new(StringArray::from(values)),
+            ],
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arroyo_storage::StorageProvider;
+    use arroyo_types::get_test_task_info;
+
+    async fn storage() -> StorageProviderRef {
+        let dir = std::env::temp_dir().join(format!(
+            "arroyo-global-table-test-{}",
+            rand::random::<u64>()
+        ));
+        Arc::new(
+            StorageProvider::for_url(&format!("file://{}", dir.to_str().unwrap()))
+                .await
+                .unwrap(),
+        )
+    }
+
+    fn table(
+        storage: StorageProviderRef,
+        checkpoint: Option<GlobalKeyedTableTaskCheckpointMetadata>,
+    ) -> GlobalKeyedTable {
+        GlobalKeyedTable::from_config(
+            GlobalKeyedTableConfig {
+                table_name: "t".to_string(),
+                description: "test".to_string(),
+                uses_two_phase_commit: false,
+                ttl_micros: Duration::from_secs(3600).as_micros() as u64,
+            },
+            Arc::new(get_test_task_info()),
+            storage,
+            checkpoint,
+        )
+        .unwrap()
+    }
+
+    fn keyed_data(key: &str, value: u64, updated_at: SystemTime) -> TableData {
+        TableData::KeyedData {
+            key: bincode::encode_to_vec(key.to_string(), config::standard()).unwrap(),
+            value: bincode::encode_to_vec(value, config::standard()).unwrap(),
+            updated_at: Some(updated_at),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_not_checkpointed() {
+        let storage = storage().await;
+        let original = table(storage.clone(), None);
+        let now = SystemTime::now();
+
+        let mut checkpointer = original.epoch_checkpointer(1, None).unwrap();
+        checkpointer
+            .insert_data(keyed_data("live", 1, now))
+            .await
+            .unwrap();
+        checkpointer
+            .insert_data(keyed_data("expired", 2, now - Duration::from_secs(7200)))
+            .await
+            .unwrap();
+        let (metadata, _) = checkpointer
+            .finish(&CheckpointMessage {
+                epoch: 1,
+                time: now,
+                watermark: None,
+                then_stop: false,
+                unaligned: false,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let restored = table(
+            storage,
+            Some(GlobalKeyedTableTaskCheckpointMetadata {
+                files: vec![metadata.file.unwrap()],
+                commit_data_by_subtask: HashMap::new(),
+            }),
+        );
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        let view = restored.memory_view::<String, u64>(tx).await.unwrap();
+        assert_eq!(view.get_all(), &HashMap::from([("live".to_string(), 1)]));
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_state_reuses_file() {
+        let table = table(storage().await, None);
+        let now = SystemTime::now();
+
+        let checkpoint = |epoch| CheckpointMessage {
+            epoch,
+            time: now,
+            watermark: None,
+            then_stop: false,
+            unaligned: false,
+        };
+
+        let mut checkpointer = table.epoch_checkpointer(1, None).unwrap();
+        checkpointer
+            .insert_data(keyed_data("a", 1, now))
+            .await
+            .unwrap();
+        let (first, bytes) = checkpointer.finish(&checkpoint(1)).await.unwrap().unwrap();
+        assert!(bytes > 0);
+
+        // the same contents are written as a reference to the previous epoch's file
+        let mut checkpointer = table.epoch_checkpointer(2, Some(first.clone())).unwrap();
+        checkpointer
+            .insert_data(keyed_data("a", 1, now))
+            .await
+            .unwrap();
+        let (second, bytes) = checkpointer.finish(&checkpoint(2)).await.unwrap().unwrap();
+        assert_eq!(bytes, 0);
+        assert_eq!(second.file, first.file);
+
+        // while changed contents get a new file
+        let mut checkpointer = table.epoch_checkpointer(3, Some(second.clone())).unwrap();
+        checkpointer
+            .insert_data(keyed_data("a", 2, now))
+            .await
+            .unwrap();
+        let (third, bytes) = checkpointer.finish(&checkpoint(3)).await.unwrap().unwrap();
+        assert!(bytes > 0);
+        assert_ne!(third.file, second.file);
+        assert_ne!(third.content_digest, second.content_digest);
+    }
+
+    #[tokio::test]
+    async fn test_view_expires_idle_keys() {
+        let table = table(storage().await, None);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let mut view = table.memory_view::<String, u64>(tx).await.unwrap();
+
+        view.insert("unchanged".to_string(), 1).await;
+        view.insert("changed".to_string(), 1).await;
+        let old = SystemTime::now() - Duration::from_secs(7200);
+        for updated_at in view.updated_at.values_mut() {
+            *updated_at = old;
+        }
+
+        // re-inserting the same value doesn't count as an update, but a new value does
+        view.insert("unchanged".to_string(), 1).await;
+        view.insert("changed".to_string(), 2).await;
+
+        let mut updated_at = HashMap::new();
+        while let Ok(StateMessage::TableData {
+            data: TableData::KeyedData {
+                key, updated_at: t, ..
+            },
+            ..
+        }) = rx.try_recv()
+        {
+            let key: String = bincode::decode_from_slice(&key, config::standard())
+                .unwrap()
+                .0;
+            updated_at.insert(key, t.unwrap());
+        }
+        assert_eq!(updated_at.get("unchanged"), Some(&old));
+        assert!(updated_at.get("changed").unwrap() > &old);
+
+        assert_eq!(view.expire(), vec![("unchanged".to_string(), 1)]);
+        assert_eq!(view.get_all(), &HashMap::from([("changed".to_string(), 2)]));
+        assert!(view.expire().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_view_without_ttl_does_not_track_updates() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let mut view = GlobalKeyedView::new("t".to_string(), HashMap::new(), tx);
+
+        view.insert("a".to_string(), 1u64).await;
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(StateMessage::TableData {
+                data: TableData::KeyedData {
+                    updated_at: None,
+                    ..
+                },
+                ..
+            })
+        ));
+        assert!(view.updated_at.is_empty());
+        assert!(view.expire().is_empty());
+    }
+}
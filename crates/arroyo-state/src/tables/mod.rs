@@ -1,14 +1,20 @@
 use crate::{CheckpointMessage, DataOperation, TableData};
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::Schema;
+use arroyo_rpc::get_hasher;
 use arroyo_rpc::grpc::rpc::{
     OperatorMetadata, TableCheckpointMetadata, TableConfig, TableEnum,
     TableSubtaskCheckpointMetadata,
 };
 use arroyo_storage::StorageProviderRef;
 use arroyo_types::TaskInfoRef;
+use datafusion::common::hash_utils::create_hashes;
 use prost::Message;
+use serde_json::{Map, Value};
 use std::any::Any;
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::time::SystemTime;
 use tracing::debug;
 
@@ -124,6 +130,14 @@ pub(crate) trait Table: Send + Sync + 'static + Clone {
         checkpoint: Self::TableCheckpointMessage,
     ) -> Result<HashSet<String>>;
 
+    // rewrites every file path referenced by the checkpoint, used when checkpoint data
+    // is copied to a different location (e.g., when writing or restoring a savepoint)
+    fn relocate_files(
+        config: Self::ConfigMessage,
+        checkpoint: Self::TableCheckpointMessage,
+        relocate: &dyn Fn(&str) -> String,
+    ) -> Result<Self::TableCheckpointMessage>;
+
     async fn compact_data(
         config: Self::ConfigMessage,
         compaction_config: &CompactionConfig,
@@ -146,6 +160,9 @@ pub struct CompactionConfig {
     pub storage_provider: StorageProviderRef,
     pub compact_generations: HashSet<u64>,
     pub min_compaction_epochs: usize,
+    /// files of at least this many bytes that compaction would copy unchanged are referenced
+    /// instead
+    pub reuse_file_bytes: usize,
 }
 
 pub trait ErasedTable: Send + Sync + 'static {
@@ -211,6 +228,14 @@ pub trait ErasedTable: Send + Sync + 'static {
     where
         Self: Sized;
 
+    fn relocate_files(
+        config: TableConfig,
+        checkpoint: TableCheckpointMetadata,
+        relocate: &dyn Fn(&str) -> String,
+    ) -> Result<TableCheckpointMetadata>
+    where
+        Self: Sized;
+
     fn as_any(&self) -> &dyn Any;
 
     #[allow(async_fn_in_trait)]
@@ -353,6 +378,26 @@ impl<T: Table + Sized + 'static> ErasedTable for T {
             Self::checked_proto_decode(T::table_type(), checkpoint.data)?,
         )
     }
+
+    fn relocate_files(
+        config: TableConfig,
+        checkpoint: TableCheckpointMetadata,
+        relocate: &dyn Fn(&str) -> String,
+    ) -> Result<TableCheckpointMetadata>
+    where
+        Self: Sized,
+    {
+        let result = T::relocate_files(
+            Self::checked_proto_decode(T::table_type(), config.config)?,
+            Self::checked_proto_decode(T::table_type(), checkpoint.data)?,
+            relocate,
+        )?;
+        Ok(TableCheckpointMetadata {
+            table_type: T::table_type().into(),
+            data: result.encode_to_vec(),
+        })
+    }
+
     fn committing_data(
         config: TableConfig,
         table_metadata: &TableCheckpointMetadata,
@@ -388,6 +433,62 @@ impl<T: Table + Sized + 'static> ErasedTable for T {
     }
 }
 
+/// An in-memory view of a table that can be read by state queries while the operator is running
+#[async_trait::async_trait]
+pub trait QueryableView: Send {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Decodes a key into the columns that the table is partitioned by, returning None if the
+    /// table is not partitioned by key
+    fn key_columns(&self, key: &Map<String, Value>) -> Result<Option<Vec<ArrayRef>>>;
+
+    /// Returns up to `limit` of the current entries in the view, restricted to `key` if set
+    async fn query(
+        &mut self,
+        key: Option<&Map<String, Value>>,
+        limit: usize,
+    ) -> Result<RecordBatch>;
+}
+
+/// Decodes a key given as a JSON object of column names to values into arrays for the
+/// `key_indices` columns of `schema`
+pub fn decode_key(
+    schema: &Schema,
+    key_indices: &[usize],
+    key: &Map<String, Value>,
+) -> Result<Vec<ArrayRef>> {
+    let key_schema = Arc::new(schema.project(key_indices)?);
+    if let Some(name) = key
+        .keys()
+        .find(|name| key_schema.field_with_name(name).is_err())
+    {
+        bail!(
+            "'{}' is not a key column; the key columns are {}",
+            name,
+            key_schema
+                .fields()
+                .iter()
+                .map(|f| format!("'{}'", f.name()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    let mut decoder = arrow_json::ReaderBuilder::new(key_schema).build_decoder()?;
+    decoder.serialize(&[key])?;
+    let batch = decoder
+        .flush()?
+        .ok_or_else(|| anyhow!("failed to decode key"))?;
+    Ok(batch.columns().to_vec())
+}
+
+/// Hashes a single decoded key in the same way as rows are routed to subtasks
+pub fn key_hash(columns: &[ArrayRef]) -> Result<u64> {
+    let mut hashes = vec![0];
+    create_hashes(columns, &get_hasher(), &mut hashes)?;
+    Ok(hashes[0])
+}
+
 #[async_trait::async_trait]
 pub trait TableEpochCheckpointer: Send {
     type SubTableCheckpointMessage: prost::Message;
@@ -1,18 +1,20 @@
-use std::any::Any;
-
 use std::{collections::HashMap, sync::Arc, time::SystemTime};
 
 use anyhow::{anyhow, bail, Context, Result};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow_array::RecordBatch;
 use arroyo_rpc::CompactionResult;
 use arroyo_rpc::{
     grpc::rpc::{
-        OperatorCheckpointMetadata, SubtaskCheckpointMetadata, TableConfig, TableEnum,
-        TableSubtaskCheckpointMetadata,
+        InFlightData, OperatorCheckpointMetadata, SubtaskCheckpointMetadata, TableConfig,
+        TableEnum, TableSubtaskCheckpointMetadata,
     },
     CheckpointCompleted, ControlResp,
 };
-use arroyo_storage::{StorageProvider, StorageProviderRef};
+use arroyo_storage::StorageProviderRef;
 use arroyo_types::{to_micros, CheckpointBarrier, Data, Key, TaskInfoRef};
+use serde_json::{Map, Value};
 use tokio::sync::{
     mpsc::{self, Receiver, Sender},
     oneshot,
@@ -21,6 +23,8 @@ use tokio::sync::{
 use arroyo_rpc::config::config;
 use tracing::{debug, error, info, warn};
 
+use crate::parquet::in_flight_path;
+use crate::spill::{MemoryBudget, MemoryBudgetRef};
 use crate::{tables::global_keyed_map::GlobalKeyedTable, StateMessage};
 use crate::{CheckpointMessage, TableData};
 
@@ -28,7 +32,7 @@ use super::expiring_time_key_map::{
     ExpiringTimeKeyTable, ExpiringTimeKeyView, KeyTimeView, LastKeyValueView,
 };
 use super::global_keyed_map::GlobalKeyedView;
-use super::{ErasedCheckpointer, ErasedTable};
+use super::{key_hash, ErasedCheckpointer, ErasedTable, QueryableView};
 
 #[allow(unused)]
 pub struct TableManager {
@@ -39,7 +43,12 @@ pub struct TableManager {
     writer: BackendWriter,
     task_info: TaskInfoRef,
     storage: StorageProviderRef,
-    caches: HashMap<String, Box<dyn Any + Send>>,
+    caches: HashMap<String, Box<dyn QueryableView>>,
+    memory_budget: MemoryBudgetRef,
+    // data restored from an unaligned checkpoint that needs to be replayed, by input index
+    in_flight: HashMap<usize, Vec<RecordBatch>>,
+    // the number of inputs this subtask had when the in-flight data was recorded
+    in_flight_partitions: Option<usize>,
 }
 
 pub struct BackendWriter {
@@ -60,6 +69,22 @@ pub struct BackendFlusher {
     table_checkpointers: HashMap<String, Box<dyn ErasedCheckpointer>>,
     current_epoch: u32,
     last_epoch_checkpoints: HashMap<String, TableSubtaskCheckpointMetadata>,
+    // unaligned checkpoints whose table data has been written, but which are still waiting for
+    // their in-flight data
+    pending_unaligned: HashMap<u32, CheckpointCompleted>,
+}
+
+fn encode_in_flight(batches: &[RecordBatch]) -> Result<Vec<u8>> {
+    let mut writer = FileWriter::try_new(vec![], &batches[0].schema())?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    Ok(writer.into_inner()?)
+}
+
+fn decode_in_flight(data: bytes::Bytes) -> Result<Vec<RecordBatch>> {
+    Ok(FileReader::try_new(std::io::Cursor::new(data), None)?.collect::<Result<_, _>>()?)
 }
 
 impl BackendFlusher {
@@ -119,6 +144,9 @@ impl BackendFlusher {
                                 .get_mut(&table).expect("checkpointer should be there")
                                 .insert_data(data).await?
                         },
+                        Some(StateMessage::InFlight { epoch, input_partitions, batches }) => {
+                            self.write_in_flight(epoch, input_partitions, batches).await?;
+                        }
                         None => {
                             debug!("Parquet flusher closed");
                             return Ok(false);
@@ -171,14 +199,22 @@ impl BackendFlusher {
             table_metadata: metadatas,
             table_configs: self.table_configs.clone(),
             bytes: bytes as u64,
+            in_flight: vec![],
         };
-        self.control_tx
-            .send(ControlResp::CheckpointCompleted(CheckpointCompleted {
-                checkpoint_epoch: cp.epoch,
-                operator_id: self.task_info.operator_id.clone(),
-                subtask_metadata,
-            }))
-            .await?;
+        let completed = CheckpointCompleted {
+            checkpoint_epoch: cp.epoch,
+            operator_id: self.task_info.operator_id.clone(),
+            subtask_metadata,
+        };
+        if cp.unaligned {
+            // the checkpoint isn't complete until the operator has seen where the barrier was
+            // on each of its inputs and sent us the data it overtook
+            self.pending_unaligned.insert(cp.epoch, completed);
+        } else {
+            self.control_tx
+                .send(ControlResp::CheckpointCompleted(completed))
+                .await?;
+        }
         if cp.then_stop {
             self.finish_tx
                 .take()
@@ -189,6 +225,43 @@ impl BackendFlusher {
         }
         Ok(true)
     }
+
+    async fn write_in_flight(
+        &mut self,
+        epoch: u32,
+        input_partitions: usize,
+        batches: HashMap<usize, Vec<RecordBatch>>,
+    ) -> Result<()> {
+        let Some(mut completed) = self.pending_unaligned.remove(&epoch) else {
+            bail!("received in-flight data for epoch {}, which is not an unaligned checkpoint in progress", epoch);
+        };
+
+        for (input, batches) in batches {
+            if batches.is_empty() {
+                continue;
+            }
+            let file = in_flight_path(
+                &self.task_info.job_id,
+                epoch,
+                &self.task_info.operator_id,
+                self.task_info.task_index,
+                input,
+            );
+            let data = encode_in_flight(&batches)?;
+            completed.subtask_metadata.bytes += data.len() as u64;
+            self.storage.put(file.clone(), data).await?;
+            completed.subtask_metadata.in_flight.push(InFlightData {
+                input_index: input as u32,
+                file,
+                input_partitions: input_partitions as u32,
+            });
+        }
+
+        self.control_tx
+            .send(ControlResp::CheckpointCompleted(completed))
+            .await?;
+        Ok(())
+    }
 }
 
 impl BackendWriter {
@@ -215,6 +288,7 @@ impl BackendWriter {
             current_epoch,
             table_checkpointers: HashMap::new(),
             last_epoch_checkpoints,
+            pending_unaligned: HashMap::new(),
         })
         .start();
 
@@ -230,12 +304,7 @@ async fn get_storage_provider() -> anyhow::Result<StorageProviderRef> {
     // to be synchronized with the workers
 
     Ok(Arc::new(
-        StorageProvider::for_url(&config().checkpoint_url)
-            .await
-            .context(format!(
-                "failed to construct checkpoint backend for URL {}",
-                config().checkpoint_url
-            ))?,
+        crate::parquet::checkpoint_storage(&config().checkpoint_url).await?,
     ))
 }
 
@@ -245,6 +314,7 @@ impl TableManager {
         table_configs: HashMap<String, TableConfig>,
         tx: Sender<ControlResp>,
         checkpoint_metadata: Option<OperatorCheckpointMetadata>,
+        next_epoch: Option<u32>,
     ) -> Result<Self> {
         let storage = get_storage_provider().await?;
 
@@ -277,16 +347,62 @@ impl TableManager {
             })
             .collect::<Result<HashMap<_, _>>>()?;
 
+        // only tasks with time-keyed tables hold enough state in memory to need spilling
+        let memory_budget = if table_configs
+            .values()
+            .any(|c| c.table_type() == TableEnum::ExpiringKeyedTimeTable)
+        {
+            MemoryBudget::for_task(&task_info).await
+        } else {
+            MemoryBudget::unbounded()
+        };
+
         let epoch;
         let min_epoch;
         let mut last_epoch_checkpoints = HashMap::new();
+        let mut in_flight = HashMap::new();
+        let mut in_flight_partitions = None;
         match checkpoint_metadata {
             Some(metadata) => {
                 // TODO: validate this logic.
                 let Some(operator_metadata) = metadata.operator_metadata else {
                     bail!("missing operator metadata");
                 };
-                epoch = operator_metadata.epoch + 1;
+                // in-flight data was read by a particular subtask, so can't be redistributed
+                if !metadata.in_flight.is_empty()
+                    && operator_metadata.parallelism != task_info.parallelism as u64
+                {
+                    bail!(
+                        "cannot change the parallelism of operator {} from {} to {} when restoring from an unaligned checkpoint with in-flight data",
+                        task_info.operator_id,
+                        operator_metadata.parallelism,
+                        task_info.parallelism
+                    );
+                }
+                if let Some(subtask_in_flight) =
+                    metadata.in_flight.get(&(task_info.task_index as u32))
+                {
+                    for input in &subtask_in_flight.inputs {
+                        let data = storage.get(input.file.as_str()).await.with_context(|| {
+                            format!("failed to read in-flight data {}", input.file)
+                        })?;
+                        in_flight.insert(input.input_index as usize, decode_in_flight(data)?);
+                        in_flight_partitions = Some(input.input_partitions as usize);
+                    }
+                }
+                // when only part of a job is restarted, the rest of it may have moved on to
+                // later epochs, and our files need to be written for the epochs they're part of
+                epoch = match next_epoch {
+                    Some(next) if next <= operator_metadata.epoch => {
+                        bail!(
+                            "cannot start at epoch {} when restoring from epoch {}",
+                            next,
+                            operator_metadata.epoch
+                        );
+                    }
+                    Some(next) => next,
+                    None => operator_metadata.epoch + 1,
+                };
                 min_epoch = operator_metadata.epoch;
                 for (table, table_metadata) in metadata.table_checkpoint_metadata.clone() {
                     let table_implementation = tables
@@ -300,7 +416,7 @@ impl TableManager {
                 }
             }
             None => {
-                epoch = 1;
+                epoch = next_epoch.unwrap_or(1);
                 min_epoch = 1;
             }
         }
@@ -322,9 +438,61 @@ impl TableManager {
             task_info,
             storage,
             caches: HashMap::new(),
+            memory_budget,
+            in_flight,
+            in_flight_partitions,
         })
     }
 
+    /// The budget for the state this task holds in memory, shared by its time-keyed tables; operators
+    /// can use it to spill their own buffered data
+    pub fn memory_budget(&self) -> MemoryBudgetRef {
+        self.memory_budget.clone()
+    }
+
+    /// Takes the data that was in flight to this task when the checkpoint it was restored from
+    /// was taken, by input index; it must be processed before any new data. Fails if the task
+    /// now has a different number of inputs, as then the indices refer to different upstream
+    /// subtasks.
+    pub fn take_in_flight(
+        &mut self,
+        input_partitions: usize,
+    ) -> Result<Vec<(usize, Vec<RecordBatch>)>> {
+        if let Some(recorded) = self.in_flight_partitions.take() {
+            if recorded != input_partitions {
+                bail!(
+                    "cannot change the parallelism of the operators upstream of {} when restoring from an unaligned checkpoint with in-flight data (subtask {} had {} inputs, but now has {})",
+                    self.task_info.operator_id,
+                    self.task_info.task_index,
+                    recorded,
+                    input_partitions
+                );
+            }
+        }
+        let mut in_flight: Vec<_> = self.in_flight.drain().collect();
+        in_flight.sort_by_key(|(input, _)| *input);
+        Ok(in_flight)
+    }
+
+    /// Writes the data that the barrier for an unaligned checkpoint overtook, by input index,
+    /// which completes the checkpoint
+    pub async fn write_in_flight(
+        &mut self,
+        epoch: u32,
+        input_partitions: usize,
+        batches: HashMap<usize, Vec<RecordBatch>>,
+    ) {
+        self.writer
+            .sender
+            .send(StateMessage::InFlight {
+                epoch,
+                input_partitions,
+                batches,
+            })
+            .await
+            .expect("should be able to send in-flight data");
+    }
+
     pub async fn checkpoint(&mut self, barrier: CheckpointBarrier, watermark: Option<SystemTime>) {
         self.writer
             .sender
@@ -333,6 +501,7 @@ impl TableManager {
                 time: barrier.timestamp,
                 watermark,
                 then_stop: barrier.then_stop,
+                unaligned: barrier.unaligned,
             }))
             .await
             .expect("should be able to send checkpoint");
@@ -386,19 +555,20 @@ impl TableManager {
             let saved_data = global_keyed_table
                 .memory_view::<K, V>(self.writer.sender.clone())
                 .await?;
-            let cache: Box<dyn Any + Send> = Box::new(saved_data);
+            let cache: Box<dyn QueryableView> = Box::new(saved_data);
             e.insert(cache);
         }
 
         let cache = self.caches.get_mut(table_name).unwrap();
-        let cache: &mut GlobalKeyedView<K, V> = cache.downcast_mut().ok_or_else(|| {
-            anyhow!(
-                "Failed to downcast table {} to key type {} and value type {}",
-                table_name,
-                std::any::type_name::<K>(),
-                std::any::type_name::<V>()
-            )
-        })?;
+        let cache: &mut GlobalKeyedView<K, V> =
+            cache.as_any_mut().downcast_mut().ok_or_else(|| {
+                anyhow!(
+                    "Failed to downcast table {} to key type {} and value type {}",
+                    table_name,
+                    std::any::type_name::<K>(),
+                    std::any::type_name::<V>()
+                )
+            })?;
         Ok(cache)
     }
 
@@ -419,13 +589,18 @@ impl TableManager {
                 .downcast_ref::<ExpiringTimeKeyTable>()
                 .ok_or_else(|| anyhow!("wrong table type for table {}", table_name))?;
             let saved_data = expiring_time_key_table
-                .get_view(self.writer.sender.clone(), watermark)
+                .get_view(
+                    self.writer.sender.clone(),
+                    watermark,
+                    self.memory_budget.clone(),
+                )
                 .await?;
-            let cache: Box<dyn Any + Send> = Box::new(saved_data);
+            let cache: Box<dyn QueryableView> = Box::new(saved_data);
             e.insert(cache);
         }
         let cache = self.caches.get_mut(table_name).unwrap();
         let cache: &mut ExpiringTimeKeyView = cache
+            .as_any_mut()
             .downcast_mut()
             .ok_or_else(|| anyhow!("Failed to downcast table {}", table_name))?;
         Ok(cache)
@@ -448,13 +623,18 @@ impl TableManager {
                 .downcast_ref::<ExpiringTimeKeyTable>()
                 .ok_or_else(|| anyhow!("wrong table type for table {}", table_name))?;
             let saved_data = expiring_time_key_table
-                .get_key_time_view(self.writer.sender.clone(), watermark)
+                .get_key_time_view(
+                    self.writer.sender.clone(),
+                    watermark,
+                    self.memory_budget.clone(),
+                )
                 .await?;
-            let cache: Box<dyn Any + Send> = Box::new(saved_data);
+            let cache: Box<dyn QueryableView> = Box::new(saved_data);
             e.insert(cache);
         }
         let cache = self.caches.get_mut(table_name).unwrap();
         let cache: &mut KeyTimeView = cache
+            .as_any_mut()
             .downcast_mut()
             .ok_or_else(|| anyhow!("Failed to downcast table {}", table_name))?;
         Ok(cache)
@@ -479,13 +659,220 @@ impl TableManager {
             let saved_data = expiring_time_key_table
                 .get_last_key_value_view(self.writer.sender.clone(), watermark)
                 .await?;
-            let cache: Box<dyn Any + Send> = Box::new(saved_data);
+            let cache: Box<dyn QueryableView> = Box::new(saved_data);
             e.insert(cache);
         }
         let cache = self.caches.get_mut(table_name).unwrap();
         let cache: &mut LastKeyValueView = cache
+            .as_any_mut()
             .downcast_mut()
             .ok_or_else(|| anyhow!("Failed to downcast table {}", table_name))?;
         Ok(cache)
     }
+
+    /// Looks up the current entries of a table for a state query. Returns None if the table is
+    /// partitioned by key and `key` belongs to another subtask.
+    pub async fn query_state(
+        &mut self,
+        table_name: &str,
+        key: Option<&Map<String, Value>>,
+        limit: usize,
+    ) -> Result<Option<RecordBatch>> {
+        if !self.tables.contains_key(table_name) {
+            bail!("no registered table {}", table_name);
+        }
+
+        let Some(view) = self.caches.get_mut(table_name) else {
+            // the operator hasn't read the table yet, so there's nothing in memory to return
+            return Ok(None);
+        };
+
+        if let Some(key) = key {
+            if let Some(columns) = view.key_columns(key)? {
+                if !self.task_info.key_range.contains(&key_hash(&columns)?) {
+                    return Ok(None);
+                }
+            }
+        }
+
+        Ok(Some(view.query(key, limit).await?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::global_table_config;
+    use crate::parquet::test::checkpoint_test_storage;
+    use crate::tables::table_checkpoint_path;
+    use arrow_array::Int64Array;
+    use arrow_schema::{DataType, Field, Schema};
+    use arroyo_rpc::grpc::rpc::{
+        GlobalKeyedTableSubtaskCheckpointMetadata, OperatorMetadata, SubtaskInFlightData,
+    };
+    use arroyo_types::TaskInfo;
+    use prost::Message;
+
+    fn batch() -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("x", DataType::Int64, false)])),
+            vec![Arc::new(Int64Array::from(vec![1, 2, 3]))],
+        )
+        .unwrap()
+    }
+
+    /// A checkpoint for a single subtask that had two inputs, with in-flight data on the second
+    async fn in_flight_checkpoint(job_id: &str) -> OperatorCheckpointMetadata {
+        let storage = checkpoint_test_storage().await;
+        let file = in_flight_path(job_id, 1, "op", 0, 1);
+        storage
+            .put(file.clone(), encode_in_flight(&[batch()]).unwrap())
+            .await
+            .unwrap();
+
+        OperatorCheckpointMetadata {
+            operator_metadata: Some(OperatorMetadata {
+                job_id: job_id.to_string(),
+                operator_id: "op".to_string(),
+                epoch: 1,
+                parallelism: 1,
+                ..Default::default()
+            }),
+            in_flight: HashMap::from([(
+                0,
+                SubtaskInFlightData {
+                    inputs: vec![InFlightData {
+                        input_index: 1,
+                        file,
+                        input_partitions: 2,
+                    }],
+                },
+            )]),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_in_flight() {
+        let metadata = in_flight_checkpoint("restore-in-flight").await;
+        let task_info = Arc::new(TaskInfo::for_test("restore-in-flight", "op"));
+        let (tx, _rx) = mpsc::channel(10);
+
+        let mut table_manager = TableManager::new(
+            task_info.clone(),
+            HashMap::new(),
+            tx.clone(),
+            Some(metadata),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            table_manager.take_in_flight(2).unwrap(),
+            vec![(1, vec![batch()])]
+        );
+        assert!(table_manager.take_in_flight(2).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restore_in_flight_with_changed_parallelism() {
+        let metadata = in_flight_checkpoint("restore-in-flight-parallelism").await;
+        let (tx, _rx) = mpsc::channel(10);
+
+        // the upstream operators changed parallelism, so the input indices no longer line up
+        let task_info = Arc::new(TaskInfo::for_test("restore-in-flight-parallelism", "op"));
+        let mut table_manager = TableManager::new(
+            task_info,
+            HashMap::new(),
+            tx.clone(),
+            Some(metadata.clone()),
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(table_manager.take_in_flight(3).is_err());
+
+        // as did the operator itself, so the in-flight data belongs to a different subtask
+        let mut task_info = TaskInfo::for_test("restore-in-flight-parallelism", "op");
+        task_info.parallelism = 2;
+        assert!(TableManager::new(
+            Arc::new(task_info),
+            HashMap::new(),
+            tx,
+            Some(metadata),
+            None
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_restore_at_later_epoch() {
+        checkpoint_test_storage().await;
+        let job_id = "restore-at-later-epoch";
+        let metadata = OperatorCheckpointMetadata {
+            operator_metadata: Some(OperatorMetadata {
+                job_id: job_id.to_string(),
+                operator_id: "op".to_string(),
+                epoch: 5,
+                parallelism: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let task_info = Arc::new(TaskInfo::for_test(job_id, "op"));
+        let (tx, mut rx) = mpsc::channel(10);
+
+        // the rest of the job has already taken checkpoints 6 and 7
+        assert!(TableManager::new(
+            task_info.clone(),
+            global_table_config("s", "state"),
+            tx.clone(),
+            Some(metadata.clone()),
+            Some(5),
+        )
+        .await
+        .is_err());
+        let mut table_manager = TableManager::new(
+            task_info,
+            global_table_config("s", "state"),
+            tx,
+            Some(metadata),
+            Some(8),
+        )
+        .await
+        .unwrap();
+
+        table_manager
+            .get_global_keyed_state::<String, u64>("s")
+            .await
+            .unwrap()
+            .insert("k".to_string(), 1)
+            .await;
+        table_manager
+            .checkpoint(
+                CheckpointBarrier {
+                    epoch: 8,
+                    min_epoch: 5,
+                    timestamp: SystemTime::now(),
+                    then_stop: false,
+                    unaligned: false,
+                },
+                None,
+            )
+            .await;
+
+        let Some(ControlResp::CheckpointCompleted(completed)) = rx.recv().await else {
+            panic!("expected checkpoint to complete");
+        };
+        assert_eq!(completed.checkpoint_epoch, 8);
+        let table_metadata = GlobalKeyedTableSubtaskCheckpointMetadata::decode(
+            &completed.subtask_metadata.table_metadata["s"].data[..],
+        )
+        .unwrap();
+        assert_eq!(
+            table_metadata.file.unwrap(),
+            table_checkpoint_path(job_id, "op", "s", 0, 8, false)
+        );
+    }
 }
@@ -4,11 +4,13 @@ use object_store::{aws::AwsCredential, CredentialProvider};
 use rusoto_core::credential::{
     AutoRefreshingProvider, ChainProvider, ProfileProvider, ProvideAwsCredentials,
 };
+use rusoto_core::Region;
+use rusoto_sts::{StsAssumeRoleSessionCredentialsProvider, StsClient};
 
 use crate::StorageError;
 
 pub struct ArroyoCredentialProvider {
-    provider: AutoRefreshingProvider<ChainProvider>,
+    provider: Box<dyn ProvideAwsCredentials + Send + Sync>,
 }
 
 impl std::fmt::Debug for ArroyoCredentialProvider {
@@ -23,12 +25,66 @@ impl ArroyoCredentialProvider {
             AutoRefreshingProvider::new(ChainProvider::new())
                 .map_err(|e| StorageError::CredentialsError(e.to_string()))?;
 
-        Ok(Self { provider: inner })
+        Ok(Self {
+            provider: Box::new(inner),
+        })
+    }
+
+    /// Loads credentials from the named profile in the shared AWS config files
+    pub fn for_profile(profile: &str) -> Result<Self, StorageError> {
+        let mut inner =
+            ProfileProvider::new().map_err(|e| StorageError::CredentialsError(e.to_string()))?;
+        inner.set_profile(profile);
+        let inner = AutoRefreshingProvider::new(inner)
+            .map_err(|e| StorageError::CredentialsError(e.to_string()))?;
+
+        Ok(Self {
+            provider: Box::new(inner),
+        })
+    }
+
+    /// Assumes `role_arn` using the default credential chain, refreshing the session credentials
+    /// as they expire
+    pub fn for_role(
+        role_arn: &str,
+        session_name: Option<&str>,
+        external_id: Option<&str>,
+        region: Option<&str>,
+    ) -> Result<Self, StorageError> {
+        let region = region
+            .map(|r| {
+                r.parse::<Region>()
+                    .map_err(|e| StorageError::CredentialsError(e.to_string()))
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        let sts = StsAssumeRoleSessionCredentialsProvider::new(
+            StsClient::new(region),
+            role_arn.to_string(),
+            session_name.unwrap_or("arroyo").to_string(),
+            external_id.map(|s| s.to_string()),
+            None,
+            None,
+            None,
+        );
+        let inner = AutoRefreshingProvider::new(sts)
+            .map_err(|e| StorageError::CredentialsError(e.to_string()))?;
+
+        Ok(Self {
+            provider: Box::new(inner),
+        })
     }
 
     pub async fn default_region() -> Option<String> {
         ProfileProvider::region().ok()?
     }
+
+    pub async fn profile_region(profile: &str) -> Option<String> {
+        let mut provider = ProfileProvider::new().ok()?;
+        provider.set_profile(profile);
+        provider.region_from_profile().ok()?
+    }
 }
 
 #[async_trait::async_trait]
@@ -0,0 +1,658 @@
+//! A local disk cache for objects read from remote object stores.
+//!
+//! Cached objects are content-addressed by the URL of the object and its ETag, so an object that
+//! is overwritten in the store is never served stale from the cache. Reads of cached objects are
+//! conditional on the ETag, so each read is still a single request to the store, but unchanged
+//! objects aren't downloaded again. The cache is bounded in size,
+//! and evicts the least-recently-used objects once that's exceeded. Objects already on disk when
+//! the cache is opened (e.g., from before the process restarted) are reused.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetRange, GetResult, GetResultPayload, ListResult, MultipartId, ObjectMeta,
+    ObjectStore, PutOptions, PutResult,
+};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, register_int_gauge, IntCounter, IntGauge};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::{debug, warn};
+
+use crate::resolve_range;
+
+const TMP_SUFFIX: &str = ".tmp";
+
+static CACHE_HITS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "arroyo_storage_cache_hits",
+        "Number of object store reads served from the local storage cache"
+    )
+    .unwrap()
+});
+
+static CACHE_MISSES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "arroyo_storage_cache_misses",
+        "Number of object store reads that were not in the local storage cache"
+    )
+    .unwrap()
+});
+
+static CACHE_EVICTIONS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "arroyo_storage_cache_evictions",
+        "Number of objects evicted from the local storage cache"
+    )
+    .unwrap()
+});
+
+static CACHE_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "arroyo_storage_cache_size_bytes",
+        "Total size of the objects in the local storage cache"
+    )
+    .unwrap()
+});
+
+#[derive(Default)]
+struct LruState {
+    // key -> (size, last used)
+    entries: HashMap<String, (u64, u64)>,
+    // last used -> key
+    order: BTreeMap<u64, String>,
+    clock: u64,
+    size: u64,
+}
+
+impl LruState {
+    fn touch(&mut self, key: &str) -> bool {
+        let Some((_, last_used)) = self.entries.get_mut(key) else {
+            return false;
+        };
+        self.clock += 1;
+        self.order.remove(last_used);
+        *last_used = self.clock;
+        self.order.insert(self.clock, key.to_string());
+        true
+    }
+
+    fn insert(&mut self, key: String, size: u64) {
+        self.remove(&key);
+        self.clock += 1;
+        self.order.insert(self.clock, key.clone());
+        self.entries.insert(key, (size, self.clock));
+        self.size += size;
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((size, last_used)) = self.entries.remove(key) {
+            self.order.remove(&last_used);
+            self.size -= size;
+        }
+    }
+
+    /// Removes the least-recently-used entries until the cache fits in `max_size`, returning
+    /// their keys
+    fn evict(&mut self, max_size: u64) -> Vec<String> {
+        let mut evicted = vec![];
+        while self.size > max_size {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            let (size, _) = self.entries.remove(&key).unwrap();
+            self.size -= size;
+            evicted.push(key);
+        }
+        evicted
+    }
+}
+
+/// A size-bounded directory of cached objects, shared by all the object stores that read
+/// through it
+pub struct DiskCache {
+    dir: PathBuf,
+    max_size: u64,
+    state: Mutex<LruState>,
+}
+
+impl Debug for DiskCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DiskCache")
+            .field("dir", &self.dir)
+            .field("max_size", &self.max_size)
+            .finish()
+    }
+}
+
+impl DiskCache {
+    /// Opens the cache in `dir`, creating it if necessary. Objects already in the directory are
+    /// kept, ordered by their modification time.
+    pub fn open(dir: impl Into<PathBuf>, max_size: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut existing = vec![];
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let Some(name) = entry.file_name().to_str().map(|s| s.to_string()) else {
+                continue;
+            };
+            if name.ends_with(TMP_SUFFIX) {
+                // left over from an interrupted write
+                let _ = std::fs::remove_file(entry.path());
+                continue;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            existing.push((modified, name, metadata.len()));
+        }
+        existing.sort();
+
+        let mut state = LruState::default();
+        for (_, name, size) in existing {
+            state.insert(name, size);
+        }
+
+        let cache = Self {
+            dir,
+            max_size,
+            state: Mutex::new(state),
+        };
+        cache.evict();
+        debug!(
+            "opened storage cache at {:?} with {} bytes of cached objects",
+            cache.dir,
+            cache.state.lock().unwrap().size
+        );
+
+        Ok(cache)
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    fn key(namespace: &str, location: &Path, e_tag: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(namespace.as_bytes());
+        hasher.update(b"/");
+        hasher.update(location.as_ref().as_bytes());
+        hasher.update(b"\0");
+        hasher.update(e_tag.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Returns the path of the cached object for `key`, if present, marking it as recently used
+    fn get(&self, key: &str) -> Option<PathBuf> {
+        self.state
+            .lock()
+            .unwrap()
+            .touch(key)
+            .then(|| self.dir.join(key))
+    }
+
+    fn remove(&self, key: &str) {
+        self.state.lock().unwrap().remove(key);
+        let _ = std::fs::remove_file(self.dir.join(key));
+    }
+
+    /// Writes the contents of `stream` to the cache under `key`, returning the path of the
+    /// cached object and the file, which is opened before anything is evicted so that it can be
+    /// read even if the object is evicted straight away
+    async fn insert(
+        &self,
+        key: &str,
+        mut stream: BoxStream<'static, object_store::Result<Bytes>>,
+    ) -> io::Result<(PathBuf, File)> {
+        let path = self.dir.join(key);
+        let tmp = self
+            .dir
+            .join(format!("{}.{:x}{}", key, rand::random::<u64>(), TMP_SUFFIX));
+
+        let mut size = 0;
+        let result = async {
+            let mut file = tokio::fs::File::create(&tmp).await?;
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk.map_err(io::Error::other)?;
+                size += chunk.len() as u64;
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+            tokio::fs::rename(&tmp, &path).await?;
+            Ok(tokio::fs::File::open(&path).await?.into_std().await)
+        }
+        .await;
+
+        let file = match result {
+            Ok(file) => file,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&tmp).await;
+                return Err(e);
+            }
+        };
+
+        self.state.lock().unwrap().insert(key.to_string(), size);
+        self.evict();
+
+        Ok((path, file))
+    }
+
+    fn evict(&self) {
+        let (evicted, size) = {
+            let mut state = self.state.lock().unwrap();
+            (state.evict(self.max_size), state.size)
+        };
+
+        for key in evicted {
+            CACHE_EVICTIONS.inc();
+            if let Err(e) = std::fs::remove_file(self.dir.join(&key)) {
+                warn!(
+                    "failed to remove evicted object {} from storage cache: {}",
+                    key, e
+                );
+            }
+        }
+
+        CACHE_SIZE.set(size as i64);
+    }
+}
+
+fn read_cached(
+    mut file: File,
+    path: PathBuf,
+    meta: ObjectMeta,
+    range: Option<GetRange>,
+) -> io::Result<GetResult> {
+    if file.metadata()?.len() != meta.size as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "cached object does not match the size of the stored object",
+        ));
+    }
+
+    match range {
+        Some(requested) => {
+            let Some(range) = resolve_range(&requested, meta.size) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "range {:?} is out of bounds for object of size {}",
+                        requested, meta.size
+                    ),
+                ));
+            };
+
+            file.seek(SeekFrom::Start(range.start as u64))?;
+            let mut buf = vec![0; range.len()];
+            file.read_exact(&mut buf)?;
+            let bytes = Bytes::from(buf);
+
+            Ok(GetResult {
+                payload: GetResultPayload::Stream(
+                    futures::stream::once(async move { Ok(bytes) }).boxed(),
+                ),
+                meta,
+                range,
+            })
+        }
+        None => Ok(GetResult {
+            range: 0..meta.size,
+            meta,
+            payload: GetResultPayload::File(file, path),
+        }),
+    }
+}
+
+/// An [ObjectStore] that serves reads from a [DiskCache] where possible, and adds objects to
+/// the cache as they're read. All other operations go directly to the underlying store.
+#[derive(Debug)]
+pub struct CachedObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    cache: Arc<DiskCache>,
+    // distinguishes objects with the same path in different stores
+    namespace: String,
+    // the version of each object we last cached, which later reads are conditional on
+    cached: Mutex<HashMap<Path, ObjectMeta>>,
+}
+
+impl CachedObjectStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, cache: Arc<DiskCache>, namespace: String) -> Self {
+        Self {
+            inner,
+            cache,
+            namespace,
+            cached: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn cacheable(options: &GetOptions) -> bool {
+        !options.head
+            && options.if_match.is_none()
+            && options.if_none_match.is_none()
+            && options.if_modified_since.is_none()
+            && options.if_unmodified_since.is_none()
+            && options.version.is_none()
+    }
+
+    fn key(&self, location: &Path, meta: &ObjectMeta) -> Option<String> {
+        let e_tag = meta.e_tag.as_ref()?;
+        (meta.size as u64 <= self.cache.max_size)
+            .then(|| DiskCache::key(&self.namespace, location, e_tag))
+    }
+
+    async fn read_cached(
+        path: &FsPath,
+        file: Option<File>,
+        meta: ObjectMeta,
+        range: Option<GetRange>,
+    ) -> io::Result<GetResult> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let file = match file {
+                Some(file) => file,
+                None => File::open(&path)?,
+            };
+            read_cached(file, path, meta, range)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
+
+impl std::fmt::Display for CachedObjectStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cached({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for CachedObjectStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        self.inner.put_opts(location, bytes, opts).await
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> object_store::Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.inner.put_multipart(location).await
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        multipart_id: &MultipartId,
+    ) -> object_store::Result<()> {
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        if !Self::cacheable(&options) {
+            return self.inner.get_opts(location, options).await;
+        }
+
+        // if we have a cached copy, only fetch the object if it's changed since
+        let known = self.cached.lock().unwrap().get(location).cloned();
+        // cacheable reads don't set any other options
+        let request = GetOptions {
+            if_none_match: known.as_ref().and_then(|meta| meta.e_tag.clone()),
+            range: options.range.clone(),
+            ..Default::default()
+        };
+        let result = match self.inner.get_opts(location, request).await {
+            Err(object_store::Error::NotModified { .. }) => None,
+            result => Some(result?),
+        };
+
+        let meta = match (&result, known) {
+            (Some(result), _) => result.meta.clone(),
+            (None, Some(known)) => known,
+            (None, None) => unreachable!("only conditional reads are not modified"),
+        };
+        let Some(key) = self.key(location, &meta) else {
+            return match result {
+                Some(result) => Ok(result),
+                None => self.inner.get_opts(location, options).await,
+            };
+        };
+
+        // the cache may also already have this version from before we started, in which case we
+        // can stop reading the response
+        if let Some(path) = self.cache.get(&key) {
+            match Self::read_cached(&path, None, meta.clone(), options.range.clone()).await {
+                Ok(cached) => {
+                    CACHE_HITS.inc();
+                    self.cached.lock().unwrap().insert(location.clone(), meta);
+                    return Ok(cached);
+                }
+                Err(e) => {
+                    warn!("failed to read {} from storage cache: {}", location, e);
+                    self.cache.remove(&key);
+                }
+            }
+        }
+        CACHE_MISSES.inc();
+
+        let result = match result {
+            Some(result) => result,
+            // our cached copy was evicted after we checked for changes
+            None => {
+                let request = GetOptions {
+                    range: options.range.clone(),
+                    ..Default::default()
+                };
+                self.inner.get_opts(location, request).await?
+            }
+        };
+
+        if options.range.is_some() {
+            // only whole objects are cached
+            return Ok(result);
+        }
+
+        let (path, file) = match self.cache.insert(&key, result.into_stream()).await {
+            Ok(inserted) => inserted,
+            Err(e) => {
+                warn!("failed to write {} to storage cache: {}", location, e);
+                return self.inner.get_opts(location, options).await;
+            }
+        };
+        self.cached
+            .lock()
+            .unwrap()
+            .insert(location.clone(), meta.clone());
+
+        match Self::read_cached(&path, Some(file), meta, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                warn!("failed to read {} from storage cache: {}", location, e);
+                self.inner.get_opts(location, options).await
+            }
+        }
+    }
+
+    async fn head(&self, location: &Path) -> object_store::Result<ObjectMeta> {
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        // cached copies of deleted objects are never read again, and age out of the cache
+        self.cached.lock().unwrap().remove(location);
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FaultInjectingObjectStore;
+    use object_store::memory::InMemory;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "arroyo-storage-cache-{}-{}",
+            name,
+            rand::random::<u64>()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_read_through() {
+        let inner = Arc::new(InMemory::new());
+        let cache = Arc::new(DiskCache::open(dir("read-through"), 1024).unwrap());
+        let store = CachedObjectStore::new(inner.clone(), cache.clone(), "mem://".to_string());
+
+        let path = Path::from("a/b");
+        inner
+            .put(&path, Bytes::from_static(b"hello world"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get(&path).await.unwrap().bytes().await.unwrap(),
+            Bytes::from_static(b"hello world")
+        );
+        assert_eq!(cache.state.lock().unwrap().entries.len(), 1);
+
+        // served from the cache
+        assert_eq!(
+            store.get_range(&path, 6..11).await.unwrap(),
+            Bytes::from_static(b"world")
+        );
+        let suffix = GetOptions {
+            range: Some(GetRange::Suffix(5)),
+            ..Default::default()
+        };
+        assert_eq!(
+            store
+                .get_opts(&path, suffix)
+                .await
+                .unwrap()
+                .bytes()
+                .await
+                .unwrap(),
+            Bytes::from_static(b"world")
+        );
+
+        // a new version of the object is cached separately
+        inner
+            .put(&path, Bytes::from_static(b"goodbye"))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get(&path).await.unwrap().bytes().await.unwrap(),
+            Bytes::from_static(b"goodbye")
+        );
+        assert_eq!(cache.state.lock().unwrap().entries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_one_request_per_read() {
+        let dir = dir("requests");
+        let inner = Arc::new(FaultInjectingObjectStore::new());
+        let cache = Arc::new(DiskCache::open(&dir, 1024).unwrap());
+        let store = CachedObjectStore::new(inner.clone(), cache, "mem://".to_string());
+
+        let path = Path::from("a/b");
+        inner
+            .inner()
+            .put(&path, Bytes::from_static(b"hello world"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get(&path).await.unwrap().bytes().await.unwrap(),
+            Bytes::from_static(b"hello world")
+        );
+        assert_eq!(inner.operations(), 1);
+
+        // unchanged, so served from the cache
+        assert_eq!(
+            store.get_range(&path, 6..11).await.unwrap(),
+            Bytes::from_static(b"world")
+        );
+        assert_eq!(inner.operations(), 2);
+
+        // a cached object that disappears from under us is read from the store instead
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            std::fs::remove_file(entry.unwrap().path()).unwrap();
+        }
+        assert_eq!(
+            store.get(&path).await.unwrap().bytes().await.unwrap(),
+            Bytes::from_static(b"hello world")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lru_eviction() {
+        let dir = dir("eviction");
+        let inner = Arc::new(InMemory::new());
+        let cache = Arc::new(DiskCache::open(&dir, 25).unwrap());
+        let store = CachedObjectStore::new(inner.clone(), cache.clone(), "mem://".to_string());
+
+        for name in ["a", "b", "c"] {
+            inner
+                .put(&Path::from(name), Bytes::from(vec![0; 10]))
+                .await
+                .unwrap();
+        }
+
+        store.get(&Path::from("a")).await.unwrap();
+        store.get(&Path::from("b")).await.unwrap();
+        // a is now more recently used than b
+        store.get(&Path::from("a")).await.unwrap();
+        store.get(&Path::from("c")).await.unwrap();
+
+        let state = cache.state.lock().unwrap();
+        assert_eq!(state.size, 20);
+        assert_eq!(state.entries.len(), 2);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        drop(state);
+
+        // existing objects are picked up when the cache is reopened
+        let reopened = DiskCache::open(&dir, 25).unwrap();
+        assert_eq!(reopened.state.lock().unwrap().size, 20);
+    }
+}
@@ -0,0 +1,737 @@
+//! Client-side encryption for object stores.
+//!
+//! Parquet files are encrypted with Parquet modular encryption (see [crate::parquet_encryption]),
+//! so that they stay readable by other parquet implementations given the key. Everything else
+//! (e.g., protobuf metadata) is envelope-encrypted: each object is encrypted with its own
+//! randomly generated data key, which is itself encrypted ("wrapped") with a master key and
+//! stored in the object's header. The body is split into fixed-size segments that are
+//! individually authenticated, so byte ranges can be read without fetching and decrypting the
+//! whole object.
+//!
+//! The layout of an envelope-encrypted object is
+//!
+//! ```text
+//! magic (8) | wrapped data key (12 nonce + 32 key + 16 tag) | nonce prefix (7) | segments...
+//! ```
+//!
+//! where every segment holds [`SEGMENT_SIZE`] bytes of plaintext plus a 16 byte tag, except for
+//! the final segment which holds the remainder (possibly nothing). The nonce for each segment is
+//! the prefix followed by the segment index and a flag marking the final segment, which prevents
+//! segments from being reordered or the object from being truncated.
+
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetRange, GetResult, GetResultPayload, ListResult, MultipartId, ObjectMeta,
+    ObjectStore, PutOptions, PutResult,
+};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWrite;
+use tracing::warn;
+
+use crate::{parquet_encryption, resolve_range, StorageError};
+
+const MAGIC: &[u8; 8] = b"ARYENC01";
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const NONCE_PREFIX_SIZE: usize = 7;
+const WRAPPED_KEY_SIZE: usize = NONCE_SIZE + KEY_SIZE + TAG_SIZE;
+const HEADER_SIZE: usize = MAGIC.len() + WRAPPED_KEY_SIZE + NONCE_PREFIX_SIZE;
+
+/// Amount of plaintext in each encrypted segment
+pub const SEGMENT_SIZE: usize = 64 * 1024;
+const ENCRYPTED_SEGMENT_SIZE: usize = SEGMENT_SIZE + TAG_SIZE;
+
+const STORE_NAME: &str = "EncryptedObjectStore";
+
+pub(crate) fn error(message: impl Into<String>) -> object_store::Error {
+    object_store::Error::Generic {
+        store: STORE_NAME,
+        source: message.into().into(),
+    }
+}
+
+/// A 256-bit master key, which encrypts parquet files and wraps the data keys of other objects
+#[derive(Clone)]
+pub struct EncryptionKey([u8; KEY_SIZE]);
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(<redacted>)")
+    }
+}
+
+impl EncryptionKey {
+    pub fn new(key: [u8; KEY_SIZE]) -> Self {
+        Self(key)
+    }
+
+    /// Parses a base64-encoded 256-bit key, ignoring surrounding whitespace
+    pub fn from_base64(encoded: &str) -> Result<Self, StorageError> {
+        let bytes = STANDARD.decode(encoded.trim()).map_err(|e| {
+            StorageError::InvalidEncryptionKey(format!("key is not valid base64: {}", e))
+        })?;
+
+        let key: [u8; KEY_SIZE] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+            StorageError::InvalidEncryptionKey(format!(
+                "key must be {} bytes, but was {}",
+                KEY_SIZE,
+                bytes.len()
+            ))
+        })?;
+
+        Ok(Self(key))
+    }
+
+    pub(crate) fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.0))
+    }
+
+    /// Identifies the key in the metadata of encrypted parquet files, without revealing it
+    pub(crate) fn key_metadata(&self) -> Vec<u8> {
+        let digest = Sha256::digest(self.0);
+        let fingerprint: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("arroyo:sha256:{}", fingerprint).into_bytes()
+    }
+}
+
+/// The per-object state needed to encrypt or decrypt its segments
+struct ObjectCipher {
+    cipher: Aes256Gcm,
+    nonce_prefix: [u8; NONCE_PREFIX_SIZE],
+}
+
+impl ObjectCipher {
+    /// Generates a new data key, returning the cipher along with the header to write at the
+    /// start of the object
+    fn generate(master: &EncryptionKey) -> (Self, Vec<u8>) {
+        let data_key: [u8; KEY_SIZE] = rand::random();
+        let key_nonce: [u8; NONCE_SIZE] = rand::random();
+        let nonce_prefix: [u8; NONCE_PREFIX_SIZE] = rand::random();
+
+        let wrapped = master
+            .cipher()
+            .encrypt(
+                Nonce::from_slice(&key_nonce),
+                Payload {
+                    msg: &data_key,
+                    aad: MAGIC,
+                },
+            )
+            .expect("encrypting the data key cannot fail");
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&key_nonce);
+        header.extend_from_slice(&wrapped);
+        header.extend_from_slice(&nonce_prefix);
+
+        (
+            Self {
+                cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key)),
+                nonce_prefix,
+            },
+            header,
+        )
+    }
+
+    fn from_header(master: &EncryptionKey, header: &[u8]) -> object_store::Result<Self> {
+        if header.len() < HEADER_SIZE || &header[..MAGIC.len()] != MAGIC {
+            return Err(error(
+                "object is not encrypted, or was written with an unsupported format",
+            ));
+        }
+
+        let key_nonce = &header[MAGIC.len()..MAGIC.len() + NONCE_SIZE];
+        let wrapped = &header[MAGIC.len() + NONCE_SIZE..MAGIC.len() + WRAPPED_KEY_SIZE];
+        let data_key = master
+            .cipher()
+            .decrypt(
+                Nonce::from_slice(key_nonce),
+                Payload {
+                    msg: wrapped,
+                    aad: MAGIC,
+                },
+            )
+            .map_err(|_| {
+                error("failed to unwrap data key; the object was encrypted with a different key")
+            })?;
+
+        let mut nonce_prefix = [0; NONCE_PREFIX_SIZE];
+        nonce_prefix.copy_from_slice(&header[MAGIC.len() + WRAPPED_KEY_SIZE..HEADER_SIZE]);
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key)),
+            nonce_prefix,
+        })
+    }
+
+    fn nonce(&self, index: usize, last: bool) -> [u8; NONCE_SIZE] {
+        let mut nonce = [0; NONCE_SIZE];
+        nonce[..NONCE_PREFIX_SIZE].copy_from_slice(&self.nonce_prefix);
+        nonce[NONCE_PREFIX_SIZE..NONCE_SIZE - 1].copy_from_slice(&(index as u32).to_be_bytes());
+        nonce[NONCE_SIZE - 1] = last as u8;
+        nonce
+    }
+
+    fn encrypt_segment(&self, index: usize, last: bool, plaintext: &[u8]) -> Vec<u8> {
+        self.cipher
+            .encrypt(Nonce::from_slice(&self.nonce(index, last)), plaintext)
+            .expect("encrypting a segment cannot fail")
+    }
+
+    fn decrypt_segment(
+        &self,
+        index: usize,
+        last: bool,
+        ciphertext: &[u8],
+    ) -> object_store::Result<Vec<u8>> {
+        self.cipher
+            .decrypt(Nonce::from_slice(&self.nonce(index, last)), ciphertext)
+            .map_err(|_| error(format!("segment {} of object failed authentication", index)))
+    }
+}
+
+/// The number of segments in an encrypted object with the given total size
+fn segment_count(encrypted_size: usize) -> object_store::Result<usize> {
+    let body = encrypted_size
+        .checked_sub(HEADER_SIZE + TAG_SIZE)
+        .ok_or_else(|| error("encrypted object is truncated"))?;
+    Ok(body / ENCRYPTED_SEGMENT_SIZE + 1)
+}
+
+/// The size of the plaintext of an encrypted object with the given total size
+pub fn plaintext_size(encrypted_size: usize) -> object_store::Result<usize> {
+    Ok(encrypted_size - HEADER_SIZE - segment_count(encrypted_size)? * TAG_SIZE)
+}
+
+/// Encrypts a complete object
+pub fn encrypt(key: &EncryptionKey, plaintext: &[u8]) -> Vec<u8> {
+    let (cipher, mut out) = ObjectCipher::generate(key);
+    let segments = plaintext.len() / SEGMENT_SIZE + 1;
+    out.reserve(plaintext.len() + segments * TAG_SIZE);
+
+    for index in 0..segments {
+        let start = index * SEGMENT_SIZE;
+        let end = (start + SEGMENT_SIZE).min(plaintext.len());
+        out.extend(cipher.encrypt_segment(index, index == segments - 1, &plaintext[start..end]));
+    }
+
+    out
+}
+
+/// Decrypts a complete object
+pub fn decrypt(key: &EncryptionKey, data: &[u8]) -> object_store::Result<Vec<u8>> {
+    let cipher = ObjectCipher::from_header(key, data)?;
+    let segments = segment_count(data.len())?;
+    decrypt_segments(&cipher, 0..segments, segments, &data[HEADER_SIZE..])
+}
+
+fn decrypt_segments(
+    cipher: &ObjectCipher,
+    indices: Range<usize>,
+    total_segments: usize,
+    data: &[u8],
+) -> object_store::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(indices.len() * SEGMENT_SIZE);
+    for (i, segment) in indices.zip(data.chunks(ENCRYPTED_SEGMENT_SIZE)) {
+        out.extend(cipher.decrypt_segment(i, i == total_segments - 1, segment)?);
+    }
+    Ok(out)
+}
+
+/// Encrypts a complete object, using modular encryption if it's a parquet file
+fn encrypt_object(key: &EncryptionKey, plaintext: &[u8]) -> Vec<u8> {
+    if parquet_encryption::is_parquet(plaintext) {
+        match parquet_encryption::encrypt(key, plaintext) {
+            Ok(encrypted) => return encrypted,
+            Err(e) => {
+                warn!(
+                    "failed to apply parquet modular encryption, falling back to envelope encryption: {}",
+                    e
+                );
+            }
+        }
+    }
+    encrypt(key, plaintext)
+}
+
+/// Decrypts a complete object written by [encrypt_object]. Objects that were written before
+/// encryption was enabled for the store are returned as they are.
+fn decrypt_object(key: &EncryptionKey, data: &[u8]) -> object_store::Result<Vec<u8>> {
+    match StoredFormat::detect(data) {
+        StoredFormat::Envelope => decrypt(key, data),
+        StoredFormat::EncryptedParquet => parquet_encryption::decrypt(key, data),
+        StoredFormat::Plaintext => Ok(data.to_vec()),
+    }
+}
+
+/// How an object read through an [EncryptedObjectStore] was stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StoredFormat {
+    Envelope,
+    EncryptedParquet,
+    /// written before encryption was enabled
+    Plaintext,
+}
+
+impl StoredFormat {
+    /// Detects the format from the start of the object, which must include at least the
+    /// envelope magic if the object is long enough
+    fn detect(prefix: &[u8]) -> Self {
+        if prefix.starts_with(MAGIC) {
+            Self::Envelope
+        } else if prefix.starts_with(parquet_encryption::ENCRYPTED_MAGIC) {
+            Self::EncryptedParquet
+        } else {
+            Self::Plaintext
+        }
+    }
+}
+
+/// An [ObjectStore] that transparently encrypts everything written to the underlying store
+/// and decrypts everything read from it. Object sizes reported by `head` are the plaintext
+/// sizes, while `list` reports the sizes of the stored objects, as finding the plaintext size
+/// of an encrypted parquet file requires decrypting it.
+#[derive(Debug)]
+pub struct EncryptedObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    key: EncryptionKey,
+}
+
+impl EncryptedObjectStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, key: EncryptionKey) -> Self {
+        Self { inner, key }
+    }
+
+    async fn get_decrypted(&self, location: &Path) -> object_store::Result<(Bytes, ObjectMeta)> {
+        let result = self.inner.get(location).await?;
+        let mut meta = result.meta.clone();
+        let plaintext = decrypt_object(&self.key, &result.bytes().await?)?;
+        meta.size = plaintext.len();
+        Ok((plaintext.into(), meta))
+    }
+
+    /// Reads the header of an object, returning it along with the format it was stored in
+    async fn header(
+        &self,
+        location: &Path,
+        meta: &ObjectMeta,
+    ) -> object_store::Result<(StoredFormat, Bytes)> {
+        if meta.size == 0 {
+            // encrypted objects always have a header, so this was written before encryption
+            return Ok((StoredFormat::Plaintext, Bytes::new()));
+        }
+        let header = self
+            .inner
+            .get_range(location, 0..HEADER_SIZE.min(meta.size))
+            .await?;
+        Ok((StoredFormat::detect(&header), header))
+    }
+
+    async fn get_range_decrypted(
+        &self,
+        location: &Path,
+        range: GetRange,
+    ) -> object_store::Result<(Bytes, ObjectMeta, Range<usize>)> {
+        let meta = self.inner.head(location).await?;
+        let header = match self.header(location, &meta).await? {
+            (StoredFormat::Envelope, header) => header,
+            (StoredFormat::EncryptedParquet, _) => {
+                // parquet files can only be decrypted whole
+                let (plaintext, meta) = self.get_decrypted(location).await?;
+                let range = Self::resolve(&range, meta.size)?;
+                return Ok((plaintext.slice(range.clone()), meta, range));
+            }
+            (StoredFormat::Plaintext, _) => {
+                let range = Self::resolve(&range, meta.size)?;
+                let bytes = self.inner.get_range(location, range.clone()).await?;
+                return Ok((bytes, meta, range));
+            }
+        };
+
+        let encrypted_size = meta.size;
+        let total_segments = segment_count(encrypted_size)?;
+        let mut meta = meta;
+        meta.size = plaintext_size(encrypted_size)?;
+        let range = Self::resolve(&range, meta.size)?;
+
+        let first = range.start / SEGMENT_SIZE;
+        let last = (range.end / SEGMENT_SIZE).min(total_segments - 1);
+        let encrypted_range = HEADER_SIZE + first * ENCRYPTED_SEGMENT_SIZE
+            ..(HEADER_SIZE + (last + 1) * ENCRYPTED_SEGMENT_SIZE).min(encrypted_size);
+
+        let body = self.inner.get_range(location, encrypted_range).await?;
+        let cipher = ObjectCipher::from_header(&self.key, &header)?;
+        let plaintext = decrypt_segments(&cipher, first..last + 1, total_segments, &body)?;
+
+        let offset = first * SEGMENT_SIZE;
+        let bytes = Bytes::from(plaintext).slice(range.start - offset..range.end - offset);
+        Ok((bytes, meta, range))
+    }
+
+    fn resolve(range: &GetRange, size: usize) -> object_store::Result<Range<usize>> {
+        resolve_range(range, size).ok_or_else(|| {
+            error(format!(
+                "range {:?} is out of bounds for object of size {}",
+                range, size
+            ))
+        })
+    }
+}
+
+impl std::fmt::Display for EncryptedObjectStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Encrypted({})", self.inner)
+    }
+}
+
+fn single_chunk(bytes: Bytes) -> GetResultPayload {
+    GetResultPayload::Stream(futures::stream::once(async move { Ok(bytes) }).boxed())
+}
+
+#[async_trait]
+impl ObjectStore for EncryptedObjectStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        self.inner
+            .put_opts(location, encrypt_object(&self.key, &bytes).into(), opts)
+            .await
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> object_store::Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        let (id, writer) = self.inner.put_multipart(location).await?;
+        Ok((id, Box::new(EncryptingWriter::new(&self.key, writer))))
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        multipart_id: &MultipartId,
+    ) -> object_store::Result<()> {
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        if options.head {
+            let meta = self.head(location).await?;
+            return Ok(GetResult {
+                range: 0..meta.size,
+                meta,
+                payload: single_chunk(Bytes::new()),
+            });
+        }
+
+        if let Some(range) = options.range.clone() {
+            let (bytes, meta, range) = self.get_range_decrypted(location, range).await?;
+            return Ok(GetResult {
+                payload: single_chunk(bytes),
+                meta,
+                range,
+            });
+        }
+
+        let result = self.inner.get_opts(location, options).await?;
+        let mut meta = result.meta.clone();
+        let plaintext = decrypt_object(&self.key, &result.bytes().await?)?;
+        meta.size = plaintext.len();
+
+        Ok(GetResult {
+            range: 0..plaintext.len(),
+            meta,
+            payload: single_chunk(plaintext.into()),
+        })
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> object_store::Result<Bytes> {
+        Ok(self
+            .get_range_decrypted(location, GetRange::Bounded(range))
+            .await?
+            .0)
+    }
+
+    async fn head(&self, location: &Path) -> object_store::Result<ObjectMeta> {
+        let mut meta = self.inner.head(location).await?;
+        match self.header(location, &meta).await?.0 {
+            StoredFormat::Envelope => {
+                meta.size = plaintext_size(meta.size)?;
+                Ok(meta)
+            }
+            StoredFormat::EncryptedParquet => Ok(self.get_decrypted(location).await?.1),
+            StoredFormat::Plaintext => Ok(meta),
+        }
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        // the data key is stored with the object, so it can be copied as-is
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+/// Buffers an object written through a multipart upload, and encrypts it into the upload once
+/// the writer is shut down. Parquet files can only be encrypted once their footer has been
+/// written, so this can't be done as the data arrives.
+struct EncryptingWriter {
+    key: EncryptionKey,
+    inner: Box<dyn AsyncWrite + Unpin + Send>,
+    // plaintext written so far
+    buffer: Vec<u8>,
+    // the encrypted object, once the writer has been shut down
+    pending: Option<Vec<u8>>,
+    pending_offset: usize,
+}
+
+impl EncryptingWriter {
+    fn new(key: &EncryptionKey, inner: Box<dyn AsyncWrite + Unpin + Send>) -> Self {
+        Self {
+            key: key.clone(),
+            inner,
+            buffer: vec![],
+            pending: None,
+            pending_offset: 0,
+        }
+    }
+}
+
+impl AsyncWrite for EncryptingWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.pending.is_some() {
+            return Poll::Ready(Err(io::Error::other("write after shutdown")));
+        }
+
+        self.buffer.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // nothing is written to the upload until the object is complete
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if this.pending.is_none() {
+            let plaintext = std::mem::take(&mut this.buffer);
+            this.pending = Some(encrypt_object(&this.key, &plaintext));
+        }
+
+        let pending = this.pending.as_ref().unwrap();
+        while this.pending_offset < pending.len() {
+            let n =
+                ready!(Pin::new(&mut this.inner).poll_write(cx, &pending[this.pending_offset..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            this.pending_offset += n;
+        }
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+    use tokio::io::AsyncWriteExt;
+
+    fn key() -> EncryptionKey {
+        EncryptionKey::new([7; KEY_SIZE])
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for len in [0, 1, SEGMENT_SIZE - 1, SEGMENT_SIZE, 3 * SEGMENT_SIZE + 17] {
+            let plaintext = data(len);
+            let encrypted = encrypt(&key(), &plaintext);
+            assert_eq!(plaintext_size(encrypted.len()).unwrap(), len);
+            assert_eq!(decrypt(&key(), &encrypted).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_wrong_key_and_tampering() {
+        let mut encrypted = encrypt(&key(), &data(100));
+        assert!(decrypt(&EncryptionKey::new([8; KEY_SIZE]), &encrypted).is_err());
+
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(decrypt(&key(), &encrypted).is_err());
+
+        // dropping the final segment must be detected
+        let encrypted = encrypt(&key(), &data(SEGMENT_SIZE * 2));
+        assert!(decrypt(
+            &key(),
+            &encrypted[..HEADER_SIZE + ENCRYPTED_SEGMENT_SIZE * 2]
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_object_store() {
+        let inner = Arc::new(InMemory::new());
+        let store = EncryptedObjectStore::new(inner.clone(), key());
+        let plaintext = data(2 * SEGMENT_SIZE + 100);
+
+        let path = Path::from("a");
+        store.put(&path, plaintext.clone().into()).await.unwrap();
+        assert_ne!(
+            inner.get(&path).await.unwrap().bytes().await.unwrap(),
+            plaintext
+        );
+
+        let (_, mut writer) = store.put_multipart(&Path::from("b")).await.unwrap();
+        for chunk in plaintext.chunks(1000) {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+
+        for path in ["a", "b"] {
+            let path = Path::from(path);
+            let read = store.get(&path).await.unwrap().bytes().await.unwrap();
+            assert_eq!(read, plaintext);
+            assert_eq!(store.head(&path).await.unwrap().size, plaintext.len());
+
+            for range in [
+                0..10,
+                SEGMENT_SIZE - 5..SEGMENT_SIZE + 5,
+                100..plaintext.len(),
+            ] {
+                assert_eq!(
+                    store.get_range(&path, range.clone()).await.unwrap(),
+                    plaintext[range]
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_objects() {
+        // objects written before encryption was enabled can still be read
+        let inner = Arc::new(InMemory::new());
+        let store = EncryptedObjectStore::new(inner.clone(), key());
+
+        let parquet = parquet_encryption::tests::parquet_file();
+        for (name, plaintext) in [
+            ("a", data(2 * SEGMENT_SIZE + 100)),
+            ("b", parquet),
+            ("c", vec![]),
+        ] {
+            let path = Path::from(name);
+            inner.put(&path, plaintext.clone().into()).await.unwrap();
+
+            let read = store.get(&path).await.unwrap().bytes().await.unwrap();
+            assert_eq!(read, plaintext);
+            assert_eq!(store.head(&path).await.unwrap().size, plaintext.len());
+            if !plaintext.is_empty() {
+                assert_eq!(
+                    store.get_range(&path, 1..plaintext.len()).await.unwrap(),
+                    plaintext[1..]
+                );
+            }
+        }
+
+        // while new writes are encrypted
+        let path = Path::from("a");
+        store.put(&path, data(100).into()).await.unwrap();
+        assert!(inner
+            .get(&path)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap()
+            .starts_with(MAGIC));
+        assert_eq!(
+            store.get(&path).await.unwrap().bytes().await.unwrap(),
+            data(100)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parquet_object_store() {
+        let inner = Arc::new(InMemory::new());
+        let store = EncryptedObjectStore::new(inner.clone(), key());
+        let plaintext = parquet_encryption::tests::parquet_file();
+        let rows = |data: Bytes| -> usize {
+            parquet_encryption::tests::read(data.to_vec())
+                .iter()
+                .map(|b| b.num_rows())
+                .sum()
+        };
+
+        store
+            .put(&Path::from("a"), plaintext.clone().into())
+            .await
+            .unwrap();
+        let (_, mut writer) = store.put_multipart(&Path::from("b")).await.unwrap();
+        for chunk in plaintext.chunks(1000) {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+
+        for path in ["a", "b"] {
+            let path = Path::from(path);
+            let stored = inner.get(&path).await.unwrap().bytes().await.unwrap();
+            assert!(parquet_encryption::is_encrypted(&stored));
+
+            let read = store.get(&path).await.unwrap().bytes().await.unwrap();
+            assert!(parquet_encryption::is_parquet(&read));
+            assert_eq!(rows(read.clone()), 1000);
+            assert_eq!(store.head(&path).await.unwrap().size, read.len());
+            assert_eq!(
+                store.get_range(&path, 10..100).await.unwrap(),
+                read.slice(10..100)
+            );
+        }
+    }
+}
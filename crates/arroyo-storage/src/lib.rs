@@ -1,4 +1,5 @@
 use std::future::ready;
+use std::ops::Range;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::{
@@ -6,24 +7,37 @@ use std::{
     sync::{Arc, OnceLock},
 };
 
-use arroyo_rpc::retry;
+use arroyo_rpc::config::config;
 use aws::ArroyoCredentialProvider;
 use bytes::Bytes;
+use futures::stream::BoxStream;
 use futures::{Stream, StreamExt};
 use object_store::aws::{AmazonS3ConfigKey, AwsCredential};
+use object_store::azure::{AzureConfigKey, MicrosoftAzureBuilder};
 use object_store::gcp::GoogleCloudStorageBuilder;
 use object_store::multipart::PartId;
 use object_store::path::Path;
-use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, ObjectStore};
-use object_store::{CredentialProvider, MultipartId};
+use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, ObjectMeta, ObjectStore};
+use object_store::{CredentialProvider, GetRange, MultipartId};
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
 use std::time::{Duration, Instant};
 use thiserror::Error;
+use tokio::io::AsyncWriteExt;
 use tokio::sync::RwLock;
-use tracing::{debug, error, trace};
+use tracing::{debug, trace, warn};
 
 mod aws;
+mod cache;
+mod encryption;
+mod parquet_encryption;
+mod retry;
+#[cfg(test)]
+pub mod testing;
+
+pub use cache::{CachedObjectStore, DiskCache};
+pub use encryption::{EncryptedObjectStore, EncryptionKey};
+pub use retry::RetryPolicy;
 
 /// A reference-counted reference to a [StorageProvider].
 pub type StorageProviderRef = Arc<StorageProvider>;
@@ -37,6 +51,8 @@ pub struct StorageProvider {
     // May require storage_options to properly instantiate
     object_store_base_url: String,
     storage_options: HashMap<String, String>,
+    aws_credentials: Option<Arc<ArroyoCredentialProvider>>,
+    retry_policy: RetryPolicy,
 }
 
 #[derive(Error, Debug)]
@@ -55,6 +71,100 @@ pub enum StorageError {
 
     #[error("failed to load credentials: {0}")]
     CredentialsError(String),
+
+    #[error("invalid encryption key: {0}")]
+    InvalidEncryptionKey(String),
+
+    #[error("storage operation timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+impl StorageError {
+    /// Whether the operation that failed with this error may succeed if retried
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            StorageError::ObjectStore(e) => is_retryable(e),
+            StorageError::Timeout(_) => true,
+            StorageError::InvalidUrl
+            | StorageError::PathError(_)
+            | StorageError::NoKeyInUrl
+            | StorageError::CredentialsError(_)
+            | StorageError::InvalidEncryptionKey(_) => false,
+        }
+    }
+
+    fn into_object_store_error(self) -> object_store::Error {
+        match self {
+            StorageError::ObjectStore(e) => e,
+            e => object_store::Error::Generic {
+                store: "StorageProvider",
+                source: Box::new(e),
+            },
+        }
+    }
+}
+
+/// Resolves a requested [GetRange] against an object of `len` bytes, following the semantics
+/// object_store gives them; returns None if the range doesn't overlap the object
+pub(crate) fn resolve_range(range: &GetRange, len: usize) -> Option<Range<usize>> {
+    match range {
+        GetRange::Bounded(r) if r.start >= r.end || r.start >= len => None,
+        GetRange::Bounded(r) => Some(r.start..r.end.min(len)),
+        GetRange::Offset(o) if *o >= len => None,
+        GetRange::Offset(o) => Some(*o..len),
+        GetRange::Suffix(n) => Some(len.saturating_sub(*n)..len),
+    }
+}
+
+/// Whether a failed object store operation may succeed if retried. Only errors known to be
+/// transient are retried: timeouts, dropped or refused connections, and responses that indicate
+/// the service is failing or overloaded (5xx and 429). Everything else, e.g. missing objects,
+/// failed preconditions, permission errors or data that fails to decrypt, will fail the same way
+/// on every attempt. Statuses are only known for responses that object_store reports through a
+/// [`reqwest::Error`]; it doesn't expose the status of the client errors it returns itself.
+fn is_retryable(e: &object_store::Error) -> bool {
+    let source = match e {
+        object_store::Error::Generic { source, .. } => source,
+        // missing objects, failed preconditions, invalid paths and so on have their own variants
+        _ => return false,
+    };
+
+    let mut next: Option<&(dyn std::error::Error + 'static)> = Some(source.as_ref());
+    while let Some(e) = next {
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout()
+                || e.is_connect()
+                // the connection was dropped while reading or writing the body
+                || e.is_body()
+                || e.status().is_some_and(|s| is_transient_status(s.as_u16()))
+            {
+                return true;
+            }
+        } else if let Some(e) = e.downcast_ref::<std::io::Error>() {
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::NotConnected
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::UnexpectedEof
+                    | std::io::ErrorKind::Interrupted
+            ) {
+                return true;
+            }
+        } else if e.is::<tokio::time::error::Elapsed>() {
+            return true;
+        }
+        next = e.source();
+    }
+
+    false
+}
+
+fn is_transient_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
 }
 
 // https://s3.us-west-2.amazonaws.com/DOC-EXAMPLE-BUCKET1/puppy.jpg
@@ -83,11 +193,19 @@ const GCS_PATH: &str =
     r"^https://storage\.googleapis\.com/(?P<bucket>[a-z\d\-_\.]+)(/(?P<key>.+))?$";
 const GCS_URL: &str = r"^[gG][sS]://(?P<bucket>[a-z0-9\-\.]+)(/(?P<key>.+))?$";
 
+// abfss://CONTAINER@ACCOUNT.dfs.core.windows.net/OBJECT_NAME
+const AZURE_ABFS: &str = r"^abfss?://(?P<container>[a-z0-9\-]+)@(?P<account>[a-z0-9]+)\.dfs\.core\.windows\.net(/(?P<key>.+))?$";
+// https://ACCOUNT.blob.core.windows.net/CONTAINER/OBJECT_NAME
+const AZURE_HTTPS: &str = r"^https://(?P<account>[a-z0-9]+)\.(blob|dfs)\.core\.windows\.net/(?P<container>[a-z0-9\-]+)(/(?P<key>.+))?$";
+// az://CONTAINER/OBJECT_NAME, with the account taken from AZURE_STORAGE_ACCOUNT_NAME
+const AZURE_URL: &str = r"^az(ure)?://(?P<container>[a-z0-9\-]+)(/(?P<key>.+))?$";
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Copy)]
 enum Backend {
     S3,
     #[allow(clippy::upper_case_acronyms)]
     GCS,
+    Azure,
     Local,
 }
 
@@ -115,6 +233,15 @@ fn matchers() -> &'static HashMap<Backend, Vec<Regex>> {
             ],
         );
 
+        m.insert(
+            Backend::Azure,
+            vec![
+                Regex::new(AZURE_ABFS).unwrap(),
+                Regex::new(AZURE_HTTPS).unwrap(),
+                Regex::new(AZURE_URL).unwrap(),
+            ],
+        );
+
         m.insert(
             Backend::Local,
             vec![
@@ -128,16 +255,27 @@ fn matchers() -> &'static HashMap<Backend, Vec<Regex>> {
     })
 }
 
-macro_rules! storage_retry {
-    ($e: expr) => {
-        retry!(
-            $e,
-            10,
-            Duration::from_millis(100),
-            Duration::from_secs(10),
-            |e| error!("Error: {}. Retrying...", e)
-        )
-    };
+// options that configure S3 credentials for a single URL, in addition to those supported by
+// object_store's AmazonS3ConfigKey
+const AWS_PROFILE_OPTION: &str = "aws_profile";
+const AWS_ROLE_ARN_OPTION: &str = "aws_role_arn";
+const AWS_ROLE_SESSION_NAME_OPTION: &str = "aws_role_session_name";
+const AWS_ROLE_EXTERNAL_ID_OPTION: &str = "aws_role_external_id";
+
+/// Credentials for a single S3 URL, used instead of the default credential chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum S3Credentials {
+    Static {
+        access_key_id: String,
+        secret_access_key: String,
+        session_token: Option<String>,
+    },
+    Profile(String),
+    AssumeRole {
+        role_arn: String,
+        session_name: Option<String>,
+        external_id: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -146,6 +284,7 @@ pub struct S3Config {
     region: Option<String>,
     bucket: String,
     key: Option<String>,
+    credentials: Option<S3Credentials>,
 }
 
 impl S3Config {
@@ -155,8 +294,70 @@ impl S3Config {
             region: Some(region),
             bucket,
             key: Some(key),
+            credentials: None,
         }
     }
+
+    /// Applies per-URL storage options on top of the config parsed from the URL and environment,
+    /// returning the remaining options for object_store. An endpoint, region or credentials set
+    /// here take precedence over both.
+    fn apply_options(
+        &mut self,
+        mut options: HashMap<String, String>,
+    ) -> Result<HashMap<AmazonS3ConfigKey, String>, StorageError> {
+        let profile = options.remove(AWS_PROFILE_OPTION);
+        let role_arn = options.remove(AWS_ROLE_ARN_OPTION);
+        let session_name = options.remove(AWS_ROLE_SESSION_NAME_OPTION);
+        let external_id = options.remove(AWS_ROLE_EXTERNAL_ID_OPTION);
+
+        let mut s3_options = HashMap::new();
+        for (key, value) in options {
+            let s3_config_key = key.parse().map_err(|_| {
+                StorageError::CredentialsError(format!("invalid S3 config key: {}", key))
+            })?;
+            s3_options.insert(s3_config_key, value);
+        }
+
+        if let Some(endpoint) = s3_options.remove(&AmazonS3ConfigKey::Endpoint) {
+            self.endpoint = Some(endpoint);
+        }
+
+        if let Some(region) = s3_options.remove(&AmazonS3ConfigKey::Region) {
+            self.region = Some(region);
+        }
+
+        let access_key_id = s3_options.remove(&AmazonS3ConfigKey::AccessKeyId);
+        let secret_access_key = s3_options.remove(&AmazonS3ConfigKey::SecretAccessKey);
+        let session_token = s3_options.remove(&AmazonS3ConfigKey::Token);
+
+        self.credentials = match (access_key_id, secret_access_key, profile, role_arn) {
+            (Some(access_key_id), Some(secret_access_key), None, None) => {
+                Some(S3Credentials::Static {
+                    access_key_id,
+                    secret_access_key,
+                    session_token,
+                })
+            }
+            (None, None, Some(profile), None) => Some(S3Credentials::Profile(profile)),
+            (None, None, None, Some(role_arn)) => Some(S3Credentials::AssumeRole {
+                role_arn,
+                session_name,
+                external_id,
+            }),
+            (None, None, None, None) => self.credentials.take(),
+            _ => {
+                return Err(StorageError::CredentialsError(format!(
+                    "exactly one of {} and {}, {} or {} may be set",
+                    AmazonS3ConfigKey::AccessKeyId.as_ref(),
+                    AmazonS3ConfigKey::SecretAccessKey.as_ref(),
+                    AWS_PROFILE_OPTION,
+                    AWS_ROLE_ARN_OPTION
+                )));
+            }
+        };
+
+        Ok(s3_options)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -165,6 +366,13 @@ pub struct GCSConfig {
     key: Option<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzureConfig {
+    account: Option<String>,
+    container: String,
+    key: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LocalConfig {
     pub path: String,
@@ -175,6 +383,7 @@ pub struct LocalConfig {
 pub enum BackendConfig {
     S3(S3Config),
     GCS(GCSConfig),
+    Azure(AzureConfig),
     Local(LocalConfig),
 }
 
@@ -185,6 +394,7 @@ impl BackendConfig {
                 return match k {
                     Backend::S3 => Self::parse_s3(matches),
                     Backend::GCS => Self::parse_gcs(matches),
+                    Backend::Azure => Self::parse_azure(matches),
                     Backend::Local => Self::parse_local(matches, with_key),
                 };
             }
@@ -201,34 +411,28 @@ impl BackendConfig {
             .as_str()
             .to_string();
 
-        let region = last([
-            std::env::var("AWS_DEFAULT_REGION").ok(),
-            matches.name("region").map(|m| m.as_str().to_string()),
-        ]);
+        let region = matches.name("region").map(|m| m.as_str().to_string());
+
+        let endpoint = matches
+            .name("endpoint")
+            .map(|endpoint| -> Result<String, StorageError> {
+                let port = if let Some(port) = matches.name("port") {
+                    u16::from_str(port.as_str()).map_err(|_| {
+                        StorageError::PathError(format!("invalid port: {}", port.as_str()))
+                    })?
+                } else {
+                    443
+                };
 
-        let endpoint = last([
-            std::env::var("AWS_ENDPOINT").ok(),
-            matches
-                .name("endpoint")
-                .map(|endpoint| -> Result<String, StorageError> {
-                    let port = if let Some(port) = matches.name("port") {
-                        u16::from_str(port.as_str()).map_err(|_| {
-                            StorageError::PathError(format!("invalid port: {}", port.as_str()))
-                        })?
-                    } else {
-                        443
-                    };
-
-                    let protocol = if let Some(protocol) = matches.name("protocol") {
-                        protocol.as_str().to_string()
-                    } else {
-                        "https".to_string()
-                    };
-
-                    Ok(format!("{}://{}:{}", protocol, endpoint.as_str(), port))
-                })
-                .transpose()?,
-        ]);
+                let protocol = if let Some(protocol) = matches.name("protocol") {
+                    protocol.as_str().to_string()
+                } else {
+                    "https".to_string()
+                };
+
+                Ok(format!("{}://{}:{}", protocol, endpoint.as_str(), port))
+            })
+            .transpose()?;
 
         let key = matches.name("key").map(|m| m.as_str().to_string());
 
@@ -237,6 +441,7 @@ impl BackendConfig {
             region,
             bucket,
             key,
+            credentials: None,
         }))
     }
 
@@ -252,6 +457,27 @@ impl BackendConfig {
         Ok(BackendConfig::GCS(GCSConfig { bucket, key }))
     }
 
+    fn parse_azure(matches: Captures) -> Result<Self, StorageError> {
+        let container = matches
+            .name("container")
+            .expect("container should always be available")
+            .as_str()
+            .to_string();
+
+        let account = last([
+            std::env::var("AZURE_STORAGE_ACCOUNT_NAME").ok(),
+            matches.name("account").map(|m| m.as_str().to_string()),
+        ]);
+
+        let key = matches.name("key").map(|m| m.as_str().to_string());
+
+        Ok(BackendConfig::Azure(AzureConfig {
+            account,
+            container,
+            key,
+        }))
+    }
+
     fn parse_local(matches: Captures, with_key: bool) -> Result<Self, StorageError> {
         let path = matches
             .name("path")
@@ -282,6 +508,7 @@ impl BackendConfig {
         match self {
             BackendConfig::S3(s3) => s3.key.as_ref(),
             BackendConfig::GCS(gcs) => gcs.key.as_ref(),
+            BackendConfig::Azure(azure) => azure.key.as_ref(),
             BackendConfig::Local(local) => local.key.as_ref(),
         }
     }
@@ -291,6 +518,28 @@ impl BackendConfig {
     }
 }
 
+/// The S3 options set in the `AWS_*` environment variables (as read by
+/// [`AmazonS3Builder::from_env`]) that apply to a connection. These are only defaults: the
+/// endpoint is only taken from the environment if the connection sets neither an endpoint nor a
+/// region, and credentials only if it doesn't set its own.
+fn s3_env_options(
+    vars: impl IntoIterator<Item = (String, String)>,
+    has_location: bool,
+    has_credentials: bool,
+) -> HashMap<AmazonS3ConfigKey, String> {
+    vars.into_iter()
+        .filter(|(key, _)| key.starts_with("AWS_"))
+        .filter_map(|(key, value)| Some((key.to_ascii_lowercase().parse().ok()?, value)))
+        .filter(|(key, _)| match key {
+            AmazonS3ConfigKey::Endpoint => !has_location,
+            AmazonS3ConfigKey::AccessKeyId
+            | AmazonS3ConfigKey::SecretAccessKey
+            | AmazonS3ConfigKey::Token => !has_credentials,
+            _ => true,
+        })
+        .collect()
+}
+
 fn last<I: Sized, const COUNT: usize>(opts: [Option<I>; COUNT]) -> Option<I> {
     opts.into_iter().flatten().last()
 }
@@ -309,6 +558,30 @@ struct CacheEntry<T> {
     inserted_at: Instant,
 }
 
+/// Returns the process-wide disk cache for remote objects, if enabled in the config
+fn storage_cache() -> Option<Arc<DiskCache>> {
+    static CACHE: OnceLock<Option<Arc<DiskCache>>> = OnceLock::new();
+    CACHE
+        .get_or_init(|| {
+            let config = &config().storage_cache;
+            if !config.enabled {
+                return None;
+            }
+
+            match DiskCache::open(&config.path, config.max_size_mb * 1024 * 1024) {
+                Ok(cache) => Some(Arc::new(cache)),
+                Err(e) => {
+                    warn!(
+                        "failed to open storage cache at {:?}, continuing without it: {}",
+                        config.path, e
+                    );
+                    None
+                }
+            }
+        })
+        .clone()
+}
+
 // The bearer token should last for 3600 seconds,
 // but regenerating it every 5 minutes to avoid token expiry
 const GCS_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
@@ -323,11 +596,14 @@ impl StorageProvider {
     ) -> Result<Self, StorageError> {
         let config: BackendConfig = BackendConfig::parse_url(url, false)?;
 
-        match config {
+        let provider = match config {
             BackendConfig::S3(config) => Self::construct_s3(config, options).await,
             BackendConfig::GCS(config) => Self::construct_gcs(config).await,
+            BackendConfig::Azure(config) => Self::construct_azure(config, options).await,
             BackendConfig::Local(config) => Self::construct_local(config).await,
-        }
+        }?;
+
+        Ok(provider.with_storage_cache())
     }
 
     pub async fn get_url(url: &str) -> Result<Bytes, StorageError> {
@@ -343,8 +619,10 @@ impl StorageProvider {
         let provider = match config {
             BackendConfig::S3(config) => Self::construct_s3(config, options).await,
             BackendConfig::GCS(config) => Self::construct_gcs(config).await,
+            BackendConfig::Azure(config) => Self::construct_azure(config, options).await,
             BackendConfig::Local(config) => Self::construct_local(config).await,
-        }?;
+        }?
+        .with_storage_cache();
 
         provider.get("").await
     }
@@ -354,6 +632,7 @@ impl StorageProvider {
         let key = match &config {
             BackendConfig::S3(s3) => s3.key.as_ref(),
             BackendConfig::GCS(gcs) => gcs.key.as_ref(),
+            BackendConfig::Azure(azure) => azure.key.as_ref(),
             BackendConfig::Local(local) => local.key.as_ref(),
         }
         .ok_or_else(|| StorageError::NoKeyInUrl)?;
@@ -369,51 +648,84 @@ impl StorageProvider {
         mut config: S3Config,
         options: HashMap<String, String>,
     ) -> Result<Self, StorageError> {
-        let mut builder = AmazonS3Builder::from_env().with_bucket_name(&config.bucket);
-        let mut aws_key_manually_set = false;
-        let mut s3_options = HashMap::new();
-        for (key, value) in options {
-            let s3_config_key = key.parse().map_err(|_| {
-                StorageError::CredentialsError(format!("invalid S3 config key: {}", key))
-            })?;
-            if AmazonS3ConfigKey::AccessKeyId == s3_config_key {
-                aws_key_manually_set = true;
+        let mut s3_options = config.apply_options(options)?;
+        let env_options = s3_env_options(
+            std::env::vars(),
+            config.endpoint.is_some() || config.region.is_some(),
+            config.credentials.is_some(),
+        );
+        config.endpoint = config
+            .endpoint
+            .or_else(|| env_options.get(&AmazonS3ConfigKey::Endpoint).cloned());
+        config.region = config.region.or_else(|| {
+            env_options
+                .get(&AmazonS3ConfigKey::Region)
+                .or_else(|| env_options.get(&AmazonS3ConfigKey::DefaultRegion))
+                .cloned()
+        });
+
+        let aws_credentials = match &config.credentials {
+            Some(S3Credentials::Static {
+                access_key_id,
+                secret_access_key,
+                session_token,
+            }) => {
+                s3_options.insert(AmazonS3ConfigKey::AccessKeyId, access_key_id.clone());
+                s3_options.insert(
+                    AmazonS3ConfigKey::SecretAccessKey,
+                    secret_access_key.clone(),
+                );
+                if let Some(token) = session_token {
+                    s3_options.insert(AmazonS3ConfigKey::Token, token.clone());
+                }
+                None
+            }
+            Some(S3Credentials::Profile(profile)) => {
+                if config.region.is_none() {
+                    config.region = ArroyoCredentialProvider::profile_region(profile).await;
+                }
+                Some(ArroyoCredentialProvider::for_profile(profile)?)
             }
-            s3_options.insert(s3_config_key, value.clone());
-            builder = builder.with_config(s3_config_key, value);
+            Some(S3Credentials::AssumeRole {
+                role_arn,
+                session_name,
+                external_id,
+            }) => Some(ArroyoCredentialProvider::for_role(
+                role_arn,
+                session_name.as_deref(),
+                external_id.as_deref(),
+                config.region.as_deref(),
+            )?),
+            None => Some(ArroyoCredentialProvider::try_new()?),
         }
+        .map(Arc::new);
 
-        if !aws_key_manually_set {
-            let credentials: Arc<ArroyoCredentialProvider> =
-                Arc::new(ArroyoCredentialProvider::try_new()?);
-            builder = builder.with_credentials(credentials);
+        if config.region.is_none() {
+            config.region = ArroyoCredentialProvider::default_region().await;
         }
-
-        let default_region = ArroyoCredentialProvider::default_region().await;
-        config.region = config.region.or(default_region);
         if let Some(region) = &config.region {
-            builder = builder.with_region(region);
             s3_options.insert(AmazonS3ConfigKey::Region, region.clone());
         }
 
-        if let Some(endpoint) = config
-            .endpoint
-            .as_ref()
-            .or(s3_options.get(&AmazonS3ConfigKey::Endpoint))
-        {
-            builder = builder
-                .with_endpoint(endpoint)
-                .with_virtual_hosted_style_request(false)
-                .with_allow_http(true);
+        if let Some(endpoint) = &config.endpoint {
             s3_options.insert(AmazonS3ConfigKey::Endpoint, endpoint.clone());
-            s3_options.insert(
-                AmazonS3ConfigKey::VirtualHostedStyleRequest,
-                "false".to_string(),
-            );
-            s3_options.insert(
-                AmazonS3ConfigKey::Client(object_store::ClientConfigKey::AllowHttp),
-                "true".to_string(),
-            );
+            // custom endpoints default to path-style requests over http, unless set for this URL
+            s3_options
+                .entry(AmazonS3ConfigKey::VirtualHostedStyleRequest)
+                .or_insert_with(|| "false".to_string());
+            s3_options
+                .entry(AmazonS3ConfigKey::Client(
+                    object_store::ClientConfigKey::AllowHttp,
+                ))
+                .or_insert_with(|| "true".to_string());
+        }
+
+        let mut builder = AmazonS3Builder::new().with_bucket_name(&config.bucket);
+        for (key, value) in env_options.iter().chain(&s3_options) {
+            builder = builder.with_config(*key, value);
+        }
+        if let Some(credentials) = &aws_credentials {
+            builder = builder.with_credentials(credentials.clone());
         }
 
         let mut canonical_url = match (&config.region, &config.endpoint) {
@@ -440,6 +752,8 @@ impl StorageProvider {
                 .into_iter()
                 .map(|(k, v)| (k.as_ref().to_string(), v))
                 .collect(),
+            aws_credentials,
+            retry_policy: RetryPolicy::from_config(),
         })
     }
 
@@ -505,6 +819,69 @@ impl StorageProvider {
             object_store_base_url,
             canonical_url,
             storage_options: HashMap::new(),
+            aws_credentials: None,
+            retry_policy: RetryPolicy::from_config(),
+        })
+    }
+
+    async fn construct_azure(
+        config: AzureConfig,
+        options: HashMap<String, String>,
+    ) -> Result<Self, StorageError> {
+        // from_env picks up an account key (AZURE_STORAGE_ACCOUNT_KEY), a SAS token
+        // (AZURE_STORAGE_SAS_TOKEN), a service principal (AZURE_CLIENT_ID, AZURE_CLIENT_SECRET
+        // and AZURE_TENANT_ID) or the Azurite emulator (AZURE_STORAGE_USE_EMULATOR); we also
+        // collect those variables so that delta-rs can build an equivalent store
+        let mut builder = MicrosoftAzureBuilder::from_env().with_container_name(&config.container);
+        let mut azure_options: HashMap<AzureConfigKey, String> = std::env::vars()
+            .filter(|(k, _)| k.starts_with("AZURE_"))
+            .filter_map(|(k, v)| Some((k.to_ascii_lowercase().parse().ok()?, v)))
+            .collect();
+
+        for (key, value) in options {
+            let azure_config_key = key.parse().map_err(|_| {
+                StorageError::CredentialsError(format!("invalid Azure config key: {}", key))
+            })?;
+            azure_options.insert(azure_config_key, value.clone());
+            builder = builder.with_config(azure_config_key, value);
+        }
+
+        if let Some(account) = &config.account {
+            builder = builder.with_account(account);
+            azure_options.insert(AzureConfigKey::AccountName, account.clone());
+        }
+
+        let account = azure_options.get(&AzureConfigKey::AccountName);
+        let mut canonical_url = match account {
+            Some(account) => format!(
+                "https://{}.blob.core.windows.net/{}",
+                account, config.container
+            ),
+            None => format!("az://{}", config.container),
+        };
+        if let Some(key) = &config.key {
+            canonical_url = format!("{}/{}", canonical_url, key);
+        }
+
+        let object_store_base_url = match account {
+            Some(account) => format!(
+                "abfs://{}@{}.dfs.core.windows.net",
+                config.container, account
+            ),
+            None => format!("az://{}", config.container),
+        };
+
+        Ok(Self {
+            config: BackendConfig::Azure(config),
+            object_store: Arc::new(builder.build().map_err(Into::<StorageError>::into)?),
+            canonical_url,
+            object_store_base_url,
+            storage_options: azure_options
+                .into_iter()
+                .map(|(k, v)| (k.as_ref().to_string(), v))
+                .collect(),
+            aws_credentials: None,
+            retry_policy: RetryPolicy::from_config(),
         })
     }
 
@@ -528,6 +905,8 @@ impl StorageProvider {
             canonical_url,
             object_store_base_url,
             storage_options: HashMap::new(),
+            aws_credentials: None,
+            retry_policy: RetryPolicy::from_config(),
         })
     }
 
@@ -540,74 +919,126 @@ impl StorageProvider {
             .as_ref()
             .map(|key| key.parts().count())
             .unwrap_or_default();
-        let list = self
-            .object_store
-            .list(key_path.as_ref())
-            .filter_map(move |meta| {
-                let result = {
-                    match meta {
-                        Ok(metadata) => {
-                            let path = metadata.location;
-                            if !include_subdirectories && path.parts().count() != key_part_count + 1
-                            {
-                                None
-                            } else {
-                                Some(Ok(path))
-                            }
+        let list = self.list_with_retries(key_path).filter_map(move |meta| {
+            let result = {
+                match meta {
+                    Ok(metadata) => {
+                        let path = metadata.location;
+                        if !include_subdirectories && path.parts().count() != key_part_count + 1 {
+                            None
+                        } else {
+                            Some(Ok(path))
                         }
-                        Err(err) => Some(Err(err)),
                     }
-                };
-                ready(result)
-            });
+                    Err(err) => Some(Err(err)),
+                }
+            };
+            ready(result)
+        });
 
         Ok(list)
     }
 
+    /// Lists the objects under `prefix`. If the listing fails part of the way through with a
+    /// retryable error it's restarted, skipping the objects that have already been returned;
+    /// object stores list objects in a consistent (lexicographic) order.
+    fn list_with_retries(
+        &self,
+        prefix: Option<Path>,
+    ) -> impl Stream<Item = Result<ObjectMeta, object_store::Error>> + '_ {
+        struct ListState<'a> {
+            stream: BoxStream<'a, object_store::Result<ObjectMeta>>,
+            prefix: Option<Path>,
+            returned: usize,
+            skip: usize,
+            attempt: u32,
+        }
+
+        let state = ListState {
+            stream: self.object_store.list(prefix.as_ref()),
+            prefix,
+            returned: 0,
+            skip: 0,
+            attempt: 1,
+        };
+
+        futures::stream::unfold(Some(state), move |state| async move {
+            let mut state = state?;
+            loop {
+                let next = self
+                    .retry_policy
+                    .attempt(async { Ok::<_, StorageError>(state.stream.next().await) })
+                    .await;
+
+                let e = match next {
+                    Ok(None) => return None,
+                    Ok(Some(Ok(_))) if state.skip > 0 => {
+                        state.skip -= 1;
+                        continue;
+                    }
+                    Ok(Some(Ok(meta))) => {
+                        state.returned += 1;
+                        state.attempt = 1;
+                        return Some((Ok(meta), Some(state)));
+                    }
+                    Ok(Some(Err(e))) => e.into(),
+                    Err(e) => e,
+                };
+
+                if !e.is_retryable() || state.attempt >= self.retry_policy.max_attempts {
+                    return Some((Err(e.into_object_store_error()), None));
+                }
+
+                let backoff = self.retry_policy.backoff(state.attempt);
+                warn!(
+                    "listing {:?} failed after {} objects (attempt {}/{}), retrying in {:?}: {}",
+                    state.prefix,
+                    state.returned,
+                    state.attempt,
+                    self.retry_policy.max_attempts,
+                    backoff,
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                state.attempt += 1;
+                state.stream = self.object_store.list(state.prefix.as_ref());
+                state.skip = state.returned;
+            }
+        })
+    }
+
     pub async fn get<P: Into<String>>(&self, path: P) -> Result<Bytes, StorageError> {
         let path: String = path.into();
-        let bytes = self
-            .object_store
-            .get(&self.qualify_path(&path.into()))
+        let path = self.qualify_path(&path.into());
+        self.retry_policy
+            .run("get", || async {
+                self.object_store.get(&path).await?.bytes().await
+            })
             .await
-            .map_err(Into::<StorageError>::into)?
-            .bytes()
-            .await?;
-
-        Ok(bytes)
     }
 
     pub async fn get_if_present<P: Into<String>>(
         &self,
         path: P,
     ) -> Result<Option<Bytes>, StorageError> {
-        let path: String = path.into();
-        match self
-            .object_store
-            .get(&self.qualify_path(&path.into()))
-            .await
-        {
-            Ok(obj) => {
-                let bytes = obj.bytes().await?;
-                Ok(Some(bytes))
-            }
-            Err(err) => {
-                if let object_store::Error::NotFound { .. } = &err {
-                    return Ok(None);
-                }
-                Err(err.into())
-            }
+        match self.get(path).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(StorageError::ObjectStore(object_store::Error::NotFound { .. })) => Ok(None),
+            Err(e) => Err(e),
         }
     }
 
     pub async fn exists<P: Into<Path>>(&self, path: P) -> Result<bool, StorageError> {
-        let path: Path = path.into();
-        let exists = self.object_store.head(&self.qualify_path(&path)).await;
+        let path = self.qualify_path(&path.into());
+        let exists = self
+            .retry_policy
+            .run("head", || self.object_store.head(&path))
+            .await;
 
         match exists {
             Ok(_) => Ok(true),
-            Err(object_store::Error::NotFound { .. }) => Ok(false),
-            Err(e) => Err(e.into()),
+            Err(StorageError::ObjectStore(object_store::Error::NotFound { .. })) => Ok(false),
+            Err(e) => Err(e),
         }
     }
 
@@ -617,8 +1048,10 @@ impl StorageProvider {
     ) -> Result<impl tokio::io::AsyncRead, StorageError> {
         let path: Path = path.into().into();
 
-        let bytes = storage_retry!(self.object_store.get(&path).await)
-            .map_err(Into::<StorageError>::into)?
+        let bytes = self
+            .retry_policy
+            .run("get", || self.object_store.get(&path))
+            .await?
             .into_stream();
 
         Ok(tokio_util::io::StreamReader::new(bytes))
@@ -630,12 +1063,53 @@ impl StorageProvider {
         bytes: Vec<u8>,
     ) -> Result<String, StorageError> {
         let path = path.into().into();
+        let qualified = self.qualify_path(&path);
         let bytes: Bytes = bytes.into();
-        storage_retry!(
-            self.object_store
-                .put(&self.qualify_path(&path), bytes.clone())
-                .await
-        )?;
+        self.retry_policy
+            .run("put", || self.object_store.put(&qualified, bytes.clone()))
+            .await?;
+
+        Ok(format!("{}/{}", self.canonical_url, path))
+    }
+
+    /// Copies `from` in `source` to `to` in this storage, streaming the data through rather than
+    /// reading the whole object into memory
+    pub async fn copy_from<P: Into<String>>(
+        &self,
+        source: &StorageProvider,
+        from: &str,
+        to: P,
+    ) -> Result<String, StorageError> {
+        let from = source.qualify_path(&from.to_string().into());
+        let path: Path = to.into().into();
+        let qualified = self.qualify_path(&path);
+
+        self.retry_policy
+            .run("copy", || async {
+                let stream = source.object_store.get(&from).await?.into_stream();
+                let mut reader = tokio_util::io::StreamReader::new(stream);
+
+                let (multipart_id, mut writer) =
+                    self.object_store.put_multipart(&qualified).await?;
+                let copied = async {
+                    tokio::io::copy(&mut reader, &mut writer).await?;
+                    writer.shutdown().await
+                }
+                .await;
+
+                if let Err(e) = copied {
+                    let _ = self
+                        .object_store
+                        .abort_multipart(&qualified, &multipart_id)
+                        .await;
+                    return Err(object_store::Error::Generic {
+                        store: "StorageProvider",
+                        source: Box::new(e),
+                    });
+                }
+                Ok(())
+            })
+            .await?;
 
         Ok(format!("{}/{}", self.canonical_url, path))
     }
@@ -651,20 +1125,26 @@ impl StorageProvider {
     }
 
     pub async fn delete_if_present<P: Into<String>>(&self, path: P) -> Result<(), StorageError> {
-        let path = path.into();
-        match self.object_store.delete(&path.into()).await {
+        let path: Path = path.into().into();
+        match self
+            .retry_policy
+            .run("delete", || self.object_store.delete(&path))
+            .await
+        {
             Ok(_) => Ok(()),
-            Err(object_store::Error::NotFound { .. }) => Ok(()),
-            Err(e) => Err(e.into()),
+            Err(StorageError::ObjectStore(object_store::Error::NotFound { .. })) => Ok(()),
+            Err(e) => Err(e),
         }
     }
 
     pub async fn start_multipart(&self, path: &Path) -> Result<MultipartId, StorageError> {
-        Ok(
-            storage_retry!(self.object_store.initiate_multipart_upload(path).await)
-                .map_err(Into::<StorageError>::into)?
-                .0,
-        )
+        Ok(self
+            .retry_policy
+            .run("start_multipart", || {
+                self.object_store.initiate_multipart_upload(path)
+            })
+            .await?
+            .0)
     }
 
     pub async fn add_multipart(
@@ -674,14 +1154,18 @@ impl StorageProvider {
         part_number: usize,
         bytes: Bytes,
     ) -> Result<PartId, StorageError> {
-        storage_retry!(
-            self.object_store
-                .get_put_part(path, multipart_id)
-                .await?
-                .put_part(bytes.clone(), part_number)
-                .await
-        )
-        .map_err(Into::<StorageError>::into)
+        self.retry_policy
+            .run("add_multipart", || {
+                let bytes = bytes.clone();
+                async move {
+                    self.object_store
+                        .get_put_part(path, multipart_id)
+                        .await?
+                        .put_part(bytes, part_number)
+                        .await
+                }
+            })
+            .await
     }
 
     pub async fn close_multipart(
@@ -690,14 +1174,18 @@ impl StorageProvider {
         multipart_id: &MultipartId,
         parts: Vec<PartId>,
     ) -> Result<(), StorageError> {
-        storage_retry!(
-            self.object_store
-                .get_put_part(path, multipart_id)
-                .await?
-                .complete(parts.clone())
-                .await
-        )
-        .map_err(Into::<StorageError>::into)
+        self.retry_policy
+            .run("close_multipart", || {
+                let parts = parts.clone();
+                async move {
+                    self.object_store
+                        .get_put_part(path, multipart_id)
+                        .await?
+                        .complete(parts)
+                        .await
+                }
+            })
+            .await
     }
 
     /// Produces a URL representation of this path that can be read by other systems,
@@ -725,18 +1213,127 @@ impl StorageProvider {
         &self.config
     }
 
+    /// Returns the current credentials for an S3 provider that isn't configured with a static
+    /// access key, for systems that build their own ObjectStore
+    pub async fn aws_credentials(&self) -> Result<Option<Arc<AwsCredential>>, StorageError> {
+        match &self.aws_credentials {
+            Some(provider) => Ok(Some(provider.get_credential().await?)),
+            None => Ok(None),
+        }
+    }
+
     pub fn get_backing_store(&self) -> Arc<dyn ObjectStore> {
         self.object_store.clone()
     }
+
+    /// Returns a provider that retries failed operations according to `policy`, rather than the
+    /// policy from the config
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Constructs a provider for an arbitrary object store, like the fault-injecting store in
+    /// the `testing` module
+    #[cfg(test)]
+    pub fn for_object_store(object_store: Arc<dyn ObjectStore>) -> Self {
+        Self {
+            config: BackendConfig::Local(LocalConfig {
+                path: "/".to_string(),
+                key: None,
+            }),
+            object_store,
+            canonical_url: "memory://".to_string(),
+            object_store_base_url: "memory://".to_string(),
+            storage_options: HashMap::new(),
+            aws_credentials: None,
+            retry_policy: RetryPolicy::from_config(),
+        }
+    }
+
+    /// Reads remote objects through the local disk cache, if one is configured
+    fn with_storage_cache(mut self) -> Self {
+        if self.config.is_local() {
+            return self;
+        }
+
+        if let Some(cache) = storage_cache() {
+            // the URL of the bucket or container, without the key prefix
+            let namespace = match self.config.key() {
+                Some(key) => self
+                    .canonical_url
+                    .strip_suffix(&format!("/{}", key))
+                    .unwrap_or(&self.canonical_url)
+                    .to_string(),
+                None => self.canonical_url.clone(),
+            };
+            self.object_store =
+                Arc::new(CachedObjectStore::new(self.object_store, cache, namespace));
+        }
+        self
+    }
+
+    /// Returns a provider that transparently encrypts all objects it writes with `key`, and
+    /// decrypts all objects it reads. Objects that were written before encryption was enabled
+    /// are read back as they are, so existing state remains readable.
+    pub fn with_encryption(mut self, key: EncryptionKey) -> Self {
+        self.object_store = Arc::new(EncryptedObjectStore::new(self.object_store, key));
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::SystemTime;
+    use std::collections::HashMap;
+    use std::io;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
 
     use arroyo_types::to_nanos;
+    use bytes::Bytes;
+    use futures::StreamExt;
+
+    use crate::testing::{Fault, FaultInjectingObjectStore};
+    use crate::{matchers, BackendConfig, RetryPolicy, StorageError, StorageProvider};
 
-    use crate::{matchers, BackendConfig, StorageProvider};
+    #[test]
+    fn test_retryable_errors() {
+        fn generic(source: impl std::error::Error + Send + Sync + 'static) -> StorageError {
+            StorageError::ObjectStore(object_store::Error::Generic {
+                store: "test",
+                source: Box::new(source),
+            })
+        }
+
+        fn message(message: &str) -> StorageError {
+            StorageError::ObjectStore(object_store::Error::Generic {
+                store: "test",
+                source: message.into(),
+            })
+        }
+
+        fn status(status: u16) -> reqwest::Error {
+            reqwest::Response::from(http::Response::builder().status(status).body("").unwrap())
+                .error_for_status()
+                .unwrap_err()
+        }
+
+        assert!(generic(io::Error::from(io::ErrorKind::ConnectionReset)).is_retryable());
+        assert!(generic(io::Error::from(io::ErrorKind::TimedOut)).is_retryable());
+        assert!(generic(status(429)).is_retryable());
+        assert!(generic(status(503)).is_retryable());
+        assert!(StorageError::Timeout(Duration::from_secs(1)).is_retryable());
+
+        assert!(!generic(io::Error::from(io::ErrorKind::PermissionDenied)).is_retryable());
+        assert!(!generic(status(403)).is_retryable());
+        assert!(!message("segment 3 of object failed authentication").is_retryable());
+        assert!(!StorageError::ObjectStore(object_store::Error::NotFound {
+            path: "a".to_string(),
+            source: "missing".into(),
+        })
+        .is_retryable());
+        assert!(!StorageError::InvalidUrl.is_retryable());
+    }
 
     #[test]
     fn test_regex_compilation() {
@@ -752,6 +1349,7 @@ mod tests {
                 region: None,
                 bucket: "mybucket".to_string(),
                 key: Some("puppy.jpg".to_string()),
+                credentials: None,
             })
         );
 
@@ -766,6 +1364,7 @@ mod tests {
                 region: Some("us-west-2".to_string()),
                 bucket: "my-bucket1".to_string(),
                 key: Some("puppy.jpg".to_string()),
+                credentials: None,
             })
         );
 
@@ -777,6 +1376,7 @@ mod tests {
                 region: Some("us-east-1".to_string()),
                 bucket: "my-bucket".to_string(),
                 key: None,
+                credentials: None,
             })
         );
 
@@ -791,6 +1391,7 @@ mod tests {
                 region: Some("us-west-2".to_string()),
                 bucket: "my-bucket".to_string(),
                 key: Some("my/path/test.pdf".to_string()),
+                credentials: None,
             })
         );
 
@@ -805,8 +1406,149 @@ mod tests {
                 region: None,
                 bucket: "my-bucket".to_string(),
                 key: Some("path/test.pdf".to_string()),
+                credentials: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_s3_options() {
+        let BackendConfig::S3(mut config) =
+            BackendConfig::parse_url("s3::http://localhost:9000/my-bucket/path/test.pdf", false)
+                .unwrap()
+        else {
+            panic!("expected an S3 config");
+        };
+
+        let options = config
+            .apply_options(
+                [
+                    ("aws_endpoint", "http://minio:9000"),
+                    ("aws_access_key_id", "minio"),
+                    ("aws_secret_access_key", "minio123"),
+                    ("aws_virtual_hosted_style_request", "true"),
+                ]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            )
+            .unwrap();
+
+        assert_eq!(config.endpoint, Some("http://minio:9000".to_string()));
+        assert_eq!(
+            config.credentials,
+            Some(crate::S3Credentials::Static {
+                access_key_id: "minio".to_string(),
+                secret_access_key: "minio123".to_string(),
+                session_token: None,
+            })
+        );
+        assert_eq!(options.len(), 1);
+
+        assert!(config
+            .apply_options(
+                [
+                    ("aws_profile", "prod"),
+                    ("aws_role_arn", "arn:aws:iam::1:role/r")
+                ]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_s3_env_options() {
+        let env = || {
+            [
+                ("AWS_ENDPOINT", "http://minio:9000"),
+                ("AWS_ACCESS_KEY_ID", "minio"),
+                ("AWS_SESSION_TOKEN", "token"),
+                ("AWS_ALLOW_HTTP", "true"),
+                ("HOME", "/root"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        };
+
+        let options = crate::s3_env_options(env(), false, false);
+        assert_eq!(options.len(), 4);
+
+        // a connection's own location and credentials take precedence over the environment
+        let options = crate::s3_env_options(env(), true, true);
+        assert_eq!(options.len(), 1);
+        assert_eq!(
+            options.get(&object_store::aws::AmazonS3ConfigKey::Client(
+                object_store::ClientConfigKey::AllowHttp
+            )),
+            Some(&"true".to_string())
+        );
+    }
+
+    #[test]
+    fn test_azure_configs() {
+        assert_eq!(
+            BackendConfig::parse_url(
+                "abfss://my-container@myaccount.dfs.core.windows.net/path/test.pdf",
+                false
+            )
+            .unwrap(),
+            BackendConfig::Azure(crate::AzureConfig {
+                account: Some("myaccount".to_string()),
+                container: "my-container".to_string(),
+                key: Some("path/test.pdf".to_string()),
+            })
+        );
+
+        assert_eq!(
+            BackendConfig::parse_url(
+                "https://myaccount.blob.core.windows.net/my-container",
+                false
+            )
+            .unwrap(),
+            BackendConfig::Azure(crate::AzureConfig {
+                account: Some("myaccount".to_string()),
+                container: "my-container".to_string(),
+                key: None,
             })
         );
+
+        let BackendConfig::Azure(config) =
+            BackendConfig::parse_url("az://my-container/puppy.jpg", false).unwrap()
+        else {
+            panic!("expected an Azure config");
+        };
+        assert_eq!(config.container, "my-container");
+        assert_eq!(config.key, Some("puppy.jpg".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_azure_options() {
+        // the Azurite emulator's well-known account
+        let storage = StorageProvider::for_url_with_options(
+            "https://devstoreaccount1.blob.core.windows.net/my-container/checkpoints",
+            HashMap::from([("use_emulator".to_string(), "true".to_string())]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            storage.canonical_url(),
+            "https://devstoreaccount1.blob.core.windows.net/my-container/checkpoints"
+        );
+        assert_eq!(
+            storage.object_store_base_url(),
+            "abfs://my-container@devstoreaccount1.dfs.core.windows.net"
+        );
+        assert!(storage.storage_options().values().any(|v| v == "true"));
+
+        assert!(StorageProvider::for_url_with_options(
+            "az://my-container",
+            HashMap::from([("not_an_option".to_string(), "x".to_string())]),
+        )
+        .await
+        .is_err());
     }
 
     #[test]
@@ -872,4 +1614,86 @@ mod tests {
                 .unwrap()
         );
     }
+
+    #[tokio::test]
+    async fn test_copy_from() {
+        let from = StorageProvider::for_url("file:///tmp/arroyo-testing/storage-tests/copy-from")
+            .await
+            .unwrap();
+        let to = StorageProvider::for_url("file:///tmp/arroyo-testing/storage-tests/copy-to")
+            .await
+            .unwrap();
+
+        let now = to_nanos(SystemTime::now());
+        let data: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let key = format!("my-test/{}", now);
+
+        from.put(&key, data.clone()).await.unwrap();
+        let url = to
+            .copy_from(&from, &key, format!("{}-copy", key))
+            .await
+            .unwrap();
+        assert!(url.ends_with("-copy"));
+        assert_eq!(to.get(format!("{}-copy", key)).await.unwrap(), data);
+
+        assert!(to
+            .copy_from(&from, "missing", "missing-copy")
+            .await
+            .is_err());
+
+        from.delete_if_present(&key).await.unwrap();
+        to.delete_if_present(format!("{}-copy", key)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        let store = Arc::new(FaultInjectingObjectStore::new());
+        let storage =
+            StorageProvider::for_object_store(store.clone()).with_retry_policy(RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(10),
+                operation_timeout: Some(Duration::from_millis(100)),
+            });
+
+        // transient errors are retried...
+        store.inject(Fault::Transient, 2);
+        storage.put("a", b"hello".to_vec()).await.unwrap();
+        assert_eq!(store.operations(), 3);
+
+        // ...until the attempts run out
+        store.inject(Fault::Transient, 3);
+        assert!(storage.get("a").await.unwrap_err().is_retryable());
+
+        // fatal errors are returned immediately
+        let operations = store.operations();
+        store.inject(Fault::Fatal, 1);
+        assert!(!storage.get("a").await.unwrap_err().is_retryable());
+        assert_eq!(store.operations(), operations + 1);
+
+        // attempts that time out are retried
+        store.inject(Fault::Delay(Duration::from_secs(10)), 1);
+        assert_eq!(
+            storage.get("a").await.unwrap(),
+            Bytes::from_static(b"hello")
+        );
+
+        // listings that fail part of the way through are resumed without duplicates
+        for i in 0..5 {
+            storage.put(format!("dir/{}", i), vec![]).await.unwrap();
+        }
+        store.inject(Fault::Delay(Duration::ZERO), 2);
+        store.inject(Fault::Transient, 1);
+        let paths: Vec<_> = storage
+            .list(true)
+            .await
+            .unwrap()
+            .map(|p| p.unwrap().to_string())
+            .collect()
+            .await;
+        assert_eq!(
+            paths,
+            vec!["a", "dir/0", "dir/1", "dir/2", "dir/3", "dir/4"]
+        );
+    }
 }
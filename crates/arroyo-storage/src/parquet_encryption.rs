@@ -0,0 +1,590 @@
+//! Parquet modular encryption for data files written through an [EncryptedObjectStore].
+//!
+//! Plaintext parquet files are rewritten into the encrypted-footer mode of the Parquet
+//! encryption spec, using the AES_GCM_V1 algorithm and uniform encryption: every page, page
+//! header and the footer are encrypted with the master key. Column statistics and the rest of
+//! the file metadata stay in the (encrypted) footer, so any reader that implements the spec can
+//! read the files given the key. The parquet version we read with doesn't, so [decrypt] turns
+//! them back into plain parquet files. Page indexes and bloom filters are dropped when a file is
+//! encrypted.
+//!
+//! Every encrypted module is stored as
+//!
+//! ```text
+//! length (4, little endian) | nonce (12) | ciphertext | tag (16)
+//! ```
+//!
+//! and authenticated with an AAD that binds it to its file, module type and position.
+//!
+//! [EncryptedObjectStore]: crate::EncryptedObjectStore
+
+use std::ops::Range;
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use parquet::format::{
+    AesGcmV1, ColumnChunk, ColumnCryptoMetaData, EncryptionAlgorithm, EncryptionWithFooterKey,
+    FileCryptoMetaData, FileMetaData, PageHeader, PageType,
+};
+use parquet::thrift::TSerializable;
+use thrift::protocol::{TCompactInputProtocol, TCompactOutputProtocol, TOutputProtocol};
+
+use crate::encryption::{error, EncryptionKey};
+
+const PLAINTEXT_MAGIC: &[u8; 4] = b"PAR1";
+pub(crate) const ENCRYPTED_MAGIC: &[u8; 4] = b"PARE";
+const FOOTER_LENGTH_SIZE: usize = 4;
+const MODULE_LENGTH_SIZE: usize = 4;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const AAD_FILE_UNIQUE_SIZE: usize = 8;
+
+/// Module types, as defined by the spec, which are mixed into each module's AAD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModuleType {
+    Footer = 0,
+    DataPage = 2,
+    DictionaryPage = 3,
+    DataPageHeader = 4,
+    DictionaryPageHeader = 5,
+}
+
+/// Whether `data` looks like a plaintext parquet file
+pub(crate) fn is_parquet(data: &[u8]) -> bool {
+    data.len() >= 2 * PLAINTEXT_MAGIC.len() + FOOTER_LENGTH_SIZE
+        && data.starts_with(PLAINTEXT_MAGIC)
+        && data.ends_with(PLAINTEXT_MAGIC)
+}
+
+/// Whether `data` looks like a parquet file with an encrypted footer
+pub(crate) fn is_encrypted(data: &[u8]) -> bool {
+    data.len() >= 2 * ENCRYPTED_MAGIC.len() + FOOTER_LENGTH_SIZE
+        && data.starts_with(ENCRYPTED_MAGIC)
+        && data.ends_with(ENCRYPTED_MAGIC)
+}
+
+struct FileCipher {
+    cipher: Aes256Gcm,
+    aad_file_unique: Vec<u8>,
+}
+
+impl FileCipher {
+    /// Builds the AAD for a module; `ordinals` are the row group, column and (for data pages and
+    /// their headers) page ordinals of the module, each stored as a little-endian i16
+    fn aad(&self, module: ModuleType, ordinals: &[usize]) -> object_store::Result<Vec<u8>> {
+        let mut aad = self.aad_file_unique.clone();
+        aad.push(module as u8);
+        for ordinal in ordinals {
+            let ordinal = i16::try_from(*ordinal).map_err(|_| {
+                error("parquet file has too many row groups, columns or pages to encrypt")
+            })?;
+            aad.extend_from_slice(&ordinal.to_le_bytes());
+        }
+        Ok(aad)
+    }
+
+    fn encrypt_module(&self, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .expect("encrypting a module cannot fail");
+
+        let mut module = Vec::with_capacity(MODULE_LENGTH_SIZE + NONCE_SIZE + ciphertext.len());
+        module.extend_from_slice(&((NONCE_SIZE + ciphertext.len()) as u32).to_le_bytes());
+        module.extend_from_slice(&nonce);
+        module.extend(ciphertext);
+        module
+    }
+
+    /// Decrypts the module at the start of `data`, advancing past it
+    fn decrypt_module(&self, data: &mut &[u8], aad: &[u8]) -> object_store::Result<Vec<u8>> {
+        let input = *data;
+        if input.len() < MODULE_LENGTH_SIZE {
+            return Err(error("encrypted parquet module is truncated"));
+        }
+        let (length, rest) = input.split_at(MODULE_LENGTH_SIZE);
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        if length < NONCE_SIZE + TAG_SIZE || length > rest.len() {
+            return Err(error("encrypted parquet module is truncated"));
+        }
+
+        let (module, rest) = rest.split_at(length);
+        *data = rest;
+        let (nonce, ciphertext) = module.split_at(NONCE_SIZE);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| {
+                error("parquet module failed authentication; the file was encrypted with a different key or has been modified")
+            })
+    }
+}
+
+fn read_thrift<T: TSerializable>(data: &mut &[u8]) -> object_store::Result<T> {
+    let mut protocol = TCompactInputProtocol::new(data);
+    T::read_from_in_protocol(&mut protocol)
+        .map_err(|e| error(format!("invalid parquet metadata: {}", e)))
+}
+
+fn write_thrift<T: TSerializable>(value: &T) -> Vec<u8> {
+    let mut out = vec![];
+    {
+        let mut protocol = TCompactOutputProtocol::new(&mut out);
+        value
+            .write_to_out_protocol(&mut protocol)
+            .and_then(|_| protocol.flush())
+            .expect("writing to a vec cannot fail");
+    }
+    out
+}
+
+/// Splits a parquet file into its body and the metadata stored between the body and the
+/// trailing footer length and magic
+fn split_footer<'a>(data: &'a [u8], magic: &[u8; 4]) -> object_store::Result<(&'a [u8], &'a [u8])> {
+    let trailer = data.len() - FOOTER_LENGTH_SIZE - magic.len();
+    let footer_length = u32::from_le_bytes(
+        data[trailer..trailer + FOOTER_LENGTH_SIZE]
+            .try_into()
+            .unwrap(),
+    ) as usize;
+    let body_end = trailer
+        .checked_sub(footer_length)
+        .filter(|end| *end >= magic.len())
+        .ok_or_else(|| error("parquet footer length is larger than the file"))?;
+    Ok((&data[..body_end], &data[body_end..trailer]))
+}
+
+/// The location of a column chunk's pages in the file
+fn chunk_range(chunk: &ColumnChunk, body_len: usize) -> object_store::Result<Range<usize>> {
+    let meta = chunk
+        .meta_data
+        .as_ref()
+        .ok_or_else(|| error("parquet column chunk has no metadata"))?;
+    let start = meta.dictionary_page_offset.unwrap_or(meta.data_page_offset);
+    let range = usize::try_from(start)
+        .ok()
+        .zip(usize::try_from(start.saturating_add(meta.total_compressed_size)).ok());
+    match range {
+        Some((start, end)) if start >= PLAINTEXT_MAGIC.len() && start <= end && end <= body_len => {
+            Ok(start..end)
+        }
+        _ => Err(error("parquet column chunk is outside of the file")),
+    }
+}
+
+/// Where a page being rewritten sits in the file
+struct PagePosition {
+    row_group: usize,
+    column: usize,
+    /// the ordinal of the next data page in the column chunk
+    data_page: usize,
+    /// whether this chunk's first page is a dictionary page that has yet to be rewritten
+    dictionary_next: bool,
+}
+
+struct RewrittenPage {
+    dictionary: bool,
+    bytes: Vec<u8>,
+}
+
+/// Writes a new file with the given magic, where each page (including its header) in `body` is
+/// rewritten by `rewrite_page`, updating the offsets and sizes in `metadata` to match. The
+/// footer is left for the caller to append.
+fn rewrite_pages(
+    body: &[u8],
+    metadata: &mut FileMetaData,
+    magic: &[u8; 4],
+    mut rewrite_page: impl FnMut(&mut &[u8], &PagePosition) -> object_store::Result<RewrittenPage>,
+) -> object_store::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(body.len());
+    out.extend_from_slice(magic);
+
+    for (rg_index, row_group) in metadata.row_groups.iter_mut().enumerate() {
+        let row_group_start = out.len();
+        for (column, chunk) in row_group.columns.iter_mut().enumerate() {
+            let range = chunk_range(chunk, body.len())?;
+            let meta = chunk.meta_data.as_mut().unwrap();
+            let chunk_start = out.len();
+
+            let mut position = PagePosition {
+                row_group: rg_index,
+                column,
+                data_page: 0,
+                dictionary_next: meta.dictionary_page_offset.is_some(),
+            };
+            meta.dictionary_page_offset = None;
+            let mut pages = &body[range.clone()];
+            while !pages.is_empty() {
+                let offset = out.len() as i64;
+                let page = rewrite_page(&mut pages, &position)?;
+                if page.dictionary {
+                    meta.dictionary_page_offset = Some(offset);
+                } else {
+                    if position.data_page == 0 {
+                        meta.data_page_offset = offset;
+                    }
+                    position.data_page += 1;
+                }
+                position.dictionary_next = false;
+                out.extend(page.bytes);
+            }
+
+            meta.total_compressed_size = (out.len() - chunk_start) as i64;
+            meta.index_page_offset = None;
+            meta.bloom_filter_offset = None;
+            meta.bloom_filter_length = None;
+            // writers differ on whether this points to the start or the end of the chunk
+            chunk.file_offset = if chunk.file_offset == range.end as i64 {
+                out.len() as i64
+            } else {
+                chunk_start as i64
+            };
+            chunk.offset_index_offset = None;
+            chunk.offset_index_length = None;
+            chunk.column_index_offset = None;
+            chunk.column_index_length = None;
+        }
+
+        row_group.file_offset = Some(row_group_start as i64);
+        row_group.total_compressed_size = Some((out.len() - row_group_start) as i64);
+    }
+
+    Ok(out)
+}
+
+fn page_size(header: &PageHeader, available: usize) -> object_store::Result<usize> {
+    usize::try_from(header.compressed_page_size)
+        .ok()
+        .filter(|size| *size <= available)
+        .ok_or_else(|| error("parquet page extends past the end of its column chunk"))
+}
+
+fn page_size_field(len: usize) -> object_store::Result<i32> {
+    i32::try_from(len).map_err(|_| error("parquet page is too large"))
+}
+
+/// Encrypts a plaintext parquet file
+pub(crate) fn encrypt(key: &EncryptionKey, data: &[u8]) -> object_store::Result<Vec<u8>> {
+    if !is_parquet(data) {
+        return Err(error("object is not a parquet file"));
+    }
+    let (body, mut footer) = split_footer(data, PLAINTEXT_MAGIC)?;
+    let mut metadata: FileMetaData = read_thrift(&mut footer)?;
+    if metadata.encryption_algorithm.is_some() {
+        return Err(error("parquet file is already encrypted"));
+    }
+
+    let file = FileCipher {
+        cipher: key.cipher(),
+        aad_file_unique: rand::random::<[u8; AAD_FILE_UNIQUE_SIZE]>().to_vec(),
+    };
+
+    let mut out = rewrite_pages(body, &mut metadata, ENCRYPTED_MAGIC, |pages, position| {
+        let mut header: PageHeader = read_thrift(pages)?;
+        let size = page_size(&header, pages.len())?;
+        let (page, rest) = (*pages).split_at(size);
+        *pages = rest;
+
+        let (header_type, page_type, ordinals) = match header.type_ {
+            PageType::DICTIONARY_PAGE if position.dictionary_next => (
+                ModuleType::DictionaryPageHeader,
+                ModuleType::DictionaryPage,
+                vec![position.row_group, position.column],
+            ),
+            PageType::DATA_PAGE | PageType::DATA_PAGE_V2 => (
+                ModuleType::DataPageHeader,
+                ModuleType::DataPage,
+                vec![position.row_group, position.column, position.data_page],
+            ),
+            page_type => {
+                return Err(error(format!(
+                    "unsupported parquet page {:?} in column chunk",
+                    page_type
+                )));
+            }
+        };
+
+        let page = file.encrypt_module(page, &file.aad(page_type, &ordinals)?);
+        header.compressed_page_size = page_size_field(page.len())?;
+        // the checksum is of the page as stored, which is now the ciphertext
+        header.crc = None;
+
+        let mut bytes =
+            file.encrypt_module(&write_thrift(&header), &file.aad(header_type, &ordinals)?);
+        bytes.extend(page);
+        Ok(RewrittenPage {
+            dictionary: header_type == ModuleType::DictionaryPageHeader,
+            bytes,
+        })
+    })?;
+
+    for (ordinal, row_group) in metadata.row_groups.iter_mut().enumerate() {
+        row_group.ordinal = Some(
+            i16::try_from(ordinal).map_err(|_| error("parquet file has too many row groups"))?,
+        );
+        for chunk in &mut row_group.columns {
+            chunk.crypto_metadata = Some(ColumnCryptoMetaData::ENCRYPTIONWITHFOOTERKEY(
+                EncryptionWithFooterKey::new(),
+            ));
+        }
+    }
+
+    let crypto_metadata = FileCryptoMetaData::new(
+        EncryptionAlgorithm::AESGCMV1(AesGcmV1::new(
+            None::<Vec<u8>>,
+            file.aad_file_unique.clone(),
+            None::<bool>,
+        )),
+        key.key_metadata(),
+    );
+
+    let footer_start = out.len();
+    out.extend(write_thrift(&crypto_metadata));
+    out.extend(file.encrypt_module(
+        &write_thrift(&metadata),
+        &file.aad(ModuleType::Footer, &[])?,
+    ));
+    let footer_length = (out.len() - footer_start) as u32;
+    out.extend_from_slice(&footer_length.to_le_bytes());
+    out.extend_from_slice(ENCRYPTED_MAGIC);
+    Ok(out)
+}
+
+/// Decrypts a parquet file written by [encrypt] into a plaintext parquet file
+pub(crate) fn decrypt(key: &EncryptionKey, data: &[u8]) -> object_store::Result<Vec<u8>> {
+    if !is_encrypted(data) {
+        return Err(error("object is not an encrypted parquet file"));
+    }
+    let (body, mut footer) = split_footer(data, ENCRYPTED_MAGIC)?;
+    let crypto_metadata: FileCryptoMetaData = read_thrift(&mut footer)?;
+    let EncryptionAlgorithm::AESGCMV1(algorithm) = crypto_metadata.encryption_algorithm else {
+        return Err(error("unsupported parquet encryption algorithm"));
+    };
+    if algorithm.aad_prefix.is_some() || algorithm.supply_aad_prefix == Some(true) {
+        return Err(error("parquet files with an AAD prefix are not supported"));
+    }
+
+    let file = FileCipher {
+        cipher: key.cipher(),
+        aad_file_unique: algorithm.aad_file_unique.unwrap_or_default(),
+    };
+
+    let footer = file.decrypt_module(&mut footer, &file.aad(ModuleType::Footer, &[])?)?;
+    let mut metadata: FileMetaData = read_thrift(&mut footer.as_slice())?;
+
+    let mut out = rewrite_pages(body, &mut metadata, PLAINTEXT_MAGIC, |pages, position| {
+        let (header_type, page_type, ordinals) = if position.dictionary_next {
+            (
+                ModuleType::DictionaryPageHeader,
+                ModuleType::DictionaryPage,
+                vec![position.row_group, position.column],
+            )
+        } else {
+            (
+                ModuleType::DataPageHeader,
+                ModuleType::DataPage,
+                vec![position.row_group, position.column, position.data_page],
+            )
+        };
+
+        let header = file.decrypt_module(pages, &file.aad(header_type, &ordinals)?)?;
+        let mut header: PageHeader = read_thrift(&mut header.as_slice())?;
+        let size = page_size(&header, pages.len())?;
+        let (mut page, rest) = (*pages).split_at(size);
+        *pages = rest;
+
+        let page = file.decrypt_module(&mut page, &file.aad(page_type, &ordinals)?)?;
+        header.compressed_page_size = page_size_field(page.len())?;
+
+        let mut bytes = write_thrift(&header);
+        bytes.extend(page);
+        Ok(RewrittenPage {
+            dictionary: header_type == ModuleType::DictionaryPageHeader,
+            bytes,
+        })
+    })?;
+
+    for row_group in &mut metadata.row_groups {
+        for chunk in &mut row_group.columns {
+            chunk.crypto_metadata = None;
+        }
+    }
+
+    let footer = write_thrift(&metadata);
+    out.extend_from_slice(&footer);
+    out.extend_from_slice(&(footer.len() as u32).to_le_bytes());
+    out.extend_from_slice(PLAINTEXT_MAGIC);
+    Ok(out)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+    use bytes::Bytes;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+    use std::sync::Arc;
+
+    const SECRET: &str = "very-secret-value";
+
+    fn key() -> EncryptionKey {
+        EncryptionKey::new([3; 32])
+    }
+
+    fn batch() -> RecordBatch {
+        let ids: ArrayRef = Arc::new(Int64Array::from_iter_values(0..1000));
+        let values: ArrayRef = Arc::new(StringArray::from_iter_values(
+            (0..1000).map(|i| format!("{}-{}", SECRET, i % 10)),
+        ));
+        RecordBatch::try_from_iter([("id", ids), ("value", values)]).unwrap()
+    }
+
+    /// A file with a dictionary-encoded column, several data pages per chunk and two row groups
+    pub(crate) fn parquet_file() -> Vec<u8> {
+        let properties = WriterProperties::builder()
+            .set_data_page_row_count_limit(100)
+            .set_write_batch_size(100)
+            .set_max_row_group_size(600)
+            .build();
+        let mut out = vec![];
+        let mut writer =
+            ArrowWriter::try_new(&mut out, batch().schema(), Some(properties)).unwrap();
+        writer.write(&batch()).unwrap();
+        writer.close().unwrap();
+        out
+    }
+
+    pub(crate) fn read(data: Vec<u8>) -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(Bytes::from(data))
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let plaintext = parquet_file();
+        let encrypted = encrypt(&key(), &plaintext).unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_parquet(&encrypted));
+        assert!(contains(&plaintext, SECRET.as_bytes()));
+        assert!(!contains(&encrypted, SECRET.as_bytes()));
+
+        let decrypted = decrypt(&key(), &encrypted).unwrap();
+        assert!(is_parquet(&decrypted));
+        let mut offset = 0;
+        for read in read(decrypted) {
+            assert_eq!(read, batch().slice(offset, read.num_rows()));
+            offset += read.num_rows();
+        }
+        assert_eq!(offset, 1000);
+    }
+
+    #[test]
+    fn test_file_structure() {
+        let encrypted = encrypt(&key(), &parquet_file()).unwrap();
+        let (body, mut footer) = split_footer(&encrypted, ENCRYPTED_MAGIC).unwrap();
+
+        let crypto_metadata: FileCryptoMetaData = read_thrift(&mut footer).unwrap();
+        assert_eq!(crypto_metadata.key_metadata, Some(key().key_metadata()));
+        let EncryptionAlgorithm::AESGCMV1(algorithm) = crypto_metadata.encryption_algorithm else {
+            panic!("expected AES_GCM_V1");
+        };
+        let file = FileCipher {
+            cipher: key().cipher(),
+            aad_file_unique: algorithm.aad_file_unique.unwrap(),
+        };
+        assert_eq!(file.aad_file_unique.len(), AAD_FILE_UNIQUE_SIZE);
+        assert_eq!(
+            file.aad(ModuleType::DataPage, &[1, 2, 3]).unwrap()[AAD_FILE_UNIQUE_SIZE..],
+            [2, 1, 0, 2, 0, 3, 0]
+        );
+
+        let footer = file
+            .decrypt_module(&mut footer, &file.aad(ModuleType::Footer, &[]).unwrap())
+            .unwrap();
+        let metadata: FileMetaData = read_thrift(&mut footer.as_slice()).unwrap();
+        assert_eq!(metadata.row_groups.len(), 2);
+
+        // every chunk is encrypted with the footer key, and its offsets point at the encrypted
+        // header of its first page
+        for (rg, row_group) in metadata.row_groups.iter().enumerate() {
+            assert_eq!(row_group.ordinal, Some(rg as i16));
+            for (column, chunk) in row_group.columns.iter().enumerate() {
+                assert!(matches!(
+                    chunk.crypto_metadata,
+                    Some(ColumnCryptoMetaData::ENCRYPTIONWITHFOOTERKEY(_))
+                ));
+                let meta = chunk.meta_data.as_ref().unwrap();
+                let mut data = &body[meta.data_page_offset as usize..];
+                let header = file
+                    .decrypt_module(
+                        &mut data,
+                        &file
+                            .aad(ModuleType::DataPageHeader, &[rg, column, 0])
+                            .unwrap(),
+                    )
+                    .unwrap();
+                let header: PageHeader = read_thrift(&mut header.as_slice()).unwrap();
+                assert_ne!(header.type_, PageType::DICTIONARY_PAGE);
+                assert_eq!(
+                    header.compressed_page_size as usize,
+                    u32::from_le_bytes(data[..4].try_into().unwrap()) as usize + 4
+                );
+            }
+        }
+
+        // the string column is dictionary encoded
+        let chunk = &metadata.row_groups[0].columns[1];
+        assert!(chunk
+            .meta_data
+            .as_ref()
+            .unwrap()
+            .dictionary_page_offset
+            .is_some());
+    }
+
+    #[test]
+    fn test_wrong_key_and_tampering() {
+        let encrypted = encrypt(&key(), &parquet_file()).unwrap();
+        assert!(decrypt(&EncryptionKey::new([4; 32]), &encrypted).is_err());
+
+        // modifying a page must be detected
+        let mut tampered = encrypted.clone();
+        tampered[100] ^= 1;
+        assert!(decrypt(&key(), &tampered).is_err());
+
+        // as must swapping in the pages of another encryption of the same file
+        let other = encrypt(&key(), &parquet_file()).unwrap();
+        let (body, footer) = split_footer(&encrypted, ENCRYPTED_MAGIC).unwrap();
+        let (other_body, _) = split_footer(&other, ENCRYPTED_MAGIC).unwrap();
+        assert_eq!(body.len(), other_body.len());
+        let mut spliced = other_body.to_vec();
+        spliced.extend_from_slice(footer);
+        spliced.extend_from_slice(&encrypted[body.len() + footer.len()..]);
+        assert!(decrypt(&key(), &spliced).is_err());
+
+        assert!(encrypt(&key(), b"not a parquet file").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use std::future::Future;
+use std::time::Duration;
+
+use arroyo_rpc::config::config;
+use rand::Rng;
+use tracing::warn;
+
+use crate::StorageError;
+
+/// How operations against an object store are retried when they fail with a transient error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Time after which a single attempt is abandoned, and counted as a retryable failure
+    pub operation_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            operation_timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_config() -> Self {
+        let config = &config().storage_retry;
+        Self {
+            max_attempts: config.max_attempts.max(1),
+            base_delay: *config.base_delay,
+            max_delay: *config.max_delay,
+            operation_timeout: config.operation_timeout.as_ref().map(|t| **t),
+        }
+    }
+
+    /// A policy that makes a single attempt with no timeout
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            operation_timeout: None,
+            ..Default::default()
+        }
+    }
+
+    /// Exponential backoff with jitter before retry number `retry` (starting at 1); the delay is
+    /// chosen uniformly from the upper half of the backoff interval
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        let delay = self.max_delay.min(
+            self.base_delay
+                .saturating_mul(2u32.saturating_pow(retry - 1)),
+        );
+        let half = delay / 2;
+        half + Duration::from_micros(rand::thread_rng().gen_range(0..=half.as_micros() as u64))
+    }
+
+    /// Runs a single attempt of an operation, applying the timeout
+    pub(crate) async fn attempt<T, E, Fut>(&self, f: Fut) -> Result<T, StorageError>
+    where
+        E: Into<StorageError>,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        match self.operation_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, f).await {
+                Ok(result) => result.map_err(Into::into),
+                Err(_) => Err(StorageError::Timeout(timeout)),
+            },
+            None => f.await.map_err(Into::into),
+        }
+    }
+
+    /// Runs `f` until it succeeds, fails with an error that isn't retryable, or runs out of
+    /// attempts
+    pub async fn run<T, E, F, Fut>(&self, op: &str, mut f: F) -> Result<T, StorageError>
+    where
+        E: Into<StorageError>,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match self.attempt(f()).await {
+                Ok(value) => return Ok(value),
+                Err(e) if e.is_retryable() && attempt < self.max_attempts => {
+                    let backoff = self.backoff(attempt);
+                    warn!(
+                        "storage operation {} failed (attempt {}/{}), retrying in {:?}: {}",
+                        op, attempt, self.max_attempts, backoff, e
+                    );
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        for retry in 1..20 {
+            let expected = policy
+                .max_delay
+                .min(policy.base_delay * 2u32.pow(retry.min(16) - 1));
+            let backoff = policy.backoff(retry);
+            assert!(backoff >= expected / 2 && backoff <= expected);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Test utilities for code that uses object storage.
+
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, PutOptions, PutResult,
+};
+use tokio::io::AsyncWrite;
+
+const STORE_NAME: &str = "FaultInjectingObjectStore";
+
+/// A failure to inject into an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Fails with an error that callers should treat as transient, like a dropped connection
+    Transient,
+    /// Fails with an error that callers should not retry
+    Fatal,
+    /// Waits for the given duration before running the operation
+    Delay(Duration),
+}
+
+/// An in-memory [ObjectStore] that injects faults into the next operations made against it,
+/// for testing how callers handle storage errors and slow responses
+#[derive(Debug, Default)]
+pub struct FaultInjectingObjectStore {
+    inner: InMemory,
+    faults: Mutex<VecDeque<Fault>>,
+    operations: AtomicUsize,
+}
+
+impl FaultInjectingObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Injects `fault` into each of the next `count` operations, after any already queued
+    pub fn inject(&self, fault: Fault, count: usize) {
+        let mut faults = self.faults.lock().unwrap();
+        faults.extend((0..count).map(|_| fault));
+    }
+
+    /// Removes any faults that haven't been triggered yet
+    pub fn clear(&self) {
+        self.faults.lock().unwrap().clear();
+    }
+
+    /// The number of operations that have been made against the store, including those that
+    /// failed
+    pub fn operations(&self) -> usize {
+        self.operations.load(Ordering::SeqCst)
+    }
+
+    /// The underlying store, which can be used to read and write without injecting faults
+    pub fn inner(&self) -> &InMemory {
+        &self.inner
+    }
+
+    async fn before(&self, op: &str, location: Option<&Path>) -> object_store::Result<()> {
+        self.operations.fetch_add(1, Ordering::SeqCst);
+        let fault = self.faults.lock().unwrap().pop_front();
+        match fault {
+            None => Ok(()),
+            Some(Fault::Transient) => Err(object_store::Error::Generic {
+                store: STORE_NAME,
+                source: Box::new(io::Error::new(
+                    io::ErrorKind::ConnectionReset,
+                    format!("injected transient error in {}", op),
+                )),
+            }),
+            Some(Fault::Fatal) => Err(object_store::Error::NotFound {
+                path: location.map(|p| p.to_string()).unwrap_or_default(),
+                source: format!("injected fatal error in {}", op).into(),
+            }),
+            Some(Fault::Delay(duration)) => {
+                tokio::time::sleep(duration).await;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for FaultInjectingObjectStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FaultInjecting({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for FaultInjectingObjectStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        self.before("put", Some(location)).await?;
+        self.inner.put_opts(location, bytes, opts).await
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> object_store::Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.before("put_multipart", Some(location)).await?;
+        self.inner.put_multipart(location).await
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        multipart_id: &MultipartId,
+    ) -> object_store::Result<()> {
+        self.before("abort_multipart", Some(location)).await?;
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        self.before("get", Some(location)).await?;
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn head(&self, location: &Path) -> object_store::Result<ObjectMeta> {
+        self.before("head", Some(location)).await?;
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        self.before("delete", Some(location)).await?;
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        // faults are injected between the items of the listing, so that callers see a failure
+        // part of the way through
+        let prefix = prefix.cloned();
+        self.inner
+            .list(prefix.as_ref())
+            .then(move |item| {
+                let prefix = prefix.clone();
+                async move {
+                    self.before("list", prefix.as_ref()).await?;
+                    item
+                }
+            })
+            .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        self.before("list", prefix).await?;
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.before("copy", Some(from)).await?;
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.before("copy", Some(from)).await?;
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
@@ -174,6 +174,9 @@ pub enum ArrowMessage {
 #[derive(Debug, Clone, PartialEq, Encode, Decode)]
 pub enum SignalMessage {
     Barrier(CheckpointBarrier),
+    /// Marks the position in the stream that an unaligned barrier overtook; data received before
+    /// it was in flight when the checkpoint was taken
+    BarrierPosition(CheckpointBarrier),
     Watermark(Watermark),
     Stop,
     EndOfData,
@@ -446,6 +449,8 @@ pub static BATCHES_SENT: &str = "arroyo_worker_batches_sent";
 pub static TX_QUEUE_SIZE: &str = "arroyo_worker_tx_queue_size";
 pub static TX_QUEUE_REM: &str = "arroyo_worker_tx_queue_rem";
 pub static DESERIALIZATION_ERRORS: &str = "arroyo_worker_deserialization_errors";
+pub static BUSY_TIME: &str = "arroyo_worker_busy_time";
+pub static SOURCE_LAG: &str = "arroyo_worker_source_lag";
 
 #[derive(Debug, Copy, Clone, Encode, Decode, PartialEq, Eq)]
 pub struct CheckpointBarrier {
@@ -453,6 +458,8 @@ pub struct CheckpointBarrier {
     pub min_epoch: u32,
     pub timestamp: SystemTime,
     pub then_stop: bool,
+    /// Unaligned barriers overtake queued data, which is instead stored as part of the checkpoint
+    pub unaligned: bool,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Hash, Serialize)]
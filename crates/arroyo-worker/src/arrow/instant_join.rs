@@ -201,7 +201,10 @@ impl ArrowOperator for InstantJoin {
             .expect("should have left table");
         let left_batches: Vec<_> = left_table
             .all_batches_for_watermark(watermark)
-            .flat_map(|(_time, batches)| batches.clone())
+            .await
+            .expect("should be able to read left table")
+            .into_iter()
+            .flat_map(|(_time, batches)| batches)
             .collect();
         for batch in left_batches {
             self.process_left(batch.clone(), ctx)
@@ -215,7 +218,10 @@ impl ArrowOperator for InstantJoin {
             .expect("should have right table");
         let right_batches: Vec<_> = right_table
             .all_batches_for_watermark(watermark)
-            .flat_map(|(_time, batches)| batches.clone())
+            .await
+            .expect("should be able to read right table")
+            .into_iter()
+            .flat_map(|(_time, batches)| batches)
             .collect();
         for batch in right_batches {
             self.process_right(batch.clone(), ctx)
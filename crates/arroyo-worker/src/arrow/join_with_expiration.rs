@@ -58,9 +58,10 @@ impl JoinWithExpiration {
         for row in left_rows {
             if let Some(batch) = right_table
                 .get_batch(row.as_ref())
+                .await
                 .expect("shouldn't error getting batch")
             {
-                right_batches.push(batch.clone());
+                right_batches.push(batch);
             }
         }
         let right_batch = concat_batches(&self.right_schema.schema, right_batches.iter()).unwrap();
@@ -96,9 +97,10 @@ impl JoinWithExpiration {
         for row in right_rows {
             if let Some(batch) = left_table
                 .get_batch(row.as_ref())
+                .await
                 .expect("shouldn't error getting batch")
             {
-                left_batches.push(batch.clone());
+                left_batches.push(batch);
             }
         }
         let left_batch = concat_batches(&self.left_schema.schema, left_batches.iter()).unwrap();
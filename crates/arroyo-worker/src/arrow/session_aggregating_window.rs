@@ -27,7 +27,10 @@ use arroyo_rpc::{
     Converter,
 };
 use arroyo_state::{
-    global_table_config, tables::global_keyed_map::GlobalKeyedView, timestamp_table_config,
+    global_table_config,
+    spill::{MemoryBudget, MemoryBudgetRef, SpillableMap},
+    tables::global_keyed_map::GlobalKeyedView,
+    timestamp_table_config,
 };
 use arroyo_types::{from_nanos, print_time, to_nanos, CheckpointBarrier, Watermark};
 use datafusion::{execution::context::SessionContext, physical_plan::ExecutionPlan};
@@ -54,6 +57,7 @@ pub struct SessionAggregatingWindowFunc {
     key_computations: HashMap<OwnedRow, KeyComputingHolder>,
     keys_by_start_time: BTreeMap<SystemTime, HashSet<OwnedRow>>,
     row_converter: Converter,
+    memory_budget: MemoryBudgetRef,
 }
 
 impl SessionAggregatingWindowFunc {
@@ -145,6 +149,22 @@ impl SessionAggregatingWindowFunc {
         Ok(results)
     }
 
+    /// If the task is over its memory budget, spills the buffered data of the keys whose next
+    /// watermark action is furthest away, as they're the least likely to be needed soon
+    async fn spill_cold_keys(&mut self) -> Result<()> {
+        for keys in self.keys_by_next_watermark_action.values().rev() {
+            for key in keys {
+                if !self.memory_budget.over_budget() {
+                    return Ok(());
+                }
+                if let Some(key_computation) = self.key_computations.get_mut(key) {
+                    key_computation.batches_by_start_time.spill_all().await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn earliest_batch_time(&self) -> Option<SystemTime> {
         self.keys_by_start_time
             .first_key_value()
@@ -201,7 +221,7 @@ impl SessionAggregatingWindowFunc {
                     .or_insert_with(|| KeyComputingHolder {
                         session_window_config: self.config.clone(),
                         active_session: None,
-                        batches_by_start_time: BTreeMap::new(),
+                        batches_by_start_time: SpillableMap::new(self.memory_budget.clone()),
                     });
             let initial_next_watermark_action = key_computation.next_watermark_action();
             let initial_data_start = key_computation.earliest_data();
@@ -507,7 +527,7 @@ struct KeyComputingHolder {
     active_session: Option<ActiveSession>,
     // buffered batches that may not be in the current session.
     // For now checkpointing happens on incoming batches, but in the future we can checkpoint partial aggregates.
-    batches_by_start_time: BTreeMap<SystemTime, Vec<RecordBatch>>,
+    batches_by_start_time: SpillableMap<SystemTime>,
 }
 
 impl KeyComputingHolder {
@@ -518,8 +538,8 @@ impl KeyComputingHolder {
             }
             None => self
                 .batches_by_start_time
-                .first_key_value()
-                .map(|(start_time, _batches)| *start_time - self.session_window_config.gap),
+                .first_key()
+                .map(|start_time| *start_time - self.session_window_config.gap),
         }
     }
     /* This method is for advancing the state machine when the watermark is incremented.
@@ -549,12 +569,11 @@ impl KeyComputingHolder {
                     break;
                 }
             } else {
-                let Some((initial_timestamp, _value)) =
-                    self.batches_by_start_time.first_key_value()
+                let Some(initial_timestamp) = self.batches_by_start_time.first_key().copied()
                 else {
                     break;
                 };
-                if watermark + self.session_window_config.gap < *initial_timestamp {
+                if watermark + self.session_window_config.gap < initial_timestamp {
                     // the next batch is after the watermark + gap, so there could be a session before it.
                     break;
                 }
@@ -569,12 +588,12 @@ impl KeyComputingHolder {
                 self.active_session = Some(
                     ActiveSession::new(
                         self.session_window_config.final_physical_exec.clone(),
-                        *initial_timestamp,
+                        initial_timestamp,
                         sender,
                     )
                     .await?,
                 );
-                self.fill_active_session()?;
+                self.fill_active_session().await?;
             }
         }
         Ok(results)
@@ -585,12 +604,12 @@ impl KeyComputingHolder {
       There are some pathological cases, e.g. if the gap is 1.5s and one batch has evens and the others odds,
       which would strip off one row at a time, but this should be rare.
     */
-    fn fill_active_session(&mut self) -> Result<()> {
+    async fn fill_active_session(&mut self) -> Result<()> {
         let Some(active_session) = self.active_session.as_mut() else {
             bail!("fill_active_session() should not be called when there is no active session");
         };
         loop {
-            let Some((first_key, _batches)) = self.batches_by_start_time.first_key_value() else {
+            let Some(first_key) = self.batches_by_start_time.first_key() else {
                 break;
             };
             if active_session.data_end + self.session_window_config.gap < *first_key {
@@ -600,6 +619,7 @@ impl KeyComputingHolder {
             let (_start_time, batches) = self
                 .batches_by_start_time
                 .pop_first()
+                .await?
                 .expect("will have already exited");
 
             for batch in batches {
@@ -608,10 +628,7 @@ impl KeyComputingHolder {
                     self.session_window_config.gap,
                     self.session_window_config.input_schema_ref.timestamp_index,
                 )? {
-                    self.batches_by_start_time
-                        .entry(start_time)
-                        .or_default()
-                        .push(batch);
+                    self.batches_by_start_time.push(start_time, batch);
                 }
             }
         }
@@ -627,22 +644,16 @@ impl KeyComputingHolder {
             start_time_for_sorted_batch(&batch, &self.session_window_config.input_schema_ref);
         let Some(watermark) = watermark else {
             // no watermark, so we can't start an active session yet, just put it in the buffer.
-            self.batches_by_start_time
-                .entry(start_time)
-                .or_default()
-                .push(batch);
+            self.batches_by_start_time.push(start_time, batch);
             return Ok(());
         };
-        self.batches_by_start_time
-            .entry(start_time)
-            .or_default()
-            .push(batch);
+        self.batches_by_start_time.push(start_time, batch);
 
         if self.active_session.is_some() {
             // the invariant may be broken, so we should fix fill in the active session.
             // this is a little inefficient as we know only the new batch could be added,
             // but it's just a couple of map adds.
-            self.fill_active_session()?;
+            self.fill_active_session().await?;
         }
         let flushed_batches = self.watermark_update(watermark).await?;
         if !flushed_batches.is_empty() {
@@ -658,10 +669,7 @@ impl KeyComputingHolder {
     fn earliest_data(&self) -> Option<SystemTime> {
         match self.active_session {
             Some(ref active_session) => Some(active_session.data_start),
-            None => self
-                .batches_by_start_time
-                .first_key_value()
-                .map(|(start_time, _batches)| *start_time),
+            None => self.batches_by_start_time.first_key().copied(),
         }
     }
 }
@@ -742,6 +750,8 @@ impl OperatorConstructor for SessionAggregatingWindowConstructor {
                 keys_by_start_time: BTreeMap::new(),
                 key_computations: HashMap::new(),
                 row_converter,
+                // this is replaced in on_start, once the task's memory budget is available
+                memory_budget: MemoryBudget::unbounded(),
             },
         )))
     }
@@ -755,6 +765,8 @@ impl ArrowOperator for SessionAggregatingWindowFunc {
     }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        self.memory_budget = ctx.table_manager.memory_budget();
+
         let start_times_map: &mut GlobalKeyedView<usize, Option<SystemTime>> =
             ctx.table_manager.get_global_keyed_state("e").await.unwrap();
         let start_time = start_times_map
@@ -774,11 +786,14 @@ impl ArrowOperator for SessionAggregatingWindowFunc {
             .get_expiring_time_key_table("s", start_time)
             .await
             .expect("should be able to load table");
-        let all_batches = table.all_batches_for_watermark(start_time);
+        let all_batches = table
+            .all_batches_for_watermark(start_time)
+            .await
+            .expect("should be able to read table");
         for (_max_timestamp, batches) in all_batches {
             for batch in batches {
                 let batch = self
-                    .filter_batch_by_time(batch.clone(), start_time)
+                    .filter_batch_by_time(batch, start_time)
                     .expect("should be able to filter");
                 if batch.num_rows() == 0 {
                     continue;
@@ -852,6 +867,9 @@ impl ArrowOperator for SessionAggregatingWindowFunc {
         self.add_at_watermark(sorted, current_watermark)
             .await
             .expect("should be able to add batch");
+        self.spill_cold_keys()
+            .await
+            .expect("should be able to spill session state");
     }
 
     async fn handle_watermark(
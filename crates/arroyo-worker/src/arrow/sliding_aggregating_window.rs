@@ -1,5 +1,5 @@
 use std::{
-    collections::{BTreeMap, HashMap, VecDeque},
+    collections::{BTreeMap, HashMap},
     fmt::{Display, Formatter},
     sync::{Arc, RwLock},
     time::SystemTime,
@@ -14,6 +14,7 @@ use arroyo_operator::{
     operator::{ArrowOperator, OperatorConstructor, OperatorNode},
 };
 use arroyo_rpc::grpc::{api, rpc::TableConfig};
+use arroyo_state::spill::{MemoryBudget, MemoryBudgetRef, SpillableMap};
 use arroyo_state::timestamp_table_config;
 use arroyo_types::{from_nanos, print_time, to_nanos, CheckpointBarrier, Watermark};
 use datafusion::common::ScalarValue;
@@ -154,15 +155,20 @@ impl SlidingAggregatingWindowFunc<SystemTime> {
                 self.tiered_record_batches.insert(batch, bin_start)?;
             }
         }
+        self.tiered_record_batches.spill_if_needed().await?;
         partial_table.flush_timestamp(bin_end).await?;
-        partial_table.expire_timestamp(bin_end - self.width + self.slide);
+        partial_table
+            .expire_timestamp(bin_end - self.width + self.slide)
+            .await?;
         let interval_start = bin_end - self.width;
         let interval_end = bin_end;
+        let interval_batches = self
+            .tiered_record_batches
+            .batches_for_interval(interval_start, interval_end)
+            .await?;
         {
             let mut batches = self.final_batches_passer.write().unwrap();
-            *batches = self
-                .tiered_record_batches
-                .batches_for_interval(interval_start, interval_end)?;
+            *batches = interval_batches;
         }
         self.finish_execution_plan.reset()?;
         let mut final_exec = self
@@ -227,18 +233,14 @@ impl SlidingAggregatingWindowFunc<SystemTime> {
 struct TieredRecordBatchHolder {
     tier_widths: Vec<Duration>,
     tiers: Vec<RecordBatchTier>,
-}
-
-#[derive(Default, Debug)]
-struct RecordBatchPane {
-    batches: Vec<RecordBatch>,
+    // the contents of each pane, keyed by tier index and pane start
+    panes: SpillableMap<(usize, SystemTime)>,
 }
 
 #[derive(Debug)]
 struct RecordBatchTier {
     width: Duration,
     start_time: Option<SystemTime>,
-    panes: VecDeque<RecordBatchPane>,
 }
 
 impl RecordBatchTier {
@@ -246,31 +248,7 @@ impl RecordBatchTier {
         Self {
             width,
             start_time: None,
-            panes: VecDeque::new(),
-        }
-    }
-    fn insert(&mut self, batch: RecordBatch, timestamp: SystemTime) -> Result<()> {
-        // calculate the bin start for timestamp, based on width
-        let bin_start = self.bin_start(timestamp);
-        // if we don't have a start time, set it to the bin start
-        if self.start_time.is_none() {
-            self.start_time = Some(bin_start);
-            let pane = RecordBatchPane {
-                batches: vec![batch],
-            };
-            self.panes.push_back(pane);
-            return Ok(());
         }
-        // if the bin_start is before start_time, error out
-        let start_time = self.start_time.unwrap();
-        let bin_index =
-            (bin_start.duration_since(start_time)?.as_nanos() / self.width.as_nanos()) as usize;
-        while self.panes.len() <= bin_index {
-            self.panes.push_back(RecordBatchPane::default());
-        }
-        let pane = self.panes.get_mut(bin_index).unwrap();
-        pane.batches.push(batch);
-        Ok(())
     }
 
     fn bin_start(&self, timestamp: SystemTime) -> SystemTime {
@@ -282,47 +260,6 @@ impl RecordBatchTier {
 
         from_nanos(nanos)
     }
-
-    fn batches_for_timestamp(&self, bin_start: SystemTime) -> Result<Vec<RecordBatch>> {
-        if self
-            .start_time
-            .map(|start_time| start_time > bin_start)
-            .unwrap_or(true)
-        {
-            return Ok(Vec::new());
-        }
-        let start_time = self.start_time.unwrap();
-        let bin_index =
-            (bin_start.duration_since(start_time)?.as_nanos() / self.width.as_nanos()) as usize;
-        if self.panes.len() <= bin_index {
-            return Ok(Vec::new());
-        }
-        Ok(self.panes[bin_index].batches.clone())
-    }
-
-    fn delete_before(&mut self, cutoff: SystemTime) -> Result<()> {
-        let bin_start = self.bin_start(cutoff);
-        if self
-            .start_time
-            .map(|start_time| start_time >= bin_start)
-            .unwrap_or(true)
-        {
-            return Ok(());
-        }
-
-        let bin_index = (bin_start
-            .duration_since(self.start_time.unwrap())
-            .unwrap()
-            .as_nanos()
-            / self.width.as_nanos()) as usize;
-        if bin_index >= self.panes.len() {
-            self.panes.clear();
-            return Ok(());
-        }
-        self.panes.drain(0..bin_index);
-        self.start_time = Some(bin_start);
-        Ok(())
-    }
 }
 
 impl TieredRecordBatchHolder {
@@ -334,13 +271,15 @@ impl TieredRecordBatchHolder {
                 i,
                 tier.start_time.map(print_time).unwrap_or_default()
             );
-            for (j, pane) in tier.panes.iter().enumerate() {
-                info!("pane {} has {} batches", j, pane.batches.len());
+            for (tier_index, pane_start) in self.panes.keys() {
+                if *tier_index == i {
+                    info!("pane {} is present", print_time(*pane_start));
+                }
             }
         }
     }
 
-    fn new(tier_widths: Vec<Duration>) -> Result<Self> {
+    fn new(tier_widths: Vec<Duration>, memory_budget: MemoryBudgetRef) -> Result<Self> {
         // check that each width evenly divides the next one:
         for i in 0..tier_widths.len() - 1 {
             let width = tier_widths[i];
@@ -357,18 +296,35 @@ impl TieredRecordBatchHolder {
             .iter()
             .map(|width| RecordBatchTier::new(*width))
             .collect::<Vec<_>>();
-        Ok(Self { tier_widths, tiers })
+        Ok(Self {
+            tier_widths,
+            tiers,
+            panes: SpillableMap::new(memory_budget),
+        })
     }
 
     fn insert(&mut self, batch: RecordBatch, timestamp: SystemTime) -> Result<()> {
-        for tier in self.tiers.iter_mut() {
-            tier.insert(batch.clone(), timestamp)?;
+        for (i, tier) in self.tiers.iter_mut().enumerate() {
+            let bin_start = tier.bin_start(timestamp);
+            match tier.start_time {
+                None => tier.start_time = Some(bin_start),
+                Some(start_time) if bin_start < start_time => {
+                    bail!(
+                        "tried to insert a batch at {} before the start of tier {} at {}",
+                        print_time(bin_start),
+                        i,
+                        print_time(start_time)
+                    );
+                }
+                Some(_) => {}
+            }
+            self.panes.push((i, bin_start), batch.clone());
         }
         Ok(())
     }
 
-    fn batches_for_interval(
-        &self,
+    async fn batches_for_interval(
+        &mut self,
         interval_start: SystemTime,
         interval_end: SystemTime,
     ) -> Result<Vec<RecordBatch>> {
@@ -398,9 +354,10 @@ impl TieredRecordBatchHolder {
                 }
             }
             // if we get here, we need to add the current tier
-            let tier = &self.tiers[current_tier];
-            batches.extend(tier.batches_for_timestamp(current_start)?);
-            current_start += tier.width;
+            if let Some(pane) = self.panes.get(&(current_tier, current_start)).await? {
+                batches.extend_from_slice(pane);
+            }
+            current_start += self.tiers[current_tier].width;
         }
         if current_start != interval_end {
             bail!(
@@ -413,17 +370,29 @@ impl TieredRecordBatchHolder {
     }
 
     fn delete_before(&mut self, cutoff: SystemTime) -> Result<()> {
-        for tier in self.tiers.iter_mut() {
-            tier.delete_before(cutoff)?;
-        }
+        let cutoffs: Vec<_> = self
+            .tiers
+            .iter_mut()
+            .map(|tier| {
+                let bin_start = tier.bin_start(cutoff);
+                if tier.start_time.is_some_and(|start| start < bin_start) {
+                    tier.start_time = Some(bin_start);
+                }
+                bin_start
+            })
+            .collect();
+        self.panes
+            .retain(|(tier, pane_start)| *pane_start >= cutoffs[*tier]);
         Ok(())
     }
 
     fn is_empty(&self) -> bool {
-        self.tiers[0]
-            .panes
-            .iter()
-            .all(|entry| entry.batches.is_empty())
+        !self.panes.keys().any(|(tier, _)| *tier == 0)
+    }
+
+    /// Spills the panes that were least recently used if the task is over its memory budget
+    async fn spill_if_needed(&mut self) -> Result<()> {
+        self.panes.spill_if_needed().await
     }
 }
 
@@ -517,9 +486,11 @@ impl OperatorConstructor for SlidingAggregatingWindowConstructor {
                 final_batches_passer,
                 futures: FuturesUnordered::new(),
                 execs: BTreeMap::new(),
-                tiered_record_batches: TieredRecordBatchHolder::new(vec![Duration::from_micros(
-                    config.slide_micros,
-                )])?,
+                // this is replaced in on_start, once the task's memory budget is available
+                tiered_record_batches: TieredRecordBatchHolder::new(
+                    vec![Duration::from_micros(config.slide_micros)],
+                    MemoryBudget::unbounded(),
+                )?,
                 projection_input_schema: final_projection.children()[0].schema().clone(),
                 final_projection,
                 state: SlidingWindowState::NoData,
@@ -536,6 +507,12 @@ impl ArrowOperator for SlidingAggregatingWindowFunc<SystemTime> {
     }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        self.tiered_record_batches = TieredRecordBatchHolder::new(
+            self.tiered_record_batches.tier_widths.clone(),
+            ctx.table_manager.memory_budget(),
+        )
+        .expect("tier widths were already validated");
+
         let watermark = ctx.last_present_watermark();
         let table = ctx
             .table_manager
@@ -544,20 +521,20 @@ impl ArrowOperator for SlidingAggregatingWindowFunc<SystemTime> {
             .expect("should be able to load table");
         // bins before the watermark should be put into the TieredRecordBatchHolder, those after in the exec.
         let watermark_bin = self.bin_start(watermark.unwrap_or(SystemTime::UNIX_EPOCH));
-        for (timestamp, batches) in table.all_batches_for_watermark(watermark) {
-            let bin = self.bin_start(*timestamp);
+        for (timestamp, batches) in table
+            .all_batches_for_watermark(watermark)
+            .await
+            .expect("should be able to read table")
+        {
+            let bin = self.bin_start(timestamp);
             if bin < watermark_bin {
                 for batch in batches {
-                    self.tiered_record_batches
-                        .insert(batch.clone(), bin)
-                        .unwrap();
+                    self.tiered_record_batches.insert(batch, bin).unwrap();
                 }
                 continue;
             }
             let holder = self.execs.entry(bin).or_default();
-            batches
-                .iter()
-                .for_each(|batch| holder.finished_batches.push(batch.clone()));
+            holder.finished_batches.extend(batches);
         }
 
         if self.tiered_record_batches.is_empty() {
@@ -211,12 +211,14 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
             .get_expiring_time_key_table("t", watermark)
             .await
             .expect("should be able to load table");
-        for (timestamp, batch) in table.all_batches_for_watermark(watermark) {
-            let bin = self.bin_start(*timestamp);
+        for (timestamp, batch) in table
+            .all_batches_for_watermark(watermark)
+            .await
+            .expect("should be able to read table")
+        {
+            let bin = self.bin_start(timestamp);
             let holder = self.execs.entry(bin).or_default();
-            batch
-                .iter()
-                .for_each(|batch| holder.finished_batches.push(batch.clone()));
+            holder.finished_batches.extend(batch);
         }
     }
 
@@ -40,6 +40,8 @@ pub struct UpdatingAggregatingFunc {
     state_partial_schema: ArroyoSchemaRef,
     state_final_schema: ArroyoSchemaRef,
     flush_interval: Duration,
+    ttl: Duration,
+    retract_on_expire: bool,
     combine_plan: Arc<dyn ExecutionPlan>,
     finish_execution_plan: Arc<dyn ExecutionPlan>,
     receiver: Arc<RwLock<Option<UnboundedReceiver<RecordBatch>>>>,
@@ -186,7 +188,7 @@ impl ArrowOperator for UpdatingAggregatingFunc {
                 timestamp_table_config(
                     "f",
                     "final_table",
-                    Duration::from_secs(60 * 60 * 24),
+                    self.ttl,
                     true,
                     self.state_final_schema.as_ref().clone(),
                 ),
@@ -196,7 +198,7 @@ impl ArrowOperator for UpdatingAggregatingFunc {
                 timestamp_table_config(
                     "p",
                     "partial_table",
-                    Duration::from_secs(60 * 60 * 24),
+                    self.ttl,
                     true,
                     self.state_partial_schema.as_ref().clone(),
                 ),
@@ -224,7 +226,10 @@ impl ArrowOperator for UpdatingAggregatingFunc {
             .get_last_key_value_table("p", last_watermark)
             .await
             .expect("should have partial table");
-        if partial_table.would_expire(last_watermark) {
+        if partial_table
+            .would_expire(last_watermark)
+            .expect("should check partial table expiration")
+        {
             self.flush(ctx).await.unwrap();
         }
         let partial_table = ctx
@@ -240,9 +245,26 @@ impl ArrowOperator for UpdatingAggregatingFunc {
             .get_last_key_value_table("f", last_watermark)
             .await
             .expect("should have final table");
-        final_table
-            .expire(last_watermark)
-            .expect("should expire final table");
+        if self.retract_on_expire {
+            let expired = final_table
+                .expire_returning(last_watermark, self.state_final_schema.schema.clone())
+                .expect("should expire final table");
+            if let Some(expired) = expired {
+                let is_retract = ColumnarValue::Scalar(ScalarValue::Boolean(Some(true)))
+                    .into_array(expired.num_rows())
+                    .unwrap();
+                let mut columns = expired.columns().to_vec();
+                columns.push(is_retract);
+                let retract_batch =
+                    RecordBatch::try_new(ctx.out_schema.as_ref().unwrap().schema.clone(), columns)
+                        .expect("should be able to build retraction batch");
+                ctx.collect(retract_batch).await;
+            }
+        } else {
+            final_table
+                .expire(last_watermark)
+                .expect("should expire final table");
+        }
         Some(watermark)
     }
 
@@ -344,6 +366,13 @@ impl OperatorConstructor for UpdatingAggregatingConstructor {
                         .try_into()?,
                 ),
                 flush_interval: Duration::from_micros(config.flush_interval_micros),
+                // programs planned before the TTL was configurable kept keys for a day
+                ttl: if config.ttl_micros == 0 {
+                    Duration::from_secs(60 * 60 * 24)
+                } else {
+                    Duration::from_micros(config.ttl_micros)
+                },
+                retract_on_expire: config.retract_on_expire,
                 finish_execution_plan,
                 receiver,
                 sender: None,
@@ -130,10 +130,14 @@ impl ArrowOperator for WindowFunctionOperator {
             .get_expiring_time_key_table("input", watermark)
             .await
             .unwrap();
-        for (timestamp, batches) in table.all_batches_for_watermark(watermark) {
-            let exec = self.get_or_insert_exec(*timestamp).await;
+        for (timestamp, batches) in table
+            .all_batches_for_watermark(watermark)
+            .await
+            .expect("should be able to read table")
+        {
+            let exec = self.get_or_insert_exec(timestamp).await;
             for batch in batches {
-                exec.sender.send(batch.clone()).unwrap();
+                exec.sender.send(batch).unwrap();
             }
         }
     }
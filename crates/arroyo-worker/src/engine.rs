@@ -24,7 +24,7 @@ use crate::arrow::window_fn::WindowFunctionConstructor;
 use crate::arrow::{KeyExecutionConstructor, ValueExecutionConstructor};
 use crate::network_manager::{NetworkManager, Quad, Senders};
 use arroyo_datastream::logical::{
-    LogicalEdge, LogicalEdgeType, LogicalGraph, LogicalNode, OperatorName,
+    LogicalEdge, LogicalEdgeType, LogicalGraph, LogicalNode, LogicalProgram, OperatorName,
 };
 use arroyo_df::physical::new_registry;
 use arroyo_operator::context::{batch_bounded, ArrowContext, BatchReceiver, BatchSender};
@@ -34,9 +34,10 @@ use arroyo_operator::ErasedConstructor;
 use arroyo_rpc::config::config;
 use arroyo_rpc::grpc::{
     api,
-    rpc::{CheckpointMetadata, TaskAssignment},
+    rpc::{CheckpointMetadata, TableConfig, TaskAssignment},
 };
 use arroyo_rpc::{ControlMessage, ControlResp};
+use arroyo_state::compatibility::StateCompatibilityReport;
 use arroyo_state::{BackingStore, StateBackend};
 use arroyo_types::{range_for_server, Key, TaskInfo, WorkerId};
 use arroyo_udf_host::LocalUdf;
@@ -177,6 +178,43 @@ impl Program {
         self.graph.read().unwrap().node_count()
     }
 
+    /// Compares the state tables of this program's operators against those in the checkpoint
+    /// for `epoch`, to determine which operators' state can be restored
+    pub async fn check_state_compatibility(
+        &self,
+        job_id: &str,
+        epoch: u32,
+    ) -> anyhow::Result<StateCompatibilityReport> {
+        let tables: HashMap<_, _> = {
+            let graph = self.graph.read().unwrap();
+            graph
+                .node_weights()
+                .filter_map(|n| match n {
+                    SubtaskOrQueueNode::SubtaskNode(n) => Some((n.id.clone(), n.node.tables())),
+                    SubtaskOrQueueNode::QueueNode(_) => None,
+                })
+                .collect()
+        };
+
+        let metadata = StateBackend::load_checkpoint_metadata(job_id, epoch).await?;
+        let mut checkpoint = HashMap::new();
+        for operator_id in &metadata.operator_ids {
+            let operator_metadata =
+                StateBackend::load_operator_metadata(job_id, operator_id, epoch)
+                    .await?
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "missing metadata for operator {} in checkpoint {}",
+                            operator_id,
+                            epoch
+                        )
+                    })?;
+            checkpoint.insert(operator_id.clone(), operator_metadata);
+        }
+
+        Ok(StateCompatibilityReport::check(&tables, &checkpoint))
+    }
+
     pub fn local_from_logical(
         name: String,
         logical: &DiGraph<LogicalNode, LogicalEdge>,
@@ -343,6 +381,8 @@ pub struct Engine {
 
 pub struct StreamConfig {
     pub restore_epoch: Option<u32>,
+    /// The epoch of the first checkpoint, if not the one after `restore_epoch`
+    pub next_epoch: Option<u32>,
 }
 
 pub struct RunningEngine {
@@ -506,6 +546,7 @@ impl Engine {
             for idx in node_indexes {
                 futures.push(self.schedule_node(
                     &checkpoint_metadata,
+                    config.next_epoch,
                     &control_tx,
                     idx,
                     ready.clone(),
@@ -537,6 +578,7 @@ impl Engine {
     async fn schedule_node(
         &self,
         checkpoint_metadata: &Option<CheckpointMetadata>,
+        next_epoch: Option<u32>,
         control_tx: &Sender<ControlResp>,
         idx: NodeIndex,
         ready: Arc<Barrier>,
@@ -567,6 +609,7 @@ impl Engine {
         if assignment.worker_id == self.worker_id.0 {
             self.run_locally(
                 checkpoint_metadata,
+                next_epoch,
                 control_tx,
                 idx,
                 node,
@@ -657,6 +700,7 @@ impl Engine {
     pub async fn run_locally(
         &self,
         checkpoint_metadata: &Option<CheckpointMetadata>,
+        next_epoch: Option<u32>,
         control_tx: &Sender<ControlResp>,
         idx: NodeIndex,
         node: SubtaskNode,
@@ -717,6 +761,7 @@ impl Engine {
         let ctx = ArrowContext::new(
             task_info,
             checkpoint_metadata.clone(),
+            next_epoch,
             control_rx,
             control_tx.clone(),
             in_qs.len(),
@@ -760,6 +805,33 @@ impl Engine {
     }
 }
 
+/// Returns the state tables of each operator in the program, keyed by operator id
+pub async fn operator_tables(
+    program: &LogicalProgram,
+) -> anyhow::Result<HashMap<String, HashMap<String, TableConfig>>> {
+    let mut registry = new_registry();
+    for (udf_name, dylib_config) in &program.program_config.udf_dylibs {
+        registry
+            .load_dylib(udf_name, dylib_config)
+            .await
+            .map_err(|e| e.context(format!("loading UDF {udf_name}")))?;
+    }
+    let registry = Arc::new(registry);
+
+    Ok(program
+        .graph
+        .node_weights()
+        .map(|node| {
+            let operator = construct_operator(
+                node.operator_name,
+                node.operator_config.clone(),
+                registry.clone(),
+            );
+            (node.operator_id.clone(), operator.tables())
+        })
+        .collect())
+}
+
 pub fn construct_operator(
     operator: OperatorName,
     config: Vec<u8>,
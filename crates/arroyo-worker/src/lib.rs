@@ -10,9 +10,10 @@ use arroyo_rpc::grpc::rpc::worker_grpc_server::{WorkerGrpc, WorkerGrpcServer};
 use arroyo_rpc::grpc::rpc::{
     CheckpointReq, CheckpointResp, CommitReq, CommitResp, HeartbeatReq, JobFinishedReq,
     JobFinishedResp, LoadCompactedDataReq, LoadCompactedDataRes, MetricFamily, MetricsReq,
-    MetricsResp, RegisterWorkerReq, StartExecutionReq, StartExecutionResp, StopExecutionReq,
-    StopExecutionResp, TaskCheckpointCompletedReq, TaskCheckpointEventReq, TaskFailedReq,
-    TaskFinishedReq, TaskStartedReq, WorkerErrorReq, WorkerResources,
+    MetricsResp, QueryStateReq, QueryStateResp, RegisterWorkerReq, StartExecutionReq,
+    StartExecutionResp, StopExecutionReq, StopExecutionResp, TaskCheckpointCompletedReq,
+    TaskCheckpointEventReq, TaskFailedReq, TaskFinishedReq, TaskStartedReq, WorkerErrorReq,
+    WorkerResources,
 };
 use arroyo_types::{
     from_millis, to_micros, CheckpointBarrier, NodeId, WorkerId, JOB_ID_ENV, RUN_ID_ENV,
@@ -25,15 +26,16 @@ use std::fmt::{Debug, Display, Formatter};
 use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::net::TcpListener;
 use tokio::select;
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
 use tokio_stream::wrappers::TcpListenerStream;
 use tonic::{Request, Response, Status};
 use tracing::{debug, error, info, warn};
 
-use arroyo_rpc::{retry, CompactionResult, ControlMessage, ControlResp};
+use arroyo_rpc::{retry, CompactionResult, ControlMessage, ControlResp, StateQuery};
 pub use ordered_float::OrderedFloat;
 use prometheus::{Encoder, ProtobufEncoder};
 use prost::Message;
@@ -111,6 +113,7 @@ impl LocalRunner {
         let (_running_engine, mut control_rx) = engine
             .start(StreamConfig {
                 restore_epoch: None,
+                next_epoch: None,
             })
             .await;
 
@@ -142,6 +145,7 @@ pub struct WorkerServer {
     controller_addr: String,
     state: Arc<Mutex<Option<EngineState>>>,
     network: Arc<Mutex<Option<NetworkManager>>>,
+    registration: Arc<Mutex<Option<RegisterWorkerReq>>>,
     shutdown_guard: ShutdownGuard,
 }
 
@@ -180,6 +184,7 @@ impl WorkerServer {
             controller_addr,
             state: Arc::new(Mutex::new(None)),
             network: Arc::new(Mutex::new(None)),
+            registration: Arc::new(Mutex::new(None)),
             shutdown_guard,
         }
     }
@@ -230,6 +235,8 @@ impl WorkerServer {
         let rpc_address = format!("http://{}:{}", local_ip, local_addr.port());
         let data_address = format!("{}:{}", local_ip, data_port);
         let job_id = self.job_id.clone();
+        let run_id = self.run_id.parse().unwrap_or(0);
+        let registration = self.registration.clone();
 
         self.shutdown_guard
             .child("grpc")
@@ -244,20 +251,22 @@ impl WorkerServer {
         // ideally, get a signal when the server is started...
         tokio::time::sleep(Duration::from_millis(50)).await;
 
-        client
-            .register_worker(Request::new(RegisterWorkerReq {
-                worker_id: id.0,
-                node_id: node_id.0,
-                job_id,
-                rpc_address,
-                data_address,
-                resources: Some(WorkerResources {
-                    slots: std::thread::available_parallelism().unwrap().get() as u64,
-                }),
-                slots: config.worker.task_slots as u64,
-            }))
-            .await
-            .unwrap();
+        let req = RegisterWorkerReq {
+            worker_id: id.0,
+            node_id: node_id.0,
+            job_id,
+            rpc_address,
+            data_address,
+            resources: Some(WorkerResources {
+                slots: std::thread::available_parallelism().unwrap().get() as u64,
+            }),
+            slots: config.worker.task_slots as u64,
+            run_id,
+            tasks: vec![],
+        };
+        *registration.lock().unwrap() = Some(req.clone());
+
+        client.register_worker(Request::new(req)).await.unwrap();
 
         Ok(())
     }
@@ -272,6 +281,7 @@ impl WorkerServer {
         mut control_rx: Receiver<ControlResp>,
         worker_id: WorkerId,
         job_id: String,
+        registration: Option<RegisterWorkerReq>,
     ) -> impl Future<Output = Result<()>> {
         let addr = self.controller_addr.clone();
 
@@ -283,6 +293,8 @@ impl WorkerServer {
                 .expect("Unable to connect to controller");
             let mut tick = tokio::time::interval(Duration::from_secs(5));
             tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            let mut last_heartbeat = Instant::now();
+            let mut leader_term = None;
             loop {
                 select! {
                     msg = control_rx.recv() => {
@@ -376,9 +388,38 @@ impl WorkerServer {
                             time: to_micros(SystemTime::now()),
                             worker_id: worker_id.0,
                         })).await;
-                        if let Err(err) = result {
-                            error!("heartbeat failed {:?}", err);
-                            break;
+                        match result {
+                            Ok(resp) => {
+                                let term = resp.into_inner().leader_term;
+                                if leader_term.is_some_and(|t| t != term) {
+                                    // a new leader has taken over; re-register with the tasks
+                                    // we're running, so that it can adopt the job rather than
+                                    // restarting it
+                                    info!(message = "controller leader changed, re-registering", term);
+                                    if let Some(registration) = &registration {
+                                        if let Err(err) = controller.register_worker(Request::new(registration.clone())).await {
+                                            warn!("failed to re-register with controller: {:?}", err);
+                                            continue;
+                                        }
+                                    }
+                                }
+                                leader_term = Some(term);
+                                last_heartbeat = Instant::now();
+                            }
+                            // by the time we've missed heartbeats for this long, the controller
+                            // (or a new leader that we haven't been able to reach) has given up on
+                            // this worker and restarted the job elsewhere
+                            Err(err) if last_heartbeat.elapsed() > *config().pipeline.worker_heartbeat_timeout => {
+                                error!("heartbeat failed for longer than the heartbeat timeout, shutting down: {:?}", err);
+                                cancel_token.cancel();
+                                break;
+                            }
+                            Err(err) => {
+                                warn!("heartbeat failed, reconnecting to controller: {:?}", err);
+                                if let Ok(client) = ControllerGrpcClient::connect(addr.clone()).await {
+                                    controller = client;
+                                }
+                            }
                         }
                     }
                 }
@@ -425,12 +466,46 @@ impl WorkerGrpc for WorkerServer {
             }
         }
 
-        let (engine, control_rx) = {
-            let network = { self.network.lock().unwrap().take().unwrap() };
+        // if the controller fails over, we re-register with the tasks we're running so that the
+        // new leader can adopt them
+        let registration = self
+            .registration
+            .lock()
+            .unwrap()
+            .clone()
+            .map(|r| RegisterWorkerReq {
+                tasks: req.tasks.clone(),
+                ..r
+            });
 
+        let (engine, control_rx) = {
             let program =
                 Program::from_logical(self.name.to_string(), &logical.graph, &req.tasks, registry);
 
+            if let Some(epoch) = req.restore_epoch.filter(|_| req.check_state_compatibility) {
+                let report = program
+                    .check_state_compatibility(&self.job_id, epoch)
+                    .await
+                    .map_err(|e| {
+                        Status::failed_precondition(format!(
+                            "failed to check state compatibility with checkpoint {}: {:?}",
+                            epoch, e
+                        ))
+                    })?;
+
+                info!("State compatibility with checkpoint {}:\n{}", epoch, report);
+
+                if !report.is_compatible() {
+                    return Err(Status::failed_precondition(format!(
+                        "the pipeline cannot be restored from checkpoint {} because it is \
+                        incompatible with the state of some operators:\n{}",
+                        epoch, report
+                    )));
+                }
+            }
+
+            let network = { self.network.lock().unwrap().take().unwrap() };
+
             let engine = Engine::new(
                 program,
                 self.id,
@@ -442,13 +517,19 @@ impl WorkerGrpc for WorkerServer {
             engine
                 .start(StreamConfig {
                     restore_epoch: req.restore_epoch,
+                    next_epoch: req.next_epoch,
                 })
                 .await
         };
 
         self.shutdown_guard
             .child("control-thread")
-            .into_spawn_task(self.start_control_thread(control_rx, self.id, self.job_id.clone()));
+            .into_spawn_task(self.start_control_thread(
+                control_rx,
+                self.id,
+                self.job_id.clone(),
+                registration,
+            ));
 
         let sources = engine.source_controls();
         let sinks = engine.sink_controls();
@@ -514,6 +595,7 @@ impl WorkerGrpc for WorkerServer {
             min_epoch: req.min_epoch,
             timestamp: from_millis(req.timestamp),
             then_stop: req.then_stop,
+            unaligned: req.unaligned,
         };
 
         for n in &senders {
@@ -657,4 +739,66 @@ impl WorkerGrpc for WorkerServer {
 
         Ok(Response::new(MetricsResp { metrics }))
     }
+
+    async fn query_state(
+        &self,
+        request: Request<QueryStateReq>,
+    ) -> Result<Response<QueryStateResp>, Status> {
+        let req = request.into_inner();
+
+        let key = req
+            .key
+            .map(|key| serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&key))
+            .transpose()
+            .map_err(|e| Status::invalid_argument(format!("key must be a JSON object: {}", e)))?;
+
+        let senders = {
+            let state = self.state.lock().unwrap();
+            let Some(state) = state.as_ref() else {
+                return Err(Status::failed_precondition("worker is not running"));
+            };
+            // this worker may not be running any subtasks of the operator
+            state
+                .operator_controls
+                .get(&req.operator_id)
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        let mut receivers = vec![];
+        for sender in senders {
+            let (tx, rx) = oneshot::channel();
+            sender
+                .send(ControlMessage::QueryState(StateQuery {
+                    table: req.table.clone(),
+                    key: key.clone(),
+                    limit: req.limit as usize,
+                    tx,
+                }))
+                .await
+                .map_err(|_| Status::unavailable("operator is no longer running"))?;
+            receivers.push(rx);
+        }
+
+        let mut rows = vec![];
+        for rx in receivers {
+            let Ok(result) = rx.await else {
+                // the subtask finished before it handled the query
+                continue;
+            };
+
+            if let Some(batch) = result.map_err(|e| Status::invalid_argument(e.to_string()))? {
+                rows.extend(
+                    arrow_json::writer::record_batches_to_json_rows(&[&batch]).map_err(|e| {
+                        Status::internal(format!("failed to convert state to JSON: {}", e))
+                    })?,
+                );
+            }
+        }
+        rows.truncate(req.limit as usize);
+
+        Ok(Response::new(QueryStateResp {
+            rows: serde_json::to_string(&rows).unwrap(),
+        }))
+    }
 }
@@ -211,31 +211,42 @@ struct NetworkReceiver {
 struct OutNetworkLink {
     _dest: String,
     stream: BufWriter<TcpStream>,
+    // barriers for unaligned checkpoints are sent on their own connection, so that they don't
+    // wait behind data that the other side isn't reading because its queues are full
+    priority_stream: TcpStream,
     receivers: Vec<NetworkReceiver>,
 }
 
+async fn connect_with_retries(dest: &str) -> TcpStream {
+    let mut rand = StdRng::from_entropy();
+    for i in 0..10 {
+        match TcpStream::connect(dest).await {
+            Ok(stream) => {
+                return stream;
+            }
+            Err(e) => {
+                warn!("Failed to connect to {dest}: {:?}", e);
+                tokio::time::sleep(Duration::from_millis(
+                    (i + 1) * (50 + rand.gen_range(1..50)),
+                ))
+                .await;
+            }
+        }
+    }
+    panic!("failed to connect to {dest}");
+}
+
 impl OutNetworkLink {
     pub async fn connect(dest: String) -> Self {
-        let mut rand = StdRng::from_entropy();
-        for i in 0..10 {
-            match TcpStream::connect(&dest).await {
-                Ok(stream) => {
-                    return Self {
-                        _dest: dest,
-                        stream: BufWriter::new(stream),
-                        receivers: vec![],
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to connect to {dest}: {:?}", e);
-                    tokio::time::sleep(Duration::from_millis(
-                        (i + 1) * (50 + rand.gen_range(1..50)),
-                    ))
-                    .await;
-                }
-            }
+        let stream = connect_with_retries(&dest).await;
+        let priority_stream = connect_with_retries(&dest).await;
+        priority_stream.set_nodelay(true).unwrap();
+        Self {
+            _dest: dest,
+            stream: BufWriter::new(stream),
+            priority_stream,
+            receivers: vec![],
         }
-        panic!("failed to connect to {dest}");
     }
 
     pub async fn add_receiver(&mut self, quad: Quad, rx: BatchReceiver) {
@@ -247,6 +258,33 @@ impl OutNetworkLink {
     }
 
     pub fn start(mut self) {
+        let mut priority = InQReader::new();
+        for receiver in &mut self.receivers {
+            if let Some(mut rx) = receiver.rx.take_priority() {
+                let quad = receiver.quad;
+                let stream = async_stream::stream! {
+                    while let Some(item) = rx.recv().await {
+                        yield (quad, item);
+                    }
+                };
+                priority.push(Box::pin(stream));
+            }
+        }
+
+        let mut priority_stream = self.priority_stream;
+        tokio::spawn(async move {
+            while let Some(((quad, msg), s)) = priority.next().await {
+                let ArrowMessage::Signal(signal) = msg else {
+                    unreachable!("only signals are sent on the priority lane");
+                };
+                let data = bincode::encode_to_vec(&signal, config::standard()).unwrap();
+                let header = Header::from_quad(quad, data.len(), MessageType::Signal);
+                header.write(&mut Pin::new(&mut priority_stream)).await;
+                priority_stream.write_all(&data).await.unwrap();
+                priority.push(s);
+            }
+        });
+
         tokio::spawn(async move {
             let mut sel = InQReader::new();
             for NetworkReceiver {
@@ -593,6 +631,7 @@ mod test {
             min_epoch: 3,
             timestamp: SystemTime::now(),
             then_stop: false,
+            unaligned: false,
         }));
 
         client_tx.send(message.clone()).await.unwrap();
@@ -604,4 +643,72 @@ mod test {
 
         assert_eq!(result, message);
     }
+
+    #[tokio::test]
+    async fn test_unaligned_barrier_overtakes_blocked_data() {
+        let (server_tx, mut server_rx) = batch_bounded(10);
+        let mut server_priority_rx = server_rx.take_priority().unwrap();
+
+        let quad = Quad {
+            src_id: 1,
+            src_idx: 0,
+            dst_id: 2,
+            dst_idx: 0,
+        };
+
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "id",
+            arrow_schema::DataType::UInt64,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt64Array::from((0..10).collect::<Vec<_>>()))],
+        )
+        .unwrap();
+
+        let mut senders = Senders::new();
+        senders.add(quad, schema.clone(), server_tx);
+
+        let shutdown = Shutdown::new("test", SignalBehavior::None);
+        let mut nm = NetworkManager::new(0);
+        let port = nm.open_listener(shutdown.guard("test")).await;
+
+        let (client_tx, client_rx) = batch_bounded(10);
+        nm.connect(format!("localhost:{}", port), quad, client_rx)
+            .await;
+        nm.start(senders).await;
+
+        // nothing reads the server's data lane, so it fills up after the first batch and the
+        // rest of the data is stuck in the connection
+        for _ in 0..5 {
+            client_tx
+                .send(ArrowMessage::Data(batch.clone()))
+                .await
+                .unwrap();
+        }
+
+        let barrier = ArrowMessage::Signal(SignalMessage::Barrier(CheckpointBarrier {
+            epoch: 1,
+            min_epoch: 0,
+            timestamp: SystemTime::now(),
+            then_stop: false,
+            unaligned: true,
+        }));
+        client_tx.send(barrier.clone()).await.unwrap();
+
+        let result = timeout(Duration::from_secs(1), server_priority_rx.recv())
+            .await
+            .expect("timed out")
+            .unwrap();
+        assert_eq!(result, barrier);
+
+        for _ in 0..5 {
+            let result = timeout(Duration::from_secs(1), server_rx.recv())
+                .await
+                .expect("timed out")
+                .unwrap();
+            assert_eq!(result, ArrowMessage::Data(batch.clone()));
+        }
+    }
 }
@@ -1,4 +1,5 @@
 mod run;
+mod state;
 
 use anyhow::{anyhow, bail};
 use std::path::PathBuf;
@@ -9,7 +10,7 @@ use arroyo_rpc::config::{config, DatabaseType};
 use arroyo_server_common::shutdown::{Shutdown, SignalBehavior};
 use arroyo_server_common::{log_event, start_admin_server};
 use arroyo_worker::WorkerServer;
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use clio::Input;
 use cornucopia_async::DatabaseSource;
 use deadpool_postgres::{ManagerConfig, Pool, RecyclingMethod};
@@ -48,19 +49,86 @@ struct RunArgs {
     #[arg(short = 's', long)]
     state_dir: Option<String>,
 
-    /// Number of parallel subtasks to run
+    /// Number of parallel subtasks to run for each operator, unless overridden in the query with
+    /// `SET parallelism.<node_id> = <n>` or a table's `parallelism` option
     #[arg(short, long, default_value = "1")]
     parallelism: u32,
 
-    /// Force the pipeline to start even if the state file does not match the query
+    /// Start the pipeline even if the state directory is for a different query; the state of
+    /// operators that are compatible with the new query is restored, and the rest start empty
     #[clap(short, long)]
     force: bool,
 
+    /// Restore from the checkpoint with this epoch instead of the latest one; any later
+    /// checkpoints are discarded
+    #[arg(long)]
+    checkpoint_epoch: Option<u32>,
+
     /// The query to run
     #[clap(value_parser, default_value = "-")]
     query: Input,
 }
 
+#[derive(Args)]
+struct StateArgs {
+    /// Id of the job whose checkpoints should be inspected
+    #[arg(short, long)]
+    job_id: String,
+
+    /// Checkpoint URL the job was run with; defaults to the configured checkpoint URL
+    #[arg(short, long)]
+    url: Option<String>,
+
+    #[command(subcommand)]
+    command: StateCommand,
+}
+
+#[derive(Subcommand)]
+enum StateCommand {
+    /// Lists the completed checkpoints for the job and the operators in each
+    List,
+
+    /// Reports the sizes, row counts and key ranges of each operator's tables
+    Stats {
+        /// Epoch of the checkpoint to inspect; defaults to the latest
+        #[arg(short, long)]
+        epoch: Option<u32>,
+
+        /// Only report on this operator
+        #[arg(short, long)]
+        operator: Option<String>,
+    },
+
+    /// Writes out the contents of a table as of a checkpoint
+    Dump {
+        /// Epoch of the checkpoint to read; defaults to the latest
+        #[arg(short, long)]
+        epoch: Option<u32>,
+
+        /// Operator that owns the table
+        #[arg(short, long)]
+        operator: String,
+
+        /// Name of the table to dump
+        #[arg(short, long)]
+        table: String,
+
+        #[arg(short, long, value_enum, default_value = "json")]
+        format: DumpFormat,
+
+        /// File to write to; defaults to stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum DumpFormat {
+    Json,
+    Csv,
+    Parquet,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Run a query as a local pipeline cluster
@@ -84,6 +152,9 @@ enum Commands {
     /// Starts an Arroyo node server
     Node {},
 
+    /// Inspects the checkpointed state of a job
+    State(StateArgs),
+
     /// Runs database migrations on the configured Postgres database
     Migrate {
         /// If set, waits for the specified number of seconds until Postgres is ready before running migrations
@@ -148,6 +219,12 @@ async fn main() {
         Commands::Run(args) => {
             run::run(args).await;
         }
+        Commands::State(args) => {
+            if let Err(e) = state::run(args).await {
+                eprintln!("{:#}", e);
+                exit(1);
+            }
+        }
     };
 }
 
@@ -1,11 +1,14 @@
 use crate::{db_source, RunArgs};
 use anyhow::{anyhow, bail};
-use arroyo_openapi::types::{Pipeline, PipelinePatch, PipelinePost, StopType, ValidateQueryPost};
+use arroyo_openapi::types::{
+    Pipeline, PipelinePatch, PipelinePost, PipelineRestart, StopType, ValidateQueryPost,
+};
 use arroyo_openapi::Client;
 use arroyo_rpc::config::{config, DatabaseType, DefaultSink, Scheduler};
 use arroyo_rpc::{config, init_db_notifier, notify_db, retry};
 use arroyo_server_common::log_event;
 use arroyo_server_common::shutdown::{Shutdown, ShutdownHandler, SignalBehavior};
+use arroyo_state::{BackingStore, StateBackend};
 use arroyo_storage::StorageProvider;
 use arroyo_types::to_millis;
 use async_trait::async_trait;
@@ -141,6 +144,56 @@ async fn get_pipelines(client: &Client) -> anyhow::Result<Vec<Pipeline>> {
     Ok(result)
 }
 
+/// Writes a savepoint from the latest checkpoint of `pipeline`, so that a pipeline for a different
+/// query can be restored from it; returns None if the pipeline was never checkpointed
+async fn savepoint_latest_checkpoint(
+    client: &Client,
+    pipeline: &Pipeline,
+) -> anyhow::Result<Option<String>> {
+    let Some(job) = client
+        .get_pipeline_jobs()
+        .id(&pipeline.id)
+        .send()
+        .await?
+        .into_inner()
+        .data
+        .into_iter()
+        .next()
+    else {
+        return Ok(None);
+    };
+
+    let Some(epoch) = client
+        .get_job_checkpoints()
+        .pipeline_id(&pipeline.id)
+        .job_id(&job.id)
+        .send()
+        .await?
+        .into_inner()
+        .data
+        .into_iter()
+        .filter(|c| c.finish_time.is_some() && c.failure_reason.is_none())
+        .map(|c| c.epoch)
+        .max()
+    else {
+        return Ok(None);
+    };
+
+    let url = format!(
+        "{}/savepoints/{}-{}",
+        config().checkpoint_url,
+        job.id,
+        epoch
+    );
+    info!(
+        "Writing checkpoint {} of pipeline {} to a savepoint at {}",
+        epoch, pipeline.id, url
+    );
+    StateBackend::write_savepoint(&job.id, epoch, &url).await?;
+
+    Ok(Some(url))
+}
+
 async fn run_pipeline(
     client: Arc<Client>,
     name: Option<String>,
@@ -149,6 +202,7 @@ async fn run_pipeline(
     http_port: u16,
     shutdown_handler: PipelineShutdownHandler,
     force: bool,
+    checkpoint_epoch: Option<u32>,
 ) -> anyhow::Result<()> {
     // wait until server is available
     wait_for_connect(&client).await.unwrap();
@@ -177,6 +231,16 @@ async fn run_pipeline(
     {
         Some(p) => {
             info!("Pipeline already exists in database as {}", p.id);
+            if let Some(epoch) = checkpoint_epoch {
+                // the restart is picked up when the pipeline is started below
+                info!("Restoring pipeline from checkpoint {}", epoch);
+                client
+                    .restart_pipeline()
+                    .id(&p.id)
+                    .body(PipelineRestart::builder().checkpoint_epoch(epoch as i64))
+                    .send()
+                    .await?;
+            }
             client
                 .patch_pipeline()
                 .id(&p.id)
@@ -186,29 +250,48 @@ async fn run_pipeline(
             p.id
         }
         None => {
+            if checkpoint_epoch.is_some() {
+                bail!("--checkpoint-epoch was supplied, but there is no existing pipeline for this query to restore");
+            }
+
             // or create it, unless there are other pipelines, which would indicate that this is
             // a DB for another query
-            if client.get_pipelines().send().await?.data.len() > 0 {
+            let mut restore_from = None;
+            if let Some(previous) = get_pipelines(&client)
+                .await?
+                .into_iter()
+                .max_by_key(|p| p.created_at)
+            {
                 let msg =
                     "The specified state is for a different pipeline; this likely means either \
                     the state directory is incorrect or the query is incorrect.";
                 if force {
                     warn!("{}", msg);
-                    warn!("--force was supplied, so continuing anyways");
+                    warn!(
+                        "--force was supplied, so continuing with the state of pipeline {} that \
+                        is compatible with the new query",
+                        previous.id
+                    );
+                    restore_from = savepoint_latest_checkpoint(&client, &previous).await?;
                 } else {
                     error!("{}", msg);
                     bail!("Exiting... if you would like to continue pass --force");
                 }
             }
 
+            let mut pipeline = PipelinePost::builder()
+                .name(name.unwrap_or_else(|| "query".to_string()))
+                .parallelism(parallelism)
+                .query(&query);
+
+            if let Some(url) = restore_from {
+                // operators whose state doesn't match the new query start empty
+                pipeline = pipeline.restore_from(url).drop_incompatible_state(true);
+            }
+
             client
                 .create_pipeline()
-                .body(
-                    PipelinePost::builder()
-                        .name(name.unwrap_or_else(|| "query".to_string()))
-                        .parallelism(parallelism)
-                        .query(&query),
-                )
+                .body(pipeline)
                 .send()
                 .await?
                 .into_inner()
@@ -456,6 +539,7 @@ pub async fn run(args: RunArgs) {
             http_port,
             shutdown_handler,
             args.force,
+            args.checkpoint_epoch,
         )
         .await
     });
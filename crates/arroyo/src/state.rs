@@ -0,0 +1,351 @@
+use crate::{DumpFormat, StateArgs, StateCommand};
+use anyhow::{bail, Context};
+use arrow::array::{ArrayRef, AsArray, RecordBatch, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arroyo_rpc::config::config;
+use arroyo_state::inspect::CheckpointInspector;
+use arroyo_types::{from_micros, print_time};
+use parquet::arrow::ArrowWriter;
+use std::fs::File;
+use std::io::{stdout, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub async fn run(args: StateArgs) -> anyhow::Result<()> {
+    let url = args.url.unwrap_or_else(|| config().checkpoint_url.clone());
+
+    let inspector = CheckpointInspector::new(&url, &args.job_id).await?;
+
+    match args.command {
+        StateCommand::List => list(&inspector).await,
+        StateCommand::Stats { epoch, operator } => stats(&inspector, epoch, operator).await,
+        StateCommand::Dump {
+            epoch,
+            operator,
+            table,
+            format,
+            output,
+        } => dump(&inspector, epoch, &operator, &table, format, output).await,
+    }
+}
+
+async fn resolve_epoch(inspector: &CheckpointInspector, epoch: Option<u32>) -> anyhow::Result<u32> {
+    match epoch {
+        Some(epoch) => Ok(epoch),
+        None => inspector.latest_epoch().await,
+    }
+}
+
+async fn list(inspector: &CheckpointInspector) -> anyhow::Result<()> {
+    let epochs = inspector.epochs().await?;
+    if epochs.is_empty() {
+        println!("No checkpoints found");
+        return Ok(());
+    }
+
+    for epoch in epochs {
+        let checkpoint = inspector.checkpoint(epoch).await?;
+        println!(
+            "epoch {} (min epoch {}, finished at {}, took {:?}, wrote {} bytes)",
+            epoch,
+            checkpoint.min_epoch,
+            print_time(from_micros(checkpoint.finish_time)),
+            Duration::from_micros(checkpoint.finish_time.saturating_sub(checkpoint.start_time)),
+            checkpoint.bytes
+        );
+        for operator_id in &checkpoint.operator_ids {
+            println!("  {}", operator_id);
+        }
+    }
+
+    Ok(())
+}
+
+async fn stats(
+    inspector: &CheckpointInspector,
+    epoch: Option<u32>,
+    operator: Option<String>,
+) -> anyhow::Result<()> {
+    let epoch = resolve_epoch(inspector, epoch).await?;
+    let checkpoint = inspector.checkpoint(epoch).await?;
+
+    let operators = match operator {
+        Some(operator) => {
+            if !checkpoint.operator_ids.contains(&operator) {
+                bail!("operator {} is not in checkpoint {}", operator, epoch);
+            }
+            vec![operator]
+        }
+        None => checkpoint.operator_ids,
+    };
+
+    println!("epoch {}", epoch);
+    for operator_id in operators {
+        println!("  {}", operator_id);
+        for table in inspector.table_stats(epoch, &operator_id).await? {
+            print!(
+                "    {} ({:?}): {} files, {} bytes, {} rows",
+                table.name, table.table_type, table.files, table.bytes, table.rows
+            );
+            if let Some(stats) = table.stats {
+                print!(
+                    ", keys {:#018x}..={:#018x}, max timestamp {}",
+                    stats.min_routing_key,
+                    stats.max_routing_key,
+                    print_time(stats.max_timestamp)
+                );
+            }
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+/// Binary columns (like the hashed keys) can't be represented in JSON or CSV, so we write them
+/// out as hex strings instead
+fn hex_encode_binary(batch: &RecordBatch) -> anyhow::Result<RecordBatch> {
+    let mut fields = vec![];
+    let mut columns: Vec<ArrayRef> = vec![];
+
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        if field.data_type() == &DataType::Binary {
+            let binary = column.as_binary::<i32>();
+            let hex: StringArray = binary
+                .iter()
+                .map(|v| v.map(|v| v.iter().map(|b| format!("{:02x}", b)).collect::<String>()))
+                .collect();
+            fields.push(Field::new(
+                field.name(),
+                DataType::Utf8,
+                field.is_nullable(),
+            ));
+            columns.push(Arc::new(hex));
+        } else {
+            fields.push(field.as_ref().clone());
+            columns.push(column.clone());
+        }
+    }
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+async fn dump(
+    inspector: &CheckpointInspector,
+    epoch: Option<u32>,
+    operator: &str,
+    table: &str,
+    format: DumpFormat,
+    output: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let epoch = resolve_epoch(inspector, epoch).await?;
+    let batches = inspector.read_table(epoch, operator, table).await?;
+
+    let out: Box<dyn Write + Send> = match &output {
+        Some(path) => Box::new(
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?,
+        ),
+        None => Box::new(stdout()),
+    };
+
+    match format {
+        DumpFormat::Json => {
+            let mut writer = arrow::json::LineDelimitedWriter::new(out);
+            for batch in &batches {
+                writer.write(&hex_encode_binary(batch)?)?;
+            }
+            writer.finish()?;
+        }
+        DumpFormat::Csv => {
+            let mut writer = arrow::csv::Writer::new(out);
+            for batch in &batches {
+                writer.write(&hex_encode_binary(batch)?)?;
+            }
+        }
+        DumpFormat::Parquet => {
+            let Some(first) = batches.first() else {
+                bail!("table {} has no data in checkpoint {}", table, epoch);
+            };
+            let mut writer = ArrowWriter::try_new(out, first.schema(), None)?;
+            for batch in &batches {
+                writer.write(batch)?;
+            }
+            writer.close()?;
+        }
+    }
+
+    if output.is_some() {
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        eprintln!(
+            "Wrote {} rows from {}/{} at epoch {}",
+            rows, operator, table, epoch
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow::array::BinaryArray;
+    use arroyo_rpc::grpc::rpc::{
+        CheckpointMetadata, GlobalKeyedTableTaskCheckpointMetadata, OperatorCheckpointMetadata,
+        TableCheckpointMetadata, TableEnum,
+    };
+    use arroyo_storage::StorageProvider;
+    use prost::Message;
+    use std::collections::HashMap;
+
+    /// Writes a checkpoint for epoch 1 of job `job` with a single operator `op`, whose global
+    /// table `t` has two entries
+    async fn write_checkpoint(url: &str) {
+        let storage = StorageProvider::for_url(url).await.unwrap();
+        let base = "job/checkpoints/checkpoint-0000001";
+        let file = format!("{}/operator-op/table-t-000", base);
+
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("key", DataType::Binary, false),
+                Field::new("value", DataType::Binary, false),
+            ])),
+            vec![
+                Arc::new(BinaryArray::from_vec(vec![&b"a"[..], &b"b"[..]])),
+                Arc::new(BinaryArray::from_vec(vec![&[1u8][..], &[2u8][..]])),
+            ],
+        )
+        .unwrap();
+        let mut writer = ArrowWriter::try_new(vec![], batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        storage
+            .put(&file, writer.into_inner().unwrap())
+            .await
+            .unwrap();
+
+        let operator = OperatorCheckpointMetadata {
+            table_checkpoint_metadata: HashMap::from([(
+                "t".to_string(),
+                TableCheckpointMetadata {
+                    table_type: TableEnum::GlobalKeyValue.into(),
+                    data: GlobalKeyedTableTaskCheckpointMetadata {
+                        files: vec![file],
+                        commit_data_by_subtask: HashMap::new(),
+                    }
+                    .encode_to_vec(),
+                },
+            )]),
+            ..Default::default()
+        };
+        storage
+            .put(
+                &format!("{}/operator-op/metadata", base),
+                operator.encode_to_vec(),
+            )
+            .await
+            .unwrap();
+
+        let checkpoint = CheckpointMetadata {
+            job_id: "job".to_string(),
+            epoch: 1,
+            min_epoch: 1,
+            operator_ids: vec!["op".to_string()],
+            ..Default::default()
+        };
+        storage
+            .put(&format!("{}/metadata", base), checkpoint.encode_to_vec())
+            .await
+            .unwrap();
+    }
+
+    fn args(url: &str, command: StateCommand) -> StateArgs {
+        StateArgs {
+            job_id: "job".to_string(),
+            url: Some(url.to_string()),
+            command,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inspect_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("arroyo-state-cli-{}", rand::random::<u64>()));
+        let url = format!("file://{}", dir.to_str().unwrap());
+        write_checkpoint(&url).await;
+
+        let inspector = CheckpointInspector::new(&url, "job").await.unwrap();
+        assert_eq!(inspector.epochs().await.unwrap(), vec![1]);
+
+        let stats = inspector.table_stats(1, "op").await.unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].name, "t");
+        assert_eq!(stats[0].files, 1);
+        assert_eq!(stats[0].rows, 2);
+
+        run(args(&url, StateCommand::List)).await.unwrap();
+        run(args(
+            &url,
+            StateCommand::Stats {
+                epoch: None,
+                operator: None,
+            },
+        ))
+        .await
+        .unwrap();
+
+        // binary keys and values are written out as hex
+        let output = dir.join("dump.json");
+        run(args(
+            &url,
+            StateCommand::Dump {
+                epoch: None,
+                operator: "op".to_string(),
+                table: "t".to_string(),
+                format: DumpFormat::Json,
+                output: Some(output.clone()),
+            },
+        ))
+        .await
+        .unwrap();
+        let rows: Vec<serde_json::Value> = std::fs::read_to_string(&output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                serde_json::json!({"key": "61", "value": "01"}),
+                serde_json::json!({"key": "62", "value": "02"}),
+            ]
+        );
+
+        // unknown tables and epochs are errors
+        assert!(run(args(
+            &url,
+            StateCommand::Dump {
+                epoch: Some(2),
+                operator: "op".to_string(),
+                table: "t".to_string(),
+                format: DumpFormat::Json,
+                output: None,
+            },
+        ))
+        .await
+        .is_err());
+        assert!(run(args(
+            &url,
+            StateCommand::Dump {
+                epoch: None,
+                operator: "op".to_string(),
+                table: "missing".to_string(),
+                format: DumpFormat::Csv,
+                output: None,
+            },
+        ))
+        .await
+        .is_err());
+    }
+}
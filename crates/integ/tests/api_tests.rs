@@ -1,12 +1,13 @@
 use anyhow::bail;
+use std::collections::HashMap;
 use std::env;
 use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 
 use arroyo_openapi::types::{
     builder, ConnectionProfilePost, ConnectionSchema, ConnectionTablePost, Format, JsonFormat,
-    MetricName, PipelinePatch, PipelinePost, SchemaDefinition, StopType, Udf, ValidateQueryPost,
-    ValidateUdfPost,
+    MetricName, PipelinePatch, PipelinePost, PipelineRestart, SavepointPost, SavepointState,
+    SchemaDefinition, StopType, Udf, ValidateQueryPost, ValidateUdfPost,
 };
 use arroyo_openapi::Client;
 use rand::random;
@@ -309,6 +310,59 @@ async fn basic_pipeline() {
         assert_eq!(node.parallelism, 2);
     }
 
+    // rescale a single operator
+    let sink_id = valid
+        .graph
+        .as_ref()
+        .unwrap()
+        .nodes
+        .iter()
+        .find(|n| n.node_id.contains("sink"))
+        .unwrap()
+        .node_id
+        .clone();
+
+    assert!(api_client
+        .patch_pipeline()
+        .id(&pipeline_id)
+        .body(
+            PipelinePatch::builder()
+                .parallelism_overrides(Some(HashMap::from([("not_a_node".to_string(), 3i64)])))
+        )
+        .send()
+        .await
+        .is_err());
+
+    println!("Rescaling sink");
+    patch_and_wait(
+        &pipeline_id,
+        PipelinePatch::builder()
+            .parallelism_overrides(Some(HashMap::from([(sink_id.clone(), 3i64)]))),
+        "Running",
+    )
+    .await
+    .unwrap();
+
+    let nodes = api_client
+        .get_pipeline()
+        .id(&pipeline_id)
+        .send()
+        .await
+        .unwrap()
+        .into_inner()
+        .graph
+        .nodes;
+    assert_eq!(
+        nodes
+            .iter()
+            .find(|n| n.node_id == sink_id)
+            .unwrap()
+            .parallelism,
+        3
+    );
+    // the source isn't forward-connected to the sink, so keeps its parallelism
+    assert!(nodes.iter().any(|n| n.parallelism == 2));
+
     // restart job
     println!("Restarting pipeline");
     api_client
@@ -409,6 +463,362 @@ select my_double(cast(counter as bigint)) from impulse;
         .unwrap();
 }
 
+#[tokio::test]
+async fn savepoints() {
+    let api_client = get_client();
+
+    let query = r#"
+create table impulse with (
+   connector = 'impulse',
+   event_rate = '10'
+);
+
+select count(*) from impulse
+group by tumble(interval '1 second');
+"#;
+
+    let run_id: u32 = random();
+    let (pipeline_id, _job_id) = start_and_monitor(run_id, query, &[], 2).await.unwrap();
+
+    let url = format!("/tmp/arroyo-test/savepoints/{}", run_id);
+
+    // take a savepoint of the running pipeline
+    println!("Creating savepoint");
+    let savepoint = api_client
+        .create_savepoint()
+        .id(&pipeline_id)
+        .body(SavepointPost::builder().url(&url))
+        .send()
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(savepoint.url, url);
+
+    println!("Waiting for savepoint to be ready");
+    loop {
+        let savepoints = api_client
+            .get_savepoints()
+            .id(&pipeline_id)
+            .send()
+            .await
+            .unwrap()
+            .into_inner();
+
+        let current = savepoints
+            .data
+            .iter()
+            .find(|s| s.id == savepoint.id)
+            .expect("savepoint is not listed for the pipeline");
+
+        match current.state {
+            SavepointState::Ready => {
+                assert!(current.epoch.is_some());
+                break;
+            }
+            SavepointState::Failed => {
+                panic!("Savepoint failed: {:?}", current.failure_message);
+            }
+            _ => {}
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // stop job
+    patch_and_wait(
+        &pipeline_id,
+        PipelinePatch::builder().stop(StopType::Immediate),
+        "Stopped",
+    )
+    .await
+    .unwrap();
+
+    // savepoints can only be taken of running pipelines
+    assert_eq!(
+        api_client
+            .create_savepoint()
+            .id(&pipeline_id)
+            .body(SavepointPost::builder().url(&url))
+            .send()
+            .await
+            .unwrap_err()
+            .status()
+            .unwrap(),
+        reqwest::StatusCode::BAD_REQUEST
+    );
+
+    // unknown savepoints can't be restored from
+    assert_eq!(
+        api_client
+            .create_pipeline()
+            .body(
+                PipelinePost::builder()
+                    .name(format!("pipeline_{}_missing", run_id))
+                    .parallelism(1)
+                    .query(query)
+                    .restore_from(Some("sp_missing".to_string())),
+            )
+            .send()
+            .await
+            .unwrap_err()
+            .status()
+            .unwrap(),
+        reqwest::StatusCode::NOT_FOUND
+    );
+
+    // start a new pipeline from the savepoint
+    println!("Restoring from savepoint");
+    let restored_id = api_client
+        .create_pipeline()
+        .body(
+            PipelinePost::builder()
+                .name(format!("pipeline_{}_restored", run_id))
+                .parallelism(1)
+                .checkpoint_interval_micros(1_000_000)
+                .query(query)
+                .restore_from(Some(savepoint.id.clone())),
+        )
+        .send()
+        .await
+        .unwrap()
+        .into_inner()
+        .id;
+
+    wait_for_state(&api_client, &restored_id, "Running")
+        .await
+        .unwrap();
+
+    // the restored state is committed as the new job's first checkpoint
+    let restored_job = api_client
+        .get_pipeline_jobs()
+        .id(&restored_id)
+        .send()
+        .await
+        .unwrap()
+        .into_inner()
+        .data
+        .remove(0);
+
+    let checkpoints = api_client
+        .get_job_checkpoints()
+        .pipeline_id(&restored_id)
+        .job_id(&restored_job.id)
+        .send()
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(checkpoints
+        .data
+        .iter()
+        .any(|c| c.epoch == 1 && c.finish_time.is_some()));
+
+    patch_and_wait(
+        &restored_id,
+        PipelinePatch::builder().stop(StopType::Immediate),
+        "Stopped",
+    )
+    .await
+    .unwrap();
+
+    for id in [&pipeline_id, &restored_id] {
+        api_client.delete_pipeline().id(id).send().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn query_state() {
+    let api_client = get_client();
+
+    let query = r#"
+create table impulse with (
+   connector = 'impulse',
+   event_rate = '10'
+);
+
+select count(*) from impulse
+group by tumble(interval '1 second');
+"#;
+
+    let run_id: u32 = random();
+    let (pipeline_id, job_id) = start_and_monitor(run_id, query, &[], 1).await.unwrap();
+
+    let nodes = api_client
+        .get_pipeline()
+        .id(&pipeline_id)
+        .send()
+        .await
+        .unwrap()
+        .into_inner()
+        .graph
+        .nodes;
+
+    // only the impulse source has a table named "i", which holds a single entry
+    let mut found = 0;
+    for node in &nodes {
+        let result = api_client
+            .query_operator_state()
+            .pipeline_id(&pipeline_id)
+            .job_id(&job_id)
+            .operator_id(&node.node_id)
+            .table("i")
+            .send()
+            .await;
+
+        match result {
+            Ok(result) => {
+                let result = result.into_inner();
+                assert_eq!(result.operator_id, node.node_id);
+                assert_eq!(result.rows.len(), 1);
+                found += 1;
+            }
+            Err(e) => {
+                assert_eq!(e.status().unwrap(), reqwest::StatusCode::BAD_REQUEST);
+            }
+        }
+    }
+    assert_eq!(found, 1);
+
+    // keys must be JSON objects
+    assert_eq!(
+        api_client
+            .query_operator_state()
+            .pipeline_id(&pipeline_id)
+            .job_id(&job_id)
+            .operator_id(&nodes[0].node_id)
+            .table("i")
+            .key("not json")
+            .send()
+            .await
+            .unwrap_err()
+            .status()
+            .unwrap(),
+        reqwest::StatusCode::BAD_REQUEST
+    );
+
+    patch_and_wait(
+        &pipeline_id,
+        PipelinePatch::builder().stop(StopType::Immediate),
+        "Stopped",
+    )
+    .await
+    .unwrap();
+
+    // state can only be queried while the job is running
+    assert_eq!(
+        api_client
+            .query_operator_state()
+            .pipeline_id(&pipeline_id)
+            .job_id(&job_id)
+            .operator_id(&nodes[0].node_id)
+            .table("i")
+            .send()
+            .await
+            .unwrap_err()
+            .status()
+            .unwrap(),
+        reqwest::StatusCode::BAD_REQUEST
+    );
+
+    api_client
+        .delete_pipeline()
+        .id(&pipeline_id)
+        .send()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn restore_checkpoint_epoch() {
+    let api_client = get_client();
+
+    let query = r#"
+create table impulse with (
+   connector = 'impulse',
+   event_rate = '10'
+);
+
+select count(*) from impulse
+group by tumble(interval '1 second');
+"#;
+
+    let run_id: u32 = random();
+    let (pipeline_id, job_id) = start_and_monitor(run_id, query, &[], 4).await.unwrap();
+
+    let rolled_back = api_client
+        .get_job_checkpoints()
+        .pipeline_id(&pipeline_id)
+        .job_id(&job_id)
+        .send()
+        .await
+        .unwrap()
+        .into_inner()
+        .data
+        .into_iter()
+        .find(|c| c.epoch == 3)
+        .expect("checkpoint 3 is not listed for the job");
+
+    // only checkpoints that exist can be restored from
+    assert_eq!(
+        api_client
+            .restart_pipeline()
+            .id(&pipeline_id)
+            .body(PipelineRestart::builder().checkpoint_epoch(1000i64))
+            .send()
+            .await
+            .unwrap_err()
+            .status()
+            .unwrap(),
+        reqwest::StatusCode::BAD_REQUEST
+    );
+
+    println!("Restoring from checkpoint 2");
+    api_client
+        .restart_pipeline()
+        .id(&pipeline_id)
+        .body(PipelineRestart::builder().checkpoint_epoch(2i64))
+        .send()
+        .await
+        .unwrap();
+
+    // the later checkpoints are discarded, so the job checkpoints epoch 3 again
+    println!("Waiting for checkpoint 3 to be taken again");
+    loop {
+        let checkpoints = api_client
+            .get_job_checkpoints()
+            .pipeline_id(&pipeline_id)
+            .job_id(&job_id)
+            .send()
+            .await
+            .unwrap()
+            .into_inner()
+            .data;
+
+        if checkpoints.iter().any(|c| {
+            c.epoch == 3 && c.start_time != rolled_back.start_time && c.finish_time.is_some()
+        }) {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    patch_and_wait(
+        &pipeline_id,
+        PipelinePatch::builder().stop(StopType::Immediate),
+        "Stopped",
+    )
+    .await
+    .unwrap();
+
+    api_client
+        .delete_pipeline()
+        .id(&pipeline_id)
+        .send()
+        .await
+        .unwrap();
+}
+
 fn create_kafka_admin() -> AdminClient<impl ClientContext> {
     ClientConfig::new()
         .set("bootstrap.servers", "localhost:9092")
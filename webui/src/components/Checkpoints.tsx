@@ -91,7 +91,17 @@ const Checkpoints: React.FC<CheckpointsProps> = ({ pipeline, job, checkpoints })
         <Heading size="md">
           Checkpoint {epoch}
           <Badge marginLeft={2}>{checkpoint.backend}</Badge>
+          {checkpoint.failureReason != null && (
+            <Badge marginLeft={2} colorScheme="red">
+              failed
+            </Badge>
+          )}
         </Heading>
+        {checkpoint.failureReason != null && (
+          <Text marginTop={2} color="red.300">
+            {checkpoint.failureReason}
+          </Text>
+        )}
         {checkpointStats}
         <CheckpointDetails operators={checkpointDetails} />
       </Flex>
@@ -91,6 +91,12 @@ export interface paths {
     /** Restart a pipeline */
     post: operations["restart_pipeline"];
   };
+  "/v1/pipelines/{id}/savepoints": {
+    /** List a pipeline's savepoints */
+    get: operations["get_savepoints"];
+    /** Trigger a savepoint for a running pipeline */
+    post: operations["create_savepoint"];
+  };
   "/v1/pipelines/{pipeline_id}/jobs/{job_id}/checkpoints": {
     /** List a job's checkpoints */
     get: operations["get_job_checkpoints"];
@@ -107,6 +113,10 @@ export interface paths {
     /** Get a job's metrics */
     get: operations["get_operator_metric_groups"];
   };
+  "/v1/pipelines/{pipeline_id}/jobs/{job_id}/operators/{operator_id}/state/{table}": {
+    /** Query the current contents of one of an operator's state tables in a running job */
+    get: operations["query_operator_state"];
+  };
   "/v1/pipelines/{pipeline_id}/jobs/{job_id}/output": {
     /** Subscribe to a job's output */
     get: operations["get_job_output"];
@@ -146,8 +156,16 @@ export interface components {
     }]>;
     Checkpoint: {
       backend: string;
+      /**
+       * Format: int64
+       * @description Bytes of new state written for this epoch, not including files carried over from
+       * earlier checkpoints
+       */
+      bytes: number;
       /** Format: int32 */
       epoch: number;
+      /** @description Why the checkpoint failed, if it did */
+      failureReason?: string | null;
       /** Format: int64 */
       finishTime?: number | null;
       /** Format: int64 */
@@ -287,6 +305,11 @@ export interface components {
       /** Format: int64 */
       finishTime?: number | null;
       id: string;
+      /**
+       * Format: int64
+       * @description How long the controller is waiting before restarting the job after a failure
+       */
+      restartDelayMicros?: number | null;
       /** Format: int64 */
       runId: number;
       runningDesired: boolean;
@@ -336,7 +359,7 @@ export interface components {
       subtasks: (components["schemas"]["SubtaskMetrics"])[];
     };
     /** @enum {string} */
-    MetricName: "bytes_recv" | "bytes_sent" | "messages_recv" | "messages_sent" | "backpressure" | "tx_queue_size" | "tx_queue_rem";
+    MetricName: "bytes_recv" | "bytes_sent" | "messages_recv" | "messages_sent" | "backpressure" | "tx_queue_size" | "tx_queue_rem" | "busy_time" | "source_lag";
     NewlineDelimitedFraming: {
       /** Format: int64 */
       maxLineLength?: number | null;
@@ -413,20 +436,106 @@ export interface components {
     PipelinePatch: {
       /** Format: int64 */
       checkpointIntervalMicros?: number | null;
-      /** Format: int64 */
+      /**
+       * @description When restoring, operators whose state is incompatible with the savepoint start with
+       * empty state instead of failing the restore
+       */
+      dropIncompatibleState?: boolean | null;
+      /**
+       * @description Replaces the pipeline's node selector; takes effect the next time its workers are
+       * scheduled
+       */
+      nodeSelector?: {
+        [key: string]: string | undefined;
+      } | null;
+      /**
+       * Format: int64
+       * @description Sets the parallelism of every operator in the pipeline
+       */
       parallelism?: number | null;
+      /**
+       * @description Sets the parallelism of individual operators, keyed by node id; applied after
+       * `parallelism`
+       */
+      parallelismOverrides?: {
+        [key: string]: number | undefined;
+      } | null;
+      /** Format: int32 */
+      priority?: number | null;
+      restartStrategy?: components["schemas"]["RestartStrategy"] | null;
+      /**
+       * @description A savepoint id or URL to restore the pipeline's state from; this is applied the next time
+       * the pipeline is started, so a running pipeline must be stopped or restarted for it to
+       * take effect
+       */
+      restoreFrom?: string | null;
+      /**
+       * @description Maps operator ids in the pipeline to the ids of the operators in the savepoint whose
+       * state they should be restored from
+       */
+      stateMapping?: {
+        [key: string]: string | undefined;
+      } | null;
       stop?: components["schemas"]["StopType"] | null;
     };
     PipelinePost: {
       /** Format: int64 */
       checkpointIntervalMicros?: number | null;
+      /**
+       * @description When restoring, operators whose state is incompatible with the savepoint start with
+       * empty state instead of failing the restore
+       */
+      dropIncompatibleState?: boolean | null;
       name: string;
-      /** Format: int64 */
+      /**
+       * @description Labels that nodes must have for the pipeline's workers to be scheduled on them; only
+       * used by the node and Kubernetes schedulers
+       */
+      nodeSelector?: {
+        [key: string]: string | undefined;
+      } | null;
+      /**
+       * Format: int64
+       * @description Default parallelism for the operators in the pipeline
+       */
       parallelism: number;
+      /**
+       * @description Parallelism for individual operators, keyed by node id; these take precedence over the
+       * default parallelism and any parallelism hints in the query
+       */
+      parallelismOverrides?: {
+        [key: string]: number | undefined;
+      } | null;
+      /**
+       * Format: int32
+       * @description When the cluster doesn't have enough free slots, queued pipelines with higher priorities
+       * are scheduled first; defaults to 0
+       */
+      priority?: number | null;
       query: string;
+      /**
+       * @description How the pipeline is restarted after failures; defaults to the cluster's configured
+       * strategy
+       */
+      restartStrategy?: components["schemas"]["RestartStrategy"] | null;
+      /** @description A savepoint id or URL to restore the pipeline's state from */
+      restoreFrom?: string | null;
+      /**
+       * @description Maps operator ids in the pipeline to the ids of the operators in the savepoint whose
+       * state they should be restored from
+       */
+      stateMapping?: {
+        [key: string]: string | undefined;
+      } | null;
       udfs?: (components["schemas"]["Udf"])[] | null;
     };
     PipelineRestart: {
+      /**
+       * Format: int32
+       * @description Restore from the checkpoint with this epoch instead of the latest one; later
+       * checkpoints are discarded
+       */
+      checkpointEpoch?: number | null;
       force?: boolean | null;
     };
     PreviewPost: {
@@ -442,6 +551,51 @@ export interface components {
     };
     RawBytesFormat: Record<string, never>;
     RawStringFormat: Record<string, never>;
+    /** @description How a pipeline is restarted after it fails */
+    RestartStrategy: OneOf<[{
+      /** Format: int64 */
+      delayMicros: number;
+      /** @enum {string} */
+      type: "fixedDelay";
+    }, {
+      /** Format: int64 */
+      initialDelayMicros: number;
+      /** Format: int64 */
+      maxDelayMicros: number;
+      /** Format: double */
+      multiplier: number;
+      /** @enum {string} */
+      type: "exponentialBackoff";
+    }, {
+      /** Format: int64 */
+      delayMicros: number;
+      /** Format: int64 */
+      intervalMicros: number;
+      /** Format: int32 */
+      maxFailures: number;
+      /** @enum {string} */
+      type: "failureRate";
+    }]>;
+    Savepoint: {
+      /** Format: int64 */
+      createdAt: number;
+      /** Format: int32 */
+      epoch?: number | null;
+      failureMessage?: string | null;
+      /** Format: int64 */
+      finishTime?: number | null;
+      id: string;
+      state: components["schemas"]["SavepointState"];
+      url: string;
+    };
+    SavepointCollection: {
+      data: (components["schemas"]["Savepoint"])[];
+    };
+    SavepointPost: {
+      url: string;
+    };
+    /** @enum {string} */
+    SavepointState: "requested" | "inProgress" | "ready" | "failed";
     SchemaDefinition: OneOf<[{
       json_schema: string;
     }, {
@@ -460,6 +614,11 @@ export interface components {
       sqlName?: string | null;
       type: components["schemas"]["FieldType"];
     };
+    StateQueryResult: {
+      operatorId: string;
+      rows: (unknown)[];
+      table: string;
+    };
     /** @enum {string} */
     StopType: "none" | "checkpoint" | "graceful" | "immediate" | "force";
     StructType: {
@@ -859,6 +1018,51 @@ export interface operations {
       };
     };
   };
+  /** List a pipeline's savepoints */
+  get_savepoints: {
+    parameters: {
+      path: {
+        /** @description Pipeline id */
+        id: string;
+      };
+    };
+    responses: {
+      /** @description Got savepoints */
+      200: {
+        content: {
+          "application/json": components["schemas"]["SavepointCollection"];
+        };
+      };
+    };
+  };
+  /** Trigger a savepoint for a running pipeline */
+  create_savepoint: {
+    parameters: {
+      path: {
+        /** @description Pipeline id */
+        id: string;
+      };
+    };
+    requestBody: {
+      content: {
+        "application/json": components["schemas"]["SavepointPost"];
+      };
+    };
+    responses: {
+      /** @description Requested savepoint */
+      200: {
+        content: {
+          "application/json": components["schemas"]["Savepoint"];
+        };
+      };
+      /** @description Bad request */
+      400: {
+        content: {
+          "application/json": components["schemas"]["ErrorResp"];
+        };
+      };
+    };
+  };
   /** List a job's checkpoints */
   get_job_checkpoints: {
     parameters: {
@@ -943,6 +1147,43 @@ export interface operations {
       };
     };
   };
+  /** Query the current contents of one of an operator's state tables in a running job */
+  query_operator_state: {
+    parameters: {
+      query?: {
+        /**
+         * @description JSON object mapping the key columns of the table to the values to look up; if not set,
+         * all entries are returned
+         */
+        key?: string | null;
+        limit?: number | null;
+      };
+      path: {
+        /** @description Pipeline id */
+        pipeline_id: string;
+        /** @description Job id */
+        job_id: string;
+        /** @description Operator id */
+        operator_id: string;
+        /** @description State table name */
+        table: string;
+      };
+    };
+    responses: {
+      /** @description Got state */
+      200: {
+        content: {
+          "application/json": components["schemas"]["StateQueryResult"];
+        };
+      };
+      /** @description Bad request */
+      400: {
+        content: {
+          "application/json": components["schemas"]["ErrorResp"];
+        };
+      };
+    };
+  };
   /** Subscribe to a job's output */
   get_job_output: {
     parameters: {
@@ -540,6 +540,7 @@ export function MapWidget({
   values,
   errors,
   readonly,
+  password,
 }: {
   schema: JSONSchema7;
   onChange: (e: React.ChangeEvent<any>) => void;
@@ -547,6 +548,7 @@ export function MapWidget({
   values: any;
   errors: any;
   readonly?: boolean;
+  password?: boolean;
 }) {
   interface KeyValuePair {
     key: string;
@@ -622,6 +624,7 @@ export function MapWidget({
             <Text>→</Text>
             <Input
               id={i + '_value'}
+              type={password ? 'password' : 'text'}
               value={pair.value}
               onChange={e => updateKeyValue(i, pair.key, e.target.value)}
               readOnly={readonly}
@@ -929,6 +932,8 @@ export function FormInner({
                         errors={errors}
                         onChange={onChange}
                         readonly={readonly}
+                        // @ts-ignore
+                        password={schema.sensitive?.includes(key)}
                       />
                     </Box>
                   );
@@ -45,7 +45,7 @@ import OperatorErrors from '../../components/OperatorErrors';
 import { PipelineGraphViewer } from './PipelineGraph';
 import PipelineNotFound from '../../components/PipelineNotFound';
 import { QuestionOutlineIcon, WarningIcon } from '@chakra-ui/icons';
-import { formatError } from '../../lib/util';
+import { durationFormat, formatError } from '../../lib/util';
 import { PipelineOutputs } from './PipelineOutputs';
 import PaginatedContent from '../../components/PaginatedContent';
 import SyntaxHighlighter from 'react-syntax-highlighter';
@@ -151,6 +151,14 @@ export function PipelineDetails() {
             <Text>{job.state}</Text>
           </Box>
         </Box>
+        {job.restartDelayMicros ? (
+          <Box className="field">
+            <Box className="fieldName">Restart Delay</Box>
+            <Box className="fieldValue">
+              <Text>{durationFormat(job.restartDelayMicros)}</Text>
+            </Box>
+          </Box>
+        ) : null}
         {operatorDetail}
       </Stack>
     </TabPanel>